@@ -39,9 +39,11 @@ pub enum SignCommands {
         delegatee: String,
         /// Whether to delegate the governance voting power too.
         governance: bool,
+        /// The height of the block that will include the transaction.
         target_height: u64,
     },
     TxUndelegate {
+        /// The height of the block that will include the transaction.
         target_height: u64,
     },
     Custom {
@@ -65,6 +67,11 @@ pub enum Commands {
     Clone {
         /// The URL of the remote repository.
         url: String,
+        /// A trusted finalized block to verify forward from, instead of the genesis.
+        ///
+        /// It is a `Checkpoint` in JSON; the hash of the block header and its finalization proof.
+        #[clap(long)]
+        checkpoint: Option<String>,
     },
 
     // ----- Modification Commands ----- //
@@ -88,10 +95,18 @@ pub enum Commands {
         /// If enabled, it will remove
         /// 1. all branches except `finalized`, `fp`, and `work`
         /// 2. all remote repositories
-        /// 3. all orphan commits
+        /// 3. all `vote-#` and `veto-#` tags on the outdated commits
+        /// 4. all orphan commits
         #[clap(long, action)]
         hard: bool,
     },
+    /// Rebase the `work` branch on the `finalized` branch, without any merge commit.
+    ///
+    /// This will replay the transactions of the `work` branch onto the `finalized` branch,
+    /// renewing the outdated timestamps, and verify them before moving the `work` branch.
+    /// The other commits (e.g., agendas for an outdated height) are dropped.
+    /// It fails if a transaction conflicts with the `finalized` branch.
+    Rebase,
     /// Create a new commit on top of the `work` branch.
     #[command(subcommand)]
     Create(CreateCommands),
@@ -128,6 +143,21 @@ pub enum Commands {
     Git,
     /// Show the overall information of the given commit.
     Show { commit: String },
+    /// Review the agenda of the given `a-#` branch before voting.
+    ///
+    /// It lists every transaction with its author, timestamp and changed files,
+    /// and summarizes the changes of the reserved state (e.g., members and voting powers).
+    Review {
+        branch: String,
+        /// If enabled, it prints the review in JSON.
+        #[clap(long, action)]
+        json: bool,
+    },
+    /// Verify the signature of the given commit and the signatures of the `vote` and `veto` tags on it.
+    ///
+    /// The block and agenda commits are signed by their authors, the `fp` commits by the nodes
+    /// that recorded them, and the tags by the members who voted or vetoed.
+    VerifyCommit { commit: String },
     /// Show the current status of the p2p network.
     Network,
 
@@ -137,6 +167,11 @@ pub enum Commands {
     /// You cannot perform any other operations while running this command;
     /// you have to run another shell to perform client-side, synchronous operations.
     Serve,
+    /// Become a relay indefinitely, storing and forwarding the messages of the recent heights
+    /// for the members who are rarely online.
+    ///
+    /// It can be run by a mirror that is not a member, which the members trust by their configs.
+    Relay,
     /// Update the node state by fetching data from the p2p network,
     /// verifying incoming data, and applying to the repository and consensus & governance status.
     Update,
@@ -158,16 +193,20 @@ pub enum Commands {
     /// Sign a message with the configured private key.
     #[command(subcommand)]
     Sign(SignCommands),
+    /// Migrate the storages of the node to the kind of the `storage` in the config,
+    /// keeping the messages and the consensus state.
+    ///
+    /// You have to stop the server node and the relay before running this.
+    MigrateStorage,
     /// A special command triggered by the Git hook, which is used to verify the push request.
     CheckPush {
         /// The hash of the tip commit of the branch that is being pushed.
         commit: String,
         /// The name of the branch that is being pushed.
         branch_name: String,
-        /// The Unix timestamp of the push request. This is for preventing replay attacks.
-        timestamp: u64,
-        /// The signature by the pusher.
-        signature: String,
+        /// The push option, `<timestamp> <signature>`, where the timestamp is
+        /// for preventing replay attacks and the signature is by the pusher.
+        push_option: String,
     },
     /// A special command triggered by the Git hook, which is used to notify the push request.
     NotifyPush { commit: String },
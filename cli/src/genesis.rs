@@ -80,6 +80,12 @@ pub async fn run_genesis_proposer(private_key: &str) {
             governance_port: 1155,
             consensus_port: 1166,
             repository_port: 1177,
+            governance_gossip_port: None,
+            consensus_gossip_port: None,
+            discovery_port: None,
+            storage: Default::default(),
+            relays: Vec::new(),
+            relay_port: None,
         },
         &dir,
     )
@@ -162,8 +168,7 @@ pub async fn run_genesis_non_proposer(private_key: &str) {
         "cd {dir}/repository/repo && git remote remove origin"
     ))
     .await;
-    let ports =
-        r#"{"dms-consensus-0-3f22f0a0":1166,"repository":1177,"dms-governance-0-3f22f0a0":1155}"#;
+    let ports = r#"{"dms-consensus":1166,"repository":1177,"dms-governance":1155}"#;
     let ports = serde_json::from_str(ports).unwrap();
     setup_peer(
         &dir,
@@ -196,6 +201,12 @@ pub async fn run_genesis_non_proposer(private_key: &str) {
             governance_port: 1155,
             consensus_port: 1166,
             repository_port: 1177,
+            governance_gossip_port: None,
+            consensus_gossip_port: None,
+            discovery_port: None,
+            storage: Default::default(),
+            relays: Vec::new(),
+            relay_port: None,
         },
         &dir,
     )
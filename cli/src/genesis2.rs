@@ -34,6 +34,12 @@ async fn fuck() {
         governance_port: 1155,
         consensus_port: 1166,
         repository_port: 1177,
+        governance_gossip_port: None,
+        consensus_gossip_port: None,
+        discovery_port: None,
+        storage: Default::default(),
+        relays: Vec::new(),
+        relay_port: None,
     }, "/Users/junhayang/pdao/genesis").await.unwrap();
 }
 
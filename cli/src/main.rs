@@ -5,7 +5,9 @@ use clap::Parser;
 use cli::*;
 use eyre::{eyre, Result};
 use simperby_node::{
-    clone, genesis, initialize, serve, simperby_common::*, simperby_repository::CommitHash,
+    check_push, clone, genesis, initialize, migrate_storage, relay, serve,
+    simperby_common::*,
+    simperby_repository::{CommitHash, PushVerifier},
     CommitInfo, Config,
 };
 
@@ -30,8 +32,13 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
             genesis(config, &path).await?;
         }
         Commands::Init => todo!(),
-        Commands::Clone { url } => {
-            clone(config, &path, &url).await?;
+        Commands::Clone { url, checkpoint } => {
+            let checkpoint = checkpoint
+                .map(|checkpoint| {
+                    serde_spb::from_str(&checkpoint).map_err(|_| eyre!("invalid checkpoint"))
+                })
+                .transpose()?;
+            clone(config, &path, &url, checkpoint).await?;
         }
         Commands::Git => todo!(),
         Commands::Show { commit } => show(config, &path, commit).await?,
@@ -39,7 +46,13 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
         Commands::Serve => {
             serve(config, &path).await?;
         }
+        Commands::Relay => {
+            relay(config, &path).await?;
+        }
         Commands::Chat { .. } => todo!("chat is not implemented yet"),
+        Commands::MigrateStorage => {
+            migrate_storage(config, &path).await?;
+        }
         Commands::Sign(SignCommands::TxDelegate {
             delegatee,
             governance,
@@ -53,14 +66,14 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                 block_height: target_height,
             };
             println!(
-                "{:?}",
+                "{}",
                 serde_spb::to_string(
                     &TypedSignature::<DelegationTransactionData>::sign(
                         &delegation_transaction_data,
                         &config.private_key,
                     )
                     .map_err(|_| eyre!("failed to sign"))?
-                )
+                )?
             );
         }
         Commands::Sign(SignCommands::TxUndelegate { target_height }) => {
@@ -69,14 +82,14 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                 block_height: target_height,
             };
             println!(
-                "{:?}",
+                "{}",
                 serde_spb::to_string(
                     &TypedSignature::<UndelegationTransactionData>::sign(
                         &undelegation_transaction_data,
                         &config.private_key,
                     )
                     .map_err(|_| eyre!("failed to sign"))?
-                )
+                )?
             );
         }
         Commands::Sign(SignCommands::Custom { hash }) => {
@@ -94,7 +107,24 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                 )
             );
         }
-        Commands::CheckPush { .. } => todo!("check push is not implemented yet"),
+        Commands::CheckPush {
+            commit,
+            branch_name,
+            push_option,
+        } => {
+            let (timestamp, signature) = PushVerifier::decode_push_option(&push_option)?;
+            if !check_push(
+                &path,
+                to_commit_hash(&commit)?,
+                branch_name,
+                timestamp,
+                signature,
+            )
+            .await?
+            {
+                return Err(eyre!("push rejected"));
+            }
+        }
         Commands::NotifyPush { .. } => todo!("notify push is not implemented yet"),
         // Commands that require `initialize` to be called.
         _ => {
@@ -111,7 +141,14 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                         .await?;
                 }
                 Commands::Clean { hard } => {
-                    simperby_node.clean(hard).await?;
+                    for message in simperby_node.clean(hard).await? {
+                        println!("{message}");
+                    }
+                }
+                Commands::Rebase => {
+                    for message in simperby_node.rebase().await? {
+                        println!("{message}");
+                    }
                 }
                 Commands::Create(CreateCommands::TxDelegate {
                     delegator,
@@ -188,6 +225,22 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                         simperby_node.progress_for_consensus().await?;
                     }
                 }
+                Commands::Review { branch, json } => {
+                    let review = simperby_node.review_agenda(&branch).await?;
+                    if json {
+                        println!("{}", serde_spb::to_string(&review)?);
+                    } else {
+                        print!("{review}");
+                    }
+                }
+                Commands::VerifyCommit { commit } => {
+                    for message in simperby_node
+                        .verify_commit(to_commit_hash(&commit)?)
+                        .await?
+                    {
+                        println!("{message}");
+                    }
+                }
                 Commands::Update => {
                     simperby_node.fetch().await?;
                 }
@@ -1,7 +1,7 @@
 //! A set of types and functions related to cryptography, that are widely used in the entire Simperby project.
 use secp256k1::{
     ecdsa::{RecoverableSignature, RecoveryId},
-    Message, Secp256k1, SecretKey,
+    Message, Secp256k1, SecretKey, SECP256K1,
 };
 use serde::{ser::SerializeTuple, Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
@@ -70,6 +70,10 @@ impl<'de, const N: usize> Deserialize<'de> for HexSerializedBytes<N> {
     where
         D: serde::de::Deserializer<'de>,
     {
+        if !deserializer.is_human_readable() {
+            let data = deserializer.deserialize_tuple(N, BytesVisitor::<N>)?;
+            return Ok(HexSerializedBytes { data });
+        }
         let s: String = Deserialize::deserialize(deserializer)?;
         let bytes = hex::decode(s).map_err(|e| serde::de::Error::custom(e.to_string()))?;
         if bytes.len() != N {
@@ -81,6 +85,30 @@ impl<'de, const N: usize> Deserialize<'de> for HexSerializedBytes<N> {
     }
 }
 
+/// Reads the tuple of bytes that `HexSerializedBytes` is serialized into, by a binary format.
+struct BytesVisitor<const N: usize>;
+
+impl<'de, const N: usize> serde::de::Visitor<'de> for BytesVisitor<N> {
+    type Value = [u8; N];
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{N} bytes")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut data = [0; N];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+        }
+        Ok(data)
+    }
+}
+
 /// A cryptographic hash.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Copy, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -149,7 +177,8 @@ impl Signature {
         let private_key = secp256k1::SecretKey::from_slice(&private_key.key.data)
             .map_err(|_| Error::InvalidFormat("private key: [omitted]".to_owned()))?;
         let message = Message::from_slice(data.as_ref()).unwrap();
-        let (recovery_id, rs) = Secp256k1::signing_only()
+        // Uses the global context, since creating one takes most of the time of signing.
+        let (recovery_id, rs) = SECP256K1
             .sign_ecdsa_recoverable(&message, &private_key)
             .serialize_compact();
         let v = recovery_id.to_i32() as u8;
@@ -172,7 +201,7 @@ impl Signature {
         let public_key = secp256k1::PublicKey::from_slice(&public_key.key.data)
             .map_err(|_| Error::InvalidFormat(format!("public_key: {public_key}")))?;
         let message = Message::from_slice(data.as_ref()).unwrap();
-        Secp256k1::verification_only()
+        SECP256K1
             .verify_ecdsa(&message, &signature, &public_key)
             .map_err(|_| Error::VerificationFailed)
     }
@@ -190,8 +219,7 @@ impl Signature {
         }
         let signature =
             RecoverableSignature::from_compact(&self.signature.data[0..64], recovery_id).unwrap();
-        let secp = Secp256k1::new();
-        let public_key = secp
+        let public_key = SECP256K1
             .recover_ecdsa(&message, &signature)
             .map_err(|_| Error::VerificationFailed)?
             .serialize();
@@ -352,6 +380,22 @@ pub fn check_keypair_match(public_key: &PublicKey, private_key: &PrivateKey) ->
     signature.verify(Hash256::hash(msg), public_key)
 }
 
+/// Derives the secret shared by the owners of the two keypairs, using ECDH.
+///
+/// Both sides get the same secret from their own private key and the other's public key.
+pub fn derive_shared_secret(
+    private_key: &PrivateKey,
+    public_key: &PublicKey,
+) -> Result<Hash256, Error> {
+    let private_key = SecretKey::from_slice(&private_key.key.data)
+        .map_err(|_| Error::InvalidFormat("private key: [omitted]".to_owned()))?;
+    let public_key = secp256k1::PublicKey::from_slice(&public_key.key.data)
+        .map_err(|_| Error::InvalidFormat(format!("public_key: {public_key}")))?;
+    Ok(Hash256::from_array(
+        secp256k1::ecdh::SharedSecret::new(&public_key, &private_key).secret_bytes(),
+    ))
+}
+
 /// Generates a new keypair using the seed.
 pub fn generate_keypair(seed: impl AsRef<[u8]>) -> (PublicKey, PrivateKey) {
     let mut seed_: [u8; 32] = [0; 32];
@@ -372,8 +416,8 @@ pub fn generate_keypair(seed: impl AsRef<[u8]>) -> (PublicKey, PrivateKey) {
 pub fn generate_keypair_random() -> (PublicKey, PrivateKey) {
     use secp256k1::rand::SeedableRng;
     let mut rng = secp256k1::rand::rngs::StdRng::from_entropy();
-    let secp = Secp256k1::new();
-    let (private_key, public_key) = secp.generate_keypair(&mut rng);
+    // Uses the global context, since this is done for every connection (e.g., ephemeral keys).
+    let (private_key, public_key) = SECP256K1.generate_keypair(&mut rng);
     (
         PublicKey::from_array(public_key.serialize()).expect("invalid public key"),
         PrivateKey::from_array(private_key.secret_bytes()).expect("invalid private key"),
@@ -440,6 +484,16 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn binary_encode_decode() {
+        let (public_key, private_key) = generate_keypair("hello world");
+        let signature = Signature::sign(Hash256::hash("hello world"), &private_key).unwrap();
+        let value = (Hash256::hash("hello world"), public_key, signature);
+        let encoded = serde_spb::to_vec(&value).unwrap();
+        let decoded = serde_spb::from_slice(&encoded).unwrap();
+        assert_eq!(value, decoded);
+    }
+
     #[test]
     fn signature_verify() {
         let (public_key, private_key) = generate_keypair("hello world");
@@ -481,4 +535,20 @@ mod tests {
             hex::encode(recovered.as_ref())
         );
     }
+
+    #[test]
+    fn shared_secret() {
+        let (public_key1, private_key1) = generate_keypair("hello world");
+        let (public_key2, private_key2) = generate_keypair("hello world2");
+        let (public_key3, _) = generate_keypair("hello world3");
+        let secret = derive_shared_secret(&private_key1, &public_key2).unwrap();
+        assert_eq!(
+            secret,
+            derive_shared_secret(&private_key2, &public_key1).unwrap()
+        );
+        assert_ne!(
+            secret,
+            derive_shared_secret(&private_key1, &public_key3).unwrap()
+        );
+    }
 }
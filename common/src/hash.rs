@@ -72,6 +72,12 @@ impl ToHash256 for GenesisInfo {
     }
 }
 
+impl ToHash256 for LastFinalizationProof {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
 impl ToHash256 for Commit {
     fn to_hash256(&self) -> Hash256 {
         match self {
@@ -115,7 +121,26 @@ impl BlockHeader {
         merkle_tree.root()
     }
 
-    // note that `repository_merkle_root` is calculated from `simperby-repository`.
+    /// Calculates `repository_merkle_root` from the files of the repository,
+    /// given as `(path, hash of the content)`.
+    ///
+    /// The files in the reserved directory (`reserved/`) are excluded
+    /// because the reserved state is already verified by the commit sequence.
+    /// Each leaf is the hash of the `(path, hash of the content)` pair, sorted by the path.
+    pub fn calculate_repository_merkle_root(files: &[(String, Hash256)]) -> Hash256 {
+        let mut files = files
+            .iter()
+            .filter(|(path, _)| !path.starts_with("reserved/"))
+            .collect::<Vec<_>>();
+        files.sort_by(|(x, _), (y, _)| x.cmp(y));
+        let merkle_tree = crate::merkle_tree::OneshotMerkleTree::create(
+            files
+                .into_iter()
+                .map(|file| Hash256::hash(serde_spb::to_vec(file).unwrap()))
+                .collect(),
+        );
+        merkle_tree.root()
+    }
 }
 
 #[cfg(test)]
@@ -12,4 +12,8 @@ pub use crypto::*;
 pub use reserved::*;
 pub use types::*;
 
-pub const SIMPERBY_CORE_PROTOCOL_VERSION: &str = "0.1.0";
+/// The version of the protocol, which changes whenever the consensus rules change.
+///
+/// `0.2.0` excludes the previous block from the commit merkle root of a block,
+/// and adds the content policy to the reserved state, which changes its hash.
+pub const SIMPERBY_CORE_PROTOCOL_VERSION: &str = "0.2.0";
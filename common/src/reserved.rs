@@ -14,10 +14,46 @@ pub struct ReservedState {
     pub members: Vec<Member>,
     /// The leader order of the consensus rounds.
     ///
-    /// It MUST be sorted by the name of the members.
+    /// It contains the members who have not delegated their consensus voting power.
+    /// A member who delegates leaves the order, and rejoins at the end of it on undelegation.
     pub consensus_leader_order: Vec<MemberName>,
     /// The semantic version of Simperby protocol for this network.
     pub version: String,
+    /// The policy on the non-reserved files that every transaction must follow.
+    ///
+    /// Adding this field changed the encoding and thus the hash of the reserved state,
+    /// including that of the genesis; `#[serde(default)]` only keeps the older JSON readable.
+    #[serde(default)]
+    pub content_policy: ContentPolicy,
+}
+
+/// A path whose changes must be approved by the specific members.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ProtectedPath {
+    /// The prefix of the protected paths (e.g., `treasury/`).
+    pub prefix: String,
+    /// The members who must sign the agenda proof of a transaction changing the path.
+    pub approvers: Vec<MemberName>,
+}
+
+/// The policy on the non-reserved files that every transaction must follow.
+///
+/// It is stored in `reserved/policy.json`, and there is no restriction if the file doesn't exist.
+///
+/// The policy is enforced locally by each node when it receives, syncs or creates commits,
+/// not by the commit sequence verifier, which never sees the files.
+/// A node which skips the check can still finalize a violating transaction.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct ContentPolicy {
+    /// The maximum size of a file in bytes.
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    /// Whether to forbid the binary files.
+    #[serde(default)]
+    pub forbid_binary_files: bool,
+    /// The paths whose changes must be approved by the specific members.
+    #[serde(default)]
+    pub protected_paths: Vec<ProtectedPath>,
 }
 
 impl ReservedState {
@@ -65,12 +101,69 @@ impl ReservedState {
             .collect())
     }
 
-    pub fn apply_delegate(&mut self, _tx: &TxDelegate) -> Result<Self, String> {
-        unimplemented!()
+    /// Applies the delegation and returns the updated state.
+    ///
+    /// Note that this does not verify the proof of the transaction.
+    pub fn apply_delegate(&mut self, tx: &TxDelegate) -> Result<Self, String> {
+        let delegator = self
+            .query_name(&tx.delegator)
+            .ok_or_else(|| format!("delegator {} is not a member", tx.delegator))?;
+        let delegatee = self
+            .query_name(&tx.delegatee)
+            .ok_or_else(|| format!("delegatee {} is not a member", tx.delegatee))?;
+        if delegator == delegatee {
+            return Err(format!("{delegator} can't delegate to itself"));
+        }
+        // Chained delegations are not allowed.
+        if let Some(x) = self.members.iter().find(|m| {
+            m.consensus_delegatee.as_ref() == Some(&delegator)
+                || m.governance_delegatee.as_ref() == Some(&delegator)
+        }) {
+            return Err(format!("{delegator} is already a delegatee of {}", x.name));
+        }
+        if self
+            .members
+            .iter()
+            .any(|m| m.name == delegatee && m.consensus_delegatee.is_some())
+        {
+            return Err(format!("delegatee {delegatee} has delegated to another"));
+        }
+        let member = self
+            .members
+            .iter_mut()
+            .find(|m| m.name == delegator)
+            .expect("checked above");
+        if member.consensus_delegatee.is_some() {
+            return Err(format!("{delegator} has already delegated"));
+        }
+        member.consensus_delegatee = Some(delegatee.clone());
+        if tx.governance {
+            member.governance_delegatee = Some(delegatee);
+        }
+        self.consensus_leader_order
+            .retain(|name| name != &delegator);
+        Ok(self.clone())
     }
 
-    pub fn apply_undelegate(&mut self, _tx: &TxUndelegate) -> Result<Self, String> {
-        unimplemented!()
+    /// Applies the undelegation and returns the updated state.
+    ///
+    /// Note that this does not verify the proof of the transaction.
+    pub fn apply_undelegate(&mut self, tx: &TxUndelegate) -> Result<Self, String> {
+        let delegator = self
+            .query_name(&tx.delegator)
+            .ok_or_else(|| format!("delegator {} is not a member", tx.delegator))?;
+        let member = self
+            .members
+            .iter_mut()
+            .find(|m| m.name == delegator)
+            .expect("checked above");
+        if member.consensus_delegatee.is_none() {
+            return Err(format!("{delegator} has not delegated"));
+        }
+        member.consensus_delegatee = None;
+        member.governance_delegatee = None;
+        self.consensus_leader_order.push(delegator);
+        Ok(self.clone())
     }
 
     pub fn query_name(&self, public_key: &PublicKey) -> Option<MemberName> {
@@ -179,6 +272,7 @@ mod tests {
             members,
             consensus_leader_order: vec!["member-0003".to_string()],
             version: "0.1.0".to_string(),
+            content_policy: ContentPolicy::default(),
         };
         assert_eq!(
             reserved_state.get_validator_set().unwrap(),
@@ -226,6 +320,7 @@ mod tests {
             members,
             consensus_leader_order: vec!["member-0001".to_string(), "member-0003".to_string()],
             version: "0.1.0".to_string(),
+            content_policy: ContentPolicy::default(),
         };
         assert_eq!(
             reserved_state.get_validator_set().unwrap(),
@@ -276,6 +371,7 @@ mod tests {
                 .map(|i| format!("member-{i:04}"))
                 .collect::<Vec<_>>(),
             version: "0.1.0".to_string(),
+            content_policy: ContentPolicy::default(),
         };
         assert_eq!(
             reserved_state.get_governance_set().unwrap(),
@@ -326,6 +422,7 @@ mod tests {
                 .map(|i| format!("member-{i:04}"))
                 .collect::<Vec<_>>(),
             version: "0.1.0".to_string(),
+            content_policy: ContentPolicy::default(),
         };
         assert_eq!(
             reserved_state
@@ -338,4 +435,155 @@ mod tests {
                 .collect::<HashSet<_>>()
         );
     }
+
+    fn create_tx_delegate(
+        keys: &[(PublicKey, PrivateKey)],
+        delegator: usize,
+        delegatee: usize,
+        governance: bool,
+    ) -> TxDelegate {
+        let data = DelegationTransactionData {
+            delegator: keys[delegator].0.clone(),
+            delegatee: keys[delegatee].0.clone(),
+            governance,
+            block_height: 1,
+        };
+        TxDelegate {
+            delegator: data.delegator.clone(),
+            delegatee: data.delegatee.clone(),
+            governance,
+            proof: TypedSignature::sign(&data, &keys[delegator].1).unwrap(),
+            timestamp: 0,
+        }
+    }
+
+    fn create_tx_undelegate(keys: &[(PublicKey, PrivateKey)], delegator: usize) -> TxUndelegate {
+        let data = UndelegationTransactionData {
+            delegator: keys[delegator].0.clone(),
+            block_height: 1,
+        };
+        TxUndelegate {
+            delegator: data.delegator.clone(),
+            proof: TypedSignature::sign(&data, &keys[delegator].1).unwrap(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn delegate_and_undelegate() {
+        setup_test();
+        let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        let original = reserved_state.clone();
+        let delegated = reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 0, 3, true))
+            .unwrap();
+        assert_eq!(delegated, reserved_state);
+        assert_eq!(
+            reserved_state.consensus_leader_order,
+            vec!["member-0001", "member-0002", "member-0003"]
+        );
+        assert_eq!(
+            reserved_state.get_validator_set().unwrap(),
+            vec![
+                (keys[1].0.clone(), 1),
+                (keys[2].0.clone(), 1),
+                (keys[3].0.clone(), 2)
+            ]
+        );
+        assert_eq!(
+            reserved_state.members[0].governance_delegatee,
+            Some("member-0003".to_owned())
+        );
+        reserved_state
+            .apply_undelegate(&create_tx_undelegate(&keys, 0))
+            .unwrap();
+        assert_eq!(reserved_state.members, original.members);
+        assert_eq!(
+            reserved_state.consensus_leader_order,
+            vec!["member-0001", "member-0002", "member-0003", "member-0000"]
+        );
+    }
+
+    #[test]
+    fn undelegate_with_unsorted_leader_order() {
+        setup_test();
+        let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        reserved_state.consensus_leader_order =
+            ["member-0002", "member-0000", "member-0003", "member-0001"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect();
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 0, 1, false))
+            .unwrap();
+        assert_eq!(
+            reserved_state.consensus_leader_order,
+            vec!["member-0002", "member-0003", "member-0001"]
+        );
+        // The order of the others stays the same, and the delegator rejoins at the end.
+        reserved_state
+            .apply_undelegate(&create_tx_undelegate(&keys, 0))
+            .unwrap();
+        assert_eq!(
+            reserved_state.consensus_leader_order,
+            vec!["member-0002", "member-0003", "member-0001", "member-0000"]
+        );
+        assert_eq!(
+            reserved_state.get_validator_set().unwrap(),
+            vec![
+                (keys[2].0.clone(), 1),
+                (keys[3].0.clone(), 1),
+                (keys[1].0.clone(), 1),
+                (keys[0].0.clone(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_delegate() {
+        setup_test();
+        let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        let (_, other_keys) = test_utils::generate_standard_genesis(5);
+        let non_member = vec![other_keys[4].clone(), keys[0].clone()];
+        // Non-member delegator or delegatee
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&non_member, 0, 1, false))
+            .unwrap_err();
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&non_member, 1, 0, false))
+            .unwrap_err();
+        // Self-delegation
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 0, 0, false))
+            .unwrap_err();
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 0, 1, false))
+            .unwrap();
+        let delegated = reserved_state.clone();
+        // Double delegation
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 0, 2, false))
+            .unwrap_err();
+        // Chained delegations
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 2, 0, false))
+            .unwrap_err();
+        reserved_state
+            .apply_delegate(&create_tx_delegate(&keys, 1, 2, false))
+            .unwrap_err();
+        assert_eq!(reserved_state, delegated);
+    }
+
+    #[test]
+    fn invalid_undelegate() {
+        setup_test();
+        let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        let (_, other_keys) = test_utils::generate_standard_genesis(5);
+        reserved_state
+            .apply_undelegate(&create_tx_undelegate(&other_keys, 4))
+            .unwrap_err();
+        reserved_state
+            .apply_undelegate(&create_tx_undelegate(&keys, 0))
+            .unwrap_err();
+    }
 }
@@ -61,6 +61,7 @@ pub fn generate_standard_genesis(
                 .map(|i| format!("member-{i:04}"))
                 .collect::<Vec<_>>(),
             version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+            content_policy: ContentPolicy::default(),
         },
         keys,
     )
@@ -128,6 +129,7 @@ pub fn generate_delegated_genesis(
                 .map(|i| format!("member-{i:04}"))
                 .collect::<Vec<_>>(),
             version: "0.1.0".to_string(),
+            content_policy: ContentPolicy::default(),
         },
         keys,
     )
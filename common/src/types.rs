@@ -43,7 +43,7 @@ pub struct BlockHeader {
     pub height: BlockHeight,
     /// The timestamp of this block.
     pub timestamp: Timestamp,
-    /// The Merkle root of all the commits for this block.
+    /// The Merkle root of all the commits for this block, excluding the previous block commit.
     pub commit_merkle_root: Hash256,
     /// The Merkle root of the non-essential state.
     pub repository_merkle_root: Hash256,
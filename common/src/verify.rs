@@ -1,5 +1,6 @@
 use crate::reserved::ReservedState;
 use crate::*;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 use std::collections::HashMap;
 use thiserror::Error;
@@ -88,7 +89,7 @@ pub fn verify_finalization_proof(
 // Phases of the `CommitSequenceVerifier`.
 //
 // Note that `Phase::X` is agenda phase where `Commit::X` is the last commit.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 enum Phase {
     // The transaction phase.
     // Note that there can be agendas without transactions.
@@ -117,7 +118,9 @@ enum Phase {
 /// Verifies whether the given sequence of commits can be agenda subset of agenda finalized chain.
 ///
 /// It may accept sequences that contain more than one `BlockHeader`.
-#[derive(Debug, Clone)]
+///
+/// It is serializable so that the state of the verification can be persisted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommitSequenceVerifier {
     header: BlockHeader,
     phase: Phase,
@@ -157,6 +160,19 @@ impl CommitSequenceVerifier {
             .collect()
     }
 
+    /// Forgets the commits before the block commit at the given index of `get_total_commits()`,
+    /// as if the verifier had been created with that block.
+    pub fn forget_before(&mut self, block_index: usize) -> Result<(), Error> {
+        if let Some(Commit::Block(_)) = self.total_commits.get(block_index) {
+            self.total_commits.drain(..block_index);
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument(format!(
+                "commit {block_index} is not a block commit"
+            )))
+        }
+    }
+
     /// Verifies finalization of the last header with the given proof.
     ///
     /// Note that due to the nature of the finalization proof (included in the next block)
@@ -165,6 +181,22 @@ impl CommitSequenceVerifier {
         verify_finalization_proof(&self.header, proof)
     }
 
+    /// Verifies `repository_merkle_root` of the last header with the files of the repository
+    /// at the last header, given as `(path, hash of the content)`.
+    ///
+    /// Like the finalization proof, it is verified separately
+    /// because the repository content is not a part of the commit sequence.
+    pub fn verify_repository_merkle_root(&self, files: &[(String, Hash256)]) -> Result<(), Error> {
+        let repository_merkle_root = BlockHeader::calculate_repository_merkle_root(files);
+        if repository_merkle_root != self.header.repository_merkle_root {
+            return Err(Error::InvalidArgument(format!(
+                "invalid repository merkle root: expected {}, got {}",
+                repository_merkle_root, self.header.repository_merkle_root
+            )));
+        }
+        Ok(())
+    }
+
     /// Verifies whether the given reserved state is valid from the current state.
     pub fn verify_reserved_state(&self, _rs: &ReservedState) -> Result<(), Error> {
         // TODO:
@@ -183,6 +215,7 @@ impl CommitSequenceVerifier {
         match (commit, &mut self.phase) {
             (Commit::Block(block_header), Phase::AgendaProof { agenda_proof: _ }) => {
                 verify_header_to_header(&self.header, block_header)?;
+                verify_validator_set(&self.reserved_state, block_header)?;
                 // Verify commit merkle root
                 let commit_merkle_root =
                     BlockHeader::calculate_commit_merkle_root(&self.next_block_commits);
@@ -203,6 +236,7 @@ impl CommitSequenceVerifier {
                 },
             ) => {
                 verify_header_to_header(&self.header, block_header)?;
+                verify_validator_set(&self.reserved_state, block_header)?;
                 // Check if the block contains all the extra-agenda transactions.
                 if block_header.timestamp < *last_extra_agenda_timestamp {
                     return Err(Error::InvalidArgument(format!(
@@ -225,7 +259,7 @@ impl CommitSequenceVerifier {
             }
             (Commit::Transaction(tx), Phase::Block) => {
                 // Update reserved_state for reserved-diff transactions.
-                if let Diff::Reserved(rs) = &tx.diff {
+                if let Diff::Reserved(rs) | Diff::General(rs, _) = &tx.diff {
                     self.reserved_state = *rs.clone();
                 }
                 self.phase = Phase::Transaction {
@@ -248,7 +282,7 @@ impl CommitSequenceVerifier {
                     )));
                 }
                 // Update reserved_state for reserved-diff transactions.
-                if let Diff::Reserved(rs) = &tx.diff {
+                if let Diff::Reserved(rs) | Diff::General(rs, _) = &tx.diff {
                     self.reserved_state = *rs.clone();
                 }
                 preceding_transactions.push(last_transaction.clone());
@@ -370,27 +404,10 @@ impl CommitSequenceVerifier {
                 };
             }
             (Commit::ExtraAgendaTransaction(tx), Phase::AgendaProof { agenda_proof: _ }) => {
-                match tx {
-                    ExtraAgendaTransaction::Delegate(tx) => {
-                        // Update reserved reserved_state by applying delegation
-                        self.reserved_state.apply_delegate(tx).map_err(|e| {
-                            Error::InvalidArgument(format!("invalid delegation: {e}"))
-                        })?;
-                        self.phase = Phase::ExtraAgendaTransaction {
-                            last_extra_agenda_timestamp: tx.timestamp,
-                        };
-                    }
-                    ExtraAgendaTransaction::Undelegate(tx) => {
-                        // Update reserved reserved_state by applying undelegation
-                        self.reserved_state.apply_undelegate(tx).map_err(|e| {
-                            Error::InvalidArgument(format!("invalid undelegation: {e}"))
-                        })?;
-                        self.phase = Phase::ExtraAgendaTransaction {
-                            last_extra_agenda_timestamp: tx.timestamp,
-                        };
-                    }
-                    ExtraAgendaTransaction::Report(_tx) => unimplemented!(),
-                }
+                apply_extra_agenda_transaction(&mut self.reserved_state, &self.header, tx)?;
+                self.phase = Phase::ExtraAgendaTransaction {
+                    last_extra_agenda_timestamp: extra_agenda_transaction_timestamp(tx)?,
+                };
             }
             (
                 Commit::ExtraAgendaTransaction(tx),
@@ -398,35 +415,15 @@ impl CommitSequenceVerifier {
                     last_extra_agenda_timestamp,
                 },
             ) => {
-                match tx {
-                    ExtraAgendaTransaction::Delegate(tx) => {
-                        // Update reserved reserved_state by applying delegation
-                        self.reserved_state.apply_delegate(tx).map_err(|e| {
-                            Error::InvalidArgument(format!("invalid delegation: {e}"))
-                        })?;
-                        // Check if extra-agenda transactions are in chronological order
-                        if tx.timestamp < *last_extra_agenda_timestamp {
-                            return Err(Error::InvalidArgument(
-                                format!("invalid extra-agenda transaction timestamp: expected larger than or equal to the last transaction timestamp {}, got {}", last_extra_agenda_timestamp, tx.timestamp)
-                            ));
-                        }
-                        *last_extra_agenda_timestamp = tx.timestamp;
-                    }
-                    ExtraAgendaTransaction::Undelegate(tx) => {
-                        // Update reserved reserved_state by applying undelegation
-                        self.reserved_state.apply_undelegate(tx).map_err(|e| {
-                            Error::InvalidArgument(format!("invalid undelegation: {e}"))
-                        })?;
-                        // Check if extra-agenda transactions are in chronological order
-                        if tx.timestamp < *last_extra_agenda_timestamp {
-                            return Err(Error::InvalidArgument(
-                                format!("invalid extra-agenda transaction timestamp: expected larger than or equal to the last transaction timestamp {}, got {}", last_extra_agenda_timestamp, tx.timestamp)
-                            ));
-                        }
-                        *last_extra_agenda_timestamp = tx.timestamp;
-                    }
-                    ExtraAgendaTransaction::Report(_tx) => unimplemented!(),
+                // Check if extra-agenda transactions are in chronological order
+                let timestamp = extra_agenda_transaction_timestamp(tx)?;
+                if timestamp < *last_extra_agenda_timestamp {
+                    return Err(Error::InvalidArgument(
+                        format!("invalid extra-agenda transaction timestamp: expected larger than or equal to the last transaction timestamp {last_extra_agenda_timestamp}, got {timestamp}")
+                    ));
                 }
+                apply_extra_agenda_transaction(&mut self.reserved_state, &self.header, tx)?;
+                *last_extra_agenda_timestamp = timestamp;
             }
             (Commit::ChatLog(_chat_log), _) => unimplemented!(),
             (commit, phase) => {
@@ -436,12 +433,102 @@ impl CommitSequenceVerifier {
                 ));
             }
         }
-        self.next_block_commits.push(commit.clone());
+        // A block commit belongs to the block itself, not to the next one.
+        if !matches!(commit, Commit::Block(_)) {
+            self.next_block_commits.push(commit.clone());
+        }
         self.total_commits.push(commit.clone());
         Ok(())
     }
 }
 
+/// Verifies that the validator set of the given block header is the one of the reserved state
+/// that the block finalizes.
+fn verify_validator_set(
+    reserved_state: &ReservedState,
+    block_header: &BlockHeader,
+) -> Result<(), Error> {
+    let validator_set = reserved_state
+        .get_validator_set()
+        .map_err(Error::InvalidArgument)?;
+    if validator_set != block_header.validator_set {
+        return Err(Error::InvalidArgument(format!(
+            "invalid validator set: expected {:?}, got {:?}",
+            validator_set, block_header.validator_set
+        )));
+    }
+    Ok(())
+}
+
+fn extra_agenda_transaction_timestamp(tx: &ExtraAgendaTransaction) -> Result<Timestamp, Error> {
+    match tx {
+        ExtraAgendaTransaction::Delegate(tx) => Ok(tx.timestamp),
+        ExtraAgendaTransaction::Undelegate(tx) => Ok(tx.timestamp),
+        ExtraAgendaTransaction::Report(_tx) => Err(Error::InvalidArgument(
+            "report transactions are not supported yet".to_string(),
+        )),
+    }
+}
+
+/// Verifies the proof of the given extra-agenda transaction and applies it to the reserved state.
+///
+/// The proof must be signed by the delegator for the height of the block being made on top of `header`.
+fn apply_extra_agenda_transaction(
+    reserved_state: &mut ReservedState,
+    header: &BlockHeader,
+    tx: &ExtraAgendaTransaction,
+) -> Result<(), Error> {
+    match tx {
+        ExtraAgendaTransaction::Delegate(tx) => {
+            let data = DelegationTransactionData {
+                delegator: tx.delegator.clone(),
+                delegatee: tx.delegatee.clone(),
+                governance: tx.governance,
+                block_height: header.height + 1,
+            };
+            verify_extra_agenda_transaction_proof(&tx.proof, &data, &tx.delegator)?;
+            // Update reserved reserved_state by applying delegation
+            reserved_state
+                .apply_delegate(tx)
+                .map_err(|e| Error::InvalidArgument(format!("invalid delegation: {e}")))?;
+        }
+        ExtraAgendaTransaction::Undelegate(tx) => {
+            let data = UndelegationTransactionData {
+                delegator: tx.delegator.clone(),
+                block_height: header.height + 1,
+            };
+            verify_extra_agenda_transaction_proof(&tx.proof, &data, &tx.delegator)?;
+            // Update reserved reserved_state by applying undelegation
+            reserved_state
+                .apply_undelegate(tx)
+                .map_err(|e| Error::InvalidArgument(format!("invalid undelegation: {e}")))?;
+        }
+        ExtraAgendaTransaction::Report(_tx) => {
+            return Err(Error::InvalidArgument(
+                "report transactions are not supported yet".to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn verify_extra_agenda_transaction_proof<T: ToHash256>(
+    proof: &TypedSignature<T>,
+    data: &T,
+    delegator: &PublicKey,
+) -> Result<(), Error> {
+    proof
+        .verify(data)
+        .map_err(|e| Error::CryptoError("invalid extra-agenda transaction proof".to_string(), e))?;
+    if proof.signer() != delegator {
+        return Err(Error::InvalidProof(format!(
+            "extra-agenda transaction proof must be signed by the delegator {delegator}, not {}",
+            proof.signer()
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -532,6 +619,7 @@ mod test {
             members, // TODO: fix to not use genesis header
             consensus_leader_order,
             version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+            content_policy: ContentPolicy::default(),
         }
     }
 
@@ -697,6 +785,64 @@ mod test {
         }
     }
 
+    /// Applies an agenda commit with no transactions and its unanimous agenda-proof commit.
+    fn apply_agenda_and_agenda_proof_commits(
+        validator_keypair: &[(PublicKey, PrivateKey)],
+        csv: &mut CommitSequenceVerifier,
+    ) -> Agenda {
+        let agenda = Agenda {
+            author: validator_keypair[0].0.clone(),
+            timestamp: 1,
+            transactions_hash: calculate_agenda_transactions_hash(csv.phase.clone()),
+            height: csv.header.height + 1,
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        csv.apply_commit(&generate_agenda_proof_commit(
+            validator_keypair,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap();
+        agenda
+    }
+
+    fn generate_delegate_commit(
+        delegator: &(PublicKey, PrivateKey),
+        delegatee: &(PublicKey, PrivateKey),
+        block_height: BlockHeight,
+        time: Timestamp,
+    ) -> Commit {
+        let data = DelegationTransactionData {
+            delegator: delegator.0.clone(),
+            delegatee: delegatee.0.clone(),
+            governance: true,
+            block_height,
+        };
+        Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Delegate(TxDelegate {
+            delegator: data.delegator.clone(),
+            delegatee: data.delegatee.clone(),
+            governance: data.governance,
+            proof: TypedSignature::sign(&data, &delegator.1).unwrap(),
+            timestamp: time,
+        }))
+    }
+
+    fn generate_undelegate_commit(
+        delegator: &(PublicKey, PrivateKey),
+        block_height: BlockHeight,
+        time: Timestamp,
+    ) -> Commit {
+        let data = UndelegationTransactionData {
+            delegator: delegator.0.clone(),
+            block_height,
+        };
+        Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Undelegate(TxUndelegate {
+            delegator: data.delegator.clone(),
+            proof: TypedSignature::sign(&data, &delegator.1).unwrap(),
+            timestamp: time,
+        }))
+    }
+
     #[test]
     /// Test the case where the commit sequence is correct.
     fn correct_commit_sequence1() {
@@ -758,6 +904,80 @@ mod test {
         .unwrap();
     }
 
+    #[test]
+    /// Test the case where the commit sequence is correct with extra-agenda transaction commits.
+    fn correct_commit_sequence3() {
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        // Apply delegation and undelegation commits
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap();
+        csv.apply_commit(&generate_undelegate_commit(
+            &validator_keypair[0],
+            csv.header.height + 1,
+            3,
+        ))
+        .unwrap();
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[2],
+            &validator_keypair[0],
+            csv.header.height + 1,
+            3,
+        ))
+        .unwrap();
+        assert_eq!(
+            csv.reserved_state.consensus_leader_order,
+            vec!["member1".to_owned(), "member0".to_owned()]
+        );
+    }
+
+    #[test]
+    /// Test the case where the block commit is invalid because its validator set
+    /// doesn't reflect the delegation in the block.
+    fn invalid_block_commit_with_stale_validator_set() {
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        let delegate_commit = generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        );
+        csv.apply_commit(&delegate_commit).unwrap();
+        let mut block_commit = generate_block_commit(
+            &validator_keypair,
+            1,
+            csv.header.clone(),
+            3,
+            BlockHeader::calculate_commit_merkle_root(&csv.total_commits[1..]),
+            Hash256::zero(),
+        );
+        csv.apply_commit(&block_commit).unwrap_err();
+        if let Commit::Block(header) = &mut block_commit {
+            header.validator_set = vec![
+                (validator_keypair[1].0.clone(), 2),
+                (validator_keypair[2].0.clone(), 1),
+            ];
+        }
+        csv.apply_commit(&block_commit).unwrap();
+    }
+
+    #[test]
+    /// Test the case where the extra-agenda transaction is a report, which is not supported yet.
+    fn unsupported_report_transaction() {
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&Commit::ExtraAgendaTransaction(
+            ExtraAgendaTransaction::Report(TxReport {}),
+        ))
+        .unwrap_err();
+    }
+
     #[test]
     /// Test the case where the block commit is invalid because the block height is invalid.
     fn invalid_block_commit_with_invalid_height() {
@@ -1051,6 +1271,79 @@ mod test {
         .unwrap_err();
     }
 
+    #[test]
+    /// Test the case where the commit merkle root of a block at height 2 doesn't include the
+    /// previous block commit.
+    fn correct_commit_merkle_root_after_block() {
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        for height in 1..=2 {
+            let agenda: Agenda = Agenda {
+                author: validator_keypair[0].0.clone(),
+                timestamp: 2 * height - 1,
+                transactions_hash: calculate_agenda_transactions_hash(csv.phase.clone()),
+                height: height as BlockHeight,
+            };
+            let agenda_commit = generate_agenda_commit(&agenda);
+            let agenda_proof_commit =
+                generate_agenda_proof_commit(&validator_keypair, &agenda, agenda.to_hash256());
+            csv.apply_commit(&agenda_commit).unwrap();
+            csv.apply_commit(&agenda_proof_commit).unwrap();
+            let commits = vec![agenda_commit, agenda_proof_commit];
+            let header = csv.header.clone();
+            let block_commit = |commits: &[Commit]| {
+                generate_block_commit(
+                    &validator_keypair,
+                    0,
+                    header.clone(),
+                    2 * height,
+                    BlockHeader::calculate_commit_merkle_root(commits),
+                    header.repository_merkle_root,
+                )
+            };
+            if height == 2 {
+                // The root including the previous block commit is rejected.
+                let previous_block = Commit::Block(header.clone());
+                let commits = [vec![previous_block], commits.clone()].concat();
+                csv.apply_commit(&block_commit(&commits)).unwrap_err();
+            }
+            csv.apply_commit(&block_commit(&commits)).unwrap();
+        }
+        assert_eq!(csv.header.height, 2);
+    }
+
+    #[test]
+    /// Test the case where the block commit is invalid because the repository merkle root is invalid.
+    fn invalid_block_commit_with_invalid_repository_merkle_root() {
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        let files = vec![
+            ("README.md".to_owned(), Hash256::hash("hello")),
+            ("src/main.rs".to_owned(), Hash256::hash("world")),
+        ];
+        // Apply agenda and agenda-proof commits
+        let agenda = apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        // Apply block commit with the repository merkle root of `files`
+        let commit_merkle_root = BlockHeader::calculate_commit_merkle_root(&csv.next_block_commits);
+        csv.apply_commit(&generate_block_commit(
+            &validator_keypair,
+            0,
+            csv.header.clone(),
+            agenda.timestamp,
+            commit_merkle_root,
+            BlockHeader::calculate_repository_merkle_root(&files),
+        ))
+        .unwrap();
+        csv.verify_repository_merkle_root(&files).unwrap();
+        // The reserved directory doesn't affect the repository merkle root.
+        let mut reserved_files = files.clone();
+        reserved_files.push(("reserved/version".to_owned(), Hash256::hash("0.1.0")));
+        csv.verify_repository_merkle_root(&reserved_files).unwrap();
+        // Verify with modified files
+        let mut modified_files = files;
+        modified_files[1].1 = Hash256::hash("modified");
+        csv.verify_repository_merkle_root(&modified_files)
+            .unwrap_err();
+    }
+
     #[test]
     /// Test the case where the block commit is invalid because block commit already exists.
     fn phase_mismatch_for_block_commit1() {
@@ -1189,13 +1482,21 @@ mod test {
             .unwrap_err();
     }
 
-    #[ignore]
     #[test]
     /// Test the case where the transaction commit is invalid because it is extra-agenda transaction phase.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
     fn phase_mismatch_for_transaction_commit3() {
-        todo!("Implement this test");
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap();
+        // Apply transaction commit at extra-agenda transaction phase
+        csv.apply_commit(&generate_empty_transaction_commit(&validator_keypair, 0, 3))
+            .unwrap_err();
     }
 
     #[test]
@@ -1317,13 +1618,21 @@ mod test {
             .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the agenda commit is invalid because it is extra-agenda transaction phase.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the agenda commit is invalid because it is extra-agenda transaction phase.
     fn phase_mismatch_for_agenda_commit3() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        let agenda = apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap();
+        // Apply agenda commit at extra-agenda transaction phase
+        csv.apply_commit(&generate_agenda_commit(&agenda))
+            .unwrap_err();
     }
 
     #[test]
@@ -1455,13 +1764,25 @@ mod test {
         .unwrap_err();
     }
 
-    #[ignore]
     #[test]
     /// Test the case where the agenda proof commit is invalid because it is extra-agenda transaction phase.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    /// TODO: enable this test case when the extra-agenda transaction is implemented.
     fn phase_mismatch_for_agenda_proof_commit3() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        let agenda = apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap();
+        // Apply agenda-proof commit at extra-agenda transaction phase
+        csv.apply_commit(&generate_agenda_proof_commit(
+            &validator_keypair,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap_err();
     }
 
     #[test]
@@ -1484,85 +1805,177 @@ mod test {
         .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Delegate` extra-agenda transaction is invalid because the delegator is not a member.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Delegate` extra-agenda transaction is invalid because the delegator is not a member.
     fn invalid_delegate_transaction_with_invalid_delegator1() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &generate_keypair([9]),
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Delegate` extra-agenda transaction is invalid because the delegator has already delegated.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Delegate` extra-agenda transaction is invalid because the delegator has already delegated.
     fn invalid_delegate_transaction_with_invalid_delegator2() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap();
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[2],
+            csv.header.height + 1,
+            3,
+        ))
+        .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Delegate` extra-agenda transaction is invalid because the delegatee is not a member.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Delegate` extra-agenda transaction is invalid because the delegatee is not a member.
     fn invalid_delegate_transaction_with_invalid_delegatee() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &generate_keypair([9]),
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Delegate` extra-agenda transaction is invalid because the signature is invalid.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Delegate` extra-agenda transaction is invalid because the signature is invalid.
     fn invalid_delegate_transaction_with_invalid_signature() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        // Apply delegation signed for another height
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 2,
+            2,
+        ))
+        .unwrap_err();
+        // Apply delegation signed by the delegatee
+        let mut commit = generate_delegate_commit(
+            &validator_keypair[1],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        );
+        if let Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Delegate(tx)) = &mut commit {
+            tx.delegator = validator_keypair[0].0.clone();
+        }
+        csv.apply_commit(&commit).unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Delegate` extra-agenda transaction is invalid because the timestamp is invalid.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Delegate` extra-agenda transaction is invalid because the timestamp is invalid.
     fn invalid_delegate_transaction_with_invalid_timestamp() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            5,
+        ))
+        .unwrap();
+        // Apply delegation older than the last extra-agenda transaction
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[2],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            4,
+        ))
+        .unwrap_err();
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[2],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            5,
+        ))
+        .unwrap();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Undelegate` extra-agenda transaction is invalid because the delegator is not a member.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    /// TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Undelegate` extra-agenda transaction is invalid because the delegator is not a member.
     fn invalid_undelegate_transaction_with_invalid_delegator1() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_undelegate_commit(
+            &generate_keypair([9]),
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Undelegate` extra-agenda transaction is invalid because the delegator has not delegated.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    /// TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Undelegate` extra-agenda transaction is invalid because the delegator has not delegated.
     fn invalid_undelegate_transaction_with_invalid_delegator2() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_undelegate_commit(
+            &validator_keypair[0],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Undelegate` extra-agenda transaction is invalid because the signature is invalid.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    // TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Undelegate` extra-agenda transaction is invalid because the signature is invalid.
     fn invalid_undelegate_transaction_with_invalid_signature() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            2,
+        ))
+        .unwrap();
+        // Apply undelegation signed by the delegatee
+        let mut commit =
+            generate_undelegate_commit(&validator_keypair[1], csv.header.height + 1, 3);
+        if let Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Undelegate(tx)) = &mut commit
+        {
+            tx.delegator = validator_keypair[0].0.clone();
+        }
+        csv.apply_commit(&commit).unwrap_err();
     }
 
-    #[ignore]
     #[test]
-    // Test the case where the `Undelegate` extra-agenda transaction is invalid because the timestamp is invalid.
-    /// This test case is ignored because the extra-agenda transaction is not implemented yet.
-    /// TODO: enable this test case when the extra-agenda transaction is implemented.
+    /// Test the case where the `Undelegate` extra-agenda transaction is invalid because the timestamp is invalid.
     fn invalid_undelegate_transaction_with_invalid_timestamp() {
-        todo!("Implement this test")
+        let (validator_keypair, _, mut csv) = setup_test(3);
+        apply_agenda_and_agenda_proof_commits(&validator_keypair, &mut csv);
+        csv.apply_commit(&generate_delegate_commit(
+            &validator_keypair[0],
+            &validator_keypair[1],
+            csv.header.height + 1,
+            5,
+        ))
+        .unwrap();
+        // Apply undelegation older than the last extra-agenda transaction
+        csv.apply_commit(&generate_undelegate_commit(
+            &validator_keypair[0],
+            csv.header.height + 1,
+            4,
+        ))
+        .unwrap_err();
     }
 
     // TODO: add test cases where the `Report` extra-agenda transactions are invalid.
@@ -9,7 +9,7 @@ use simperby_common::{
     Timestamp, ToHash256, TypedSignature, VotingPower,
 };
 use simperby_network::{
-    dms::{DistributedMessageSet as DMS, Message, MessageFilter},
+    dms::{DistributedMessageSet as DMS, DmsMessage, Message, MessageFilter, Metrics},
     primitives::{GossipNetwork, Storage},
 };
 use std::collections::BTreeSet;
@@ -126,6 +126,8 @@ pub enum ConsensusMessage {
     NilPreCommitted(ConsensusRound),
 }
 
+impl DmsMessage for ConsensusMessage {}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProgressResult {
     Proposed(ConsensusRound, Hash256, Timestamp),
@@ -144,15 +146,13 @@ pub struct ConsensusMessageFilter {
     validator_set: BTreeSet<PublicKey>,
 }
 
-impl MessageFilter for ConsensusMessageFilter {
-    fn filter(&self, message: &Message) -> Result<(), String> {
+impl MessageFilter<ConsensusMessage> for ConsensusMessageFilter {
+    fn filter(&self, message: &Message<ConsensusMessage>) -> Result<(), String> {
         let signer = message.signature().signer();
         if !self.validator_set.contains(signer) {
             return Err("the signer is not in the validator set".to_string());
         }
-        let consensus_message =
-            serde_spb::from_str::<ConsensusMessage>(message.data()).map_err(|e| e.to_string())?;
-        match consensus_message {
+        match message.data().clone() {
             ConsensusMessage::Proposal { block_hash, .. } => self.verify_block_hash(block_hash),
             ConsensusMessage::NonNilPreVoted(_, block_hash, prevote) => {
                 if signer != prevote.signer() {
@@ -191,7 +191,7 @@ impl ConsensusMessageFilter {
 
 pub struct Consensus<N: GossipNetwork, S: Storage> {
     /// The distributed consensus message set.
-    dms: DMS<N, S>,
+    dms: DMS<N, S, ConsensusMessage>,
     /// The local storage for the consensus state.
     state_storage: S,
     /// The cache of the consensus state.
@@ -210,7 +210,7 @@ impl<N: GossipNetwork, S: Storage> Consensus<N, S> {
     /// It clears and re-initializes the DMS and the stroage
     /// if the block header is different from the last one.
     pub async fn new(
-        mut dms: DMS<N, S>,
+        mut dms: DMS<N, S, ConsensusMessage>,
         mut state_storage: S,
         block_header: BlockHeader,
         consensus_parameters: ConsensusParams,
@@ -352,9 +352,7 @@ impl<N: GossipNetwork, S: Storage> Consensus<N, S> {
                     .iter()
                     .position(|(pubkey, _)| pubkey == message.signature().signer())
                     .expect("this must be already verified by the message filter");
-                let consensus_message = serde_spb::from_str::<ConsensusMessage>(message.data())
-                    .expect("this must be already verified by the message filter");
-                self.consensus_message_to_event(&consensus_message, signer)
+                self.consensus_message_to_event(message.data(), signer)
             })
             .collect();
         let progress_responses = vetomint_copy.progress(events, timestamp)?;
@@ -381,6 +379,11 @@ impl<N: GossipNetwork, S: Storage> Consensus<N, S> {
         self.dms.fetch().await
     }
 
+    /// Returns the counts of the consensus messages received from the other peers.
+    pub fn dms_metrics(&self) -> Metrics {
+        self.dms.metrics()
+    }
+
     /// Serves the consensus protocol indefinitely.
     ///
     /// Note: currently it just returns itself after the given time.
@@ -400,13 +403,7 @@ impl<N: GossipNetwork, S: Storage> Consensus<N, S> {
         let raw_messages = self.dms.read_messages().await?;
         let messages = raw_messages
             .into_iter()
-            .map(|m| {
-                (
-                    serde_spb::from_str::<ConsensusMessage>(m.data()),
-                    m.signature().signer().clone(),
-                )
-            })
-            .filter_map(|(cm, pk)| cm.ok().map(|cm| (cm, pk)))
+            .map(|m| (m.data().clone(), m.signature().signer().clone()))
             .collect();
         Ok(messages)
     }
@@ -477,10 +474,12 @@ impl<N: GossipNetwork, S: Storage> Consensus<N, S> {
         &mut self,
         consensus_message: &ConsensusMessage,
     ) -> Result<(), Error> {
-        let serialized = serde_spb::to_string(consensus_message).unwrap();
-        let signature = TypedSignature::sign(&serialized, self.this_node_key.as_ref().unwrap())
-            .expect("invalid(malformed) private key");
-        let message = Message::new(serialized, signature).expect("signature just created");
+        let message = Message::sign(
+            &self.dms.get_key(),
+            consensus_message.clone(),
+            self.this_node_key.as_ref().unwrap(),
+        )
+        .expect("invalid(malformed) private key");
         self.dms.add_message(message).await
     }
 
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use simperby_common::*;
 use simperby_network::{
-    dms::{DistributedMessageSet as DMS, Message},
+    dms::{DistributedMessageSet as DMS, DmsMessage, Message},
     primitives::{GossipNetwork, Storage},
 };
 use std::collections::HashMap;
@@ -22,20 +22,25 @@ pub struct GovernanceStatus {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-struct Vote {
+pub struct Vote {
     pub agenda_hash: Hash256,
     pub voter: PublicKey,
     pub signature: Signature,
 }
 
+impl DmsMessage for Vote {}
+
 pub struct Governance<N: GossipNetwork, S: Storage> {
-    pub dms: DMS<N, S>,
+    pub dms: DMS<N, S, Vote>,
     pub this_node_key: Option<PrivateKey>,
 }
 
 impl<N: GossipNetwork, S: Storage> Governance<N, S> {
     /// TODO: this must take the eligible governance set for this height.
-    pub async fn new(dms: DMS<N, S>, this_node_key: Option<PrivateKey>) -> Result<Self, Error> {
+    pub async fn new(
+        dms: DMS<N, S, Vote>,
+        this_node_key: Option<PrivateKey>,
+    ) -> Result<Self, Error> {
         Ok(Self { dms, this_node_key })
     }
 
@@ -44,7 +49,7 @@ impl<N: GossipNetwork, S: Storage> Governance<N, S> {
         let votes = messages
             .iter()
             .map(|message| {
-                let vote: Vote = serde_spb::from_str(message.data()).unwrap();
+                let vote = message.data().clone();
                 (vote.agenda_hash, vote.voter, vote.signature)
             })
             .fold(
@@ -59,15 +64,15 @@ impl<N: GossipNetwork, S: Storage> Governance<N, S> {
     }
 
     pub async fn vote(&mut self, agenda_hash: Hash256) -> Result<(), Error> {
-        let data = serde_spb::to_string(&Vote {
+        let vote = Vote {
             agenda_hash,
             voter: self.this_node_key.as_ref().unwrap().public_key(),
             signature: Signature::sign(agenda_hash, self.this_node_key.as_ref().unwrap())?,
-        })
-        .unwrap();
-        let message = Message::new(
-            data.clone(),
-            TypedSignature::sign(&data, self.this_node_key.as_ref().unwrap())?,
+        };
+        let message = Message::sign(
+            &self.dms.get_key(),
+            vote,
+            self.this_node_key.as_ref().unwrap(),
         )?;
 
         self.dms.add_message(message).await?;
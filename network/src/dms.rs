@@ -3,66 +3,160 @@ use super::*;
 use async_trait::async_trait;
 use eyre::eyre;
 use futures::prelude::*;
-use serde_tc::http::*;
-use serde_tc::{serde_tc_full, StubCall};
+use reconciliation::{RangeQuery, RangeReply, Reconciler};
 use simperby_common::*;
 use std::collections::HashSet;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
+use transport::AuthenticatedConnection;
 
 const STATE_FILE_PATH: &str = "_state.json";
 type DmsKey = String;
 
-#[derive(Debug, Clone, Serialize)]
-pub struct Message {
-    data: String,
-    signature: TypedSignature<String>,
+/// A type of the messages in a DMS.
+///
+/// A message is encoded by `serde_spb::to_vec()` into the bytes that are signed and transferred,
+/// so that a malformed one is rejected when it is received, before reaching the consumer.
+pub trait DmsMessage:
+    Serialize + serde::de::DeserializeOwned + Clone + std::fmt::Debug + Send + Sync + 'static
+{
+    /// Encodes the message into its canonical bytes.
+    fn to_bytes(&self) -> Vec<u8> {
+        serde_spb::to_vec(self).expect("a message must be serializable")
+    }
+
+    /// Decodes a message, rejecting any encoding other than the canonical one.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let message: Self = serde_spb::from_slice(bytes)?;
+        if message.to_bytes() != bytes {
+            return Err(eyre!("non-canonical encoding of a message"));
+        }
+        Ok(message)
+    }
 }
 
-impl Message {
-    pub fn new(data: String, signature: TypedSignature<String>) -> Result<Self, CryptoError> {
-        signature.verify(&data)?;
-        Ok(Self { data, signature })
+/// What the signature of a message is created on.
+///
+/// It includes the key of the DMS so that a message can't be taken into another DMS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageCommitment {
+    pub dms_key: DmsKey,
+    pub data: Vec<u8>,
+}
+
+impl ToHash256 for MessageCommitment {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
     }
+}
 
-    pub fn data(&self) -> &str {
+/// A verified message of a DMS.
+#[derive(Debug, Clone)]
+pub struct Message<M> {
+    data: M,
+    raw: RawMessage,
+}
+
+impl<M: DmsMessage> Message<M> {
+    /// Creates a message for the DMS of the given key, verifying the signature.
+    pub fn new(
+        dms_key: &str,
+        data: M,
+        signature: TypedSignature<MessageCommitment>,
+    ) -> Result<Self, Error> {
+        RawMessage {
+            data: data.to_bytes(),
+            signature,
+        }
+        .into_message(dms_key)
+    }
+
+    /// Creates a message for the DMS of the given key, signing it.
+    pub fn sign(dms_key: &str, data: M, private_key: &PrivateKey) -> Result<Self, CryptoError> {
+        let commitment = MessageCommitment {
+            dms_key: dms_key.to_owned(),
+            data: data.to_bytes(),
+        };
+        let signature = TypedSignature::sign(&commitment, private_key)?;
+        Ok(Self {
+            data,
+            raw: RawMessage {
+                data: commitment.data,
+                signature,
+            },
+        })
+    }
+
+    pub fn data(&self) -> &M {
         &self.data
     }
 
-    pub fn signature(&self) -> &TypedSignature<String> {
-        &self.signature
+    pub fn signature(&self) -> &TypedSignature<MessageCommitment> {
+        &self.raw.signature
     }
 }
 
-impl ToHash256 for Message {
+impl<M> ToHash256 for Message<M> {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        self.raw.to_hash256()
     }
 }
 
 /// Decides whether a message should be accepted or not.
-pub trait MessageFilter: Send + Sync + 'static {
-    fn filter(&self, message: &Message) -> Result<(), String>;
+pub trait MessageFilter<M>: Send + Sync + 'static {
+    fn filter(&self, message: &Message<M>) -> Result<(), String>;
 }
 
-/// A message before verification.
+/// A message before decoding and verification.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawMessage {
-    pub data: String,
-    pub signature: TypedSignature<String>,
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+    pub signature: TypedSignature<MessageCommitment>,
 }
 
 impl RawMessage {
-    pub fn into_message(self) -> eyre::Result<Message> {
-        Message::new(self.data, self.signature).map_err(|e| eyre!(e))
+    /// Decodes and verifies the message for the DMS of the given key.
+    pub fn into_message<M: DmsMessage>(self, dms_key: &str) -> Result<Message<M>, Error> {
+        let data = M::from_bytes(&self.data)?;
+        let commitment = MessageCommitment {
+            dms_key: dms_key.to_owned(),
+            data: self.data,
+        };
+        self.signature.verify(&commitment)?;
+        Ok(Message {
+            data,
+            raw: RawMessage {
+                data: commitment.data,
+                signature: self.signature,
+            },
+        })
     }
 
-    pub fn from_message(message: Message) -> Self {
-        RawMessage {
-            data: message.data().to_owned(),
-            signature: message.signature().to_owned(),
-        }
+    pub fn from_message<M>(message: Message<M>) -> Self {
+        message.raw
+    }
+}
+
+impl ToHash256 for RawMessage {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// Serializes the encoded messages as hex strings.
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(deserializer)?).map_err(D::Error::custom)
     }
 }
 
@@ -71,55 +165,215 @@ pub struct State {
     pub dms_key: DmsKey,
 }
 
-/// The interface that will be wrapped into an HTTP RPC server for the peers.
-#[serde_tc_full]
-trait DistributedMessageSetRpcInterface: Send + Sync + 'static {
-    /// Returns the messages except `knowns`.
-    async fn get_message(
+/// A message on the gossip network, whose topic is shared by the keys of a DMS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GossipedMessage {
+    dms_key: DmsKey,
+    message: RawMessage,
+}
+
+/// The interface that will be served to the peers over an [`AuthenticatedConnection`].
+#[async_trait]
+pub(crate) trait DistributedMessageSetRpcInterface: Send + Sync + 'static {
+    /// Answers a round of the set reconciliation, with the messages that the requester is missing.
+    async fn reconcile(
         &self,
         dms_key: DmsKey,
-        knowns: Vec<Hash256>,
-    ) -> Result<Vec<RawMessage>, String>;
+        queries: Vec<RangeQuery>,
+    ) -> Result<Vec<RangeReply<RawMessage>>, String>;
 
     /// Requests this node to accept a new message.
     async fn add_messages(&self, dms_key: DmsKey, messages: Vec<RawMessage>) -> Result<(), String>;
 }
 
-struct DmsWrapper<N: GossipNetwork, S: Storage> {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum RpcRequest {
+    Reconcile {
+        dms_key: DmsKey,
+        queries: Vec<RangeQuery>,
+    },
+    AddMessages {
+        dms_key: DmsKey,
+        messages: Vec<RawMessage>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum RpcResponse {
+    Reconcile(Result<Vec<RangeReply<RawMessage>>, String>),
+    AddMessages(Result<(), String>),
+}
+
+/// The client of the RPC, which connects to a member peer on the first call
+/// and makes the following calls (e.g., the rounds of a reconciliation) over the same connection.
+struct DmsRpcClient {
+    network_config: NetworkConfig,
+    peer: Peer,
+    port: u16,
+    connection: tokio::sync::Mutex<Option<AuthenticatedConnection>>,
+}
+
+impl DmsRpcClient {
+    fn new(network_config: NetworkConfig, peer: Peer, port: u16) -> Self {
+        Self {
+            network_config,
+            peer,
+            port,
+            connection: Default::default(),
+        }
+    }
+
+    async fn call(&self, request: RpcRequest) -> Result<RpcResponse, String> {
+        let mut connection = self.connection.lock().await;
+        let result = async {
+            if connection.is_none() {
+                *connection = Some(
+                    AuthenticatedConnection::connect(
+                        &self.network_config,
+                        std::net::SocketAddrV4::new(*self.peer.address.ip(), self.port),
+                        Some(&self.peer.public_key),
+                    )
+                    .await?,
+                );
+            }
+            let connection = connection.as_mut().expect("connected above");
+            connection.send(&request).await?;
+            connection.recv().await
+        }
+        .await;
+        // The connection may be left in the middle of a call, so the next call reconnects.
+        if result.is_err() {
+            *connection = None;
+        }
+        result.map_err(|e: Error| e.to_string())
+    }
+}
+
+#[async_trait]
+impl DistributedMessageSetRpcInterface for DmsRpcClient {
+    async fn reconcile(
+        &self,
+        dms_key: DmsKey,
+        queries: Vec<RangeQuery>,
+    ) -> Result<Vec<RangeReply<RawMessage>>, String> {
+        match self
+            .call(RpcRequest::Reconcile { dms_key, queries })
+            .await?
+        {
+            RpcResponse::Reconcile(result) => result,
+            response => Err(format!("unexpected response: {response:?}")),
+        }
+    }
+
+    async fn add_messages(&self, dms_key: DmsKey, messages: Vec<RawMessage>) -> Result<(), String> {
+        match self
+            .call(RpcRequest::AddMessages { dms_key, messages })
+            .await?
+        {
+            RpcResponse::AddMessages(result) => result,
+            response => Err(format!("unexpected response: {response:?}")),
+        }
+    }
+}
+
+/// Serves the requests of an accepted connection until it is closed.
+pub(crate) async fn serve_rpc_connection(
+    network_config: &NetworkConfig,
+    stream: tokio::net::TcpStream,
+    object: &dyn DistributedMessageSetRpcInterface,
+) -> Result<(), Error> {
+    let mut connection = AuthenticatedConnection::accept(network_config, stream).await?;
+    loop {
+        let request = match connection.recv().await {
+            Ok(request) => request,
+            // The client closes the connection after the call.
+            Err(e)
+                if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                    == Some(std::io::ErrorKind::UnexpectedEof) =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        };
+        let response = match request {
+            RpcRequest::Reconcile { dms_key, queries } => {
+                RpcResponse::Reconcile(object.reconcile(dms_key, queries).await)
+            }
+            RpcRequest::AddMessages { dms_key, messages } => {
+                RpcResponse::AddMessages(object.add_messages(dms_key, messages).await)
+            }
+        };
+        connection.send(&response).await?;
+    }
+}
+
+struct DmsWrapper<N: GossipNetwork, S: Storage, M: DmsMessage> {
+    #[allow(clippy::type_complexity)]
+    dms: Arc<parking_lot::RwLock<Option<Arc<RwLock<DistributedMessageSet<N, S, M>>>>>>,
+    /// The address of the peer of the connection.
+    peer_address: IpAddr,
+    /// The messages sorted by their hashes, with the key that they are read for.
+    ///
+    /// It is read once for all the reconciliation rounds over the connection,
+    /// and read again only if the key changes or the peer adds messages.
     #[allow(clippy::type_complexity)]
-    dms: Arc<parking_lot::RwLock<Option<Arc<RwLock<DistributedMessageSet<N, S>>>>>>,
+    index: tokio::sync::Mutex<Option<(DmsKey, Vec<(Hash256, RawMessage)>)>>,
+}
+
+impl<N: GossipNetwork, S: Storage, M: DmsMessage> DmsWrapper<N, S, M> {
+    #[allow(clippy::type_complexity)]
+    fn new(
+        dms: Arc<parking_lot::RwLock<Option<Arc<RwLock<DistributedMessageSet<N, S, M>>>>>>,
+        peer_address: IpAddr,
+    ) -> Self {
+        Self {
+            dms,
+            peer_address,
+            index: Default::default(),
+        }
+    }
 }
 
 #[async_trait]
-impl<N: GossipNetwork, S: Storage> DistributedMessageSetRpcInterface for DmsWrapper<N, S> {
-    async fn get_message(
+impl<N: GossipNetwork, S: Storage, M: DmsMessage> DistributedMessageSetRpcInterface
+    for DmsWrapper<N, S, M>
+{
+    async fn reconcile(
         &self,
         dms_key: DmsKey,
-        knowns: Vec<Hash256>,
-    ) -> Result<Vec<RawMessage>, String> {
+        queries: Vec<RangeQuery>,
+    ) -> Result<Vec<RangeReply<RawMessage>>, String> {
         let dms = Arc::clone(
             self.dms
                 .read()
                 .as_ref()
                 .ok_or_else(|| "server terminated".to_owned())?,
         );
-        let mut messages = dms
-            .read()
-            .await
-            .read_messages()
-            .await
+        let dms = dms.read().await;
+        if dms_key != dms.key {
+            return Err(format!(
+                "key mismatch: requested {dms_key}, but {}",
+                dms.key
+            ));
+        }
+        dms.admission
+            .lock()
+            .check_peer(self.peer_address, 0)
             .map_err(|e| e.to_string())?;
-        let dms_key_ = dms.read().await.key.clone();
-        if dms_key != dms.read().await.key {
-            return Err(format!("key mismatch: requested {dms_key}, but {dms_key_}"));
+        let mut index = self.index.lock().await;
+        if !matches!(&*index, Some((key, _)) if key == &dms.key) {
+            let mut messages: Vec<_> = dms
+                .read_messages()
+                .await
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|m| (m.to_hash256(), RawMessage::from_message(m)))
+                .collect();
+            messages.sort_by_key(|(hash, _)| *hash);
+            *index = Some((dms.key.clone(), messages));
         }
-        let knowns: HashSet<_> = knowns.into_iter().collect();
-        let messages: Vec<_> = messages
-            .drain(..)
-            .filter(|m| !knowns.contains(&m.to_hash256()))
-            .map(RawMessage::from_message)
-            .collect();
-        Ok(messages)
+        let (_, messages) = index.as_ref().expect("read above");
+        Ok(reconciliation::respond(messages, queries))
     }
 
     async fn add_messages(&self, dms_key: DmsKey, messages: Vec<RawMessage>) -> Result<(), String> {
@@ -133,14 +387,35 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSetRpcInterface for DmsWrap
         if dms_key != dms.read().await.key {
             return Err(format!("key mismatch: requested {dms_key}, but {dms_key_}"));
         }
+        let dms = dms.read().await;
+        dms.admission
+            .lock()
+            .check_peer(self.peer_address, messages.len())
+            .map_err(|e| e.to_string())?;
+        *self.index.lock().await = None;
+        // The pushed messages are not filtered, to relay the ones that this node can't accept yet
+        // (e.g., for a block that this node hasn't verified) to the others who fetch from this node.
+        // A rejected message doesn't stop the others, but the peer is told of it.
+        let mut rejected = 0;
+        let count = messages.len();
         for message in messages {
-            let message = message.into_message().map_err(|e| e.to_string())?;
-            DistributedMessageSet::<N, S>::add_message_but_not_broadcast(
-                &mut (*dms.write().await.storage.write().await),
+            if let Err(e) = DistributedMessageSet::<N, S, M>::receive_message(
+                &dms.storage,
+                &dms.key,
+                &dms.config.network_config.members,
+                None,
+                &dms.admission,
+                Some(self.peer_address),
                 message,
             )
             .await
-            .map_err(|e| e.to_string())?;
+            {
+                log::warn!("rejected a message from {}: {}", self.peer_address, e);
+                rejected += 1;
+            }
+        }
+        if rejected > 0 {
+            return Err(format!("rejected {rejected} of {count} messages"));
         }
         Ok(())
     }
@@ -148,8 +423,8 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSetRpcInterface for DmsWrap
 
 struct DummyFilter;
 
-impl MessageFilter for DummyFilter {
-    fn filter(&self, _message: &Message) -> Result<(), String> {
+impl<M> MessageFilter<M> for DummyFilter {
+    fn filter(&self, _message: &Message<M>) -> Result<(), String> {
         Ok(())
     }
 }
@@ -166,16 +441,20 @@ impl MessageFilter for DummyFilter {
 /// - It locks the storage.
 /// - If the given directory is locked (possibly by another instance of `DistributedMessageSet`),
 /// it will `await` until the lock is released.
-pub struct DistributedMessageSet<N, S> {
+pub struct DistributedMessageSet<N, S, M> {
     storage: Arc<RwLock<S>>,
     config: Config,
-    filter: Arc<dyn MessageFilter>,
+    filter: Arc<dyn MessageFilter<M>>,
     peers: SharedKnownPeers,
+    /// The key that every message is signed for, which changes on `clear()`.
     key: DmsKey,
+    /// The messages that have been gossiped by this instance, which are not gossiped again.
+    gossiped: Arc<RwLock<HashSet<Hash256>>>,
+    admission: Arc<parking_lot::Mutex<Admission>>,
     _marker: std::marker::PhantomData<N>,
 }
 
-impl<N, S> std::fmt::Debug for DistributedMessageSet<N, S> {
+impl<N, S, M> std::fmt::Debug for DistributedMessageSet<N, S, M> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "?")
     }
@@ -183,15 +462,252 @@ impl<N, S> std::fmt::Debug for DistributedMessageSet<N, S> {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// The name of the DMS, which stays the same while the key changes on `clear()`.
+    ///
+    /// The RPC port is found by `dms-{name}` in the ports of the network config and the peers,
+    /// and the gossip port by [`gossip::port_key`] of the name.
+    pub name: String,
     pub network_config: NetworkConfig,
     /// The interval of the broadcasts.
     /// If none, it will broadcast only in `add_message()`, not in `serve()`.
     pub broadcast_interval: Option<Duration>,
     /// The interval of the direct-peer fetch. If none, it will fetch only in `fetch()`, not in `serve()`.
     pub fetch_interval: Option<Duration>,
+    /// The limits on the messages from the other peers.
+    #[serde(default)]
+    pub limits: Limits,
+    /// The public keys of the relays that this DMS trusts along with the members.
+    ///
+    /// They are trusted by the RPC only, to serve the messages; the gossip and the peer discovery
+    /// don't trust them, and the messages they serve are rejected unless signed by the members.
+    #[serde(default)]
+    pub relays: Vec<PublicKey>,
+}
+
+impl Config {
+    /// The network config for authenticating the RPC connections, whose members include the relays.
+    ///
+    /// The signers of the messages are checked against `network_config` instead.
+    fn rpc_network_config(&self) -> NetworkConfig {
+        let mut network_config = self.network_config.clone();
+        network_config.members.extend(self.relays.iter().cloned());
+        network_config
+    }
+}
+
+/// The limits on the messages that a DMS instance accepts from the other peers.
+///
+/// The messages added by this node itself are not limited, but counted for the quotas.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Limits {
+    /// The maximum number of messages signed by a single key.
+    pub max_messages_per_signer: usize,
+    /// The maximum total size (in bytes) of the messages signed by a single key.
+    pub max_bytes_per_signer: usize,
+    /// The maximum number of messages that a single peer address can push in a `rate_window`.
+    pub max_messages_per_peer: usize,
+    pub rate_window: Duration,
+    /// The number of the distinct messages from a single peer address rejected in a `rate_window`,
+    /// which gets the peer banned for `ban_duration`.
+    ///
+    /// Note that an honest peer may send messages that the filter rejects only temporarily
+    /// (e.g., ones for a block that this node hasn't verified yet), so it shouldn't be too small.
+    pub max_rejections_per_peer: usize,
+    pub ban_duration: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_messages_per_signer: 4096,
+            max_bytes_per_signer: 16 * 1024 * 1024,
+            max_messages_per_peer: 4096,
+            rate_window: Duration::from_secs(10),
+            max_rejections_per_peer: 256,
+            ban_duration: Duration::from_secs(600),
+        }
+    }
+}
+
+/// The counts of the messages that a DMS instance has received from the other peers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub accepted: u64,
+    /// The messages which are malformed or have an invalid signature.
+    pub rejected_invalid: u64,
+    /// The messages rejected by the `MessageFilter`.
+    pub rejected_by_filter: u64,
+    /// The messages whose signers have exceeded their quotas.
+    pub rejected_by_quota: u64,
+    /// The messages from the peers that have exceeded the rate limit.
+    pub rejected_by_rate_limit: u64,
+    /// The messages from the banned peers.
+    pub rejected_from_banned: u64,
+    /// The number of times that peers have been banned.
+    pub bans: u64,
+    /// The peers that are currently banned.
+    pub banned_peers: Vec<IpAddr>,
+}
+
+#[derive(Debug)]
+struct PeerUsage {
+    window_start: Instant,
+    messages: usize,
+    rejected: HashSet<Hash256>,
+}
+
+impl PeerUsage {
+    fn new(window_start: Instant) -> Self {
+        Self {
+            window_start,
+            messages: 0,
+            rejected: HashSet::new(),
+        }
+    }
+}
+
+/// Tracks the usage of the signers and the peers, enforcing the [`Limits`].
+#[derive(Debug)]
+pub(crate) struct Admission {
+    limits: Limits,
+    /// The number and the total size of the messages of each signer.
+    usage: HashMap<PublicKey, (usize, usize)>,
+    counted: HashSet<Hash256>,
+    peers: HashMap<IpAddr, PeerUsage>,
+    /// The last time that the usages of the expired windows were removed from `peers`.
+    pruned: Instant,
+    /// The banned peers with the time when the ban ends.
+    bans: HashMap<IpAddr, Instant>,
+    metrics: Metrics,
+}
+
+impl Admission {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            usage: Default::default(),
+            counted: Default::default(),
+            peers: Default::default(),
+            pruned: Instant::now(),
+            bans: Default::default(),
+            metrics: Default::default(),
+        }
+    }
+
+    /// Clears the usage of the signers, for a new set of messages.
+    pub(crate) fn clear_usage(&mut self) {
+        self.usage.clear();
+        self.counted.clear();
+    }
+
+    pub(crate) fn is_banned(&mut self, address: &IpAddr) -> bool {
+        let now = Instant::now();
+        self.bans.retain(|_, until| *until > now);
+        self.bans.contains_key(address)
+    }
+
+    fn peer_usage(&mut self, address: IpAddr) -> &mut PeerUsage {
+        let now = Instant::now();
+        let rate_window = self.limits.rate_window;
+        // An expired usage would be reset anyway, so it is removed not to pile up.
+        if now.duration_since(self.pruned) >= rate_window {
+            self.peers
+                .retain(|_, usage| now.duration_since(usage.window_start) < rate_window);
+            self.pruned = now;
+        }
+        let usage = self
+            .peers
+            .entry(address)
+            .or_insert_with(|| PeerUsage::new(now));
+        if now.duration_since(usage.window_start) >= rate_window {
+            *usage = PeerUsage::new(now);
+        }
+        usage
+    }
+
+    /// Checks whether the peer is allowed to push the given number of messages.
+    pub(crate) fn check_peer(&mut self, address: IpAddr, messages: usize) -> Result<(), Error> {
+        if self.is_banned(&address) {
+            self.metrics.rejected_from_banned += messages as u64;
+            return Err(eyre!("banned peer: {}", address));
+        }
+        let max_messages = self.limits.max_messages_per_peer;
+        let usage = self.peer_usage(address);
+        if usage.messages + messages > max_messages {
+            self.metrics.rejected_by_rate_limit += messages as u64;
+            return Err(eyre!("rate limit exceeded: {}", address));
+        }
+        usage.messages += messages;
+        Ok(())
+    }
+
+    /// Records a message rejected for being invalid or by the filter,
+    /// banning the peer that sent it if it has sent too many.
+    pub(crate) fn reject(&mut self, address: Option<IpAddr>, hash: Hash256, by_filter: bool) {
+        if by_filter {
+            self.metrics.rejected_by_filter += 1;
+        } else {
+            self.metrics.rejected_invalid += 1;
+        }
+        let address = if let Some(address) = address {
+            address
+        } else {
+            return;
+        };
+        let max_rejections = self.limits.max_rejections_per_peer;
+        let usage = self.peer_usage(address);
+        usage.rejected.insert(hash);
+        if usage.rejected.len() >= max_rejections {
+            log::warn!("banned a peer for the rejected messages: {}", address);
+            self.peers.remove(&address);
+            self.bans
+                .insert(address, Instant::now() + self.limits.ban_duration);
+            self.metrics.bans += 1;
+        }
+    }
+
+    /// Counts a message for the quota of its signer, failing if it exceeds the quota.
+    pub(crate) fn admit(
+        &mut self,
+        hash: Hash256,
+        signer: &PublicKey,
+        size: usize,
+    ) -> Result<(), Error> {
+        if self.counted.contains(&hash) {
+            return Ok(());
+        }
+        let (messages, bytes) = self.usage.get(signer).cloned().unwrap_or_default();
+        if messages + 1 > self.limits.max_messages_per_signer
+            || bytes + size > self.limits.max_bytes_per_signer
+        {
+            self.metrics.rejected_by_quota += 1;
+            return Err(eyre!("quota exceeded: {}", signer));
+        }
+        self.count(hash, signer, size);
+        self.metrics.accepted += 1;
+        Ok(())
+    }
+
+    /// Counts a message for the quota of its signer, without the limit.
+    pub(crate) fn count(&mut self, hash: Hash256, signer: &PublicKey, size: usize) {
+        if self.counted.insert(hash) {
+            let usage = self.usage.entry(signer.clone()).or_default();
+            usage.0 += 1;
+            usage.1 += size;
+        }
+    }
+
+    pub(crate) fn metrics(&mut self) -> Metrics {
+        let now = Instant::now();
+        self.bans.retain(|_, until| *until > now);
+        Metrics {
+            banned_peers: self.bans.keys().cloned().collect(),
+            ..self.metrics.clone()
+        }
+    }
 }
 
-impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
+impl<N: GossipNetwork, S: Storage, M: DmsMessage> DistributedMessageSet<N, S, M> {
     /// Creates a DMS instnace.
     ///
     /// If the storage is empty, it creates a new one.
@@ -201,6 +717,8 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
     ///
     /// - `dms_key`: The unique key for distinguishing the DMS instance.
     /// Note that it will be further extended with the height.
+    ///
+    /// Every message is signed for `dms_key`, so that it can't be replayed into another DMS.
     pub async fn new(
         mut storage: S,
         dms_key: String,
@@ -217,32 +735,83 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
                 Self::write_state(&mut storage, State { dms_key }).await?;
             }
         };
-        Ok(Self {
+        let admission = Admission::new(config.limits.clone());
+        let this = Self {
             storage: Arc::new(RwLock::new(storage)),
             config,
             filter: Arc::new(DummyFilter),
             peers,
             key: dms_key_,
+            gossiped: Default::default(),
+            admission: Arc::new(parking_lot::Mutex::new(admission)),
             _marker: std::marker::PhantomData,
-        })
+        };
+        for message in this.read_messages().await? {
+            let (hash, signer) = (message.to_hash256(), message.signature().signer().clone());
+            let size = serde_spb::to_string(&RawMessage::from_message(message))?.len();
+            this.admission.lock().count(hash, &signer, size);
+        }
+        Ok(this)
     }
 
+    /// Clears all the messages, to be a new and empty DMS of the given key.
+    ///
+    /// The messages signed for the previous key are no longer accepted.
     pub async fn clear(&mut self, dms_key: DmsKey) -> Result<(), Error> {
         self.storage.write().await.remove_all_files().await?;
-        Self::write_state(&mut (*self.storage.write().await), State { dms_key }).await?;
+        Self::write_state(
+            &mut (*self.storage.write().await),
+            State {
+                dms_key: dms_key.clone(),
+            },
+        )
+        .await?;
+        self.key = dms_key;
+        self.gossiped.write().await.clear();
+        self.admission.lock().clear_usage();
         Ok(())
     }
 
+    /// Returns the counts of the messages received from the other peers, with the banned peers.
+    pub fn metrics(&self) -> Metrics {
+        self.admission.lock().metrics()
+    }
+
     pub fn get_key(&self) -> String {
         self.key.clone()
     }
 
-    pub fn set_filter(&mut self, filter: Arc<dyn MessageFilter>) {
+    fn rpc_port_key(&self) -> String {
+        format!("dms-{}", self.config.name)
+    }
+
+    pub fn set_filter(&mut self, filter: Arc<dyn MessageFilter<M>>) {
         self.filter = filter;
     }
 
+    /// Runs the set reconciliation with a peer, returning the messages that this node is missing
+    /// and the hashes of the ones that the peer is missing.
+    async fn reconcile(
+        stub: &DmsRpcClient,
+        key: &DmsKey,
+        known_messages: Vec<Hash256>,
+    ) -> Result<(Vec<RawMessage>, Vec<Hash256>), Error> {
+        let mut reconciler = Reconciler::new(known_messages);
+        while !reconciler.is_finished() {
+            let replies = stub
+                .reconcile(key.clone(), reconciler.queries())
+                .await
+                .map_err(|e| eyre!(e))?;
+            reconciler.handle_replies(replies)?;
+        }
+        Ok(reconciler.finish())
+    }
+
     /// Fetches unknown messages from the peers using an RPC protocol,
     /// and adds them to the local storage.
+    ///
+    /// Only the messages missing in this node are transferred, by the set reconciliation.
+    /// A peer that serves no port for this DMS is reached at its [`relay::PORT_KEY`], if any.
     pub async fn fetch(&mut self) -> Result<(), Error> {
         let mut tasks = Vec::new();
         let messages = self.read_messages().await?;
@@ -254,30 +823,38 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
         for peer in self.peers.read().await {
             let storage = Arc::clone(&self.storage);
             let filter = Arc::clone(&self.filter);
-            let port_key = format!("dms-{}", self.key);
+            let admission = Arc::clone(&self.admission);
+            let port_key = self.rpc_port_key();
             let known_messages_ = known_messages.clone();
             let key = self.key.clone();
+            let network_config = self.config.rpc_network_config();
+            let members = self.config.network_config.members.clone();
             let task = async move {
-                let stub = DistributedMessageSetRpcInterfaceStub::new(Box::new(HttpClient::new(
-                    format!(
-                        "{}:{}/dms",
-                        peer.address.ip(),
-                        peer.ports
-                            .get(&port_key)
-                            .ok_or_else(|| eyre!("can't find port key: {}", port_key))?
-                    ),
-                    reqwest::Client::new(),
-                )));
-                let raw_messages = stub
-                    .get_message(key, known_messages_)
-                    .await
-                    .map_err(|e| eyre!("{}", e))?
-                    .map_err(|e| eyre!(e))?;
-                let mut storage = storage.write().await;
+                let address = IpAddr::V4(*peer.address.ip());
+                if admission.lock().is_banned(&address) {
+                    return Err(eyre!("banned peer: {}", address));
+                }
+                let port = *peer
+                    .ports
+                    .get(&port_key)
+                    .or_else(|| peer.ports.get(relay::PORT_KEY))
+                    .ok_or_else(|| eyre!("can't find port key: {}", port_key))?;
+                let stub = DmsRpcClient::new(network_config, peer, port);
+                let (raw_messages, _) = Self::reconcile(&stub, &key, known_messages_).await?;
                 for raw_message in raw_messages {
-                    let message = raw_message.into_message()?;
-                    filter.filter(&message).map_err(|e| eyre!("{}", e))?;
-                    Self::add_message_but_not_broadcast(&mut *storage, message).await?;
+                    if let Err(e) = Self::receive_message(
+                        &storage,
+                        &key,
+                        &members,
+                        Some(&*filter),
+                        &admission,
+                        Some(address),
+                        raw_message,
+                    )
+                    .await
+                    {
+                        log::warn!("rejected a message from {}: {}", address, e);
+                    }
                 }
                 Result::<(), Error>::Ok(())
             };
@@ -296,54 +873,74 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
     ///
     /// Note that it is guaranteed that the message will not be broadcasted unless it
     /// is successfully added to the storage. (but it is not guaranteed for the other way around)
-    pub async fn add_message(&mut self, message: Message) -> Result<(), Error> {
-        Self::add_message_but_not_broadcast(&mut *(self.storage.write().await), message.clone())
-            .await?;
+    pub async fn add_message(&mut self, message: Message<M>) -> Result<(), Error> {
+        let message = RawMessage::from_message(message).into_message(&self.key)?;
+        let (hash, signer) = (message.to_hash256(), message.signature().signer().clone());
+        let size = serde_spb::to_string(&RawMessage::from_message(message.clone()))?.len();
+        Self::add_message_but_not_broadcast(&mut *(self.storage.write().await), message).await?;
+        self.admission.lock().count(hash, &signer, size);
         Ok(())
     }
 
     /// Tries to broadcast all the message that this DMS instance has.
+    ///
+    /// Each peer receives only the messages it is missing, found by the set reconciliation.
+    /// A message is gossiped only once by this instance.
     pub async fn broadcast_all(&self) -> Result<(), Error> {
-        let mut tasks1 = Vec::new();
         let messages = self
             .read_messages()
             .await?
             .into_iter()
-            .map(RawMessage::from_message)
-            .collect::<Vec<_>>();
+            .map(|m| (m.to_hash256(), RawMessage::from_message(m)))
+            .collect::<HashMap<_, _>>();
+        let mut tasks1 = Vec::new();
         for peer in self.peers.read().await {
-            let port_key = format!("dms-{}", self.key);
-            let messages_ = messages.clone();
+            let port_key = self.rpc_port_key();
+            let messages = &messages;
+            let public_key = peer.public_key.clone();
             let task = async move {
-                let stub = DistributedMessageSetRpcInterfaceStub::new(Box::new(HttpClient::new(
-                    format!(
-                        "{}:{}/dms",
-                        peer.address.ip(),
-                        peer.ports
-                            .get(&port_key)
-                            .ok_or_else(|| eyre!("can't find port key: {}", port_key))?
-                    ),
-                    reqwest::Client::new(),
-                )));
-                stub.add_messages(self.key.clone(), messages_.clone())
-                    .await
-                    .map_err(|e| eyre!(e))?
-                    .map_err(|e| eyre!(e))?;
+                let port = *peer
+                    .ports
+                    .get(&port_key)
+                    .or_else(|| peer.ports.get(relay::PORT_KEY))
+                    .ok_or_else(|| eyre!("can't find port key: {}", port_key))?;
+                let stub = DmsRpcClient::new(self.config.rpc_network_config(), peer, port);
+                let (_, missing) =
+                    Self::reconcile(&stub, &self.key, messages.keys().cloned().collect()).await?;
+                if !missing.is_empty() {
+                    let messages = missing.iter().map(|hash| messages[hash].clone()).collect();
+                    stub.add_messages(self.key.clone(), messages)
+                        .await
+                        .map_err(|e| eyre!(e))?;
+                }
                 Result::<(), Error>::Ok(())
             };
-            tasks1.push((task, format!("RPC message add to {}", peer.public_key)));
+            tasks1.push((task, format!("RPC message add to {public_key}")));
         }
         let peers_ = self.peers.read().await;
+        let mut gossiped = self.gossiped.write().await;
+        let messages = messages
+            .iter()
+            .filter(|(hash, _)| gossiped.insert(**hash))
+            .map(|(_, message)| message.clone())
+            .collect::<Vec<_>>();
+        drop(gossiped);
         let tasks2 = messages.into_iter().map(|message| {
             let network_config = self.config.network_config.clone();
             let peers = peers_.clone();
-            let message_hash = message.data.to_hash256();
+            let topic = self.config.name.clone();
+            let message_hash = message.to_hash256();
+            let message = GossipedMessage {
+                dms_key: self.key.clone(),
+                message,
+            };
             (
                 async move {
                     N::broadcast(
                         &network_config,
                         &peers,
-                        serde_spb::to_vec(&message).unwrap(),
+                        &topic,
+                        serde_spb::to_string(&message).unwrap().into_bytes(),
                     )
                     .await?;
                     Result::<(), Error>::Ok(())
@@ -371,35 +968,80 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
     }
 
     /// Reads the messages from the storage.
-    pub async fn read_messages(&self) -> Result<Vec<Message>, Error> {
-        let files = self.storage.read().await.list_files().await?;
-        let tasks = files
-            .into_iter()
-            .filter(|x| x != STATE_FILE_PATH)
-            .map(|f| async move { self.storage.read().await.read_file(&f).await });
-        let data = future::join_all(tasks)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()?;
-        let messages = data
-            .into_iter()
-            .map(|d| serde_spb::from_str::<RawMessage>(&d))
-            .collect::<Result<Vec<RawMessage>, _>>()?;
-        let messages = messages
-            .into_iter()
-            .map(|d| d.into_message())
-            .collect::<Result<Vec<_>, _>>()?;
+    ///
+    /// A corrupted message file is quarantined in the storage, instead of failing the whole read.
+    pub async fn read_messages(&self) -> Result<Vec<Message<M>>, Error> {
+        let files = self.storage.read().await.read_all_files().await?;
+        let mut messages = Vec::new();
+        for (file, data) in files.into_iter().filter(|(x, _)| x != STATE_FILE_PATH) {
+            let message = match data {
+                Ok(data) => serde_spb::from_str::<RawMessage>(&data)
+                    .map_err(Error::from)
+                    .and_then(|message| message.into_message(&self.key)),
+                // Not a valid UTF-8.
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Err(e.into()),
+                Err(e) => return Err(e.into()),
+            };
+            match message {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    log::warn!("quarantining a corrupted message file {}: {}", file, e);
+                    self.storage.write().await.quarantine_file(&file).await?;
+                }
+            }
+        }
         Ok(messages)
     }
 
+    /// Verifies and adds a message from the other peers, enforcing the limits.
+    ///
+    /// The message must be signed by one of `members`, which don't include the relays.
+    /// `source` is the address of the peer that sent the message, if known.
+    async fn receive_message(
+        storage: &RwLock<S>,
+        dms_key: &str,
+        members: &[PublicKey],
+        filter: Option<&dyn MessageFilter<M>>,
+        admission: &parking_lot::Mutex<Admission>,
+        source: Option<IpAddr>,
+        raw_message: RawMessage,
+    ) -> Result<(), Error> {
+        let serialized = serde_spb::to_string(&raw_message)?;
+        let raw_hash = Hash256::hash(&serialized);
+        let message = match raw_message.into_message(dms_key) {
+            Ok(message) => message,
+            Err(e) => {
+                admission.lock().reject(source, raw_hash, false);
+                return Err(e);
+            }
+        };
+        // Otherwise anyone could sign with new keys, each of which gets a fresh quota.
+        if !members.contains(message.signature().signer()) {
+            admission.lock().reject(source, raw_hash, false);
+            return Err(eyre!(
+                "the signer is not a member: {}",
+                message.signature().signer()
+            ));
+        }
+        if let Some(Err(e)) = filter.map(|filter| filter.filter(&message)) {
+            admission.lock().reject(source, raw_hash, true);
+            return Err(eyre!("{}", e));
+        }
+        let size = serialized.len();
+        admission
+            .lock()
+            .admit(message.to_hash256(), message.signature().signer(), size)?;
+        Self::add_message_but_not_broadcast(&mut *storage.write().await, message).await
+    }
+
     async fn add_message_but_not_broadcast(
         storage: &mut impl Storage,
-        message: Message,
+        message: Message<M>,
     ) -> Result<(), Error> {
         storage
             .add_or_overwrite_file(
                 &format!("{}.json", message.to_hash256()),
-                serde_spb::to_string(&message).unwrap(),
+                serde_spb::to_string(&RawMessage::from_message(message)).unwrap(),
             )
             .await?;
         Ok(())
@@ -413,6 +1055,7 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
     }
 
     async fn serve_rpc(this: Arc<RwLock<Self>>, rpc_port: u16) -> Result<(), Error> {
+        let network_config = Arc::new(this.read().await.config.rpc_network_config());
         let wrapped_this = Arc::new(parking_lot::RwLock::new(Some(this)));
         let wrapped_this_ = Arc::clone(&wrapped_this);
 
@@ -425,19 +1068,17 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
             }
         }
         let _drop_helper = DropHelper { wrapped_this };
-        run_server(
-            rpc_port,
-            [(
-                "dms".to_owned(),
-                create_http_object(Arc::new(DmsWrapper { dms: wrapped_this_ })
-                    as Arc<dyn DistributedMessageSetRpcInterface>),
-            )]
-            .iter()
-            .cloned()
-            .collect(),
-        )
-        .await;
-        Ok(())
+        let listener = TcpListener::bind(("0.0.0.0", rpc_port)).await?;
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let network_config = Arc::clone(&network_config);
+            let object = DmsWrapper::new(Arc::clone(&wrapped_this_), address.ip());
+            tokio::spawn(async move {
+                if let Err(e) = serve_rpc_connection(&network_config, stream, &object).await {
+                    log::warn!("failed to serve an RPC connection: {}", e);
+                }
+            });
+        }
     }
 
     async fn serve_fetch(this: Arc<RwLock<Self>>) -> Result<(), Error> {
@@ -471,20 +1112,28 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
     async fn serve_gossip(this: Arc<RwLock<Self>>) -> Result<(), Error> {
         let mut recv = N::serve(
             this.read().await.config.network_config.clone(),
+            this.read().await.config.name.clone(),
             this.read().await.peers.clone(),
         )
         .await?;
-        while let Some(m) = recv.0.recv().await {
+        while let Some((source, m)) = recv.0.recv().await {
             let result = async {
-                let message: RawMessage = serde_spb::from_slice(&m)?;
-                let message = message.into_message()?;
-                this.read()
-                    .await
-                    .filter
-                    .filter(&message)
-                    .map_err(|e| eyre!("{}", e))?;
-                Self::add_message_but_not_broadcast(
-                    &mut *this.read().await.storage.write().await,
+                let GossipedMessage { dms_key, message } =
+                    serde_spb::from_str(std::str::from_utf8(&m)?)?;
+                let this = this.read().await;
+                // The topic is shared by every key, so the messages of the other heights are ignored.
+                if dms_key != this.key {
+                    return Ok(());
+                }
+                // The peer that forwarded the message is limited as the one that pushes it by the RPC.
+                this.admission.lock().check_peer(source, 1)?;
+                Self::receive_message(
+                    &this.storage,
+                    &this.key,
+                    &this.config.network_config.members,
+                    Some(&*this.filter),
+                    &this.admission,
+                    Some(source),
                     message,
                 )
                 .await?;
@@ -502,7 +1151,7 @@ impl<N: GossipNetwork, S: Storage> DistributedMessageSet<N, S> {
     ///
     /// TODO: currently it just returns itself after the given time.
     pub async fn serve(self, time_in_ms: u64) -> Result<Self, Error> {
-        let port_key = format!("dms-{}", self.key);
+        let port_key = self.rpc_port_key();
         let port = *self
             .config
             .network_config
@@ -551,7 +1200,9 @@ mod tests {
     use futures::future::join_all;
 
     // TODO: Add other DMS types that use a working gossip network.
-    type Dms = DistributedMessageSet<crate::primitives::DummyGossipNetwork, StorageImpl>;
+    type Dms = DistributedMessageSet<crate::primitives::DummyGossipNetwork, StorageImpl, String>;
+
+    impl DmsMessage for String {}
 
     async fn sleep(ms: u64) {
         tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
@@ -620,9 +1271,12 @@ mod tests {
             storage,
             network_config.network_id.clone(),
             dms::Config {
+                name: network_config.network_id.clone(),
                 fetch_interval: Some(std::time::Duration::from_millis(500)),
                 broadcast_interval: Some(std::time::Duration::from_millis(500)),
                 network_config,
+                limits: Default::default(),
+                relays: Vec::new(),
             },
             peers,
         )
@@ -647,10 +1301,9 @@ mod tests {
 
         for i in 0..10 {
             let msg = format!("{i}");
-            dms.add_message(Message {
-                data: msg.clone(),
-                signature: TypedSignature::sign(&msg, &network_config.private_key).unwrap(),
-            })
+            dms.add_message(
+                Message::sign(&dms.get_key(), msg, &network_config.private_key).unwrap(),
+            )
             .await
             .unwrap();
         }
@@ -663,7 +1316,7 @@ mod tests {
                 .collect::<std::collections::BTreeSet<_>>(),
             messages
                 .into_iter()
-                .map(|x| x.data)
+                .map(|x| x.data().clone())
                 .collect::<std::collections::BTreeSet<_>>()
         );
     }
@@ -678,10 +1331,9 @@ mod tests {
         // Add the assigned messages to the DMS
         for i in &my_numbers {
             let msg = format!("{i}");
-            dms.add_message(Message {
-                data: msg.clone(),
-                signature: TypedSignature::sign(&msg, &network_config.private_key).unwrap(),
-            })
+            dms.add_message(
+                Message::sign(&dms.get_key(), msg, &network_config.private_key).unwrap(),
+            )
             .await
             .unwrap();
         }
@@ -712,7 +1364,7 @@ mod tests {
             .await
             .unwrap()
             .into_iter()
-            .map(|x| x.data.parse::<usize>().unwrap())
+            .map(|x| x.data().parse::<usize>().unwrap())
             .collect::<Vec<_>>();
         messages.sort();
         let mut expected = other_numbers;
@@ -796,4 +1448,407 @@ mod tests {
         join_all(tasks).await;
         handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn quarantine_corrupted_messages() {
+        setup_test();
+        let network_config = generate_node_configs(dispense_port(), 1).0;
+        let mut dms = setup(
+            network_config.clone(),
+            SharedKnownPeers::new(Default::default()),
+        )
+        .await;
+        for i in 0..3 {
+            let msg = format!("{i}");
+            dms.add_message(
+                Message::sign(&dms.get_key(), msg, &network_config.private_key).unwrap(),
+            )
+            .await
+            .unwrap();
+        }
+        // Simulates a message file truncated by a crash in the previous version.
+        let file = dms
+            .storage
+            .read()
+            .await
+            .list_files()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|file| file != STATE_FILE_PATH)
+            .unwrap();
+        let content = dms.storage.read().await.read_file(&file).await.unwrap();
+        dms.storage
+            .write()
+            .await
+            .add_or_overwrite_file(&file, content[..content.len() / 2].to_owned())
+            .await
+            .unwrap();
+
+        assert_eq!(dms.read_messages().await.unwrap().len(), 2);
+        assert!(!dms
+            .storage
+            .read()
+            .await
+            .list_files()
+            .await
+            .unwrap()
+            .contains(&file));
+    }
+
+    #[tokio::test]
+    async fn limits_and_bans() {
+        setup_test();
+        let network_config = generate_node_configs(dispense_port(), 1).0;
+        let private_key = network_config.private_key.clone();
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let storage = StorageImpl::open(&path).await.unwrap();
+        let key = "limits".to_owned();
+        let dms = Dms::new(
+            storage,
+            key.clone(),
+            dms::Config {
+                name: key.clone(),
+                fetch_interval: None,
+                broadcast_interval: None,
+                network_config,
+                limits: Limits {
+                    max_messages_per_signer: 2,
+                    max_messages_per_peer: 4,
+                    max_rejections_per_peer: 2,
+                    ..Default::default()
+                },
+                relays: Vec::new(),
+            },
+            SharedKnownPeers::new(Default::default()),
+        )
+        .await
+        .unwrap();
+        let dms = Arc::new(RwLock::new(dms));
+        let wrapper = |address: &str| {
+            DmsWrapper::new(
+                Arc::new(parking_lot::RwLock::new(Some(Arc::clone(&dms)))),
+                address.parse().unwrap(),
+            )
+        };
+        let message = |i: usize| {
+            RawMessage::from_message(Message::sign(&key, format!("{i}"), &private_key).unwrap())
+        };
+
+        // The signer exceeds the quota, and then the peer exceeds the rate limit.
+        let peer = wrapper("127.0.0.1");
+        peer.add_messages(key.clone(), vec![message(0), message(1)])
+            .await
+            .unwrap();
+        peer.add_messages(key.clone(), vec![message(2)])
+            .await
+            .unwrap_err();
+        peer.add_messages(key.clone(), vec![message(0), message(1)])
+            .await
+            .unwrap_err();
+        assert_eq!(dms.read().await.read_messages().await.unwrap().len(), 2);
+
+        // Another peer gets banned for the invalid messages.
+        let peer = wrapper("127.0.0.2");
+        for i in 0..3 {
+            let mut invalid_message = message(i);
+            invalid_message.data = format!("invalid {i}").to_bytes();
+            peer.add_messages(key.clone(), vec![invalid_message])
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(
+            dms.read().await.metrics(),
+            Metrics {
+                accepted: 2,
+                rejected_invalid: 2,
+                rejected_by_filter: 0,
+                rejected_by_quota: 1,
+                rejected_by_rate_limit: 2,
+                rejected_from_banned: 1,
+                bans: 1,
+                banned_peers: vec!["127.0.0.2".parse().unwrap()],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_non_member_signers() {
+        setup_test();
+        let network_config = generate_node_configs(dispense_port(), 1).0;
+        let private_key = network_config.private_key.clone();
+        let dms = setup(network_config, SharedKnownPeers::new(Default::default())).await;
+        let key = dms.get_key();
+        let dms = Arc::new(RwLock::new(dms));
+        let peer = DmsWrapper::new(
+            Arc::new(parking_lot::RwLock::new(Some(Arc::clone(&dms)))),
+            "127.0.0.1".parse().unwrap(),
+        );
+        let message = |data: &str, private_key: &PrivateKey| {
+            RawMessage::from_message(Message::sign(&key, data.to_owned(), private_key).unwrap())
+        };
+
+        // Validly signed, but by a key that the pushing member made up.
+        // The rejected message doesn't stop the rest of the batch.
+        let (_, non_member) = generate_keypair_random();
+        peer.add_messages(
+            key.clone(),
+            vec![
+                message("hello", &non_member),
+                message("hello", &private_key),
+            ],
+        )
+        .await
+        .unwrap_err();
+        let messages = dms.read().await.read_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].signature().signer(),
+            &dms.read().await.config.network_config.public_key
+        );
+        assert_eq!(dms.read().await.metrics().rejected_invalid, 1);
+    }
+
+    /// A relay is trusted to serve the messages, but not to sign them.
+    #[tokio::test]
+    async fn reject_messages_signed_by_relays() {
+        setup_test();
+        let (relay_network_config, network_configs, relay_peer) =
+            generate_node_configs(dispense_port(), 2);
+        let relay_key = relay_network_config.public_key.clone();
+        let mut dms = setup(
+            network_configs[0].clone(),
+            SharedKnownPeers::new_static(vec![relay_peer]),
+        )
+        .await;
+        dms.config
+            .network_config
+            .members
+            .retain(|x| x != &relay_key);
+        dms.config.relays = vec![relay_key];
+        let key = dms.get_key();
+
+        // A malicious relay serves a message signed by itself and one by a non-member.
+        let mut relay = setup(
+            relay_network_config.clone(),
+            SharedKnownPeers::new(Default::default()),
+        )
+        .await;
+        let (_, non_member) = generate_keypair_random();
+        for private_key in [&relay_network_config.private_key, &non_member] {
+            relay
+                .add_message(Message::sign(&key, "hello".to_owned(), private_key).unwrap())
+                .await
+                .unwrap();
+        }
+        let relay_task = tokio::spawn(relay.serve(3000));
+        sleep_ms(500).await;
+
+        dms.fetch().await.unwrap();
+        assert!(dms.read_messages().await.unwrap().is_empty());
+        assert!(dms.metrics().rejected_invalid > 0);
+        relay_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_malformed_messages() {
+        setup_test();
+        let network_config = generate_node_configs(dispense_port(), 1).0;
+        let private_key = network_config.private_key.clone();
+        let dms = setup(network_config, SharedKnownPeers::new(Default::default())).await;
+        let key = dms.get_key();
+        let dms = Arc::new(RwLock::new(dms));
+        let peer = DmsWrapper::new(
+            Arc::new(parking_lot::RwLock::new(Some(Arc::clone(&dms)))),
+            "127.0.0.1".parse().unwrap(),
+        );
+        let sign = |dms_key: &str, data: Vec<u8>| RawMessage {
+            signature: TypedSignature::sign(
+                &MessageCommitment {
+                    dms_key: dms_key.to_owned(),
+                    data: data.clone(),
+                },
+                &private_key,
+            )
+            .unwrap(),
+            data,
+        };
+
+        // Not a `String`, with a valid signature.
+        let mut malformed = "hello".to_owned().to_bytes();
+        malformed.push(0);
+        peer.add_messages(key.clone(), vec![sign(&key, malformed)])
+            .await
+            .unwrap_err();
+        // Signed for another DMS.
+        let foreign = sign("another", "hello".to_owned().to_bytes());
+        peer.add_messages(key.clone(), vec![foreign.clone()])
+            .await
+            .unwrap_err();
+        assert!(foreign.into_message::<String>("another").is_ok());
+
+        peer.add_messages(key.clone(), vec![sign(&key, "hello".to_owned().to_bytes())])
+            .await
+            .unwrap();
+        let messages = dms.read().await.read_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].data(), "hello");
+        assert_eq!(dms.read().await.metrics().rejected_invalid, 2);
+    }
+
+    #[tokio::test]
+    async fn replay_across_keys() {
+        setup_test();
+        let network_config = generate_node_configs(dispense_port(), 1).0;
+        let private_key = network_config.private_key.clone();
+        let mut dms = setup(network_config, SharedKnownPeers::new(Default::default())).await;
+        let message = Message::sign(&dms.get_key(), "hello".to_owned(), &private_key).unwrap();
+        dms.add_message(message.clone()).await.unwrap();
+
+        // Moves on to the next height.
+        let key = format!("{}-next", dms.get_key());
+        dms.clear(key.clone()).await.unwrap();
+        assert!(dms.add_message(message.clone()).await.is_err());
+        let dms = Arc::new(RwLock::new(dms));
+        let peer = DmsWrapper::new(
+            Arc::new(parking_lot::RwLock::new(Some(Arc::clone(&dms)))),
+            "127.0.0.1".parse().unwrap(),
+        );
+        peer.add_messages(key.clone(), vec![RawMessage::from_message(message)])
+            .await
+            .unwrap_err();
+        assert!(dms.read().await.read_messages().await.unwrap().is_empty());
+
+        let message = Message::sign(&key, "hello".to_owned(), &private_key).unwrap();
+        dms.write().await.add_message(message).await.unwrap();
+        assert_eq!(dms.read().await.read_messages().await.unwrap().len(), 1);
+    }
+
+    /// All the rounds of a reconciliation are made over one connection.
+    #[tokio::test]
+    async fn reconcile_over_one_connection() {
+        setup_test();
+        let port = dispense_port();
+        let (server_config, client_configs, server_peer) = generate_node_configs(port, 2);
+        let private_key = server_config.private_key.clone();
+        let mut dms = setup(
+            server_config.clone(),
+            SharedKnownPeers::new(Default::default()),
+        )
+        .await;
+        let key = dms.get_key();
+        let mut hashes = Vec::new();
+        for i in 0..100 {
+            let message = Message::sign(&key, format!("{i}"), &private_key).unwrap();
+            hashes.push(message.to_hash256());
+            dms.add_message(message).await.unwrap();
+        }
+        let dms = Arc::new(parking_lot::RwLock::new(Some(Arc::new(RwLock::new(dms)))));
+
+        let connections = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        let connections_ = Arc::clone(&connections);
+        let _server = tokio::spawn(async move {
+            loop {
+                let (stream, address) = listener.accept().await.unwrap();
+                connections_.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let object = DmsWrapper::new(Arc::clone(&dms), address.ip());
+                let server_config = server_config.clone();
+                tokio::spawn(async move {
+                    serve_rpc_connection(&server_config, stream, &object)
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+
+        let stub = DmsRpcClient::new(client_configs[0].clone(), server_peer, port);
+        let (messages, missing) = Dms::reconcile(&stub, &key, hashes[..50].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 50);
+        assert!(missing.is_empty());
+        stub.add_messages(key.clone(), Vec::new()).await.unwrap();
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    /// The usages of the peers whose windows have expired are removed.
+    #[test]
+    fn prune_peer_usage() {
+        let mut admission = Admission::new(Limits {
+            rate_window: Duration::from_millis(10),
+            ..Default::default()
+        });
+        for i in 0..100u8 {
+            admission
+                .check_peer(IpAddr::from([10, 0, 0, i]), 1)
+                .unwrap();
+        }
+        assert_eq!(admission.peers.len(), 100);
+        std::thread::sleep(Duration::from_millis(20));
+        admission
+            .check_peer("10.0.1.0".parse().unwrap(), 1)
+            .unwrap();
+        assert_eq!(admission.peers.len(), 1);
+    }
+
+    /// The peer that forwards messages by the gossip is rate-limited.
+    #[tokio::test]
+    async fn limit_gossip() {
+        setup_test();
+        let (mut server_config, client_configs, mut server_peer) =
+            generate_node_configs(dispense_port(), 2);
+        let name = server_config.network_id.clone();
+        let gossip_port = dispense_port();
+        server_config
+            .ports
+            .insert(gossip::port_key(&name), gossip_port);
+        server_peer
+            .ports
+            .insert(gossip::port_key(&name), gossip_port);
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let storage = StorageImpl::open(&path).await.unwrap();
+        let dms = DistributedMessageSet::<gossip::TcpGossipNetwork, StorageImpl, String>::new(
+            storage,
+            name.clone(),
+            dms::Config {
+                name: name.clone(),
+                fetch_interval: None,
+                broadcast_interval: None,
+                network_config: server_config,
+                limits: Limits {
+                    max_messages_per_peer: 1,
+                    ..Default::default()
+                },
+                relays: Vec::new(),
+            },
+            SharedKnownPeers::new(Default::default()),
+        )
+        .await
+        .unwrap();
+        let task = tokio::spawn(dms.serve(2000));
+        sleep(200).await;
+
+        for i in 0..2 {
+            let message = GossipedMessage {
+                dms_key: name.clone(),
+                message: RawMessage::from_message(
+                    Message::sign(&name, format!("{i}"), &client_configs[0].private_key).unwrap(),
+                ),
+            };
+            gossip::TcpGossipNetwork::broadcast(
+                &client_configs[0],
+                std::slice::from_ref(&server_peer),
+                &name,
+                serde_spb::to_string(&message).unwrap().into_bytes(),
+            )
+            .await
+            .unwrap();
+        }
+        let dms = task.await.unwrap().unwrap();
+        assert_eq!(dms.read_messages().await.unwrap().len(), 1);
+        assert_eq!(dms.metrics().rejected_by_rate_limit, 1);
+    }
 }
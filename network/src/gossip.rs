@@ -0,0 +1,370 @@
+use super::*;
+use eyre::eyre;
+use futures::prelude::*;
+use primitives::GossipNetwork;
+use simperby_common::*;
+use std::collections::{HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use transport::AuthenticatedConnection;
+
+/// The number of the recent messages remembered to avoid delivering and forwarding them again.
+const SEEN_MESSAGES_CAPACITY: usize = 4096;
+/// The timeout for connecting and sending a message to a peer, and for receiving it.
+const SEND_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Returns the key of `NetworkConfig::ports` and `Peer::ports` for the gossip of the given topic.
+pub fn port_key(topic: &str) -> String {
+    format!("gossip-{topic}")
+}
+
+/// A message on the wire, signed by the member who originally broadcasted it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GossipMessage {
+    network_id: String,
+    topic: String,
+    payload: Vec<u8>,
+    origin: PublicKey,
+    signature: Signature,
+}
+
+impl GossipMessage {
+    fn signing_hash(network_id: &str, topic: &str, payload: &[u8]) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(&(network_id, topic, payload)).unwrap())
+    }
+
+    fn new(config: &NetworkConfig, topic: &str, payload: Vec<u8>) -> Result<Self, Error> {
+        let signature = Signature::sign(
+            Self::signing_hash(&config.network_id, topic, &payload),
+            &config.private_key,
+        )?;
+        Ok(Self {
+            network_id: config.network_id.clone(),
+            topic: topic.to_owned(),
+            payload,
+            origin: config.public_key.clone(),
+            signature,
+        })
+    }
+
+    /// Checks that the message is for this network and topic, and signed by a member.
+    fn verify(&self, config: &NetworkConfig, topic: &str) -> Result<(), Error> {
+        if self.network_id != config.network_id {
+            return Err(eyre!("network id mismatch: {}", self.network_id));
+        }
+        if self.topic != topic {
+            return Err(eyre!("topic mismatch: {}", self.topic));
+        }
+        if !config.members.contains(&self.origin) {
+            return Err(eyre!("message from a non-member: {}", self.origin));
+        }
+        self.signature.verify(
+            Self::signing_hash(&self.network_id, &self.topic, &self.payload),
+            &self.origin,
+        )?;
+        Ok(())
+    }
+
+    fn id(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// The recently seen messages, bounded by [`SEEN_MESSAGES_CAPACITY`].
+#[derive(Default)]
+struct SeenMessages {
+    set: HashSet<Hash256>,
+    queue: VecDeque<Hash256>,
+}
+
+impl SeenMessages {
+    /// Marks the message as seen, returning `false` if it was already seen.
+    fn insert(&mut self, id: Hash256) -> bool {
+        if !self.set.insert(id) {
+            return false;
+        }
+        self.queue.push_back(id);
+        if self.queue.len() > SEEN_MESSAGES_CAPACITY {
+            let oldest = self.queue.pop_front().expect("the queue is not empty");
+            self.set.remove(&oldest);
+        }
+        true
+    }
+}
+
+/// A gossip network over TCP, where a topic is usually a DMS key.
+///
+/// Each node listens on the port of [`port_key`] for the topic,
+/// and a message is flooded to the known peers that are members of the network,
+/// which forward it once to their known peers in turn.
+///
+/// Each message is sent over its own [`AuthenticatedConnection`], so a connection from
+/// a non-member (`NetworkConfig::members`) is closed in the handshake before any message is read.
+/// A forwarded message must also be signed by the member who originally broadcasted it.
+pub struct TcpGossipNetwork;
+
+impl TcpGossipNetwork {
+    async fn send(
+        config: &NetworkConfig,
+        peer: &Peer,
+        port: u16,
+        message: &GossipMessage,
+    ) -> Result<(), Error> {
+        let address = std::net::SocketAddrV4::new(*peer.address.ip(), port);
+        tokio::time::timeout(SEND_TIMEOUT, async {
+            let mut connection =
+                AuthenticatedConnection::connect(config, address, Some(&peer.public_key)).await?;
+            connection.send(message).await
+        })
+        .await
+        .map_err(|_| eyre!("timeout"))?
+    }
+
+    /// Sends the message to all the known peers who are members, except the origin and this node.
+    async fn send_to_peers(
+        config: &NetworkConfig,
+        known_peers: &[Peer],
+        message: &GossipMessage,
+    ) -> Result<(), Error> {
+        let port_key = port_key(&message.topic);
+        let tasks = known_peers
+            .iter()
+            .filter(|peer| {
+                peer.public_key != config.public_key
+                    && peer.public_key != message.origin
+                    && config.members.contains(&peer.public_key)
+            })
+            .filter_map(|peer| {
+                let port = *peer.ports.get(&port_key)?;
+                Some(async move {
+                    if let Err(e) = Self::send(config, peer, port, message).await {
+                        log::warn!("failed to gossip to {}: {}", peer.name, e);
+                    }
+                })
+            });
+        future::join_all(tasks).await;
+        Ok(())
+    }
+
+    async fn handle_connection(
+        stream: TcpStream,
+        config: Arc<NetworkConfig>,
+        topic: Arc<String>,
+        peers: SharedKnownPeers,
+        seen: Arc<parking_lot::Mutex<SeenMessages>>,
+        send: mpsc::Sender<(IpAddr, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        let address = stream.peer_addr()?.ip();
+        let mut connection = AuthenticatedConnection::accept(&config, stream).await?;
+        let message: GossipMessage = tokio::time::timeout(SEND_TIMEOUT, connection.recv())
+            .await
+            .map_err(|_| eyre!("timeout"))??;
+        message.verify(&config, &topic)?;
+        if !seen.lock().insert(message.id()) {
+            return Ok(());
+        }
+        // The receiver may be gone, in which case the serving task will stop soon.
+        let _ = send.send((address, message.payload.clone())).await;
+        Self::send_to_peers(&config, &peers.read().await, &message).await
+    }
+}
+
+#[async_trait]
+impl GossipNetwork for TcpGossipNetwork {
+    async fn broadcast(
+        config: &NetworkConfig,
+        known_peers: &[Peer],
+        topic: &str,
+        message: Vec<u8>,
+    ) -> Result<(), Error> {
+        let message = GossipMessage::new(config, topic, message)?;
+        Self::send_to_peers(config, known_peers, &message).await
+    }
+
+    /// Serves until the returned receiver is dropped.
+    ///
+    /// If there is no port for the topic in the config,
+    /// it doesn't listen and so never receives a message.
+    async fn serve(
+        config: NetworkConfig,
+        topic: String,
+        peers: SharedKnownPeers,
+    ) -> Result<
+        (
+            mpsc::Receiver<(IpAddr, Vec<u8>)>,
+            tokio::task::JoinHandle<Result<(), Error>>,
+        ),
+        Error,
+    > {
+        let (send, recv) = mpsc::channel(1024);
+        let port = if let Some(port) = config.ports.get(&port_key(&topic)) {
+            *port
+        } else {
+            log::warn!(
+                "no port for the gossip of {}; it will not receive messages",
+                topic
+            );
+            let task = tokio::spawn(async move {
+                send.closed().await;
+                Ok(())
+            });
+            return Ok((recv, task));
+        };
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await?;
+        let config = Arc::new(config);
+        let topic = Arc::new(topic);
+        let seen = Arc::new(parking_lot::Mutex::new(SeenMessages::default()));
+        let task = tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    _ = send.closed() => return Ok(()),
+                    result = listener.accept() => result?.0,
+                };
+                let task = Self::handle_connection(
+                    stream,
+                    Arc::clone(&config),
+                    Arc::clone(&topic),
+                    peers.clone(),
+                    Arc::clone(&seen),
+                    send.clone(),
+                );
+                tokio::spawn(async move {
+                    if let Err(e) = task.await {
+                        log::warn!("failed to receive a gossip message: {}", e);
+                    }
+                });
+            }
+        });
+        Ok((recv, task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn peer(config: &NetworkConfig) -> Peer {
+        Peer {
+            public_key: config.public_key.clone(),
+            name: config.public_key.to_string(),
+            address: std::net::SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0),
+            ports: config.ports.clone(),
+            message: "".to_owned(),
+            recently_seen_timestamp: 0,
+        }
+    }
+
+    fn generate_configs(size: usize, topic: &str) -> Vec<NetworkConfig> {
+        let keys = (0..size)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        keys.iter()
+            .map(|(public_key, private_key)| NetworkConfig {
+                network_id: "gossip".to_owned(),
+                ports: [(port_key(topic), dispense_port())].into_iter().collect(),
+                members: keys.iter().map(|(x, _)| x.clone()).collect(),
+                public_key: public_key.clone(),
+                private_key: private_key.clone(),
+            })
+            .collect()
+    }
+
+    async fn recv_timeout(recv: &mut mpsc::Receiver<(IpAddr, Vec<u8>)>) -> Option<Vec<u8>> {
+        tokio::time::timeout(Duration::from_millis(1000), recv.recv())
+            .await
+            .ok()
+            .flatten()
+            .map(|(address, message)| {
+                assert_eq!(address, IpAddr::from(Ipv4Addr::LOCALHOST));
+                message
+            })
+    }
+
+    /// A message is propagated through a node that is not known by the broadcaster.
+    #[tokio::test]
+    async fn propagation() {
+        setup_test();
+        let topic = "topic";
+        let configs = generate_configs(3, topic);
+        // `0` knows only `1`, and `1` knows only `2`.
+        let (mut recv1, _) = TcpGossipNetwork::serve(
+            configs[1].clone(),
+            topic.to_owned(),
+            SharedKnownPeers::new_static(vec![peer(&configs[2])]),
+        )
+        .await
+        .unwrap();
+        let (mut recv2, _) = TcpGossipNetwork::serve(
+            configs[2].clone(),
+            topic.to_owned(),
+            SharedKnownPeers::new_static(vec![peer(&configs[1])]),
+        )
+        .await
+        .unwrap();
+
+        TcpGossipNetwork::broadcast(&configs[0], &[peer(&configs[1])], topic, b"hello".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(recv_timeout(&mut recv1).await.unwrap(), b"hello".to_vec());
+        assert_eq!(recv_timeout(&mut recv2).await.unwrap(), b"hello".to_vec());
+        // It is delivered only once even if it comes back.
+        assert_eq!(recv_timeout(&mut recv1).await, None);
+
+        // The same message broadcasted again is also not delivered again.
+        TcpGossipNetwork::broadcast(&configs[0], &[peer(&configs[1])], topic, b"hello".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(recv_timeout(&mut recv1).await, None);
+    }
+
+    /// Messages from a non-member or for another topic are dropped.
+    #[tokio::test]
+    async fn reject_invalid_messages() {
+        setup_test();
+        let topic = "topic";
+        let configs = generate_configs(2, topic);
+        let (mut recv, _) = TcpGossipNetwork::serve(
+            configs[1].clone(),
+            topic.to_owned(),
+            SharedKnownPeers::new_static(Vec::new()),
+        )
+        .await
+        .unwrap();
+        let target = peer(&configs[1]);
+
+        let (public_key, private_key) = generate_keypair_random();
+        let mut non_member = configs[0].clone();
+        non_member.public_key = public_key;
+        non_member.private_key = private_key;
+        non_member.members.push(non_member.public_key.clone());
+        TcpGossipNetwork::broadcast(
+            &non_member,
+            std::slice::from_ref(&target),
+            topic,
+            b"a".to_vec(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(recv_timeout(&mut recv).await, None);
+
+        // Sent to the port of `topic`, but for another topic.
+        let message = GossipMessage::new(&configs[0], "other", b"b".to_vec()).unwrap();
+        TcpGossipNetwork::send(
+            &configs[0],
+            &target,
+            configs[1].ports[&port_key(topic)],
+            &message,
+        )
+        .await
+        .unwrap();
+        assert_eq!(recv_timeout(&mut recv).await, None);
+
+        TcpGossipNetwork::broadcast(&configs[0], &[target], topic, b"c".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(recv_timeout(&mut recv).await.unwrap(), b"c".to_vec());
+    }
+}
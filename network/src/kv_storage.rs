@@ -0,0 +1,256 @@
+use crate::primitives::{Storage, StorageError};
+use async_trait::async_trait;
+use fs2::FileExt;
+use redb::{Database, ReadableTable, TableDefinition};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::{fs, task::spawn_blocking};
+
+/// The database file in the storage directory.
+pub const DATABASE_FILE: &str = "storage.redb";
+
+const FILES: TableDefinition<&str, &str> = TableDefinition::new("files");
+const QUARANTINE: TableDefinition<&str, &str> = TableDefinition::new("quarantine");
+
+// `std::io::Error::other` is not available in the pinned toolchain.
+#[allow(unknown_lints, clippy::io_other_error)]
+fn to_io_error(error: impl Into<redb::Error>) -> StorageError {
+    StorageError::new(ErrorKind::Other, error.into())
+}
+
+fn not_found(name: &str) -> StorageError {
+    StorageError::new(ErrorKind::NotFound, format!("no such file: {name}"))
+}
+
+/// A `Storage` backed by an embedded key-value database in the directory.
+///
+/// Unlike `StorageImpl`, which keeps a file for each, it keeps all the files in a single database,
+/// so that reading many small files (e.g., the messages of a DMS) doesn't hit the disk for each.
+/// Every operation is an ACID transaction.
+pub struct KvStorage {
+    lock_file: Option<std::fs::File>,
+    database: Option<Arc<Database>>,
+}
+
+impl KvStorage {
+    async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Database) -> Result<T, StorageError> + Send + 'static,
+    ) -> Result<T, StorageError> {
+        let database = Arc::clone(self.database.as_ref().expect("not dropped yet"));
+        spawn_blocking(move || f(&database)).await?
+    }
+}
+
+#[async_trait]
+impl Storage for KvStorage {
+    async fn create(storage_directory: &str) -> Result<(), StorageError> {
+        let _ = fs::remove_dir_all(storage_directory).await;
+        fs::create_dir_all(storage_directory).await?;
+        fs::File::create(format!("{storage_directory}/lock")).await?;
+        let path = format!("{storage_directory}/{DATABASE_FILE}");
+        spawn_blocking(move || {
+            let database = Database::create(path).map_err(to_io_error)?;
+            let transaction = database.begin_write().map_err(to_io_error)?;
+            transaction.open_table(FILES).map_err(to_io_error)?;
+            transaction.open_table(QUARANTINE).map_err(to_io_error)?;
+            transaction.commit().map_err(to_io_error)
+        })
+        .await?
+    }
+
+    async fn open(storage_directory: &str) -> Result<Self, StorageError>
+    where
+        Self: Sized,
+    {
+        let storage_directory_ = storage_directory.to_owned();
+        let file =
+            spawn_blocking(move || std::fs::File::open(format!("{storage_directory_}/lock")))
+                .await??;
+        let file = spawn_blocking(move || {
+            let result = file.lock_exclusive();
+            result.map(|_| file)
+        })
+        .await??;
+        let path = format!("{storage_directory}/{DATABASE_FILE}");
+        let database = spawn_blocking(move || Database::open(path).map_err(to_io_error)).await??;
+        Ok(Self {
+            lock_file: Some(file),
+            database: Some(Arc::new(database)),
+        })
+    }
+
+    async fn list_files(&self) -> Result<Vec<String>, StorageError> {
+        self.run(|database| {
+            let transaction = database.begin_read().map_err(to_io_error)?;
+            let table = transaction.open_table(FILES).map_err(to_io_error)?;
+            let mut names = Vec::new();
+            for entry in table.iter().map_err(to_io_error)? {
+                let (name, _) = entry.map_err(to_io_error)?;
+                names.push(name.value().to_owned());
+            }
+            Ok(names)
+        })
+        .await
+    }
+
+    async fn add_or_overwrite_file(
+        &mut self,
+        name: &str,
+        content: String,
+    ) -> Result<(), StorageError> {
+        let name = name.to_owned();
+        self.run(move |database| {
+            let transaction = database.begin_write().map_err(to_io_error)?;
+            transaction
+                .open_table(FILES)
+                .map_err(to_io_error)?
+                .insert(name.as_str(), content.as_str())
+                .map_err(to_io_error)?;
+            transaction.commit().map_err(to_io_error)
+        })
+        .await
+    }
+
+    async fn read_file(&self, name: &str) -> Result<String, StorageError> {
+        let name = name.to_owned();
+        self.run(move |database| {
+            let transaction = database.begin_read().map_err(to_io_error)?;
+            let table = transaction.open_table(FILES).map_err(to_io_error)?;
+            let content = table.get(name.as_str()).map_err(to_io_error)?;
+            content
+                .map(|content| content.value().to_owned())
+                .ok_or_else(|| not_found(&name))
+        })
+        .await
+    }
+
+    async fn read_all_files(
+        &self,
+    ) -> Result<Vec<(String, Result<String, StorageError>)>, StorageError> {
+        self.run(|database| {
+            let transaction = database.begin_read().map_err(to_io_error)?;
+            let table = transaction.open_table(FILES).map_err(to_io_error)?;
+            let mut files = Vec::new();
+            for entry in table.iter().map_err(to_io_error)? {
+                let (name, content) = entry.map_err(to_io_error)?;
+                files.push((name.value().to_owned(), Ok(content.value().to_owned())));
+            }
+            Ok(files)
+        })
+        .await
+    }
+
+    async fn remove_file(&mut self, name: &str) -> Result<(), StorageError> {
+        let name = name.to_owned();
+        self.run(move |database| {
+            let transaction = database.begin_write().map_err(to_io_error)?;
+            transaction
+                .open_table(FILES)
+                .map_err(to_io_error)?
+                .remove(name.as_str())
+                .map_err(to_io_error)?
+                .ok_or_else(|| not_found(&name))?;
+            transaction.commit().map_err(to_io_error)
+        })
+        .await
+    }
+
+    async fn remove_all_files(&mut self) -> Result<(), StorageError> {
+        self.run(|database| {
+            let transaction = database.begin_write().map_err(to_io_error)?;
+            transaction.delete_table(FILES).map_err(to_io_error)?;
+            transaction.open_table(FILES).map_err(to_io_error)?;
+            transaction.commit().map_err(to_io_error)
+        })
+        .await
+    }
+
+    async fn quarantine_file(&mut self, name: &str) -> Result<(), StorageError> {
+        let name = name.to_owned();
+        self.run(move |database| {
+            let transaction = database.begin_write().map_err(to_io_error)?;
+            {
+                let mut files = transaction.open_table(FILES).map_err(to_io_error)?;
+                let content = files
+                    .remove(name.as_str())
+                    .map_err(to_io_error)?
+                    .ok_or_else(|| not_found(&name))?
+                    .value()
+                    .to_owned();
+                transaction
+                    .open_table(QUARANTINE)
+                    .map_err(to_io_error)?
+                    .insert(name.as_str(), content.as_str())
+                    .map_err(to_io_error)?;
+            }
+            transaction.commit().map_err(to_io_error)
+        })
+        .await
+    }
+}
+
+impl Drop for KvStorage {
+    fn drop(&mut self) {
+        // The database must be closed before the lock is released.
+        drop(self.database.take());
+        let lock_file = self.lock_file.take().unwrap();
+        spawn_blocking(move || {
+            if let Err(e) = lock_file.unlock() {
+                log::error!("failed to unlock storage: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn files() {
+        let dir = simperby_test_suite::create_temp_dir();
+        KvStorage::create(&dir).await.unwrap();
+        let mut storage = KvStorage::open(&dir).await.unwrap();
+        for i in 0..10 {
+            storage
+                .add_or_overwrite_file(&format!("{i}"), format!("content {i}"))
+                .await
+                .unwrap();
+        }
+        storage
+            .add_or_overwrite_file("0", "overwritten".to_owned())
+            .await
+            .unwrap();
+        storage.remove_file("1").await.unwrap();
+        storage.quarantine_file("2").await.unwrap();
+        assert_eq!(
+            storage.read_file("1").await.unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        drop(storage);
+
+        // The files persist, and the storage waits for the previous one to be dropped.
+        let mut storage = KvStorage::open(&dir).await.unwrap();
+        let mut files = storage.list_files().await.unwrap();
+        files.sort();
+        let mut expected = (3..10).map(|i| format!("{i}")).collect::<Vec<_>>();
+        expected.push("0".to_owned());
+        expected.sort();
+        assert_eq!(files, expected);
+        assert_eq!(storage.read_file("0").await.unwrap(), "overwritten");
+        let mut contents = storage
+            .read_all_files()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(name, content)| (name, content.unwrap()))
+            .collect::<Vec<_>>();
+        contents.sort();
+        assert_eq!(contents[0], ("0".to_owned(), "overwritten".to_owned()));
+        assert_eq!(contents[1], ("3".to_owned(), "content 3".to_owned()));
+        assert_eq!(contents.len(), 8);
+        storage.remove_all_files().await.unwrap();
+        assert!(storage.list_files().await.unwrap().is_empty());
+    }
+}
@@ -1,8 +1,13 @@
 pub mod dms;
-#[cfg(never)]
-mod peer_discovery;
+pub mod gossip;
+pub mod kv_storage;
+pub mod peer_discovery;
 pub mod primitives;
+pub mod relay;
 pub mod storage;
+pub mod transport;
+
+mod reconciliation;
 
 use async_trait::async_trait;
 use primitives::*;
@@ -13,7 +18,7 @@ use std::{net::SocketAddrV4, sync::Arc};
 use tokio::sync::RwLock;
 
 pub type Error = eyre::Error;
-pub type Dms = dms::DistributedMessageSet<primitives::DummyGossipNetwork, storage::StorageImpl>;
+pub type Dms<M> = dms::DistributedMessageSet<gossip::TcpGossipNetwork, storage::StorageImpl, M>;
 
 /// The information of a network peer that is discovered by the discovery protocol.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -93,13 +98,17 @@ pub trait PeerDiscovery {
     /// Fails if there is already a directory.
     async fn create(storage_directory: &str) -> Result<(), Error>;
 
-    /// Serves the discovery protocol indefinitely, updating the known peers on the storage.
+    /// Serves the discovery protocol indefinitely, updating `known_peers` and the storage.
     ///
+    /// - The peers in the storage are added to `known_peers` as the initially known peers.
     /// - It may discard members in the storage who are not in `NetworkConfig::members`.
+    /// - The non-member peers in `known_peers` (e.g., the relays) are kept but neither queried
+    ///   nor stored.
     async fn serve(
         storage_directory: &str,
         network_config: &NetworkConfig,
-    ) -> Result<(SharedKnownPeers, tokio::task::JoinHandle<Result<(), Error>>), Error>;
+        known_peers: SharedKnownPeers,
+    ) -> Result<tokio::task::JoinHandle<Result<(), Error>>, Error>;
 
     /// Reads the known peers from the storage.
     async fn read_known_peers(storage_directory: &str) -> Result<Vec<Peer>, Error>;
@@ -1,32 +1,101 @@
-#[cfg(feature = "full")]
-mod behaviour;
-#[cfg(feature = "full")]
 mod primitive;
 #[cfg(test)]
-#[cfg(feature = "full")]
 mod tests;
-#[cfg(feature = "full")]
-mod utils;
 
 use super::*;
+use crate::{primitives::Storage, storage::StorageImpl};
 use async_trait::async_trait;
+use eyre::eyre;
+use primitive::PeerDiscoveryPrimitiveImpl;
+use simperby_common::*;
+use std::time::Duration;
+use tokio::task::JoinHandle;
 
-pub struct PeerDiscoveryImpl {}
+/// The key of `NetworkConfig::ports` and `Peer::ports` for the peer discovery protocol.
+pub const PORT_KEY: &str = "discovery";
+
+/// The file in the storage that keeps the known peers.
+const KNOWN_PEERS_FILE: &str = "known_peers.json";
+/// The interval of writing the known peers to the storage.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Adds the peers to `known_peers`, unless a more recently seen one is already known.
+async fn merge_peers(known_peers: &SharedKnownPeers, peers: Vec<Peer>) {
+    for peer in peers {
+        let is_newer = !known_peers.read().await.iter().any(|known_peer| {
+            known_peer.public_key == peer.public_key
+                && known_peer.recently_seen_timestamp >= peer.recently_seen_timestamp
+        });
+        if is_newer {
+            known_peers.add_or_replace(peer).await;
+        }
+    }
+}
+
+/// The peer discovery protocol, persisting the known peers to a `StorageImpl`.
+///
+/// Nodes exchange signed records of themselves with their ports,
+/// and look up the network in the manner of Kademlia.
+/// The node listens on the port of [`PORT_KEY`] in `NetworkConfig::ports`.
+pub struct PeerDiscoveryImpl;
 
 #[async_trait]
 impl PeerDiscovery for PeerDiscoveryImpl {
-    async fn create(_storage_directory: &str) -> Result<(), Error> {
-        unimplemented!();
+    async fn create(storage_directory: &str) -> Result<(), Error> {
+        if tokio::fs::metadata(storage_directory).await.is_ok() {
+            return Err(eyre!("directory already exists: {}", storage_directory));
+        }
+        StorageImpl::create(storage_directory).await?;
+        let mut storage = StorageImpl::open(storage_directory).await?;
+        storage
+            .add_or_overwrite_file(KNOWN_PEERS_FILE, serde_spb::to_string(&Vec::<Peer>::new())?)
+            .await?;
+        Ok(())
     }
 
     async fn serve(
-        _storage_directory: &str,
-        _network_config: &NetworkConfig,
-    ) -> Result<(SharedKnownPeers, tokio::task::JoinHandle<Result<(), Error>>), Error> {
-        unimplemented!();
+        storage_directory: &str,
+        network_config: &NetworkConfig,
+        known_peers: SharedKnownPeers,
+    ) -> Result<JoinHandle<Result<(), Error>>, Error> {
+        let mut storage = StorageImpl::open(storage_directory).await?;
+        let stored_peers: Vec<Peer> =
+            serde_spb::from_str(&storage.read_file(KNOWN_PEERS_FILE).await?)?;
+        merge_peers(&known_peers, stored_peers).await;
+        // Runs in the serving task, so that aborting it releases the port.
+        let members = network_config.members.clone();
+        let task = PeerDiscoveryPrimitiveImpl::run(
+            network_config.clone(),
+            String::new(),
+            network_config.ports.clone(),
+            known_peers.clone(),
+        )
+        .await?;
+        Ok(tokio::spawn(async move {
+            tokio::pin!(task);
+            let mut interval = tokio::time::interval(PERSIST_INTERVAL);
+            loop {
+                tokio::select! {
+                    result = &mut task => return result,
+                    _ = interval.tick() => (),
+                }
+                let peers = known_peers
+                    .read()
+                    .await
+                    .into_iter()
+                    .filter(|peer| members.contains(&peer.public_key))
+                    .collect::<Vec<_>>();
+                storage
+                    .add_or_overwrite_file(KNOWN_PEERS_FILE, serde_spb::to_string(&peers)?)
+                    .await?;
+            }
+        }))
     }
 
-    async fn read_known_peers(_storage_directory: &str) -> Result<Vec<Peer>, Error> {
-        unimplemented!();
+    async fn read_known_peers(storage_directory: &str) -> Result<Vec<Peer>, Error> {
+        let storage = StorageImpl::open(storage_directory).await?;
+        Ok(serde_spb::from_str(
+            &storage.read_file(KNOWN_PEERS_FILE).await?,
+        )?)
     }
 }
@@ -1,170 +1,559 @@
-use super::behaviour::{DiscoveryBehaviour, DiscoveryEvent};
-use super::utils::{
-    convert_keypair, convert_multiaddr_into_sockv4, convert_public_key, get_peer_id,
-};
-use crate::{primitives::PeerDiscoveryPrimitive, *};
-use async_trait::async_trait;
+use super::*;
+use crate::transport::AuthenticatedConnection;
 use chrono::Utc;
-use eyre::eyre;
-use futures::StreamExt;
-use ip_rfc::global_v4;
-use libp2p::{
-    core::{muxing::StreamMuxerBox, transport, upgrade},
-    identify, identity,
-    noise::NoiseAuthenticated,
-    swarm::{SwarmBuilder, SwarmEvent},
-    tcp,
-    yamux::YamuxConfig,
-    Multiaddr, PeerId, Swarm, Transport,
-};
-use tokio::task::JoinHandle;
+use futures::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::net::{TcpListener, TcpStream};
 
-pub(crate) struct PeerDiscoveryPrimitiveImpl;
+/// The number of the closest peers returned for a lookup (`k` in Kademlia).
+///
+/// Since the network is restricted to the members, which are not many,
+/// it is large enough for every node to eventually know all the others.
+const K: usize = 32;
+/// The number of the concurrent requests in a lookup (`alpha` in Kademlia).
+const ALPHA: usize = 3;
+/// The interval of the regular lookup.
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(10);
+/// The interval of the lookup while new peers are being found (e.g., many nodes are joining).
+const QUICK_DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+/// The timeout for a request to a peer, after the handshake.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+/// The allowed amount of time that a record can be ahead of the local clock, in milliseconds.
+const MAX_CLOCK_SKEW: Timestamp = 30_000;
+/// The interval of re-signing the record of this node, in milliseconds.
+///
+/// The `recently_seen_timestamp` of this node in the other nodes may lag behind as much.
+const RECORD_REFRESH_INTERVAL: Timestamp = 5_000;
 
-#[async_trait]
-impl PeerDiscoveryPrimitive for PeerDiscoveryPrimitiveImpl {
-    async fn serve(
-        network_config: NetworkConfig,
-        message: String,
-        port_map: HashMap<String, u16>,
-        initially_known_peers: Vec<Peer>,
-    ) -> Result<(SharedKnownPeers, JoinHandle<Result<(), Error>>), Error> {
-        let mut swarm = Self::create_swarm(&network_config, message, port_map).await?;
-        swarm
-            .listen_on(format!("/ip4/0.0.0.0/tcp/{}", network_config.port.unwrap_or(0)).parse()?)?;
-        let shared_known_peers = SharedKnownPeers {
-            lock: Arc::new(RwLock::new(initially_known_peers)),
-        };
-        Ok((
-            shared_known_peers.to_owned(),
-            tokio::spawn(Self::discovery_task(swarm, shared_known_peers)),
-        ))
+fn now() -> Timestamp {
+    Utc::now().timestamp_millis() as Timestamp
+}
+
+/// The key of a node in the Kademlia key space.
+fn node_key(public_key: &PublicKey) -> Hash256 {
+    Hash256::hash(public_key)
+}
+
+/// The XOR distance between two keys, which is compared lexicographically.
+fn distance(a: &Hash256, b: &Hash256) -> [u8; 32] {
+    let mut result = [0; 32];
+    for (i, (x, y)) in a.as_ref().iter().zip(b.as_ref()).enumerate() {
+        result[i] = x ^ y;
     }
+    result
 }
 
-impl PeerDiscoveryPrimitiveImpl {
-    async fn create_swarm(
-        network_config: &NetworkConfig,
-        message: String,
-        port_map: HashMap<String, u16>,
-    ) -> Result<Swarm<DiscoveryBehaviour>, Error> {
-        let libp2p_keypair =
-            convert_keypair(&network_config.public_key, &network_config.private_key)?;
-        let transport = Self::create_transport(&libp2p_keypair).await?;
-        let behaviour = Self::create_behaviour(&libp2p_keypair, message, port_map).await?;
-        let swarm = SwarmBuilder::with_executor(
-            transport,
-            behaviour,
-            libp2p_keypair.public().to_peer_id(),
-            |fut| {
-                tokio::spawn(fut);
-            },
+/// The information that a node advertises about itself, signed by the node.
+///
+/// The IP address is not a part of it; it is observed by the node that receives the record
+/// directly from its owner, and relayed along with the record.
+/// A relayed address is only a hint until a node connects to it and authenticates the owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PeerRecord {
+    network_id: String,
+    public_key: PublicKey,
+    ports: BTreeMap<String, u16>,
+    message: String,
+    timestamp: Timestamp,
+    signature: Signature,
+}
+
+impl PeerRecord {
+    fn signing_hash(
+        network_id: &str,
+        public_key: &PublicKey,
+        ports: &BTreeMap<String, u16>,
+        message: &str,
+        timestamp: Timestamp,
+    ) -> Hash256 {
+        Hash256::hash(
+            serde_spb::to_vec(&(network_id, public_key, ports, message, timestamp)).unwrap(),
         )
-        .build();
-        Ok(swarm)
     }
 
-    async fn create_transport(
-        libp2p_keypair: &identity::Keypair,
-    ) -> Result<transport::Boxed<(PeerId, StreamMuxerBox)>, Error> {
-        let transport = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
-            .upgrade(upgrade::Version::V1)
-            .authenticate(NoiseAuthenticated::xx(libp2p_keypair).unwrap())
-            .multiplex(YamuxConfig::default())
-            .boxed();
-        Ok(transport)
+    fn new(
+        config: &NetworkConfig,
+        ports: BTreeMap<String, u16>,
+        message: String,
+    ) -> Result<Self, Error> {
+        let timestamp = now();
+        let signature = Signature::sign(
+            Self::signing_hash(
+                &config.network_id,
+                &config.public_key,
+                &ports,
+                &message,
+                timestamp,
+            ),
+            &config.private_key,
+        )?;
+        Ok(Self {
+            network_id: config.network_id.clone(),
+            public_key: config.public_key.clone(),
+            ports,
+            message,
+            timestamp,
+            signature,
+        })
     }
 
-    async fn create_behaviour(
-        libp2p_keypair: &identity::Keypair,
-        message: String,
-        port_map: HashMap<String, u16>,
-    ) -> Result<DiscoveryBehaviour, Error> {
-        let message = serde_spb::to_string(&(message, port_map))?;
-        Ok(DiscoveryBehaviour::new(libp2p_keypair.public(), message))
+    /// Checks that the record is of a member of this network, and signed by it.
+    fn verify(&self, config: &NetworkConfig) -> Result<(), Error> {
+        if self.network_id != config.network_id {
+            return Err(eyre!("network id mismatch: {}", self.network_id));
+        }
+        if !config.members.contains(&self.public_key) {
+            return Err(eyre!("record of a non-member: {}", self.public_key));
+        }
+        if self.timestamp > now() + MAX_CLOCK_SKEW {
+            return Err(eyre!("record from the future: {}", self.timestamp));
+        }
+        if !self.ports.contains_key(PORT_KEY) {
+            return Err(eyre!("no port for the peer discovery"));
+        }
+        self.signature.verify(
+            Self::signing_hash(
+                &self.network_id,
+                &self.public_key,
+                &self.ports,
+                &self.message,
+                self.timestamp,
+            ),
+            &self.public_key,
+        )?;
+        Ok(())
     }
+}
 
-    #[allow(clippy::single_match)]
-    /// The background task that serves peer discovery protocol.
-    async fn discovery_task(
-        mut swarm: Swarm<DiscoveryBehaviour>,
-        shared_known_peers: SharedKnownPeers,
-    ) -> Result<(), Error> {
-        Self::add_known_peers_to_routing_table(&mut swarm, &shared_known_peers).await?;
-        // Todo: Make the interval configurable.
-        let mut discovery_timer = tokio::time::interval(tokio::time::Duration::from_secs(10));
-        loop {
-            tokio::select! {
-                event = swarm.select_next_some() => match event {
-                    SwarmEvent::Behaviour(event) => Self::update_known_peers(&mut swarm, &shared_known_peers, event).await,
-                    // All incoming events should be comsumed even though we don't handle them.
-                    _any_other_event => (),
-                },
-                _ = discovery_timer.tick() =>
-                    Self::regular_discovery(&mut swarm).await,
+/// Asks for the records of the peers closest to `target`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FindNodeRequest {
+    sender: PeerRecord,
+    target: Hash256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FindNodeResponse {
+    sender: PeerRecord,
+    closest: Vec<(Ipv4Addr, PeerRecord)>,
+}
+
+struct State {
+    config: NetworkConfig,
+    ports: BTreeMap<String, u16>,
+    message: String,
+    /// The last signed record of this node.
+    record: parking_lot::Mutex<Option<PeerRecord>>,
+    /// The verified records of the peers, with their IP addresses confirmed by
+    /// an authenticated connection.
+    records: parking_lot::RwLock<HashMap<PublicKey, (Ipv4Addr, PeerRecord)>>,
+    /// The verified records relayed by the other peers, whose IP addresses are not confirmed yet.
+    hints: parking_lot::RwLock<HashMap<PublicKey, (Ipv4Addr, PeerRecord)>>,
+    /// The routing table, shared with the other modules.
+    known_peers: SharedKnownPeers,
+}
+
+impl State {
+    /// Returns the record of this node, re-signing it if it is old.
+    fn record(&self) -> Result<PeerRecord, Error> {
+        let mut record = self.record.lock();
+        match &*record {
+            Some(record) if record.timestamp + RECORD_REFRESH_INTERVAL > now() => {
+                Ok(record.clone())
+            }
+            _ => {
+                let new_record =
+                    PeerRecord::new(&self.config, self.ports.clone(), self.message.clone())?;
+                *record = Some(new_record.clone());
+                Ok(new_record)
             }
         }
     }
 
-    async fn update_known_peers(
-        swarm: &mut Swarm<DiscoveryBehaviour>,
-        shared_known_peers: &SharedKnownPeers,
-        event: DiscoveryEvent,
-    ) {
-        if let DiscoveryEvent::Identify(identify::Event::Received { info, peer_id }) = event {
-            swarm
-                .behaviour_mut()
-                .kademlia
-                .add_address(&peer_id, info.listen_addrs[0].clone());
-            let _ = Self::add_new_peer(info, shared_known_peers).await;
+    /// Returns whether the record is the same as the known one, which is already verified.
+    fn is_known(&self, record: &PeerRecord) -> bool {
+        matches!(self.records.read().get(&record.public_key), Some((_, known)) if known == record)
+    }
+
+    /// Returns whether the record is not more recent than the known one or the hint, if any.
+    fn is_outdated(&self, record: &PeerRecord) -> bool {
+        let is_outdated = |records: &HashMap<PublicKey, (Ipv4Addr, PeerRecord)>| {
+            matches!(
+                records.get(&record.public_key),
+                Some((_, known)) if known.timestamp >= record.timestamp
+            )
+        };
+        is_outdated(&self.records.read()) || is_outdated(&self.hints.read())
+    }
+
+    /// Adds a verified record of a relayed address.
+    ///
+    /// It refreshes the known record if the address is the confirmed one,
+    /// and otherwise is kept as a hint to be confirmed by the lookup.
+    async fn add_hint(&self, address: Ipv4Addr, record: PeerRecord) {
+        if record.public_key == self.config.public_key {
+            return;
+        }
+        let is_confirmed = matches!(
+            self.records.read().get(&record.public_key),
+            Some((known_address, _)) if *known_address == address
+        );
+        if is_confirmed {
+            self.add_record(address, record).await;
+        } else {
+            self.hints
+                .write()
+                .insert(record.public_key.clone(), (address, record));
         }
     }
 
-    async fn regular_discovery(swarm: &mut Swarm<DiscoveryBehaviour>) {
-        // If the routing table is empty, `bootstrap` returns an error, which we just ignore.
-        let _ = swarm.behaviour_mut().kademlia.bootstrap();
+    /// Adds a verified record of the confirmed address unless a more recent one is known.
+    async fn add_record(&self, address: Ipv4Addr, record: PeerRecord) {
+        if record.public_key == self.config.public_key {
+            return;
+        }
+        {
+            let mut records = self.records.write();
+            match records.get(&record.public_key) {
+                Some((known_address, known))
+                    if known.timestamp > record.timestamp
+                        || (known.timestamp == record.timestamp && *known_address == address) =>
+                {
+                    return
+                }
+                _ => records.insert(record.public_key.clone(), (address, record.clone())),
+            };
+        }
+        let name = self
+            .known_peers
+            .read()
+            .await
+            .into_iter()
+            .find(|peer| peer.public_key == record.public_key)
+            .map_or_else(|| record.public_key.to_string(), |peer| peer.name);
+        self.known_peers
+            .add_or_replace(Peer {
+                public_key: record.public_key,
+                name,
+                address: SocketAddrV4::new(address, record.ports[PORT_KEY]),
+                ports: record.ports.into_iter().collect(),
+                message: record.message,
+                recently_seen_timestamp: record.timestamp,
+            })
+            .await;
+    }
+
+    /// Returns the records of the peers closest to `target`, except `requester`.
+    ///
+    /// Only the records of the confirmed addresses are relayed.
+    fn closest(&self, target: &Hash256, requester: &PublicKey) -> Vec<(Ipv4Addr, PeerRecord)> {
+        let mut records = self
+            .records
+            .read()
+            .values()
+            .filter(|(_, record)| &record.public_key != requester)
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by_cached_key(|(_, record)| distance(&node_key(&record.public_key), target));
+        records.truncate(K);
+        records
     }
 
-    /// Lets the kademlia know about the known peers,
-    /// so that it can initiate the bootstrap process later.
-    async fn add_known_peers_to_routing_table(
-        swarm: &mut Swarm<DiscoveryBehaviour>,
-        shared_known_peers: &SharedKnownPeers,
+    /// Queries the peer of `public_key` at `address`, confirming the address
+    /// by authenticating the peer.
+    async fn find_node(
+        &self,
+        address: SocketAddrV4,
+        public_key: &PublicKey,
+        target: Hash256,
     ) -> Result<(), Error> {
-        let known_peers = shared_known_peers.lock.read().await;
-        for peer in known_peers.iter() {
-            let address: Multiaddr =
-                format!("/ip4/{}/tcp/{}", peer.address.ip(), peer.address.port()).parse()?;
-            swarm
-                .behaviour_mut()
-                .kademlia
-                .add_address(&get_peer_id(peer)?, address);
+        let request = FindNodeRequest {
+            sender: self.record()?,
+            target,
+        };
+        let mut connection =
+            AuthenticatedConnection::connect(&self.config, address, Some(public_key)).await?;
+        let response: FindNodeResponse = tokio::time::timeout(REQUEST_TIMEOUT, async {
+            connection.send(&request).await?;
+            connection.recv().await
+        })
+        .await
+        .map_err(|_| eyre!("timeout"))??;
+        if &response.sender.public_key != connection.peer() {
+            return Err(eyre!(
+                "record of another peer: {}",
+                response.sender.public_key
+            ));
+        }
+        if !self.is_known(&response.sender) {
+            response.sender.verify(&self.config)?;
+        }
+        self.add_record(*address.ip(), response.sender).await;
+        for (ip, record) in response.closest {
+            // Skips the verification of the already known records, which are most of them.
+            if self.is_outdated(&record) {
+                continue;
+            }
+            if let Err(e) = record.verify(&self.config) {
+                log::debug!("invalid record from {}: {}", address, e);
+                continue;
+            }
+            self.add_hint(ip, record).await;
         }
         Ok(())
     }
 
-    async fn add_new_peer(
-        info: identify::Info,
-        shared_known_peers: &SharedKnownPeers,
-    ) -> Result<(), Error> {
-        let public_key = convert_public_key(&info.public_key)?;
-        // Todo: `is_loopback` is for testing only.
-        //        We should take only public IPs. Remove it in the production.
-        let public_ip_addr = info
-            .listen_addrs
-            .iter()
-            .filter_map(|multiaddr| convert_multiaddr_into_sockv4(multiaddr.to_owned()).ok())
-            .find(|address| global_v4(address.ip()) || address.ip().is_loopback())
-            .ok_or_else(|| eyre!("no public ip address found"))?;
-        let (message, ports) = serde_spb::from_str(&info.agent_version)?;
-        let peer = Peer {
-            public_key,
-            address: public_ip_addr,
+    /// Iteratively queries the closest peers to `target` until no closer peer is found.
+    ///
+    /// The hints are queried along with the known peers, all at once since they are
+    /// only to be confirmed, and are discarded once queried.
+    async fn lookup(&self, target: Hash256) {
+        let mut queried = HashSet::new();
+        loop {
+            let mut candidates = self
+                .known_peers
+                .read()
+                .await
+                .into_iter()
+                .filter(|peer| {
+                    peer.public_key != self.config.public_key
+                        && self.config.members.contains(&peer.public_key)
+                })
+                .map(|peer| (peer.public_key, peer.address))
+                .collect::<Vec<_>>();
+            candidates
+                .sort_by_cached_key(|(public_key, _)| distance(&node_key(public_key), &target));
+            let mut batch = candidates
+                .into_iter()
+                .take(K)
+                .filter(|candidate| !queried.contains(candidate))
+                .take(ALPHA)
+                .collect::<Vec<_>>();
+            let hints = self
+                .hints
+                .read()
+                .values()
+                .map(|(ip, record)| {
+                    (
+                        record.public_key.clone(),
+                        SocketAddrV4::new(*ip, record.ports[PORT_KEY]),
+                    )
+                })
+                .filter(|candidate| !queried.contains(candidate) && !batch.contains(candidate))
+                .collect::<Vec<_>>();
+            batch.extend(hints);
+            if batch.is_empty() {
+                return;
+            }
+            queried.extend(batch.iter().cloned());
+            future::join_all(batch.iter().map(|(public_key, address)| async move {
+                if let Err(e) = self.find_node(*address, public_key, target).await {
+                    log::debug!("failed to query {} ({}): {}", public_key, address, e);
+                }
+                let mut hints = self.hints.write();
+                if matches!(
+                    hints.get(public_key),
+                    Some((ip, record)) if SocketAddrV4::new(*ip, record.ports[PORT_KEY]) == *address
+                ) {
+                    hints.remove(public_key);
+                }
+            }))
+            .await;
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        let address = match stream.peer_addr()? {
+            SocketAddr::V4(address) => *address.ip(),
+            SocketAddr::V6(address) => address
+                .ip()
+                .to_ipv4()
+                .ok_or_else(|| eyre!("not an ipv4 address: {}", address))?,
+        };
+        let mut connection = AuthenticatedConnection::accept(&self.config, stream).await?;
+        let request: FindNodeRequest = tokio::time::timeout(REQUEST_TIMEOUT, connection.recv())
+            .await
+            .map_err(|_| eyre!("timeout"))??;
+        if &request.sender.public_key != connection.peer() {
+            return Err(eyre!(
+                "record of another peer: {}",
+                request.sender.public_key
+            ));
+        }
+        if !self.is_known(&request.sender) {
+            request.sender.verify(&self.config)?;
+        }
+        let requester = request.sender.public_key.clone();
+        // Adds the requester first, so that the concurrent requesters can find each other.
+        self.add_record(address, request.sender).await;
+        let response = FindNodeResponse {
+            sender: self.record()?,
+            closest: self.closest(&request.target, &requester),
+        };
+        connection.send(&response).await
+    }
+}
+
+pub(crate) struct PeerDiscoveryPrimitiveImpl;
+
+impl PeerDiscoveryPrimitiveImpl {
+    /// Binds the port and returns the protocol to run, which is what
+    /// [`PeerDiscoveryPrimitive::serve`] spawns.
+    ///
+    /// The port is released as soon as the returned future is dropped.
+    pub(crate) async fn run(
+        network_config: NetworkConfig,
+        message: String,
+        port_map: HashMap<String, u16>,
+        known_peers: SharedKnownPeers,
+    ) -> Result<impl Future<Output = Result<(), Error>> + Send, Error> {
+        let port = *network_config
+            .ports
+            .get(PORT_KEY)
+            .ok_or_else(|| eyre!("no port for the peer discovery"))?;
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await?;
+        // The non-member peers (e.g., the relays) are kept for the other modules,
+        // but never queried.
+        known_peers
+            .lock
+            .write()
+            .await
+            .retain(|peer| peer.public_key != network_config.public_key);
+        let mut ports = port_map.into_iter().collect::<BTreeMap<_, _>>();
+        ports.insert(PORT_KEY.to_owned(), port);
+        let state = Arc::new(State {
+            config: network_config,
             ports,
             message,
-            recently_seen_timestamp: Utc::now().timestamp_millis() as Timestamp,
+            record: Default::default(),
+            records: Default::default(),
+            hints: Default::default(),
+            known_peers,
+        });
+
+        let server = {
+            let state = Arc::clone(&state);
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    let state = Arc::clone(&state);
+                    tokio::spawn(async move {
+                        if let Err(e) = state.handle_connection(stream).await {
+                            log::debug!("failed to handle a discovery request: {}", e);
+                        }
+                    });
+                }
+            }
         };
-        shared_known_peers.add_or_replace(peer).await;
-        Ok(())
+        let client = async move {
+            loop {
+                let known = state.records.read().len();
+                state.lookup(node_key(&state.config.public_key)).await;
+                let interval = if state.records.read().len() > known {
+                    QUICK_DISCOVERY_INTERVAL
+                } else {
+                    DISCOVERY_INTERVAL
+                };
+                tokio::time::sleep(interval).await;
+            }
+        };
+        Ok(async move {
+            tokio::select! {
+                result = server => result,
+                result = client => result,
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl PeerDiscoveryPrimitive for PeerDiscoveryPrimitiveImpl {
+    async fn serve(
+        network_config: NetworkConfig,
+        message: String,
+        port_map: HashMap<String, u16>,
+        known_peers: SharedKnownPeers,
+    ) -> Result<JoinHandle<Result<(), Error>>, Error> {
+        Ok(tokio::spawn(
+            Self::run(network_config, message, port_map, known_peers).await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn generate_configs(size: usize) -> Vec<NetworkConfig> {
+        let keys = (0..size)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        keys.iter()
+            .map(|(public_key, private_key)| NetworkConfig {
+                network_id: "discovery".to_owned(),
+                ports: [(PORT_KEY.to_owned(), dispense_port())]
+                    .into_iter()
+                    .collect(),
+                members: keys.iter().map(|(x, _)| x.clone()).collect(),
+                public_key: public_key.clone(),
+                private_key: private_key.clone(),
+            })
+            .collect()
+    }
+
+    fn new_state(config: &NetworkConfig) -> State {
+        State {
+            config: config.clone(),
+            ports: config.ports.clone().into_iter().collect(),
+            message: String::new(),
+            record: Default::default(),
+            records: Default::default(),
+            hints: Default::default(),
+            known_peers: SharedKnownPeers::new_static(Vec::new()),
+        }
+    }
+
+    /// A relayed address is not trusted until the peer is authenticated on it.
+    #[tokio::test]
+    async fn confirm_relayed_address() {
+        setup_test();
+        let configs = generate_configs(2);
+        let state = new_state(&configs[0]);
+        let task = tokio::spawn(
+            PeerDiscoveryPrimitiveImpl::run(
+                configs[1].clone(),
+                String::new(),
+                Default::default(),
+                SharedKnownPeers::new_static(Vec::new()),
+            )
+            .await
+            .unwrap(),
+        );
+
+        let record =
+            PeerRecord::new(&configs[1], new_state(&configs[1]).ports, String::new()).unwrap();
+        state.add_hint("192.0.2.1".parse().unwrap(), record).await;
+        assert!(state.records.read().is_empty());
+        assert!(state.known_peers.read().await.is_empty());
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let record =
+            PeerRecord::new(&configs[1], new_state(&configs[1]).ports, String::new()).unwrap();
+        assert!(!state.is_outdated(&record));
+        state.add_hint(Ipv4Addr::LOCALHOST, record).await;
+        assert!(state.known_peers.read().await.is_empty());
+
+        state.lookup(node_key(&configs[0].public_key)).await;
+        assert!(state.hints.read().is_empty());
+        let known_peers = state.known_peers.read().await;
+        assert_eq!(known_peers.len(), 1);
+        assert_eq!(known_peers[0].public_key, configs[1].public_key);
+        assert_eq!(
+            known_peers[0].address,
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, configs[1].ports[PORT_KEY])
+        );
+        task.abort();
     }
 }
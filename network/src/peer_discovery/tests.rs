@@ -1,4 +1,5 @@
 use super::primitive::PeerDiscoveryPrimitiveImpl;
+use super::{PeerDiscoveryImpl, PORT_KEY};
 use crate::{primitives::PeerDiscoveryPrimitive, *};
 use simperby_common::crypto::*;
 
@@ -107,10 +108,9 @@ impl TestNet {
     fn new() -> Self {
         let keystore = KeyStore::new();
         let (dummy_pubkey, dummy_privkey) = generate_keypair(DeterministicRng::new(0).get_bytes(1));
-        let dummy_port = Some(1);
         let default_network_config = NetworkConfig {
             network_id: format!("test-{}", thread_rng().gen::<u32>()),
-            port: dummy_port,
+            ports: HashMap::new(),
             members: keystore
                 .store
                 .iter()
@@ -160,17 +160,17 @@ impl TestNet {
         let port = get_port().await;
         let (public_key, private_key) = self.keystore.generate_keypair();
         let network_config = NetworkConfig {
-            port: Some(port),
+            ports: [(PORT_KEY.to_owned(), port)].into_iter().collect(),
             public_key,
             private_key,
             ..self.default_network_config.to_owned()
         };
-        let initially_known_peers = self.get_initially_known_peers();
-        let (shared_known_peers, handle) = PeerDiscoveryPrimitiveImpl::serve(
+        let shared_known_peers = SharedKnownPeers::new_static(self.get_initially_known_peers());
+        let handle = PeerDiscoveryPrimitiveImpl::serve(
             network_config.clone(),
             "".to_owned(),
             Default::default(),
-            initially_known_peers,
+            shared_known_peers.clone(),
         )
         .await
         .unwrap();
@@ -192,13 +192,9 @@ impl TestNet {
                     .rem_euclid(self.nodes.len() as u64)
             })
             .map(|peer_index| self.nodes[peer_index as usize].network_config.to_owned())
-            .map(|network_config| {
-                (
-                    network_config.public_key,
-                    network_config.port.expect("binding port was not provided"),
-                )
-            })
+            .map(|network_config| (network_config.public_key, network_config.ports[PORT_KEY]))
             .map(|(pubkey, port)| Peer {
+                name: pubkey.to_string(),
                 public_key: pubkey,
                 address: format!("127.0.0.1:{}", port).parse().unwrap(),
                 message: String::new(),
@@ -225,7 +221,7 @@ impl TestNet {
             .timestamp_millis()
             .checked_sub(PERMITTED_ERROR_FOR_PEER_DISCOVERY as i64)
             .unwrap();
-        recent <= peer.recently_seen_timestamp as i64
+        recent <= peer.recently_seen_timestamp
     }
 
     fn panic_if_recently_seen_peers_incorrect(&self, recently_seen_peers: Vec<&Peer>) {
@@ -326,3 +322,93 @@ async fn arbitrary_join_2() {
     wait_ms(3_000).await;
     testnet.panic_if_discovery_failed().await;
 }
+
+/// The known peers are persisted to the storage and loaded again, discarding non-members.
+#[tokio::test(flavor = "multi_thread")]
+async fn persist_known_peers() {
+    let mut keystore = KeyStore::new();
+    let network_id = format!("test-{}", thread_rng().gen::<u32>());
+    let mut configs = Vec::new();
+    for _ in 0..2 {
+        let (public_key, private_key) = keystore.generate_keypair();
+        configs.push(NetworkConfig {
+            network_id: network_id.clone(),
+            ports: [(PORT_KEY.to_owned(), get_port().await)]
+                .into_iter()
+                .collect(),
+            members: Vec::new(),
+            public_key,
+            private_key,
+        });
+    }
+    let members = configs
+        .iter()
+        .map(|config| config.public_key.clone())
+        .collect::<Vec<_>>();
+    for config in &mut configs {
+        config.members = members.clone();
+    }
+    let peer = |public_key: PublicKey, port: u16| Peer {
+        name: public_key.to_string(),
+        public_key,
+        address: format!("127.0.0.1:{port}").parse().unwrap(),
+        message: String::new(),
+        ports: HashMap::new(),
+        recently_seen_timestamp: 0,
+    };
+    let directories = (0..2)
+        .map(|_| format!("{}/discovery", simperby_test_suite::create_temp_dir()))
+        .collect::<Vec<_>>();
+    for directory in &directories {
+        PeerDiscoveryImpl::create(directory).await.unwrap();
+    }
+    assert!(PeerDiscoveryImpl::create(&directories[0]).await.is_err());
+
+    let handle_0 = PeerDiscoveryImpl::serve(
+        &directories[0],
+        &configs[0],
+        SharedKnownPeers::new_static(Vec::new()),
+    )
+    .await
+    .unwrap();
+    let (non_member, _) = keystore.generate_keypair();
+    let known_peers_1 = SharedKnownPeers::new_static(vec![
+        peer(configs[0].public_key.clone(), configs[0].ports[PORT_KEY]),
+        peer(non_member, 1),
+    ]);
+    let handle_1 = PeerDiscoveryImpl::serve(&directories[1], &configs[1], known_peers_1.clone())
+        .await
+        .unwrap();
+    // Waits until the peer is discovered, and then for it to be persisted.
+    for _ in 0..30 {
+        let discovered = known_peers_1
+            .read()
+            .await
+            .iter()
+            .any(|peer| peer.recently_seen_timestamp > 0);
+        if discovered {
+            break;
+        }
+        wait_ms(1_000).await;
+    }
+    wait_ms(11_000).await;
+    for handle in [handle_0, handle_1] {
+        handle.abort();
+        let _ = handle.await;
+    }
+
+    let stored_peers = PeerDiscoveryImpl::read_known_peers(&directories[1])
+        .await
+        .unwrap();
+    assert_eq!(stored_peers.len(), 1);
+    assert_eq!(stored_peers[0].public_key, configs[0].public_key);
+    assert!(stored_peers[0].recently_seen_timestamp > 0);
+    assert_eq!(stored_peers[0].ports[PORT_KEY], configs[0].ports[PORT_KEY]);
+
+    // The restarted node starts with the stored peers.
+    let known_peers = SharedKnownPeers::new_static(Vec::new());
+    let _handle = PeerDiscoveryImpl::serve(&directories[1], &configs[1], known_peers.clone())
+        .await
+        .unwrap();
+    assert_eq!(known_peers.read().await, stored_peers);
+}
@@ -20,6 +20,8 @@ pub trait Storage: Send + Sync + 'static {
     async fn list_files(&self) -> Result<Vec<String>, StorageError>;
 
     /// Adds the given file to the storage.
+    ///
+    /// It is atomic; even on a crash, the file is either the old one or the new one.
     async fn add_or_overwrite_file(
         &mut self,
         name: &str,
@@ -29,11 +31,23 @@ pub trait Storage: Send + Sync + 'static {
     /// Reads the given file.
     async fn read_file(&self, name: &str) -> Result<String, StorageError>;
 
+    /// Reads all the files at once, with their names.
+    ///
+    /// A file that can't be read by itself (e.g., not a valid UTF-8) comes with its own error,
+    /// so that the caller can quarantine it and keep the others.
+    #[allow(clippy::type_complexity)]
+    async fn read_all_files(
+        &self,
+    ) -> Result<Vec<(String, Result<String, StorageError>)>, StorageError>;
+
     /// Removes the given file.
     async fn remove_file(&mut self, name: &str) -> Result<(), StorageError>;
 
     /// Removes all files.
     async fn remove_all_files(&mut self) -> Result<(), StorageError>;
+
+    /// Moves the given (possibly corrupted) file out of the list, keeping it aside for inspection.
+    async fn quarantine_file(&mut self, name: &str) -> Result<(), StorageError>;
 }
 
 #[async_trait]
@@ -41,32 +55,42 @@ pub trait PeerDiscoveryPrimitive: Send + Sync + 'static {
     /// Remains online on the network indefinitely,
     /// responding to discovery requests from other nodes,
     /// updating `known_peers`.
+    ///
+    /// - The peers in `known_peers` are used as the initially known peers.
+    /// - `port_map` is advertised to the other nodes along with `message`.
     async fn serve(
         network_config: NetworkConfig,
         message: String,
         port_map: HashMap<String, u16>,
-        initially_known_peers: Vec<Peer>,
-    ) -> Result<(SharedKnownPeers, tokio::task::JoinHandle<Result<(), Error>>), Error>;
+        known_peers: SharedKnownPeers,
+    ) -> Result<tokio::task::JoinHandle<Result<(), Error>>, Error>;
 }
 
 /// The p2p gossip network.
+///
+/// Messages are separated by the topic (e.g., the DMS key);
+/// a node receives only the messages of the topic that it serves.
 #[async_trait]
 pub trait GossipNetwork: Send + Sync + 'static {
-    /// Broadcasts a message to the network.
+    /// Broadcasts a message of the topic to the network.
     async fn broadcast(
         config: &NetworkConfig,
         known_peers: &[Peer],
+        topic: &str,
         message: Vec<u8>,
     ) -> Result<(), Error>;
 
     /// Remains online on the network indefinitely,
-    /// serving (propagating) messages broadcasted over the network.
+    /// serving (propagating) messages of the topic broadcasted over the network.
+    ///
+    /// Each message is received with the address of the peer that forwarded it to this node.
     async fn serve(
         config: NetworkConfig,
+        topic: String,
         peers: SharedKnownPeers,
     ) -> Result<
         (
-            mpsc::Receiver<Vec<u8>>,
+            mpsc::Receiver<(std::net::IpAddr, Vec<u8>)>,
             tokio::task::JoinHandle<Result<(), Error>>,
         ),
         Error,
@@ -80,6 +104,7 @@ impl GossipNetwork for DummyGossipNetwork {
     async fn broadcast(
         _config: &NetworkConfig,
         _known_peers: &[Peer],
+        _topic: &str,
         _message: Vec<u8>,
     ) -> Result<(), Error> {
         Ok(())
@@ -87,10 +112,11 @@ impl GossipNetwork for DummyGossipNetwork {
 
     async fn serve(
         _config: NetworkConfig,
+        _topic: String,
         _peers: SharedKnownPeers,
     ) -> Result<
         (
-            mpsc::Receiver<Vec<u8>>,
+            mpsc::Receiver<(std::net::IpAddr, Vec<u8>)>,
             tokio::task::JoinHandle<Result<(), Error>>,
         ),
         Error,
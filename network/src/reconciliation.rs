@@ -0,0 +1,303 @@
+//! A range-based set reconciliation over the sorted hashes of the items.
+//!
+//! The client sends the fingerprints of ranges of its hash set, and the server answers
+//! for each range whether it has the same items.
+//! A differing range is split by the client into smaller ones,
+//! until it is small enough to send the hashes themselves,
+//! so that each side learns exactly which items the other is missing.
+//! It takes `O(d log n)` of the traffic for `d` differences in `n` items,
+//! instead of `O(n)` for exchanging the whole set.
+use super::*;
+use eyre::eyre;
+use simperby_common::*;
+
+/// A range small enough to be sent as the hashes themselves, instead of a fingerprint.
+const LEAF_SIZE: usize = 16;
+/// The number of sub-ranges that a differing range is split into.
+const BRANCHING_FACTOR: usize = 16;
+
+/// A range of hashes, `[start, end)`, where `None` means unbounded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct HashRange {
+    pub start: Option<Hash256>,
+    pub end: Option<Hash256>,
+}
+
+impl HashRange {
+    pub fn full() -> Self {
+        Self {
+            start: None,
+            end: None,
+        }
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        !matches!(self.start, Some(start) if start > *hash)
+            && !matches!(self.end, Some(end) if end <= *hash)
+    }
+
+    /// Returns the indices of the sorted hashes that belong to this range.
+    fn indices(&self, sorted_hashes: &[Hash256]) -> std::ops::Range<usize> {
+        let start = self
+            .start
+            .map(|start| sorted_hashes.partition_point(|x| x < &start))
+            .unwrap_or(0);
+        let end = self
+            .end
+            .map(|end| sorted_hashes.partition_point(|x| x < &end))
+            .unwrap_or(sorted_hashes.len());
+        start..end.max(start)
+    }
+
+    fn slice<'a>(&self, sorted_hashes: &'a [Hash256]) -> &'a [Hash256] {
+        &sorted_hashes[self.indices(sorted_hashes)]
+    }
+}
+
+fn fingerprint(sorted_hashes: &[Hash256]) -> Hash256 {
+    Hash256::hash(serde_spb::to_vec(&sorted_hashes).unwrap())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum RangeQuery {
+    /// Asks whether the server has the same items in the range.
+    Fingerprint {
+        range: HashRange,
+        count: usize,
+        fingerprint: Hash256,
+    },
+    /// Asks for the items in the range other than the given ones.
+    Hashes {
+        range: HashRange,
+        hashes: Vec<Hash256>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum RangeReply<T> {
+    /// The server has the same items in the range.
+    Equal,
+    /// The range differs; the client should split it.
+    Split,
+    /// The answer to [`RangeQuery::Hashes`].
+    Difference {
+        /// The items that the server has but the client doesn't.
+        items: Vec<T>,
+        /// The hashes of the items that the client has but the server doesn't.
+        missing: Vec<Hash256>,
+    },
+}
+
+/// Answers the queries of a client, with the server's items sorted by their hashes.
+pub(crate) fn respond<T: Clone>(
+    items: &[(Hash256, T)],
+    queries: Vec<RangeQuery>,
+) -> Vec<RangeReply<T>> {
+    let hashes = items.iter().map(|(hash, _)| *hash).collect::<Vec<_>>();
+    queries
+        .into_iter()
+        .map(|query| match query {
+            RangeQuery::Fingerprint {
+                range,
+                count,
+                fingerprint: fingerprint_,
+            } => {
+                let own = range.slice(&hashes);
+                if own.len() == count && fingerprint(own) == fingerprint_ {
+                    RangeReply::Equal
+                } else {
+                    RangeReply::Split
+                }
+            }
+            RangeQuery::Hashes {
+                range,
+                hashes: theirs,
+            } => {
+                let theirs = theirs
+                    .into_iter()
+                    .filter(|hash| range.contains(hash))
+                    .collect::<std::collections::BTreeSet<_>>();
+                let indices = range.indices(&hashes);
+                let own = &hashes[indices.clone()];
+                let items = items[indices]
+                    .iter()
+                    .filter(|(hash, _)| !theirs.contains(hash))
+                    .map(|(_, item)| item.clone())
+                    .collect();
+                let missing = theirs
+                    .into_iter()
+                    .filter(|hash| own.binary_search(hash).is_err())
+                    .collect();
+                RangeReply::Difference { items, missing }
+            }
+        })
+        .collect()
+}
+
+/// The client side of the reconciliation, which runs in rounds of
+/// [`Reconciler::queries`] and [`Reconciler::handle_replies`] until it is finished.
+pub(crate) struct Reconciler<T> {
+    hashes: Vec<Hash256>,
+    ranges: Vec<HashRange>,
+    items: Vec<T>,
+    missing: Vec<Hash256>,
+}
+
+impl<T> Reconciler<T> {
+    pub fn new(mut hashes: Vec<Hash256>) -> Self {
+        hashes.sort();
+        hashes.dedup();
+        Self {
+            hashes,
+            ranges: vec![HashRange::full()],
+            items: Vec::new(),
+            missing: Vec::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the queries of this round.
+    pub fn queries(&self) -> Vec<RangeQuery> {
+        self.ranges
+            .iter()
+            .map(|range| {
+                let own = range.slice(&self.hashes);
+                if own.len() <= LEAF_SIZE {
+                    RangeQuery::Hashes {
+                        range: range.clone(),
+                        hashes: own.to_vec(),
+                    }
+                } else {
+                    RangeQuery::Fingerprint {
+                        range: range.clone(),
+                        count: own.len(),
+                        fingerprint: fingerprint(own),
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Handles the replies to the queries of this round, preparing the next round.
+    pub fn handle_replies(&mut self, replies: Vec<RangeReply<T>>) -> Result<(), Error> {
+        if replies.len() != self.ranges.len() {
+            return Err(eyre!(
+                "expected {} replies, but {}",
+                self.ranges.len(),
+                replies.len()
+            ));
+        }
+        let mut next_ranges = Vec::new();
+        for (range, reply) in std::mem::take(&mut self.ranges).into_iter().zip(replies) {
+            let own = range.slice(&self.hashes);
+            match reply {
+                RangeReply::Equal => (),
+                RangeReply::Split => {
+                    if own.len() <= LEAF_SIZE {
+                        return Err(eyre!("split requested for a leaf range"));
+                    }
+                    // Splits at the own hashes, so that every sub-range gets smaller.
+                    let bounds = (1..BRANCHING_FACTOR)
+                        .map(|i| Some(own[i * own.len() / BRANCHING_FACTOR]))
+                        .collect::<Vec<_>>();
+                    let starts = std::iter::once(range.start).chain(bounds.iter().cloned());
+                    let ends = bounds.iter().cloned().chain(std::iter::once(range.end));
+                    next_ranges.extend(
+                        starts
+                            .zip(ends)
+                            .map(|(start, end)| HashRange { start, end }),
+                    );
+                }
+                RangeReply::Difference { items, missing } => {
+                    if missing.iter().any(|hash| own.binary_search(hash).is_err()) {
+                        return Err(eyre!("reported missing an unknown hash"));
+                    }
+                    self.items.extend(items);
+                    self.missing.extend(missing);
+                }
+            }
+        }
+        self.ranges = next_ranges;
+        Ok(())
+    }
+
+    /// Returns the items that the server has but the client doesn't,
+    /// and the hashes of the ones that the client has but the server doesn't.
+    pub fn finish(self) -> (Vec<T>, Vec<Hash256>) {
+        (self.items, self.missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn hashes(range: std::ops::Range<u64>) -> Vec<Hash256> {
+        range.map(|i| Hash256::hash(i.to_be_bytes())).collect()
+    }
+
+    /// Returns the result and the number of rounds.
+    fn reconcile(
+        client: Vec<Hash256>,
+        server: Vec<Hash256>,
+    ) -> (Vec<Hash256>, Vec<Hash256>, usize) {
+        let mut server = server.into_iter().map(|x| (x, x)).collect::<Vec<_>>();
+        server.sort();
+        let mut reconciler = Reconciler::new(client);
+        let mut rounds = 0;
+        while !reconciler.is_finished() {
+            let replies = respond(&server, reconciler.queries());
+            reconciler.handle_replies(replies).unwrap();
+            rounds += 1;
+        }
+        let (items, missing) = reconciler.finish();
+        (items, missing, rounds)
+    }
+
+    #[test]
+    fn equal_sets() {
+        let (items, missing, rounds) = reconcile(hashes(0..1000), hashes(0..1000));
+        assert!(items.is_empty());
+        assert!(missing.is_empty());
+        assert_eq!(rounds, 1);
+    }
+
+    #[test]
+    fn empty_sets() {
+        let (items, missing, _) = reconcile(Vec::new(), hashes(0..100));
+        assert_eq!(
+            items.into_iter().collect::<BTreeSet<_>>(),
+            hashes(0..100).into_iter().collect()
+        );
+        assert!(missing.is_empty());
+
+        let (items, missing, _) = reconcile(hashes(0..100), Vec::new());
+        assert!(items.is_empty());
+        assert_eq!(
+            missing.into_iter().collect::<BTreeSet<_>>(),
+            hashes(0..100).into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn few_differences() {
+        let mut client = hashes(0..1000);
+        let mut server = hashes(0..1000);
+        client.extend(hashes(2000..2003));
+        server.extend(hashes(3000..3005));
+        let (items, missing, rounds) = reconcile(client, server);
+        assert_eq!(
+            items.into_iter().collect::<BTreeSet<_>>(),
+            hashes(3000..3005).into_iter().collect()
+        );
+        assert_eq!(
+            missing.into_iter().collect::<BTreeSet<_>>(),
+            hashes(2000..2003).into_iter().collect()
+        );
+        assert!(rounds <= 4);
+    }
+}
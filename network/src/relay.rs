@@ -0,0 +1,655 @@
+//! A store-and-forward server of the DMS messages, for the members who are rarely online.
+//!
+//! A DMS instance keeps the messages of its current key only,
+//! so the members who come online after the others have moved on can't catch up from them.
+//! A relay keeps the messages of the recent DMS keys (i.e., of the current and the next few heights)
+//! and serves the same RPC as a DMS, so the members push their messages to it and fetch from it
+//! as they do with the other peers.
+//!
+//! A relay doesn't vote nor sign any message; it can be run by a mirror that is not a member,
+//! as long as the members trust its public key by `dms::Config::relays`.
+use super::*;
+use dms::{
+    serve_rpc_connection, Admission, DistributedMessageSetRpcInterface, Limits, MessageCommitment,
+    Metrics, RawMessage,
+};
+use eyre::eyre;
+use futures::prelude::*;
+use reconciliation::{RangeQuery, RangeReply};
+use simperby_common::*;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use tokio::net::TcpListener;
+
+/// The key of `NetworkConfig::ports` and `Peer::ports` for the relay,
+/// which serves every DMS key on the same port.
+pub const PORT_KEY: &str = "dms-relay";
+const STATE_FILE_PATH: &str = "_relay.json";
+/// The maximum length of a DMS key that a relay accepts.
+const MAX_KEY_LENGTH: usize = 128;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayConfig {
+    /// The network config of the relay, which must have the port of [`PORT_KEY`].
+    ///
+    /// The relay accepts the messages signed by the `members` only.
+    pub network_config: NetworkConfig,
+    /// The number of the DMS keys whose messages are retained, of the most recent heights.
+    ///
+    /// Note that there are two DMS keys (governance and consensus) for each height.
+    pub retained_keys: usize,
+    /// The height of the last finalized block, updated by [`Relay::set_finalized_height()`].
+    ///
+    /// The DMS keys below it are rejected.
+    pub finalized_height: BlockHeight,
+    /// The number of the heights above `finalized_height` whose DMS keys are accepted,
+    /// for the members who are ahead of the relay.
+    ///
+    /// It bounds the heights that a member can claim by a DMS key,
+    /// which otherwise would evict the keys of the actual heights.
+    pub max_heights_ahead: BlockHeight,
+    /// The limits on the messages from the peers, where the quota of a signer
+    /// applies to its messages in all the retained keys.
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+/// The retained DMS keys from the oldest height, stored in the storage.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct State {
+    keys: Vec<String>,
+}
+
+struct Inner<S> {
+    storage: S,
+    keys: Vec<String>,
+    messages: HashMap<String, BTreeMap<Hash256, RawMessage>>,
+}
+
+/// A relay of the DMS messages.
+///
+/// The messages of a new DMS key are accepted from any member,
+/// and the keys of the oldest heights are discarded when there are more than
+/// `RelayConfig::retained_keys`. A DMS key must be `<name>-<height>[-<suffix>]`,
+/// as the keys of the governance and the consensus are.
+/// The messages are verified against their DMS keys and signers, but not decoded,
+/// so a relay serves any type of DMS.
+pub struct Relay<S> {
+    config: RelayConfig,
+    inner: Arc<RwLock<Inner<S>>>,
+    admission: Arc<parking_lot::Mutex<Admission>>,
+}
+
+impl<S> Clone for Relay<S> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            inner: Arc::clone(&self.inner),
+            admission: Arc::clone(&self.admission),
+        }
+    }
+}
+
+fn file_name(dms_key: &str, hash: &Hash256) -> String {
+    format!("{dms_key}.{hash}.json")
+}
+
+fn check_key(dms_key: &str) -> Result<(), Error> {
+    if dms_key.is_empty()
+        || dms_key.len() > MAX_KEY_LENGTH
+        || !dms_key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(eyre!("invalid DMS key: {}", dms_key));
+    }
+    key_height(dms_key)?;
+    Ok(())
+}
+
+/// Parses the height of a DMS key, `<name>-<height>[-<suffix>]`.
+fn key_height(dms_key: &str) -> Result<BlockHeight, Error> {
+    dms_key
+        .split('-')
+        .nth(1)
+        .and_then(|height| height.parse().ok())
+        .ok_or_else(|| eyre!("no height in the DMS key: {}", dms_key))
+}
+
+/// The order of the DMS keys, from the oldest height.
+fn key_order(dms_key: &str) -> Result<(BlockHeight, &str), Error> {
+    Ok((key_height(dms_key)?, dms_key))
+}
+
+/// Sorts the DMS keys by [`key_order()`].
+fn sort_keys(keys: &mut Vec<String>) -> Result<(), Error> {
+    let mut ordered = keys
+        .iter()
+        .map(|key| Ok((key_height(key)?, key.clone())))
+        .collect::<Result<Vec<_>, Error>>()?;
+    ordered.sort();
+    *keys = ordered.into_iter().map(|(_, key)| key).collect();
+    Ok(())
+}
+
+impl<S: Storage> Relay<S> {
+    /// Creates a relay, loading the messages retained in the storage.
+    pub async fn new(mut storage: S, config: RelayConfig) -> Result<Self, Error> {
+        let mut state: State = match storage.read_file(STATE_FILE_PATH).await {
+            Ok(state) => serde_spb::from_str(&state)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(e.into()),
+        };
+        // The keys are checked when added, but not by an older version.
+        state.keys.retain(|key| match key_height(key) {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("discarding the messages of an invalid key: {}", e);
+                false
+            }
+        });
+        sort_keys(&mut state.keys)?;
+        let mut messages: HashMap<_, BTreeMap<_, _>> = state
+            .keys
+            .iter()
+            .map(|key| (key.clone(), Default::default()))
+            .collect();
+        let mut admission = Admission::new(config.limits.clone());
+        for (file, data) in storage.read_all_files().await? {
+            if file == STATE_FILE_PATH {
+                continue;
+            }
+            let key = file.split('.').next().unwrap_or_default().to_owned();
+            let set = if let Some(set) = messages.get_mut(&key) {
+                set
+            } else {
+                // Left by an interrupted discard.
+                storage.remove_file(&file).await?;
+                continue;
+            };
+            let message = data
+                .map_err(Error::from)
+                .and_then(|data| Ok(serde_spb::from_str::<RawMessage>(&data)?))
+                .and_then(|message| verify(&config, &key, &message).map(|_| message));
+            match message {
+                Ok(message) => {
+                    let hash = message.to_hash256();
+                    let size = serde_spb::to_string(&message)?.len();
+                    admission.count(hash, message.signature.signer(), size);
+                    set.insert(hash, message);
+                }
+                Err(e) => {
+                    log::warn!("quarantining a corrupted message file {}: {}", file, e);
+                    storage.quarantine_file(&file).await?;
+                }
+            }
+        }
+        Ok(Self {
+            config,
+            inner: Arc::new(RwLock::new(Inner {
+                storage,
+                keys: state.keys,
+                messages,
+            })),
+            admission: Arc::new(parking_lot::Mutex::new(admission)),
+        })
+    }
+
+    /// Returns the retained DMS keys, from the oldest height.
+    pub async fn keys(&self) -> Vec<String> {
+        self.inner.read().await.keys.clone()
+    }
+
+    /// Returns the messages retained for the given DMS key.
+    pub async fn read_messages(&self, dms_key: &str) -> Vec<RawMessage> {
+        self.inner
+            .read()
+            .await
+            .messages
+            .get(dms_key)
+            .map(|set| set.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the counts of the messages received from the peers, with the banned peers.
+    pub fn metrics(&self) -> Metrics {
+        self.admission.lock().metrics()
+    }
+
+    /// Replaces the members whose messages are accepted, as the reserved state changes by heights.
+    ///
+    /// It applies to the connections accepted in the next `serve()`.
+    pub fn set_members(&mut self, members: Vec<PublicKey>) {
+        self.config.network_config.members = members;
+    }
+
+    /// Updates the height of the last finalized block, moving the window of the accepted keys.
+    pub fn set_finalized_height(&mut self, finalized_height: BlockHeight) {
+        self.config.finalized_height = finalized_height;
+    }
+
+    /// Verifies and adds the messages pushed by a peer, enforcing the limits.
+    async fn receive_messages(
+        &self,
+        dms_key: String,
+        messages: Vec<RawMessage>,
+        source: IpAddr,
+    ) -> Result<(), Error> {
+        check_key(&dms_key)?;
+        let height = key_height(&dms_key)?;
+        let max_height = self
+            .config
+            .finalized_height
+            .saturating_add(self.config.max_heights_ahead);
+        if height < self.config.finalized_height || height > max_height {
+            return Err(eyre!(
+                "the DMS key is not of the heights from {} to {}: {}",
+                self.config.finalized_height,
+                max_height,
+                dms_key
+            ));
+        }
+        self.admission.lock().check_peer(source, messages.len())?;
+        let mut inner = self.inner.write().await;
+        if !inner.messages.contains_key(&dms_key) {
+            // It would be discarded right away.
+            if inner.keys.len() >= self.config.retained_keys.max(1)
+                && key_order(&dms_key)? < key_order(&inner.keys[0])?
+            {
+                return Err(eyre!(
+                    "the DMS key is older than the retained ones: {}",
+                    dms_key
+                ));
+            }
+        }
+        for message in messages {
+            let hash = message.to_hash256();
+            if let Err(e) = verify(&self.config, &dms_key, &message) {
+                self.admission.lock().reject(Some(source), hash, false);
+                return Err(e);
+            }
+            if matches!(inner.messages.get(&dms_key), Some(set) if set.contains_key(&hash)) {
+                continue;
+            }
+            let size = serde_spb::to_string(&message)?.len();
+            self.admission
+                .lock()
+                .admit(hash, message.signature.signer(), size)?;
+            if !inner.messages.contains_key(&dms_key) {
+                self.add_key(&mut inner, dms_key.clone()).await?;
+            }
+            inner
+                .storage
+                .add_or_overwrite_file(&file_name(&dms_key, &hash), serde_spb::to_string(&message)?)
+                .await?;
+            inner
+                .messages
+                .get_mut(&dms_key)
+                .expect("just added")
+                .insert(hash, message);
+        }
+        Ok(())
+    }
+
+    /// Adds a new DMS key, discarding the ones of the oldest heights beyond the retention.
+    async fn add_key(&self, inner: &mut Inner<S>, dms_key: String) -> Result<(), Error> {
+        inner.keys.push(dms_key.clone());
+        sort_keys(&mut inner.keys)?;
+        inner.messages.insert(dms_key, Default::default());
+        let excess = inner
+            .keys
+            .len()
+            .saturating_sub(self.config.retained_keys.max(1));
+        let discarded = inner.keys.drain(..excess).collect::<Vec<_>>();
+        // The state is written first, so that an interrupted discard leaves no retained key
+        // with missing messages.
+        let state = State {
+            keys: inner.keys.clone(),
+        };
+        inner
+            .storage
+            .add_or_overwrite_file(STATE_FILE_PATH, serde_spb::to_string(&state)?)
+            .await?;
+        if discarded.is_empty() {
+            return Ok(());
+        }
+        for key in discarded {
+            let set = inner.messages.remove(&key).unwrap_or_default();
+            for hash in set.keys() {
+                inner.storage.remove_file(&file_name(&key, hash)).await?;
+            }
+        }
+        // Recounts the quotas for the retained messages only.
+        let mut admission = self.admission.lock();
+        admission.clear_usage();
+        for message in inner.messages.values().flat_map(|set| set.values()) {
+            let size = serde_spb::to_string(message)?.len();
+            admission.count(message.to_hash256(), message.signature.signer(), size);
+        }
+        Ok(())
+    }
+
+    /// Serves the RPC of the DMS for the peers.
+    ///
+    /// TODO: currently it just returns itself after the given time.
+    pub async fn serve(self, time_in_ms: u64) -> Result<Self, Error> {
+        let port = *self
+            .config
+            .network_config
+            .ports
+            .get(PORT_KEY)
+            .ok_or_else(|| eyre!("`ports` has no field of {}", PORT_KEY))?;
+        let tasks = vec![
+            self.clone().serve_rpc(port).boxed(),
+            tokio::time::sleep(std::time::Duration::from_millis(time_in_ms))
+                .map(|_| Ok(()))
+                .boxed(),
+        ];
+        // The RPC server drops here, after the given time.
+        future::select_all(tasks).await.0?;
+        Ok(self)
+    }
+
+    async fn serve_rpc(self, rpc_port: u16) -> Result<(), Error> {
+        let network_config = Arc::new(self.config.network_config.clone());
+        let listener = TcpListener::bind(("0.0.0.0", rpc_port)).await?;
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let network_config = Arc::clone(&network_config);
+            let object = RelayConnection {
+                relay: self.clone(),
+                peer_address: address.ip(),
+            };
+            tokio::spawn(async move {
+                if let Err(e) = serve_rpc_connection(&network_config, stream, &object).await {
+                    log::warn!("failed to serve an RPC connection: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Checks that the message is signed by a member for the DMS key.
+fn verify(config: &RelayConfig, dms_key: &str, message: &RawMessage) -> Result<(), Error> {
+    let signer = message.signature.signer();
+    if !config.network_config.members.contains(signer) {
+        return Err(eyre!("the signer is not a member: {}", signer));
+    }
+    message.signature.verify(&MessageCommitment {
+        dms_key: dms_key.to_owned(),
+        data: message.data.clone(),
+    })?;
+    Ok(())
+}
+
+struct RelayConnection<S> {
+    relay: Relay<S>,
+    /// The address of the peer of the connection.
+    peer_address: IpAddr,
+}
+
+#[async_trait]
+impl<S: Storage> DistributedMessageSetRpcInterface for RelayConnection<S> {
+    async fn reconcile(
+        &self,
+        dms_key: String,
+        queries: Vec<RangeQuery>,
+    ) -> Result<Vec<RangeReply<RawMessage>>, String> {
+        self.relay
+            .admission
+            .lock()
+            .check_peer(self.peer_address, 0)
+            .map_err(|e| e.to_string())?;
+        // An unknown key is served as an empty set, to which the peer pushes its messages.
+        let inner = self.relay.inner.read().await;
+        let messages = inner
+            .messages
+            .get(&dms_key)
+            .map(|set| {
+                set.iter()
+                    .map(|(hash, message)| (*hash, message.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        Ok(reconciliation::respond(&messages, queries))
+    }
+
+    async fn add_messages(&self, dms_key: String, messages: Vec<RawMessage>) -> Result<(), String> {
+        self.relay
+            .receive_messages(dms_key, messages, self.peer_address)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dms::{DistributedMessageSet, Message};
+    use crate::primitives::DummyGossipNetwork;
+    use crate::storage::StorageImpl;
+    use simperby_test_suite::*;
+
+    type Dms = DistributedMessageSet<DummyGossipNetwork, StorageImpl, String>;
+
+    async fn create_storage() -> (String, StorageImpl) {
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let storage = StorageImpl::open(&path).await.unwrap();
+        (path, storage)
+    }
+
+    fn network_config(
+        network_id: &str,
+        members: &[(PublicKey, PrivateKey)],
+        (public_key, private_key): (PublicKey, PrivateKey),
+    ) -> NetworkConfig {
+        NetworkConfig {
+            network_id: network_id.to_owned(),
+            ports: Default::default(),
+            members: members.iter().map(|(x, _)| x.clone()).collect(),
+            public_key,
+            private_key,
+        }
+    }
+
+    fn message(dms_key: &str, data: &str, private_key: &PrivateKey) -> RawMessage {
+        RawMessage::from_message(Message::sign(dms_key, data.to_owned(), private_key).unwrap())
+    }
+
+    #[tokio::test]
+    async fn store_and_forward() {
+        setup_test();
+        let members = (0..2)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        let relay_key = generate_keypair_random();
+        let port = dispense_port();
+        let mut relay_network_config = network_config("relay", &members, relay_key.clone());
+        relay_network_config.ports.insert(PORT_KEY.to_owned(), port);
+        let relay = Relay::new(
+            create_storage().await.1,
+            RelayConfig {
+                network_config: relay_network_config,
+                retained_keys: 4,
+                finalized_height: 1,
+                max_heights_ahead: 1,
+                limits: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+        let relay_peer = Peer {
+            public_key: relay_key.0.clone(),
+            name: "relay".to_owned(),
+            address: SocketAddrV4::new("127.0.0.1".parse().unwrap(), port),
+            ports: [(PORT_KEY.to_owned(), port)].into_iter().collect(),
+            message: "".to_owned(),
+            recently_seen_timestamp: 0,
+        };
+        let dms = |i: usize| {
+            let config = network_config("relay", &members, members[i].clone());
+            let relay_public_key = relay_key.0.clone();
+            let peers = SharedKnownPeers::new_static(vec![relay_peer.clone()]);
+            async move {
+                Dms::new(
+                    create_storage().await.1,
+                    "height-1".to_owned(),
+                    dms::Config {
+                        name: "relay-test".to_owned(),
+                        fetch_interval: None,
+                        broadcast_interval: None,
+                        network_config: config,
+                        limits: Default::default(),
+                        // The members trust the relay, which is not a member.
+                        relays: vec![relay_public_key],
+                    },
+                    peers,
+                )
+                .await
+                .unwrap()
+            }
+        };
+        let relay_task = tokio::spawn(relay.serve(6000));
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+        // The first member pushes a message and goes offline.
+        let mut dms_0 = dms(0).await;
+        let message = Message::sign("height-1", "hello".to_owned(), &members[0].1).unwrap();
+        dms_0.add_message(message).await.unwrap();
+        dms_0.broadcast_all().await.unwrap();
+        drop(dms_0);
+
+        // The second member comes online later and catches up.
+        let mut dms_1 = dms(1).await;
+        dms_1.fetch().await.unwrap();
+        let messages = dms_1.read_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].data(), "hello");
+
+        let relay = relay_task.await.unwrap().unwrap();
+        assert_eq!(relay.keys().await, vec!["height-1".to_owned()]);
+        assert_eq!(relay.metrics().accepted, 1);
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        setup_test();
+        let member = generate_keypair_random();
+        let relay_key = generate_keypair_random();
+        let config = RelayConfig {
+            network_config: network_config(
+                "relay",
+                &[member.clone(), relay_key.clone()],
+                relay_key.clone(),
+            ),
+            retained_keys: 2,
+            finalized_height: 0,
+            max_heights_ahead: 3,
+            limits: Default::default(),
+        };
+        let (path, storage) = create_storage().await;
+        let relay = Relay::new(storage, config.clone()).await.unwrap();
+        let peer = RelayConnection {
+            relay,
+            peer_address: "127.0.0.1".parse().unwrap(),
+        };
+        // The keys are retained by their heights, not by their arrival.
+        for height in [0, 2, 1] {
+            let key = format!("height-{height}");
+            peer.add_messages(
+                key.clone(),
+                vec![message(&key, "a", &member.1), message(&key, "b", &member.1)],
+            )
+            .await
+            .unwrap();
+        }
+        // Signed for another key, by a non-member, or for an invalid key.
+        let (_, non_member) = generate_keypair_random();
+        peer.add_messages(
+            "height-2".to_owned(),
+            vec![message("height-1", "c", &member.1)],
+        )
+        .await
+        .unwrap_err();
+        peer.add_messages(
+            "height-2".to_owned(),
+            vec![message("height-2", "c", &non_member)],
+        )
+        .await
+        .unwrap_err();
+        peer.add_messages("../x".to_owned(), vec![message("../x", "c", &member.1)])
+            .await
+            .unwrap_err();
+        // Without a height, or older than the retained ones.
+        for key in ["height", "height-0"] {
+            peer.add_messages(key.to_owned(), vec![message(key, "c", &member.1)])
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(
+            peer.relay.keys().await,
+            vec!["height-1".to_owned(), "height-2".to_owned()]
+        );
+        drop(peer);
+
+        // The retained messages persist.
+        let relay = Relay::new(StorageImpl::open(&path).await.unwrap(), config)
+            .await
+            .unwrap();
+        assert_eq!(
+            relay.keys().await,
+            vec!["height-1".to_owned(), "height-2".to_owned()]
+        );
+        assert!(relay.read_messages("height-0").await.is_empty());
+        assert_eq!(relay.read_messages("height-2").await.len(), 2);
+        let files = relay.inner.read().await.storage.list_files().await.unwrap();
+        assert_eq!(files.len(), 5);
+    }
+
+    /// A member can't claim a far height to evict the keys of the actual heights.
+    #[tokio::test]
+    async fn reject_far_heights() {
+        setup_test();
+        let member = generate_keypair_random();
+        let relay_key = generate_keypair_random();
+        let config = RelayConfig {
+            network_config: network_config("relay", std::slice::from_ref(&member), relay_key),
+            retained_keys: 2,
+            finalized_height: 10,
+            max_heights_ahead: 1,
+            limits: Default::default(),
+        };
+        let mut relay = Relay::new(create_storage().await.1, config).await.unwrap();
+        let push = |relay: Relay<StorageImpl>, key: String| {
+            let message = message(&key, "a", &member.1);
+            async move {
+                RelayConnection {
+                    relay,
+                    peer_address: "127.0.0.1".parse().unwrap(),
+                }
+                .add_messages(key, vec![message])
+                .await
+            }
+        };
+        for key in ["height-10", "height-11"] {
+            push(relay.clone(), key.to_owned()).await.unwrap();
+        }
+        for key in ["height-18446744073709551615", "height-12", "height-9"] {
+            push(relay.clone(), key.to_owned()).await.unwrap_err();
+        }
+        assert_eq!(
+            relay.keys().await,
+            vec!["height-10".to_owned(), "height-11".to_owned()]
+        );
+        assert_eq!(relay.read_messages("height-10").await.len(), 1);
+
+        // The window moves as the blocks are finalized.
+        relay.set_finalized_height(11);
+        push(relay.clone(), "height-12".to_owned()).await.unwrap();
+        assert_eq!(
+            relay.keys().await,
+            vec!["height-11".to_owned(), "height-12".to_owned()]
+        );
+    }
+}
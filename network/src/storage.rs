@@ -1,9 +1,31 @@
+use crate::kv_storage::{self, KvStorage};
 use crate::primitives::{Storage, StorageError};
 use async_trait::async_trait;
 use fs2::FileExt;
 use futures::stream::*;
+use serde::{Deserialize, Serialize};
 use tokio::{fs, io::AsyncWriteExt, task::spawn_blocking};
 
+/// The prefix of the files being written, which are renamed to the actual names when complete.
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+/// The directory where the quarantined files are moved to.
+const QUARANTINE_DIRECTORY: &str = "quarantine";
+
+/// Makes the renames and the removals in the directory durable.
+async fn sync_directory(path: &str) -> Result<(), std::io::Error> {
+    // Directories can't be opened as files on Windows.
+    if cfg!(unix) {
+        let path = path.to_owned();
+        spawn_blocking(move || std::fs::File::open(path)?.sync_all()).await??;
+    }
+    Ok(())
+}
+
+/// A `Storage` on a directory of the local file system.
+///
+/// Every write goes to a temporary file first, which is synced and then renamed,
+/// so that a crash never leaves a partially written file.
+/// The temporary files left by a crash are removed when opened.
 pub struct StorageImpl {
     lock_file: Option<std::fs::File>,
     path: String,
@@ -31,10 +53,12 @@ impl Storage for StorageImpl {
             result.map(|_| file)
         })
         .await??;
-        Ok(Self {
+        let this = Self {
             lock_file: Some(file),
             path: storage_directory.to_owned(),
-        })
+        };
+        this.recover().await?;
+        Ok(this)
     }
 
     async fn list_files(&self) -> Result<Vec<String>, StorageError> {
@@ -44,11 +68,17 @@ impl Storage for StorageImpl {
             .await
             .into_iter()
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(files
-            .into_iter()
-            .map(|file| file.file_name().into_string().unwrap())
-            .filter(|file| file != "lock")
-            .collect())
+        let mut names = Vec::new();
+        for file in files {
+            if file.file_type().await?.is_dir() {
+                continue;
+            }
+            let name = file.file_name().into_string().unwrap();
+            if name != "lock" && !name.starts_with(TEMP_FILE_PREFIX) {
+                names.push(name);
+            }
+        }
+        Ok(names)
     }
 
     async fn add_or_overwrite_file(
@@ -56,17 +86,30 @@ impl Storage for StorageImpl {
         name: &str,
         content: String,
     ) -> Result<(), StorageError> {
-        let mut file = fs::File::create(format!("{}/{}", self.path, name)).await?;
+        let temp_path = format!("{}/{}{}", self.path, TEMP_FILE_PREFIX, name);
+        let mut file = fs::File::create(&temp_path).await?;
         file.write_all(content.as_bytes()).await?;
         // IMPORTANT!
         file.flush().await?;
-        Ok(())
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&temp_path, format!("{}/{}", self.path, name)).await?;
+        sync_directory(&self.path).await
     }
 
     async fn read_file(&self, name: &str) -> Result<String, StorageError> {
         fs::read_to_string(format!("{}/{}", self.path, name)).await
     }
 
+    async fn read_all_files(
+        &self,
+    ) -> Result<Vec<(String, Result<String, StorageError>)>, StorageError> {
+        let names = self.list_files().await?;
+        let contents =
+            futures::future::join_all(names.iter().map(|name| self.read_file(name))).await;
+        Ok(names.into_iter().zip(contents).collect())
+    }
+
     async fn remove_file(&mut self, name: &str) -> Result<(), StorageError> {
         fs::remove_file(format!("{}/{}", self.path, name)).await
     }
@@ -78,6 +121,37 @@ impl Storage for StorageImpl {
         }
         Ok(())
     }
+
+    async fn quarantine_file(&mut self, name: &str) -> Result<(), StorageError> {
+        let directory = format!("{}/{}", self.path, QUARANTINE_DIRECTORY);
+        fs::create_dir_all(&directory).await?;
+        fs::rename(
+            format!("{}/{}", self.path, name),
+            format!("{directory}/{name}"),
+        )
+        .await?;
+        sync_directory(&self.path).await
+    }
+}
+
+impl StorageImpl {
+    /// Removes the temporary files of the writes interrupted by a crash.
+    async fn recover(&self) -> Result<(), std::io::Error> {
+        let mut dir = fs::read_dir(&self.path).await?;
+        let mut removed = false;
+        while let Some(file) = dir.next_entry().await? {
+            let name = file.file_name().into_string().unwrap();
+            if name.starts_with(TEMP_FILE_PREFIX) {
+                log::warn!("removing an incomplete file in {}: {}", self.path, name);
+                fs::remove_file(file.path()).await?;
+                removed = true;
+            }
+        }
+        if removed {
+            sync_directory(&self.path).await?;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for StorageImpl {
@@ -91,6 +165,167 @@ impl Drop for StorageImpl {
     }
 }
 
+/// The format of a storage directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageKind {
+    /// A file for each, by `StorageImpl`.
+    #[default]
+    Directory,
+    /// A single embedded key-value database, by `KvStorage`.
+    KeyValue,
+}
+
+impl StorageKind {
+    /// Creates a new and empty storage of this kind, as `Storage::create()` does.
+    pub async fn create(self, storage_directory: &str) -> Result<(), StorageError> {
+        match self {
+            Self::Directory => StorageImpl::create(storage_directory).await,
+            Self::KeyValue => KvStorage::create(storage_directory).await,
+        }
+    }
+
+    /// Detects the kind of an existing storage.
+    pub async fn detect(storage_directory: &str) -> Result<Self, StorageError> {
+        fs::metadata(format!("{storage_directory}/lock")).await?;
+        if fs::metadata(format!("{storage_directory}/{}", kv_storage::DATABASE_FILE))
+            .await
+            .is_ok()
+        {
+            Ok(Self::KeyValue)
+        } else {
+            Ok(Self::Directory)
+        }
+    }
+}
+
+/// A storage of any kind, which is detected when opened.
+///
+/// `Storage::create()` creates one of `StorageKind::Directory`;
+/// use `StorageKind::create()` to choose the kind.
+pub enum AnyStorage {
+    Directory(StorageImpl),
+    KeyValue(KvStorage),
+}
+
+#[async_trait]
+impl Storage for AnyStorage {
+    async fn create(storage_directory: &str) -> Result<(), StorageError> {
+        StorageKind::Directory.create(storage_directory).await
+    }
+
+    async fn open(storage_directory: &str) -> Result<Self, StorageError>
+    where
+        Self: Sized,
+    {
+        Ok(match StorageKind::detect(storage_directory).await? {
+            StorageKind::Directory => Self::Directory(StorageImpl::open(storage_directory).await?),
+            StorageKind::KeyValue => Self::KeyValue(KvStorage::open(storage_directory).await?),
+        })
+    }
+
+    async fn list_files(&self) -> Result<Vec<String>, StorageError> {
+        match self {
+            Self::Directory(storage) => storage.list_files().await,
+            Self::KeyValue(storage) => storage.list_files().await,
+        }
+    }
+
+    async fn add_or_overwrite_file(
+        &mut self,
+        name: &str,
+        content: String,
+    ) -> Result<(), StorageError> {
+        match self {
+            Self::Directory(storage) => storage.add_or_overwrite_file(name, content).await,
+            Self::KeyValue(storage) => storage.add_or_overwrite_file(name, content).await,
+        }
+    }
+
+    async fn read_file(&self, name: &str) -> Result<String, StorageError> {
+        match self {
+            Self::Directory(storage) => storage.read_file(name).await,
+            Self::KeyValue(storage) => storage.read_file(name).await,
+        }
+    }
+
+    async fn read_all_files(
+        &self,
+    ) -> Result<Vec<(String, Result<String, StorageError>)>, StorageError> {
+        match self {
+            Self::Directory(storage) => storage.read_all_files().await,
+            Self::KeyValue(storage) => storage.read_all_files().await,
+        }
+    }
+
+    async fn remove_file(&mut self, name: &str) -> Result<(), StorageError> {
+        match self {
+            Self::Directory(storage) => storage.remove_file(name).await,
+            Self::KeyValue(storage) => storage.remove_file(name).await,
+        }
+    }
+
+    async fn remove_all_files(&mut self) -> Result<(), StorageError> {
+        match self {
+            Self::Directory(storage) => storage.remove_all_files().await,
+            Self::KeyValue(storage) => storage.remove_all_files().await,
+        }
+    }
+
+    async fn quarantine_file(&mut self, name: &str) -> Result<(), StorageError> {
+        match self {
+            Self::Directory(storage) => storage.quarantine_file(name).await,
+            Self::KeyValue(storage) => storage.quarantine_file(name).await,
+        }
+    }
+}
+
+/// Opens the storage, creating an empty one of the given kind only if there is none.
+///
+/// An existing storage of another kind is opened as it is; use `migrate()` to convert it.
+pub async fn open_or_create(
+    storage_directory: &str,
+    kind: StorageKind,
+) -> Result<AnyStorage, StorageError> {
+    match StorageKind::detect(storage_directory).await {
+        Ok(existing) if existing != kind => log::warn!(
+            "the storage {} is {:?}, not {:?} as configured; migrate it to change",
+            storage_directory,
+            existing,
+            kind
+        ),
+        Ok(_) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            kind.create(storage_directory).await?
+        }
+        Err(e) => return Err(e),
+    }
+    AnyStorage::open(storage_directory).await
+}
+
+/// Migrates an existing storage to the given kind, keeping its files (except the quarantined ones).
+///
+/// The new storage is built aside and then swapped in,
+/// so the original one is intact if it fails in the middle.
+pub async fn migrate(storage_directory: &str, kind: StorageKind) -> Result<(), StorageError> {
+    let storage = AnyStorage::open(storage_directory).await?;
+    if StorageKind::detect(storage_directory).await? == kind {
+        return Ok(());
+    }
+    let migrating_directory = format!("{storage_directory}.migrating");
+    kind.create(&migrating_directory).await?;
+    let mut new_storage = AnyStorage::open(&migrating_directory).await?;
+    for (name, content) in storage.read_all_files().await? {
+        new_storage.add_or_overwrite_file(&name, content?).await?;
+    }
+    drop(new_storage);
+    drop(storage);
+
+    let old_directory = format!("{storage_directory}.old");
+    fs::rename(storage_directory, &old_directory).await?;
+    fs::rename(&migrating_directory, storage_directory).await?;
+    fs::remove_dir_all(&old_directory).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,4 +398,85 @@ mod tests {
         // assert that files are removed
         assert_eq!(storage.list_files().await.unwrap().len(), 0);
     }
+
+    #[tokio::test]
+    async fn interrupted_write() {
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create(&dir).await.unwrap();
+        let mut storage = StorageImpl::open(&dir).await.unwrap();
+        storage
+            .add_or_overwrite_file("a", "old".to_owned())
+            .await
+            .unwrap();
+        // Simulates crashes in the middle of overwriting `a` and of adding `b`.
+        std::fs::write(format!("{dir}/{TEMP_FILE_PREFIX}a"), "ne").unwrap();
+        std::fs::write(format!("{dir}/{TEMP_FILE_PREFIX}b"), "{\"trunc").unwrap();
+        assert_eq!(storage.list_files().await.unwrap(), vec!["a".to_owned()]);
+        drop(storage);
+
+        let storage = StorageImpl::open(&dir).await.unwrap();
+        assert_eq!(storage.list_files().await.unwrap(), vec!["a".to_owned()]);
+        assert_eq!(storage.read_file("a").await.unwrap(), "old");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn quarantine() {
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create(&dir).await.unwrap();
+        let mut storage = StorageImpl::open(&dir).await.unwrap();
+        for name in ["a", "b"] {
+            storage
+                .add_or_overwrite_file(name, name.to_owned())
+                .await
+                .unwrap();
+        }
+        storage.quarantine_file("a").await.unwrap();
+        assert_eq!(storage.list_files().await.unwrap(), vec!["b".to_owned()]);
+        assert_eq!(
+            std::fs::read_to_string(format!("{dir}/{QUARANTINE_DIRECTORY}/a")).unwrap(),
+            "a"
+        );
+    }
+
+    #[tokio::test]
+    async fn migrate_to_key_value() {
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create(&dir).await.unwrap();
+        let mut storage = StorageImpl::open(&dir).await.unwrap();
+        for i in 0..10 {
+            storage
+                .add_or_overwrite_file(&format!("{i}"), format!("content {i}"))
+                .await
+                .unwrap();
+        }
+        drop(storage);
+
+        migrate(&dir, StorageKind::KeyValue).await.unwrap();
+        assert_eq!(
+            StorageKind::detect(&dir).await.unwrap(),
+            StorageKind::KeyValue
+        );
+        let storage = AnyStorage::open(&dir).await.unwrap();
+        assert!(matches!(storage, AnyStorage::KeyValue(_)));
+        assert_eq!(storage.list_files().await.unwrap().len(), 10);
+        for i in 0..10 {
+            assert_eq!(
+                storage.read_file(&format!("{i}")).await.unwrap(),
+                format!("content {i}")
+            );
+        }
+        drop(storage);
+
+        // Opening doesn't recreate nor convert the existing one.
+        let storage = open_or_create(&dir, StorageKind::Directory).await.unwrap();
+        assert!(matches!(storage, AnyStorage::KeyValue(_)));
+        assert_eq!(storage.list_files().await.unwrap().len(), 10);
+        drop(storage);
+
+        // Migrates back.
+        migrate(&dir, StorageKind::Directory).await.unwrap();
+        let storage = StorageImpl::open(&dir).await.unwrap();
+        assert_eq!(storage.list_files().await.unwrap().len(), 10);
+    }
 }
@@ -0,0 +1,440 @@
+use super::*;
+use eyre::eyre;
+use serde::de::DeserializeOwned;
+use simperby_common::*;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// The maximum size of a frame on the wire.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+/// The maximum size of a handshake frame, which is read before the other side is authenticated.
+const MAX_HANDSHAKE_FRAME_SIZE: usize = 4 * 1024;
+/// The timeout for connecting and completing the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Writes a length-prefixed frame of the parts, concatenated.
+async fn write_raw_frame(
+    stream: &mut TcpStream,
+    parts: &[&[u8]],
+    max_size: usize,
+) -> Result<(), Error> {
+    let size = parts.iter().map(|part| part.len()).sum::<usize>();
+    if size > max_size {
+        return Err(eyre!("message too large: {} bytes", size));
+    }
+    // Writes at once, to avoid the delay of the Nagle's algorithm between the writes.
+    let mut frame = Vec::with_capacity(4 + size);
+    frame.extend((size as u32).to_be_bytes());
+    for part in parts {
+        frame.extend(*part);
+    }
+    stream.write_all(&frame).await?;
+    Ok(())
+}
+
+/// Reads a frame written by [`write_raw_frame`].
+async fn read_raw_frame(stream: &mut TcpStream, max_size: usize) -> Result<Vec<u8>, Error> {
+    let size = stream.read_u32().await? as usize;
+    if size > max_size {
+        return Err(eyre!("message too large: {} bytes", size));
+    }
+    let mut buffer = vec![0; size];
+    stream.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+/// Writes a length-prefixed frame of the JSON-encoded message.
+pub(crate) async fn write_frame<T: Serialize>(
+    stream: &mut TcpStream,
+    message: &T,
+    max_size: usize,
+) -> Result<(), Error> {
+    let encoded = serde_spb::to_string(message)?;
+    write_raw_frame(stream, &[encoded.as_bytes()], max_size).await
+}
+
+/// Reads a frame written by [`write_frame`].
+pub(crate) async fn read_frame<T: DeserializeOwned>(
+    stream: &mut TcpStream,
+    max_size: usize,
+) -> Result<T, Error> {
+    let buffer = read_raw_frame(stream, max_size).await?;
+    Ok(serde_spb::from_str(std::str::from_utf8(&buffer)?)?)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Hello {
+    network_id: String,
+    public_key: PublicKey,
+    nonce: Hash256,
+    ephemeral_key: PublicKey,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Challenge {
+    public_key: PublicKey,
+    nonce: Hash256,
+    ephemeral_key: PublicKey,
+    signature: Signature,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChallengeResponse {
+    signature: Signature,
+}
+
+/// The parameters of a handshake, which both sides sign.
+struct Transcript<'a> {
+    network_id: &'a str,
+    initiator: &'a PublicKey,
+    responder: &'a PublicKey,
+    initiator_nonce: &'a Hash256,
+    responder_nonce: &'a Hash256,
+    initiator_ephemeral_key: &'a PublicKey,
+    responder_ephemeral_key: &'a PublicKey,
+}
+
+impl Transcript<'_> {
+    fn session(&self) -> Hash256 {
+        Hash256::hash(
+            serde_spb::to_vec(&(
+                self.network_id,
+                self.initiator,
+                self.responder,
+                self.initiator_nonce,
+                self.responder_nonce,
+                self.initiator_ephemeral_key,
+                self.responder_ephemeral_key,
+            ))
+            .unwrap(),
+        )
+    }
+
+    /// The hash that each side signs, distinguished by the role.
+    fn signing_hash(&self, role: &str) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(&(role, self.session())).unwrap())
+    }
+
+    /// Derives the key of the session from the ephemeral keys, which only the two sides know.
+    fn session_key(
+        &self,
+        ephemeral_private_key: &PrivateKey,
+        peer_ephemeral_key: &PublicKey,
+    ) -> Result<Hash256, Error> {
+        let secret = derive_shared_secret(ephemeral_private_key, peer_ephemeral_key)?;
+        Ok(Hash256::hash(
+            serde_spb::to_vec(&(secret, self.session())).unwrap(),
+        ))
+    }
+}
+
+fn check_member(config: &NetworkConfig, public_key: &PublicKey) -> Result<(), Error> {
+    if !config.members.contains(public_key) {
+        return Err(eyre!("not a member: {}", public_key));
+    }
+    Ok(())
+}
+
+/// A TCP connection between two members of the network, mutually authenticated by their keys.
+///
+/// In the handshake, each side signs the session, which consists of both public keys,
+/// fresh nonces and ephemeral keys, so a non-member is rejected before anything else is exchanged.
+/// The two sides then derive the session key from the ephemeral keys by ECDH.
+/// Every frame after that starts with a MAC of the session key, its sender and its sequence number,
+/// followed by the JSON-encoded message, so that it can't be forged, reordered,
+/// or replayed in another connection.
+///
+/// Note that the frames are not encrypted; they are authenticated only.
+pub struct AuthenticatedConnection {
+    stream: TcpStream,
+    key: Hash256,
+    public_key: PublicKey,
+    peer: PublicKey,
+    sent: u64,
+    received: u64,
+}
+
+impl AuthenticatedConnection {
+    /// Connects to a member at the address, expecting the given public key if any.
+    pub async fn connect(
+        config: &NetworkConfig,
+        address: std::net::SocketAddrV4,
+        expected_peer: Option<&PublicKey>,
+    ) -> Result<Self, Error> {
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+            let mut stream = TcpStream::connect(address).await?;
+            let (ephemeral_key, ephemeral_private_key) = generate_keypair_random();
+            let hello = Hello {
+                network_id: config.network_id.clone(),
+                public_key: config.public_key.clone(),
+                nonce: Hash256::from_array(rand::random()),
+                ephemeral_key,
+            };
+            write_frame(&mut stream, &hello, MAX_HANDSHAKE_FRAME_SIZE).await?;
+            let challenge: Challenge = read_frame(&mut stream, MAX_HANDSHAKE_FRAME_SIZE).await?;
+            check_member(config, &challenge.public_key)?;
+            if let Some(expected_peer) = expected_peer {
+                if expected_peer != &challenge.public_key {
+                    return Err(eyre!(
+                        "peer mismatch: expected {}, but {}",
+                        expected_peer,
+                        challenge.public_key
+                    ));
+                }
+            }
+            let transcript = Transcript {
+                network_id: &config.network_id,
+                initiator: &config.public_key,
+                responder: &challenge.public_key,
+                initiator_nonce: &hello.nonce,
+                responder_nonce: &challenge.nonce,
+                initiator_ephemeral_key: &hello.ephemeral_key,
+                responder_ephemeral_key: &challenge.ephemeral_key,
+            };
+            challenge
+                .signature
+                .verify(transcript.signing_hash("responder"), &challenge.public_key)?;
+            let response = ChallengeResponse {
+                signature: Signature::sign(
+                    transcript.signing_hash("initiator"),
+                    &config.private_key,
+                )?,
+            };
+            write_frame(&mut stream, &response, MAX_HANDSHAKE_FRAME_SIZE).await?;
+            Ok(Self {
+                key: transcript.session_key(&ephemeral_private_key, &challenge.ephemeral_key)?,
+                stream,
+                public_key: config.public_key.clone(),
+                peer: challenge.public_key,
+                sent: 0,
+                received: 0,
+            })
+        })
+        .await
+        .map_err(|_| eyre!("handshake timeout"))?
+    }
+
+    /// Accepts a connection from a member, rejecting a non-member.
+    pub async fn accept(config: &NetworkConfig, mut stream: TcpStream) -> Result<Self, Error> {
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+            let hello: Hello = read_frame(&mut stream, MAX_HANDSHAKE_FRAME_SIZE).await?;
+            if hello.network_id != config.network_id {
+                return Err(eyre!("network id mismatch: {}", hello.network_id));
+            }
+            check_member(config, &hello.public_key)?;
+            let nonce = Hash256::from_array(rand::random());
+            let (ephemeral_key, ephemeral_private_key) = generate_keypair_random();
+            let transcript = Transcript {
+                network_id: &config.network_id,
+                initiator: &hello.public_key,
+                responder: &config.public_key,
+                initiator_nonce: &hello.nonce,
+                responder_nonce: &nonce,
+                initiator_ephemeral_key: &hello.ephemeral_key,
+                responder_ephemeral_key: &ephemeral_key,
+            };
+            let challenge = Challenge {
+                public_key: config.public_key.clone(),
+                nonce,
+                ephemeral_key: ephemeral_key.clone(),
+                signature: Signature::sign(
+                    transcript.signing_hash("responder"),
+                    &config.private_key,
+                )?,
+            };
+            write_frame(&mut stream, &challenge, MAX_HANDSHAKE_FRAME_SIZE).await?;
+            let response: ChallengeResponse =
+                read_frame(&mut stream, MAX_HANDSHAKE_FRAME_SIZE).await?;
+            response
+                .signature
+                .verify(transcript.signing_hash("initiator"), &hello.public_key)?;
+            Ok(Self {
+                key: transcript.session_key(&ephemeral_private_key, &hello.ephemeral_key)?,
+                stream,
+                public_key: config.public_key.clone(),
+                peer: hello.public_key,
+                sent: 0,
+                received: 0,
+            })
+        })
+        .await
+        .map_err(|_| eyre!("handshake timeout"))?
+    }
+
+    /// Returns the authenticated public key of the other side.
+    pub fn peer(&self) -> &PublicKey {
+        &self.peer
+    }
+
+    /// Keccak256 is not subject to the length extension, so prefixing the key makes a MAC.
+    ///
+    /// The payload is hashed as it is, without being encoded again.
+    fn mac(&self, sender: &PublicKey, sequence: u64, payload: &[u8]) -> Hash256 {
+        Hash256::hash(
+            [
+                self.key.as_ref(),
+                sender.as_ref(),
+                &sequence.to_be_bytes(),
+                payload,
+            ]
+            .concat(),
+        )
+    }
+
+    pub async fn send<T: Serialize>(&mut self, message: &T) -> Result<(), Error> {
+        let payload = serde_spb::to_string(message)?;
+        let mac = self.mac(&self.public_key, self.sent, payload.as_bytes());
+        write_raw_frame(
+            &mut self.stream,
+            &[mac.as_ref(), payload.as_bytes()],
+            MAX_FRAME_SIZE,
+        )
+        .await?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    pub async fn recv<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let frame = read_raw_frame(&mut self.stream, MAX_FRAME_SIZE).await?;
+        if frame.len() < 32 {
+            return Err(eyre!("frame too short: {} bytes", frame.len()));
+        }
+        let (received_mac, payload) = frame.split_at(32);
+        let mac = self.mac(&self.peer, self.received, payload);
+        // Compares in constant time, not to leak how much of a forged MAC is correct.
+        if mac
+            .as_ref()
+            .iter()
+            .zip(received_mac)
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            != 0
+        {
+            return Err(eyre!("invalid MAC of frame {}", self.received));
+        }
+        self.received += 1;
+        Ok(serde_spb::from_str(std::str::from_utf8(payload)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+    use tokio::net::TcpListener;
+
+    fn generate_configs(size: usize) -> Vec<NetworkConfig> {
+        let keys = (0..size)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        keys.iter()
+            .map(|(public_key, private_key)| NetworkConfig {
+                network_id: "transport".to_owned(),
+                ports: Default::default(),
+                members: keys.iter().map(|(x, _)| x.clone()).collect(),
+                public_key: public_key.clone(),
+                private_key: private_key.clone(),
+            })
+            .collect()
+    }
+
+    /// Accepts a connection, echoing the received messages back.
+    async fn serve_echo(
+        config: NetworkConfig,
+    ) -> (
+        std::net::SocketAddrV4,
+        tokio::task::JoinHandle<Result<(), Error>>,
+    ) {
+        let port = dispense_port();
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        let task = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut connection = AuthenticatedConnection::accept(&config, stream).await?;
+            loop {
+                let message: String = connection.recv().await?;
+                connection.send(&message).await?;
+            }
+        });
+        (format!("127.0.0.1:{port}").parse().unwrap(), task)
+    }
+
+    #[tokio::test]
+    async fn authenticated_echo() {
+        setup_test();
+        let configs = generate_configs(2);
+        let (address, _task) = serve_echo(configs[1].clone()).await;
+        let mut connection =
+            AuthenticatedConnection::connect(&configs[0], address, Some(&configs[1].public_key))
+                .await
+                .unwrap();
+        assert_eq!(connection.peer(), &configs[1].public_key);
+        for message in ["hello", "world"] {
+            connection.send(&message.to_owned()).await.unwrap();
+            assert_eq!(connection.recv::<String>().await.unwrap(), message);
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_non_member() {
+        setup_test();
+        let configs = generate_configs(2);
+
+        // A non-member is rejected by the server.
+        let (address, task) = serve_echo(configs[1].clone()).await;
+        let (public_key, private_key) = generate_keypair_random();
+        let mut non_member = configs[0].clone();
+        non_member.public_key = public_key;
+        non_member.private_key = private_key;
+        non_member.members.push(non_member.public_key.clone());
+        assert!(AuthenticatedConnection::connect(&non_member, address, None)
+            .await
+            .is_err());
+        assert!(task.await.unwrap().is_err());
+
+        // A non-member server is rejected by the client.
+        let (address, _task) = serve_echo(non_member).await;
+        assert!(AuthenticatedConnection::connect(&configs[0], address, None)
+            .await
+            .is_err());
+
+        // A member other than the expected one is rejected by the client.
+        let (address, _task) = serve_echo(configs[1].clone()).await;
+        assert!(AuthenticatedConnection::connect(
+            &configs[0],
+            address,
+            Some(&configs[0].public_key)
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_forged_frame() {
+        setup_test();
+        let configs = generate_configs(2);
+        let (address, task) = serve_echo(configs[1].clone()).await;
+        let mut connection = AuthenticatedConnection::connect(&configs[0], address, None)
+            .await
+            .unwrap();
+        // Someone who doesn't know the session key can't make a valid frame.
+        connection.key = Hash256::hash("forged");
+        connection.send(&"hello".to_owned()).await.unwrap();
+        let error = task.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("invalid MAC"));
+    }
+
+    #[tokio::test]
+    async fn reject_large_handshake_frame() {
+        setup_test();
+        let configs = generate_configs(1);
+        let (address, task) = serve_echo(configs[0].clone()).await;
+        // An unauthenticated peer can't make the server allocate a large frame.
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(&(MAX_FRAME_SIZE as u32).to_be_bytes())
+            .await
+            .unwrap();
+        let error = task.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("message too large"));
+    }
+}
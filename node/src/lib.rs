@@ -10,6 +10,8 @@
 //! - `consensus`
 //! - `git`
 //! - `show`
+//! - `review`
+//! - `verify-commit`
 //! - `network`
 //! - `update`
 //! - `broadcast`
@@ -18,12 +20,14 @@
 //! The following CLI commands are provided as global functions as they are node-stateless.
 //!
 //! - `genesis`
+//! - `check-push`
 //!
 //! The following CLI commands are provided as global functions as they are about the node creation.
 //!
 //! - `init`
 //! - `clone`
 //! - `serve`
+//! - `relay`
 //!
 //! The following CLI commands are not provided here because they are simple
 //! and so directly implemented in the CLI.
@@ -35,15 +39,20 @@ pub use simperby_common;
 pub use simperby_network;
 pub use simperby_repository;
 
-use eyre::Result;
+use eyre::{eyre, Result};
 use serde::{Deserialize, Serialize};
 use simperby_common::crypto::*;
 use simperby_common::*;
 use simperby_governance::Governance;
 use simperby_network::{Peer, SharedKnownPeers};
 use simperby_repository::raw::{RawRepository, RawRepositoryImpl, SemanticCommit};
-use simperby_repository::CommitHash;
 use simperby_repository::DistributedRepository;
+use simperby_repository::{Checkpoint, CommitHash, PushVerifier, FP_BRANCH_NAME};
+
+/// The name of the governance DMS, which gives its port keys (`dms-governance`, `gossip-governance`).
+pub const GOVERNANCE_DMS_NAME: &str = "governance";
+/// The name of the consensus DMS, which gives its port keys (`dms-consensus`, `gossip-consensus`).
+pub const CONSENSUS_DMS_NAME: &str = "consensus";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -63,6 +72,30 @@ pub struct Config {
     pub governance_port: u16,
     pub consensus_port: u16,
     pub repository_port: u16,
+    /// The ports for the gossip of the governance and consensus messages.
+    ///
+    /// If `None`, the node doesn't receive the gossip and relies on fetching from the peers.
+    #[serde(default)]
+    pub governance_gossip_port: Option<u16>,
+    #[serde(default)]
+    pub consensus_gossip_port: Option<u16>,
+    /// The port for the peer discovery.
+    ///
+    /// If `None`, the node doesn't run the discovery and uses only the peers in `peers.json`.
+    #[serde(default)]
+    pub discovery_port: Option<u16>,
+    /// The kind of the storage for the DMS messages and the consensus state.
+    #[serde(default)]
+    pub storage: simperby_network::storage::StorageKind,
+    /// The public keys of the relays that this node trusts along with the members.
+    ///
+    /// They are trusted by the DMS RPC only, not by the gossip nor the peer discovery.
+    /// A relay is listed in `peers.json` with the port of `simperby_network::relay::PORT_KEY`.
+    #[serde(default)]
+    pub relays: Vec<PublicKey>,
+    /// The port for running a relay. Required only for `relay()`.
+    #[serde(default)]
+    pub relay_port: Option<u16>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -72,7 +105,11 @@ pub struct ConsensusStatus {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NetworkStatus {
-    // TODO
+    /// The counts of the governance messages received from the other peers.
+    pub governance: simperby_network::dms::Metrics,
+    /// The counts of the consensus messages received from the other peers.
+    pub consensus: simperby_network::dms::Metrics,
+    // TODO: add the peers and their connectivity
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -105,8 +142,8 @@ pub enum CommitInfo {
 }
 
 pub type SimperbyNode = node::Node<
-    simperby_network::primitives::DummyGossipNetwork,
-    simperby_network::storage::StorageImpl,
+    simperby_network::gossip::TcpGossipNetwork,
+    simperby_network::storage::AnyStorage,
     simperby_repository::raw::RawRepositoryImpl,
 >;
 
@@ -121,8 +158,10 @@ pub async fn genesis(config: Config, path: &str) -> Result<()> {
         simperby_repository::Config {
             mirrors: config.public_repo_url.clone(),
             long_range_attack_distance: 3,
+            verification_cache_path: Some(format!("{path}/repository/verification_cache.json")),
         },
         peers.clone(),
+        Some(config.private_key.clone()),
     )
     .await?;
     repository.genesis().await?;
@@ -130,16 +169,107 @@ pub async fn genesis(config: Config, path: &str) -> Result<()> {
     Ok(())
 }
 
+/// The maximum difference between the timestamp of a push and the current time.
+/// A push beyond this is considered a replay.
+const PUSH_TIMESTAMP_TOLERANCE_MS: Timestamp = 60 * 1000;
+
+/// Verifies that the push is signed by a member, recently.
+fn verify_push(
+    reserved_state: &ReservedState,
+    commit_hash: CommitHash,
+    branch_name: String,
+    timestamp: Timestamp,
+    signature: Signature,
+    now: Timestamp,
+) -> Result<()> {
+    if !(branch_name.starts_with("a-")
+        || branch_name.starts_with("b-")
+        || branch_name == FP_BRANCH_NAME)
+    {
+        return Err(eyre::eyre!("branch {branch_name} cannot be pushed"));
+    }
+    if (timestamp - now).abs() > PUSH_TIMESTAMP_TOLERANCE_MS {
+        return Err(eyre::eyre!(
+            "push timestamp {timestamp} is too far from {now}"
+        ));
+    }
+    let push_verifier = PushVerifier {
+        commit_hash,
+        branch_name,
+        timestamp,
+    };
+    let signer = signature.recover(push_verifier.to_hash256())?;
+    if reserved_state.query_name(&signer).is_none() {
+        return Err(eyre::eyre!("push signed by a non-member: {signer}"));
+    }
+    Ok(())
+}
+
+/// Checks whether the push is signed by a member; this is run by the Git hook of a serving node.
+///
+/// Unlike [`node::Node::check_push`], it doesn't initialize the node
+/// because the serving node holds the locks of the storages.
+pub async fn check_push(
+    path: &str,
+    commit_hash: CommitHash,
+    branch_name: String,
+    timestamp: Timestamp,
+    signature: Signature,
+) -> Result<bool> {
+    let raw_repository = RawRepositoryImpl::open(&format!("{path}/repository/repo")).await?;
+    let reserved_state = raw_repository.read_reserved_state().await?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as Timestamp;
+    match verify_push(
+        &reserved_state,
+        commit_hash,
+        branch_name,
+        timestamp,
+        signature,
+        now,
+    ) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            log::warn!("rejected a push: {}", e);
+            Ok(false)
+        }
+    }
+}
+
 /// Initializes a node.
 pub async fn initialize(config: Config, path: &str) -> Result<SimperbyNode> {
     SimperbyNode::initialize(config, path).await
 }
 
 /// Clones a remote repository and initializes a node.
-pub async fn clone(config: Config, path: &str, url: &str) -> Result<SimperbyNode> {
-    RawRepositoryImpl::clone(&format!("{path}/repository/repo"), url)
-        .await
-        .unwrap();
+///
+/// If `checkpoint` is given, the repository trusts it and verifies forward from there,
+/// instead of verifying the history from the genesis.
+pub async fn clone(
+    config: Config,
+    path: &str,
+    url: &str,
+    checkpoint: Option<Checkpoint>,
+) -> Result<SimperbyNode> {
+    let raw_repository = RawRepositoryImpl::clone(&format!("{path}/repository/repo"), url).await?;
+    if let Some(checkpoint) = checkpoint {
+        let peers: Vec<Peer> =
+            serde_spb::from_str(&tokio::fs::read_to_string(&format!("{path}/peers.json")).await?)?;
+        let mut repository = DistributedRepository::new(
+            raw_repository,
+            simperby_repository::Config {
+                mirrors: config.public_repo_url.clone(),
+                long_range_attack_distance: 3,
+                verification_cache_path: Some(format!("{path}/repository/verification_cache.json")),
+            },
+            SharedKnownPeers::new_static(peers),
+            Some(config.private_key.clone()),
+        )
+        .await?;
+        repository.initialize_from_checkpoint(&checkpoint).await?;
+    }
     SimperbyNode::initialize(config, path).await
 }
 
@@ -151,3 +281,100 @@ pub async fn serve(config: Config, path: &str) -> Result<()> {
     }
     Ok(())
 }
+
+/// The number of the heights above its finalized height for which a relay accepts the messages.
+///
+/// The repository of a relay is updated only as often as its operator fetches it,
+/// so the members may be a few heights ahead of it.
+const RELAY_MAX_HEIGHTS_AHEAD: BlockHeight = 3;
+/// The number of the DMS keys retained by a relay; the governance and the consensus keys
+/// of its finalized height and of the heights ahead of it.
+const RELAY_RETAINED_KEYS: usize = 2 * (RELAY_MAX_HEIGHTS_AHEAD as usize + 1);
+
+/// Reads the members and the last finalized height from the repository, which a relay follows.
+async fn read_relay_state(
+    raw_repository: &RawRepositoryImpl,
+) -> Result<(Vec<PublicKey>, BlockHeight)> {
+    let members = raw_repository
+        .read_reserved_state()
+        .await?
+        .members
+        .iter()
+        .map(|m| m.public_key.clone())
+        .collect();
+    let commit_hash = raw_repository
+        .locate_branch(simperby_repository::FINALIZED_BRANCH_NAME.into())
+        .await?;
+    let commit = simperby_repository::format::from_semantic_commit(
+        raw_repository.read_semantic_commit(commit_hash).await?,
+    )
+    .map_err(|e| eyre!(e))?;
+    if let Commit::Block(header) = commit {
+        Ok((members, header.height))
+    } else {
+        Err(eyre!("`finalized` branch is not on a block"))
+    }
+}
+
+/// Runs a relay indefinitely, which stores and forwards the DMS messages of the recent heights
+/// for the members who are rarely online.
+///
+/// It doesn't need the node to be a member; the members must trust it by `Config::relays`.
+pub async fn relay(config: Config, path: &str) -> Result<()> {
+    let port = config
+        .relay_port
+        .ok_or_else(|| eyre!("`relay_port` is not configured"))?;
+    let raw_repository = RawRepositoryImpl::open(&format!("{path}/repository/repo")).await?;
+    let reserved_state = raw_repository.read_reserved_state().await?;
+    let (members, finalized_height) = read_relay_state(&raw_repository).await?;
+    let network_config = simperby_network::NetworkConfig {
+        network_id: reserved_state.genesis_info.chain_name.clone(),
+        ports: vec![(simperby_network::relay::PORT_KEY.to_owned(), port)]
+            .into_iter()
+            .collect(),
+        members,
+        public_key: config.public_key.clone(),
+        private_key: config.private_key.clone(),
+    };
+    let storage_path = format!("{path}/relay");
+    let storage = simperby_network::storage::open_or_create(&storage_path, config.storage).await?;
+    let mut relay = simperby_network::relay::Relay::new(
+        storage,
+        simperby_network::relay::RelayConfig {
+            network_config,
+            retained_keys: RELAY_RETAINED_KEYS,
+            finalized_height,
+            max_heights_ahead: RELAY_MAX_HEIGHTS_AHEAD,
+            limits: Default::default(),
+        },
+    )
+    .await?;
+    loop {
+        relay = relay.serve(60_000).await?;
+        // The repository is kept up to date by the mirror, which moves the members and the heights.
+        let (members, finalized_height) = read_relay_state(&raw_repository).await?;
+        relay.set_members(members);
+        relay.set_finalized_height(finalized_height);
+    }
+}
+
+/// Migrates the existing storages of the node (and of the relay) to the kind of `Config::storage`.
+///
+/// It must be run while neither the node nor the relay is running, which hold the locks.
+pub async fn migrate_storage(config: Config, path: &str) -> Result<()> {
+    for directory in [
+        "governance/dms",
+        "consensus/dms",
+        "consensus/state",
+        "relay",
+    ] {
+        let storage_path = format!("{path}/{directory}");
+        if simperby_network::storage::StorageKind::detect(&storage_path)
+            .await
+            .is_ok()
+        {
+            simperby_network::storage::migrate(&storage_path, config.storage).await?;
+        }
+    }
+    Ok(())
+}
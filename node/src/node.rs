@@ -1,11 +1,13 @@
 use super::*;
 use eyre::eyre;
 use simperby_consensus::{Consensus, ConsensusParameters, ProgressResult};
+use simperby_network::peer_discovery::{self, PeerDiscoveryImpl};
 use simperby_network::primitives::{GossipNetwork, Storage};
 use simperby_network::NetworkConfig;
-use simperby_network::{dms, storage::StorageImpl, Dms, Peer, SharedKnownPeers};
+use simperby_network::{dms, gossip, storage, Peer, PeerDiscovery, SharedKnownPeers};
 use simperby_repository::raw::{RawRepository, RawRepositoryImpl};
-use simperby_repository::{DistributedRepository, WORK_BRANCH_NAME};
+use simperby_repository::review::AgendaReview;
+use simperby_repository::{DistributedRepository, ForkEvidence, ServerConfig, WORK_BRANCH_NAME};
 use std::collections::HashMap;
 
 fn get_timestamp() -> Timestamp {
@@ -27,6 +29,8 @@ pub struct Node<N: GossipNetwork, S: Storage, R: RawRepository> {
 
     path: String,
     network_config: NetworkConfig,
+    /// The known peers shared by the modules, which are updated by the peer discovery.
+    peers: SharedKnownPeers,
 }
 
 impl SimperbyNode {
@@ -41,8 +45,10 @@ impl SimperbyNode {
             simperby_repository::Config {
                 mirrors: config.public_repo_url.clone(),
                 long_range_attack_distance: 3,
+                verification_cache_path: Some(format!("{path}/repository/verification_cache.json")),
             },
             peers.clone(),
+            Some(config.private_key.clone()),
         )
         .await?;
 
@@ -54,17 +60,26 @@ impl SimperbyNode {
         let network_config = NetworkConfig {
             network_id: reserved_state.genesis_info.chain_name.clone(),
             ports: vec![
-                (
-                    format!("dms-{}", governance_dms_key.clone()),
-                    config.governance_port,
-                ),
-                (
-                    format!("dms-{}", consensus_dms_key.clone()),
-                    config.consensus_port,
-                ),
+                (format!("dms-{GOVERNANCE_DMS_NAME}"), config.governance_port),
+                (format!("dms-{CONSENSUS_DMS_NAME}"), config.consensus_port),
                 ("repository".to_owned(), config.repository_port),
             ]
             .into_iter()
+            .chain(
+                config
+                    .governance_gossip_port
+                    .map(|port| (gossip::port_key(GOVERNANCE_DMS_NAME), port)),
+            )
+            .chain(
+                config
+                    .consensus_gossip_port
+                    .map(|port| (gossip::port_key(CONSENSUS_DMS_NAME), port)),
+            )
+            .chain(
+                config
+                    .discovery_port
+                    .map(|port| (peer_discovery::PORT_KEY.to_owned(), port)),
+            )
             .collect(),
             members: reserved_state
                 .members
@@ -75,16 +90,18 @@ impl SimperbyNode {
             private_key: config.private_key.clone(),
         };
         let dms_config = dms::Config {
+            name: GOVERNANCE_DMS_NAME.to_owned(),
             fetch_interval: Some(std::time::Duration::from_millis(500)),
             broadcast_interval: Some(std::time::Duration::from_millis(500)),
             network_config: network_config.clone(),
+            limits: Default::default(),
+            relays: config.relays.clone(),
         };
 
         // Step 2: initialize the governance module
         let dms_path = format!("{path}/governance/dms");
-        StorageImpl::create(&dms_path).await.unwrap();
-        let storage = StorageImpl::open(&dms_path).await.unwrap();
-        let dms = Dms::new(
+        let storage = storage::open_or_create(&dms_path, config.storage).await?;
+        let dms = dms::DistributedMessageSet::new(
             storage,
             governance_dms_key,
             dms_config.clone(),
@@ -95,18 +112,19 @@ impl SimperbyNode {
 
         // Step 3: initialize the consensus module
         let dms_path = format!("{path}/consensus/dms");
-        StorageImpl::create(&dms_path).await.unwrap();
-        let storage = StorageImpl::open(&dms_path).await.unwrap();
-        let dms = Dms::new(
+        let storage = storage::open_or_create(&dms_path, config.storage).await?;
+        let dms = dms::DistributedMessageSet::new(
             storage,
             consensus_dms_key,
-            dms_config.clone(),
+            dms::Config {
+                name: CONSENSUS_DMS_NAME.to_owned(),
+                ..dms_config.clone()
+            },
             peers.clone(),
         )
         .await?;
         let state_path = format!("{path}/consensus/state");
-        StorageImpl::create(&state_path).await.unwrap();
-        let consensus_state_storage = StorageImpl::open(&state_path).await.unwrap();
+        let consensus_state_storage = storage::open_or_create(&state_path, config.storage).await?;
         let consensus = Consensus::new(
             dms,
             consensus_state_storage,
@@ -129,6 +147,7 @@ impl SimperbyNode {
             last_finalized_header,
             path: path.to_owned(),
             network_config,
+            peers,
         })
     }
 
@@ -168,8 +187,51 @@ impl SimperbyNode {
     }
 
     /// Cleans the repository, removing all the outdated commits.
-    pub async fn clean(&mut self, _hard: bool) -> Result<()> {
-        self.repository.clean().await
+    ///
+    /// Returns the descriptions of what has been removed.
+    pub async fn clean(&mut self, hard: bool) -> Result<Vec<String>> {
+        let report = self.repository.clean(hard).await?;
+        Ok(report
+            .branches
+            .into_iter()
+            .map(|(branch, reason)| format!("repository: deleted branch {branch} ({reason})"))
+            .chain(
+                report
+                    .remotes
+                    .into_iter()
+                    .map(|remote| format!("repository: removed remote {remote}")),
+            )
+            .chain(
+                report
+                    .tags
+                    .into_iter()
+                    .map(|tag| format!("repository: removed tag {tag}")),
+            )
+            .collect())
+    }
+
+    /// Rebases the `work` branch on the `finalized` branch.
+    ///
+    /// Returns the descriptions of what has been done.
+    pub async fn rebase(&mut self) -> Result<Vec<String>> {
+        let report = self.repository.rebase().await?;
+        Ok(report
+            .replayed
+            .into_iter()
+            .map(|(from, to)| format!("repository: replayed transaction {from} as {to}"))
+            .chain(
+                report
+                    .restamped
+                    .into_iter()
+                    .map(|commit| format!("repository: renewed the timestamp of {commit}")),
+            )
+            .chain(
+                report
+                    .dropped
+                    .into_iter()
+                    .map(|(commit, reason)| format!("repository: dropped {commit} ({reason})")),
+            )
+            .collect())
     }
 
     /// Creates a block commit on the `work` branch.
@@ -275,6 +337,34 @@ impl SimperbyNode {
         Ok(result)
     }
 
+    /// Verifies the signatures of the given commit and the tags on it.
+    ///
+    /// Returns the descriptions of who signed what.
+    pub async fn verify_commit(&self, commit_hash: CommitHash) -> Result<Vec<String>> {
+        let signatures = self.repository.verify_commit(commit_hash).await?;
+        let name = |public_key: &PublicKey| {
+            self.last_reserved_state
+                .query_name(public_key)
+                .unwrap_or_else(|| format!("{public_key} (not a member)"))
+        };
+        let mut result = vec![match &signatures.author {
+            Some(author) => format!("commit {commit_hash} is signed by {}", name(author)),
+            None => format!("commit {commit_hash} is not signed"),
+        }];
+        result.extend(
+            signatures
+                .tags
+                .iter()
+                .map(|(tag, signer)| format!("tag {tag} is signed by {}", name(signer))),
+        );
+        Ok(result)
+    }
+
+    /// Reviews the agenda of the given `a-#` branch.
+    pub async fn review_agenda(&self, branch: &str) -> Result<AgendaReview> {
+        self.repository.review_agenda(branch).await
+    }
+
     /// Makes a progress for the consensus, returning the result.
     ///
     /// TODO: it has to consume the object if finalized.
@@ -295,47 +385,86 @@ impl SimperbyNode {
 
     /// Gets the current status of the p2p network.
     pub async fn get_network_status(&self) -> Result<NetworkStatus> {
-        unimplemented!()
+        Ok(NetworkStatus {
+            governance: self.governance.dms.metrics(),
+            consensus: self.consensus.dms_metrics(),
+        })
     }
 
     pub async fn serve(self, ms: u64) -> Result<Self> {
-        let repository_port = self.config.repository_port;
+        let repository = self
+            .repository
+            .serve(ServerConfig {
+                path: Some(self.path.clone()),
+                port: self.config.repository_port,
+                simperby_executable_path: std::env::current_exe()
+                    .ok()
+                    .map(|executable| format!("{} {}", executable.display(), self.path)),
+                fetch_interval: self
+                    .config
+                    .fetch_interval_ms
+                    .map(std::time::Duration::from_millis),
+                update_interval: Some(std::time::Duration::from_millis(500)),
+            })
+            .await?;
+
+        let discovery = if self.config.discovery_port.is_some() {
+            let discovery_path = format!("{}/discovery", self.path);
+            if tokio::fs::metadata(&discovery_path).await.is_err() {
+                PeerDiscoveryImpl::create(&discovery_path).await?;
+            }
+            Some(
+                PeerDiscoveryImpl::serve(&discovery_path, &self.network_config, self.peers.clone())
+                    .await?,
+            )
+        } else {
+            None
+        };
 
         let t1 = tokio::spawn(async move { self.governance.serve(ms).await.unwrap() });
         let t2 = tokio::spawn(async move { self.consensus.serve(ms).await.unwrap() });
-        let path = self.path.clone();
-        let t3 = tokio::spawn(async move {
-            let server = simperby_repository::server::run_server_legacy(
-                &format!("{path}/repository"),
-                repository_port,
-            )
-            .await;
-            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
-            drop(server);
-        });
 
         let governance = t1.await?;
         let consensus = t2.await?;
-        t3.await?;
+        let repository = repository.join().await??;
+        if let Some(discovery) = discovery {
+            discovery.abort();
+        }
 
         Ok(Self {
             governance,
             consensus,
             config: self.config,
-            repository: self.repository,
+            repository,
             last_reserved_state: self.last_reserved_state,
             last_finalized_header: self.last_finalized_header,
             path: self.path,
             network_config: self.network_config,
+            peers: self.peers,
         })
     }
 
     pub async fn fetch(&mut self) -> Result<()> {
+        let known_evidence = self.repository.list_evidence().await?;
         let t1 = async { self.governance.fetch().await };
         let t2 = async { self.consensus.fetch().await };
         let t3 = async { self.repository.fetch().await };
         futures::try_join!(t1, t2, t3)?;
 
+        // Alert the operator of any newly found conflicting finalization.
+        // TODO: turn it into a `TxReport` once the extra-agenda transaction is implemented.
+        for evidence in self.repository.list_evidence().await? {
+            if !known_evidence.contains(&evidence) {
+                log::error!(
+                    "SAFETY VIOLATION: block {} at height {} conflicts with the finalized block {} (reported by {})",
+                    evidence.conflicting_block.to_hash256(),
+                    evidence.conflicting_block.height,
+                    evidence.canonical_block.to_hash256(),
+                    evidence.source.as_deref().unwrap_or("unknown peer")
+                );
+            }
+        }
+
         // Update governance
         let governance_set = self
             .last_reserved_state
@@ -383,24 +512,43 @@ impl SimperbyNode {
         Ok(())
     }
 
+    /// Lists the evidences of conflicting finalization found by the repository.
+    pub async fn list_evidence(&self) -> Result<Vec<ForkEvidence>> {
+        self.repository.list_evidence().await
+    }
+
     /// Broadcasts all the local messages and reports the result.
     pub async fn broadcast(&mut self) -> Result<Vec<String>> {
         let t1 = async { self.governance.broadcast().await };
         let t2 = async { self.consensus.broadcast().await };
         let t3 = async { self.repository.broadcast().await };
-        futures::try_join!(t1, t2, t3)?;
-        // TODO: report the result
-        Ok(vec![])
+        let (_, _, repository_result) = futures::try_join!(t1, t2, t3)?;
+        Ok(repository_result
+            .into_iter()
+            .map(|(peer, result)| match result {
+                Ok(()) => format!("repository: pushed to {peer}"),
+                Err(e) => format!("repository: failed to push to {peer}: {e}"),
+            })
+            .collect())
     }
 
+    /// Checks whether the push is signed by a member (see [`simperby_repository::PushVerifier`]).
     pub async fn check_push(
         &mut self,
-        _commit_hash: CommitHash,
-        _branch_name: String,
-        _timestamp: Timestamp,
-        _signature: Signature,
+        commit_hash: CommitHash,
+        branch_name: String,
+        timestamp: Timestamp,
+        signature: Signature,
     ) -> Result<bool> {
-        todo!()
+        Ok(verify_push(
+            &self.last_reserved_state,
+            commit_hash,
+            branch_name,
+            timestamp,
+            signature,
+            get_timestamp(),
+        )
+        .is_ok())
     }
 
     pub async fn notify_push(&mut self, _commit_hash: CommitHash) -> Result<()> {
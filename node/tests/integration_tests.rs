@@ -16,6 +16,12 @@ fn generate_config(key: PrivateKey, chain_name: String) -> Config {
         governance_port: dispense_port(),
         consensus_port: dispense_port(),
         repository_port: dispense_port(),
+        governance_gossip_port: Some(dispense_port()),
+        consensus_gossip_port: Some(dispense_port()),
+        discovery_port: Some(dispense_port()),
+        storage: Default::default(),
+        relays: Vec::new(),
+        relay_port: None,
     }
 }
 
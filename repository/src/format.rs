@@ -1,10 +1,18 @@
 use crate::raw::SemanticCommit;
+use crate::ForkEvidence;
 use eyre::{eyre, Error};
 use regex::Regex;
 use simperby_common::*;
 
-pub fn to_semantic_commit(commit: &Commit) -> SemanticCommit {
-    match commit {
+/// Converts a commit to a semantic commit.
+///
+/// `reserved_state` is the reserved state right before the commit,
+/// to which an extra-agenda transaction is applied to make its diff.
+pub fn to_semantic_commit(
+    commit: &Commit,
+    mut reserved_state: ReservedState,
+) -> Result<SemanticCommit, Error> {
+    let semantic_commit = match commit {
         Commit::Agenda(agenda) => {
             let title = format!(">agenda: {}", agenda.height);
             let body = serde_spb::to_string(agenda).unwrap();
@@ -12,6 +20,8 @@ pub fn to_semantic_commit(commit: &Commit) -> SemanticCommit {
                 title,
                 body,
                 diff: Diff::None,
+                author: agenda.author.clone(),
+                timestamp: agenda.timestamp,
             }
         }
         Commit::Block(block_header) => {
@@ -21,12 +31,16 @@ pub fn to_semantic_commit(commit: &Commit) -> SemanticCommit {
                 title,
                 body,
                 diff: Diff::None,
+                author: block_header.author.clone(),
+                timestamp: block_header.timestamp,
             }
         }
         Commit::Transaction(transaction) => SemanticCommit {
             title: transaction.head.clone(),
             body: transaction.body.clone(),
             diff: transaction.diff.clone(),
+            author: transaction.author.clone(),
+            timestamp: transaction.timestamp,
         },
         Commit::AgendaProof(agenda_proof) => {
             let title = format!(">agenda-proof: {}", agenda_proof.height);
@@ -35,17 +49,52 @@ pub fn to_semantic_commit(commit: &Commit) -> SemanticCommit {
                 title,
                 body,
                 diff: Diff::None,
+                author: PublicKey::zero(),
+                timestamp: 0,
             }
         }
-        Commit::ExtraAgendaTransaction(_) => unimplemented!(),
-        Commit::ChatLog(_) => unimplemented!(),
-    }
+        Commit::ExtraAgendaTransaction(tx) => {
+            let (title, author, timestamp) = match tx {
+                ExtraAgendaTransaction::Delegate(tx) => {
+                    reserved_state.apply_delegate(tx).map_err(|e| eyre!(e))?;
+                    (">tx-delegate", tx.delegator.clone(), tx.timestamp)
+                }
+                ExtraAgendaTransaction::Undelegate(tx) => {
+                    reserved_state.apply_undelegate(tx).map_err(|e| eyre!(e))?;
+                    (">tx-undelegate", tx.delegator.clone(), tx.timestamp)
+                }
+                ExtraAgendaTransaction::Report(_) => {
+                    return Err(eyre!("report transactions are not supported yet"))
+                }
+            };
+            SemanticCommit {
+                title: title.to_owned(),
+                body: serde_spb::to_string(tx).unwrap(),
+                diff: Diff::Reserved(Box::new(reserved_state)),
+                author,
+                timestamp,
+            }
+        }
+        Commit::ChatLog(_) => return Err(eyre!("chat logs can't be committed yet")),
+    };
+    Ok(semantic_commit)
 }
 
 /// Converts a semantic commit to a commit.
-///
-/// TODO: retrieve author and timestamp from the commit metadata.
 pub fn from_semantic_commit(semantic_commit: SemanticCommit) -> Result<Commit, Error> {
+    if let ">tx-delegate" | ">tx-undelegate" = semantic_commit.title.as_str() {
+        let tx: ExtraAgendaTransaction = serde_spb::from_str(&semantic_commit.body)?;
+        return match (semantic_commit.title.as_str(), &tx) {
+            (">tx-delegate", ExtraAgendaTransaction::Delegate(_))
+            | (">tx-undelegate", ExtraAgendaTransaction::Undelegate(_)) => {
+                Ok(Commit::ExtraAgendaTransaction(tx))
+            }
+            _ => Err(eyre!(
+                "extra-agenda transaction type mismatch: {}",
+                semantic_commit.title
+            )),
+        };
+    }
     let pattern = Regex::new(r"^>((agenda)|(block)|(agenda-proof)): (\d+)$").unwrap();
     let captures = pattern.captures(&semantic_commit.title);
     if let Some(captures) = captures {
@@ -100,8 +149,8 @@ pub fn from_semantic_commit(semantic_commit: SemanticCommit) -> Result<Commit, E
         }
     } else {
         Ok(Commit::Transaction(Transaction {
-            author: PublicKey::zero(),
-            timestamp: 0,
+            author: semantic_commit.author,
+            timestamp: semantic_commit.timestamp,
             head: semantic_commit.title,
             body: semantic_commit.body,
             diff: semantic_commit.diff,
@@ -116,6 +165,8 @@ pub fn fp_to_semantic_commit(fp: &LastFinalizationProof) -> SemanticCommit {
         title,
         body,
         diff: Diff::None,
+        author: PublicKey::zero(),
+        timestamp: 0,
     }
 }
 
@@ -146,22 +197,66 @@ pub fn fp_from_semantic_commit(
     }
 }
 
+pub fn evidence_to_semantic_commit(evidence: &ForkEvidence) -> SemanticCommit {
+    let title = format!(">evidence: {}", evidence.conflicting_block.height);
+    let body = serde_spb::to_string(&evidence).unwrap();
+    SemanticCommit {
+        title,
+        body,
+        diff: Diff::None,
+        author: PublicKey::zero(),
+        timestamp: 0,
+    }
+}
+
+pub fn evidence_from_semantic_commit(
+    semantic_commit: SemanticCommit,
+) -> Result<ForkEvidence, Error> {
+    let pattern = Regex::new(r"^>evidence: (\d+)$").unwrap();
+    let captures = pattern.captures(&semantic_commit.title);
+    if let Some(captures) = captures {
+        let height = captures.get(1).map(|m| m.as_str()).ok_or_else(|| {
+            eyre!(
+                "Failed to parse commit height from commit title: {}",
+                semantic_commit.title
+            )
+        })?;
+        let height = height.parse::<u64>()?;
+        let evidence: ForkEvidence = serde_spb::from_str(&semantic_commit.body)?;
+        if height != evidence.conflicting_block.height {
+            return Err(eyre!(
+                "evidence height mismatch: expected {}, got {}",
+                evidence.conflicting_block.height,
+                height
+            ));
+        }
+        Ok(evidence)
+    } else {
+        Err(eyre!("unknown commit type: {}", semantic_commit.title))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn reserved_state() -> ReservedState {
+        test_utils::generate_standard_genesis(4).0
+    }
+
     #[test]
     fn format_transaction_commit() {
         let transaction = Commit::Transaction(Transaction {
-            author: PublicKey::zero(),
-            timestamp: 0,
+            author: generate_keypair("format_transaction_commit").0,
+            timestamp: 1234,
             head: "abc".to_string(),
             body: "def".to_string(),
             diff: Diff::None,
         });
         assert_eq!(
             transaction,
-            from_semantic_commit(to_semantic_commit(&transaction)).unwrap()
+            from_semantic_commit(to_semantic_commit(&transaction, reserved_state()).unwrap())
+                .unwrap()
         );
     }
 
@@ -175,7 +270,7 @@ mod tests {
         });
         assert_eq!(
             agenda,
-            from_semantic_commit(to_semantic_commit(&agenda)).unwrap()
+            from_semantic_commit(to_semantic_commit(&agenda, reserved_state()).unwrap()).unwrap()
         );
     }
 
@@ -197,7 +292,7 @@ mod tests {
         });
         assert_eq!(
             block,
-            from_semantic_commit(to_semantic_commit(&block)).unwrap()
+            from_semantic_commit(to_semantic_commit(&block, reserved_state()).unwrap()).unwrap()
         );
     }
 
@@ -210,10 +305,49 @@ mod tests {
         });
         assert_eq!(
             agenda_proof,
-            from_semantic_commit(to_semantic_commit(&agenda_proof)).unwrap()
+            from_semantic_commit(to_semantic_commit(&agenda_proof, reserved_state()).unwrap())
+                .unwrap()
         );
     }
 
+    #[test]
+    fn format_extra_agenda_transaction_commit() {
+        let (reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        let delegate =
+            Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Delegate(TxDelegate {
+                delegator: keys[1].0.clone(),
+                delegatee: keys[2].0.clone(),
+                governance: true,
+                proof: TypedSignature::new(Signature::zero(), PublicKey::zero()),
+                timestamp: 123,
+            }));
+        let semantic_commit = to_semantic_commit(&delegate, reserved_state.clone()).unwrap();
+        let mut delegated = reserved_state.clone();
+        delegated.members[1].consensus_delegatee = Some("member-0002".to_owned());
+        delegated.members[1].governance_delegatee = Some("member-0002".to_owned());
+        delegated.consensus_leader_order.remove(1);
+        assert_eq!(
+            semantic_commit.diff,
+            Diff::Reserved(Box::new(delegated.clone()))
+        );
+        assert_eq!(delegate, from_semantic_commit(semantic_commit).unwrap());
+        let undelegate =
+            Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Undelegate(TxUndelegate {
+                delegator: keys[1].0.clone(),
+                proof: TypedSignature::new(Signature::zero(), PublicKey::zero()),
+                timestamp: 123,
+            }));
+        let mut semantic_commit = to_semantic_commit(&undelegate, delegated).unwrap();
+        assert_eq!(
+            undelegate,
+            from_semantic_commit(semantic_commit.clone()).unwrap()
+        );
+        semantic_commit.title = ">tx-delegate".to_owned();
+        assert!(from_semantic_commit(semantic_commit).is_err());
+        // Member 1 has not delegated.
+        assert!(to_semantic_commit(&undelegate, reserved_state).is_err());
+    }
+
     #[test]
     fn format_fp() {
         let fp = LastFinalizationProof {
@@ -228,4 +362,40 @@ mod tests {
             fp_from_semantic_commit(fp_to_semantic_commit(&fp)).unwrap()
         );
     }
+
+    #[test]
+    fn format_evidence() {
+        let block = BlockHeader {
+            height: 3,
+            author: PublicKey::zero(),
+            prev_block_finalization_proof: vec![TypedSignature::new(
+                Signature::zero(),
+                PublicKey::zero(),
+            )],
+            previous_hash: Hash256::hash("hello1"),
+            timestamp: 0,
+            commit_merkle_root: Hash256::hash("hello2"),
+            repository_merkle_root: Hash256::hash("hello3"),
+            validator_set: vec![(PublicKey::zero(), 1)],
+            version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+        };
+        let evidence = ForkEvidence {
+            fork_point: crate::CommitHash { hash: [1; 20] },
+            canonical_block: block.clone(),
+            conflicting_block: BlockHeader {
+                timestamp: 1,
+                ..block
+            },
+            conflicting_block_proof: vec![TypedSignature::new(
+                Signature::zero(),
+                PublicKey::zero(),
+            )],
+            conflicting_block_commit: crate::CommitHash { hash: [2; 20] },
+            source: Some("peer".to_owned()),
+        };
+        assert_eq!(
+            evidence,
+            evidence_from_semantic_commit(evidence_to_semantic_commit(&evidence)).unwrap()
+        );
+    }
 }
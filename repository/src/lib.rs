@@ -1,9 +1,13 @@
 pub mod format;
+pub mod policy;
 pub mod raw;
 mod receive;
-mod utils;
-// TODO: integrate the server feature with `DistributedRepository`
+pub mod review;
+mod serve;
 pub mod server;
+pub mod signing;
+mod utils;
+mod verification_cache;
 
 use eyre::eyre;
 use format::*;
@@ -14,9 +18,11 @@ use serde::{Deserialize, Serialize};
 use simperby_common::reserved::ReservedState;
 use simperby_common::verify::CommitSequenceVerifier;
 use simperby_common::*;
-use simperby_network::{NetworkConfig, Peer, SharedKnownPeers};
-use std::{collections::HashSet, fmt};
-use utils::{read_commits, retrieve_local_branches};
+use simperby_network::{Peer, Serve, SharedKnownPeers};
+use std::{collections::HashSet, fmt, sync::Arc};
+use tokio::sync::RwLock;
+use utils::{read_and_verify_commits, read_commits, retrieve_local_branches, CommitError};
+use verification_cache::VerificationCache;
 
 pub type Branch = String;
 pub type Tag = String;
@@ -24,6 +30,8 @@ pub type Tag = String;
 pub const FINALIZED_BRANCH_NAME: &str = "finalized";
 pub const WORK_BRANCH_NAME: &str = "work";
 pub const FP_BRANCH_NAME: &str = "fp";
+pub const EVIDENCE_TAG_PREFIX: &str = "evidence-";
+pub const CHECKPOINT_TAG_NAME: &str = "checkpoint";
 pub const COMMIT_TITLE_HASH_DIGITS: usize = 8;
 pub const TAG_NAME_HASH_DIGITS: usize = 8;
 pub const BRANCH_NAME_HASH_DIGITS: usize = 8;
@@ -90,7 +98,128 @@ pub struct Config {
     /// will be considered a long range attack and thus ignored.
     ///
     /// If zero, fork can be detected only from the currently last-finalized commit.
+    ///
+    /// A forked branch within the distance is also rejected, but checked for a finalized block
+    /// that conflicts with the `finalized` branch (see [`DistributedRepository::list_evidence`]).
     pub long_range_attack_distance: usize,
+    /// The file where the commit sequence verification results are cached,
+    /// so that the already-verified commits are not verified again.
+    ///
+    /// The file is signed with the private key of the repository, so that a tampered file is
+    /// not trusted. If `None` or there is no private key, the results are cached only in memory.
+    pub verification_cache_path: Option<String>,
+}
+
+/// The configuration for [`DistributedRepository::serve`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// The root directory of the Simperby node, whose `repository/repo` is served by the Git server.
+    ///
+    /// If `None`, the Git server is not run (e.g., for an in-memory repository).
+    pub path: Option<String>,
+    /// The port to run the Git server on.
+    pub port: u16,
+    /// The Simperby executable that the push hook runs (see [`server::run_server`]).
+    ///
+    /// If `None`, the Git server does not accept any push.
+    pub simperby_executable_path: Option<String>,
+    /// The interval of the fetch from the peers. If none, it will not fetch in `serve()`.
+    pub fetch_interval: Option<std::time::Duration>,
+    /// The interval of receiving the pushed branches and cleaning the outdated ones.
+    /// If none, it will do neither in `serve()`.
+    pub update_interval: Option<std::time::Duration>,
+}
+
+/// A proof that a block conflicting with the `finalized` branch has been finalized,
+/// which means that the safety of the consensus has been violated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkEvidence {
+    /// The last common commit of the `finalized` branch and the forked branch.
+    pub fork_point: CommitHash,
+    /// The first block after the fork point in the `finalized` branch.
+    pub canonical_block: BlockHeader,
+    /// The finalized block in the forked branch.
+    pub conflicting_block: BlockHeader,
+    /// The finalization proof of `conflicting_block`.
+    pub conflicting_block_proof: FinalizationProof,
+    /// The commit of `conflicting_block`.
+    pub conflicting_block_commit: CommitHash,
+    /// The name of the peer (remote) that the forked branch was received from, if known.
+    pub source: Option<String>,
+}
+
+/// The data that a node signs to authenticate its push to a peer.
+///
+/// The signature is carried in the push option as `<timestamp> <signature>`,
+/// and the pre-receive hook of the peer checks it with `simperby check-push`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushVerifier {
+    pub commit_hash: CommitHash,
+    pub branch_name: Branch,
+    pub timestamp: Timestamp,
+}
+
+impl ToHash256 for PushVerifier {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+impl PushVerifier {
+    /// Signs the data and encodes it as a push option.
+    pub fn to_push_option(&self, private_key: &PrivateKey) -> Result<String, Error> {
+        let signature = Signature::sign(self.to_hash256(), private_key)?;
+        Ok(format!("{} {}", self.timestamp, signature))
+    }
+
+    /// Decodes the timestamp and the signature from a push option made by [`Self::to_push_option`].
+    pub fn decode_push_option(push_option: &str) -> Result<(Timestamp, Signature), Error> {
+        let (timestamp, signature) = push_option
+            .split_once(' ')
+            .ok_or_else(|| eyre!("push option must be `<timestamp> <signature>`"))?;
+        let timestamp = timestamp
+            .parse()
+            .map_err(|_| eyre!("invalid timestamp: {}", timestamp))?;
+        let signature = Signature::from_array(
+            hex::decode(signature)
+                .map_err(|_| eyre!("invalid signature: {}", signature))?
+                .as_slice()
+                .try_into()
+                .map_err(|_| eyre!("a signature must be in 65 bytes"))?,
+        );
+        Ok((timestamp, signature))
+    }
+}
+
+/// A finalized block that a new member trusts, instead of verifying the history from the genesis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The hash of the block header.
+    pub block_hash: Hash256,
+    /// The finalization proof of the block.
+    pub proof: FinalizationProof,
+}
+
+/// What has been removed by [`DistributedRepository::clean`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanReport {
+    /// The deleted branches, with the reasons.
+    pub branches: Vec<(Branch, String)>,
+    /// The removed remote repositories.
+    pub remotes: Vec<String>,
+    /// The removed tags.
+    pub tags: Vec<Tag>,
+}
+
+/// What has been done by [`DistributedRepository::rebase`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RebaseReport {
+    /// The replayed transactions, with the commits before and after the rebase.
+    pub replayed: Vec<(CommitHash, CommitHash)>,
+    /// The replayed transactions whose timestamps have been renewed.
+    pub restamped: Vec<CommitHash>,
+    /// The dropped commits, with the reasons.
+    pub dropped: Vec<(CommitHash, String)>,
 }
 
 /// The local Simperby blockchain data repository.
@@ -103,6 +232,8 @@ pub struct DistributedRepository<T> {
     raw: T,
     config: Config,
     peers: SharedKnownPeers,
+    private_key: Option<PrivateKey>,
+    verification_cache: tokio::sync::Mutex<VerificationCache>,
 }
 
 fn get_timestamp() -> Timestamp {
@@ -120,8 +251,40 @@ impl<T: RawRepository> DistributedRepository<T> {
         &self.raw
     }
 
-    pub async fn new(raw: T, config: Config, peers: SharedKnownPeers) -> Result<Self, Error> {
-        Ok(Self { raw, config, peers })
+    pub async fn new(
+        raw: T,
+        config: Config,
+        peers: SharedKnownPeers,
+        private_key: Option<PrivateKey>,
+    ) -> Result<Self, Error> {
+        let verification_cache =
+            VerificationCache::load(config.verification_cache_path.clone(), private_key.clone())
+                .await;
+        Ok(Self {
+            raw,
+            config,
+            peers,
+            private_key,
+            verification_cache: tokio::sync::Mutex::new(verification_cache),
+        })
+    }
+
+    /// Lists the evidences of conflicting finalization found while receiving branches.
+    ///
+    /// Each evidence is persisted as a commit on top of the conflicting block,
+    /// tagged with `evidence-#` (the fork point), so that it survives `clean`
+    /// and the garbage collection.
+    pub async fn list_evidence(&self) -> Result<Vec<ForkEvidence>, Error> {
+        let mut result = Vec::new();
+        for tag in self.raw.list_tags().await? {
+            if !tag.starts_with(EVIDENCE_TAG_PREFIX) {
+                continue;
+            }
+            let commit_hash = self.raw.locate_tag(tag).await?;
+            let semantic_commit = self.raw.read_semantic_commit(commit_hash).await?;
+            result.push(format::evidence_from_semantic_commit(semantic_commit)?);
+        }
+        Ok(result)
     }
 
     /// Initializes the genesis repository, leaving a genesis header.
@@ -134,7 +297,7 @@ impl<T: RawRepository> DistributedRepository<T> {
     pub async fn genesis(&mut self) -> Result<(), Error> {
         let reserved_state = self.get_reserved_state().await?;
         let block_commit = Commit::Block(reserved_state.genesis_info.header.clone());
-        let semantic_commit = to_semantic_commit(&block_commit);
+        let semantic_commit = to_semantic_commit(&block_commit, reserved_state.clone())?;
 
         self.raw.checkout_clean().await?;
         // TODO: ignore only if the error is 'already exists'. Otherwise, propagate the error.
@@ -152,15 +315,142 @@ impl<T: RawRepository> DistributedRepository<T> {
         // TODO: ignore only if the error is 'already exists'. Otherwise, propagate the error.
         let _ = self.raw.create_branch(FP_BRANCH_NAME.into(), result).await;
         self.raw.checkout(FP_BRANCH_NAME.into()).await?;
+        self.create_fp_commit(&LastFinalizationProof {
+            height: 0,
+            proof: reserved_state.genesis_info.genesis_proof.clone(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Initializes a cloned repository from the given checkpoint,
+    /// trusting it instead of verifying the history from the genesis.
+    ///
+    /// It
+    /// 1. finds the block of the checkpoint in the history of `origin/finalized`.
+    /// 2. verifies the finalization proof of the checkpoint.
+    /// 3. creates the `finalized`, `work` and `fp` branches on the checkpoint,
+    ///    and records it with the `checkpoint` tag.
+    /// 4. verifies every commit from the checkpoint to `origin/finalized`
+    ///    with the finalization proof in `origin/fp`, and syncs `finalized` and `work` to it.
+    ///
+    /// Note that the history before the checkpoint is still cloned, but is never read nor verified.
+    /// A shallow clone can't be used because libgit2 fails to walk the history of it.
+    pub async fn initialize_from_checkpoint(
+        &mut self,
+        checkpoint: &Checkpoint,
+    ) -> Result<(), Error> {
+        let remote_finalized = self
+            .raw
+            .locate_remote_tracking_branch("origin".into(), FINALIZED_BRANCH_NAME.into())
+            .await?;
+        let (checkpoint_commit_hash, header) = self
+            .find_block_commit(remote_finalized, |header| {
+                header.to_hash256() == checkpoint.block_hash
+            })
+            .await?
+            .ok_or_else(|| {
+                eyre!(
+                    "checkpoint {} is not in the history of `origin/finalized`",
+                    checkpoint.block_hash
+                )
+            })?;
+
+        self.raw.checkout_clean().await?;
+        self.raw.checkout_detach(checkpoint_commit_hash).await?;
+        let reserved_state = self.get_reserved_state().await?;
+        CommitSequenceVerifier::new(header.clone(), reserved_state)
+            .map_err(|e| eyre!("checkpoint is not accepted by CSV: {}", e))?
+            .verify_last_header_finalization(&checkpoint.proof)
+            .map_err(|e| eyre!("verification error on the checkpoint: {}", e))?;
+
+        let branches = self.raw.list_branches().await?;
+        for branch in [FINALIZED_BRANCH_NAME, WORK_BRANCH_NAME, FP_BRANCH_NAME] {
+            if branches.iter().any(|b| b == branch) {
+                self.raw
+                    .move_branch(branch.into(), checkpoint_commit_hash)
+                    .await?;
+            } else {
+                self.raw
+                    .create_branch(branch.into(), checkpoint_commit_hash)
+                    .await?;
+            }
+        }
+        self.raw.checkout(FP_BRANCH_NAME.into()).await?;
+        self.create_fp_commit(&LastFinalizationProof {
+            height: header.height,
+            proof: checkpoint.proof.clone(),
+        })
+        .await?;
+        // The tag might have been cloned from a remote repository with its own checkpoint.
+        if self
+            .raw
+            .list_tags()
+            .await?
+            .iter()
+            .any(|tag| tag == CHECKPOINT_TAG_NAME)
+        {
+            self.raw.remove_tag(CHECKPOINT_TAG_NAME.into()).await?;
+        }
         self.raw
-            .create_semantic_commit(fp_to_semantic_commit(&LastFinalizationProof {
-                height: 0,
-                proof: reserved_state.genesis_info.genesis_proof.clone(),
-            }))
+            .create_tag(CHECKPOINT_TAG_NAME.into(), checkpoint_commit_hash)
             .await?;
+
+        if remote_finalized != checkpoint_commit_hash {
+            let remote_fp = self
+                .raw
+                .locate_remote_tracking_branch("origin".into(), FP_BRANCH_NAME.into())
+                .await?;
+            let fp = fp_from_semantic_commit(self.raw.read_semantic_commit(remote_fp).await?)?;
+            self.sync_commit(remote_finalized, &fp.proof).await?;
+            self.raw
+                .move_branch(WORK_BRANCH_NAME.into(), remote_finalized)
+                .await?;
+        }
         Ok(())
     }
 
+    /// Returns the block commit and its header of the checkpoint
+    /// that the repository has been initialized from, if any.
+    pub async fn get_checkpoint(&self) -> Result<Option<(CommitHash, BlockHeader)>, Error> {
+        if !self
+            .raw
+            .list_tags()
+            .await?
+            .iter()
+            .any(|tag| tag == CHECKPOINT_TAG_NAME)
+        {
+            return Ok(None);
+        }
+        let commit_hash = self.raw.locate_tag(CHECKPOINT_TAG_NAME.into()).await?;
+        if let Commit::Block(header) = self.read_commit(commit_hash).await? {
+            Ok(Some((commit_hash, header)))
+        } else {
+            Err(eyre!(IntegrityError {
+                msg: "`checkpoint` tag is not on a block".to_owned(),
+            }))
+        }
+    }
+
+    /// Finds the latest block commit that satisfies the predicate,
+    /// among the given commit and its ancestors.
+    async fn find_block_commit(
+        &self,
+        commit_hash: CommitHash,
+        predicate: impl Fn(&BlockHeader) -> bool,
+    ) -> Result<Option<(CommitHash, BlockHeader)>, Error> {
+        let ancestors = self.raw.list_ancestors(commit_hash, None).await?;
+        for commit_hash in std::iter::once(commit_hash).chain(ancestors) {
+            // The commits before the genesis are not Simperby commits.
+            if let Ok(Commit::Block(header)) = self.read_commit(commit_hash).await {
+                if predicate(&header) {
+                    return Ok(Some((commit_hash, header)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     /// Returns the block header from the `finalized` branch.
     pub async fn get_last_finalized_block_header(&self) -> Result<BlockHeader, Error> {
         let commit_hash = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
@@ -185,6 +475,19 @@ impl<T: RawRepository> DistributedRepository<T> {
         self.raw.read_reserved_state().await.map_err(|e| eyre!(e))
     }
 
+    /// Returns the content policy from the `finalized` branch.
+    pub async fn get_content_policy(&self) -> Result<ContentPolicy, Error> {
+        policy::read_content_policy(self).await
+    }
+
+    /// Reviews the agenda of the given `a-#` branch.
+    ///
+    /// It lists every transaction that the agenda would finalize with the files it changes
+    /// and the changes of the reserved state, for a human to review before voting.
+    pub async fn review_agenda(&self, branch: &str) -> Result<review::AgendaReview, Error> {
+        review::review_agenda(self, branch).await
+    }
+
     /// Cleans all the outdated commits, remote repositories and branches.
     ///
     /// It will leave only
@@ -196,47 +499,226 @@ impl<T: RawRepository> DistributedRepository<T> {
     /// - the `p` branch
     /// - the `a-#` branches
     /// - the `b-#` branches
+    ///
     /// if only the branches are not outdated (branched from the last finalized commit).
-    pub async fn clean(&mut self) -> Result<(), Error> {
+    /// The remaining `a-#` and `b-#` branches are verified again, and deleted if invalid.
+    ///
+    /// If `hard` is set, it removes all the branches except `finalized`, `work` and `fp`,
+    /// the `vote-#` and `veto-#` tags on the outdated commits, and the orphan commits.
+    pub async fn clean(&mut self, hard: bool) -> Result<CleanReport, Error> {
         let finalized_branch_commit_hash =
             self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
-
-        let branches = self.raw.list_branches().await?;
+        let mut report = CleanReport::default();
 
         // delete outdated p branch, a-# branches, b-# branches
-        for branch in branches {
-            if !(branch.as_str() == WORK_BRANCH_NAME
+        for branch in self.raw.list_branches().await? {
+            if branch.as_str() == WORK_BRANCH_NAME
                 || branch.as_str() == FINALIZED_BRANCH_NAME
-                || branch.as_str() == FP_BRANCH_NAME)
+                || branch.as_str() == FP_BRANCH_NAME
             {
-                let branch_commit = self.raw.locate_branch(branch.clone()).await?;
+                continue;
+            }
+            let branch_commit = self.raw.locate_branch(branch.clone()).await?;
+            let reason = if hard {
+                Some("hard clean".to_owned())
+            } else if finalized_branch_commit_hash
+                != self
+                    .raw
+                    .find_merge_base(branch_commit, finalized_branch_commit_hash)
+                    .await?
+            {
+                Some("outdated".to_owned())
+            } else if branch.starts_with("a-") || branch.starts_with("b-") {
+                utils::verify_branch(self, &branch, branch_commit)
+                    .await?
+                    .err()
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                self.raw.delete_branch(branch.clone()).await?;
+                report.branches.push((branch, reason));
+            }
+        }
+
+        // remove remote branches
+        for (remote_name, _) in self.raw.list_remotes().await? {
+            self.raw.remove_remote(remote_name.clone()).await?;
+            report.remotes.push(remote_name);
+        }
 
+        if hard {
+            for tag in self.raw.list_tags().await? {
+                if !(tag.starts_with("vote-") || tag.starts_with("veto-")) {
+                    continue;
+                }
+                let tag_commit = self.raw.locate_tag(tag.clone()).await?;
                 if finalized_branch_commit_hash
                     != self
                         .raw
-                        .find_merge_base(branch_commit, finalized_branch_commit_hash)
+                        .find_merge_base(tag_commit, finalized_branch_commit_hash)
                         .await?
                 {
-                    self.raw.delete_branch(branch.to_string()).await?;
+                    self.raw.remove_tag(tag.clone()).await?;
+                    report.tags.push(tag);
                 }
             }
+            self.raw.run_garbage_collection().await?;
         }
 
-        // remove remote branches
-        let remote_list = self.raw.list_remotes().await?;
-        for (remote_name, _) in remote_list {
-            self.raw.remove_remote(remote_name).await?;
+        for (branch, reason) in &report.branches {
+            info!("deleted branch {}: {}", branch, reason);
         }
+        Ok(report)
+    }
 
-        // TODO : CSV
+    /// Rebases the `work` branch on the `finalized` branch, without any merge commit.
+    ///
+    /// It replays the transactions of `work` that are not finalized yet
+    /// onto the `finalized` tip, in order. The other commits (e.g., the agendas for an outdated height)
+    /// are dropped as they are valid only for their height, and so are the transactions
+    /// that `finalized` already contains (compared by the semantic hash).
+    /// A transaction older than the last finalized block is re-stamped with the block's timestamp,
+    /// keeping the chronological order.
+    ///
+    /// It fails if a transaction conflicts with `finalized` in either the reserved or non-reserved area,
+    /// or if the rebased commits are not accepted by the CSV or the content policy.
+    /// In such cases, `work` is left as is.
+    pub async fn rebase(&mut self) -> Result<RebaseReport, Error> {
+        let finalized = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let work = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+        let merge_base = self.raw.find_merge_base(finalized, work).await?;
+        let mut report = RebaseReport::default();
+        if merge_base == finalized {
+            return Ok(report);
+        }
+        let commits = read_commits(self, merge_base, work)
+            .await
+            .map_err(|e| eyre!("failed to read the commits to rebase: {}", e))?;
+        let finalized_commits = read_commits(self, merge_base, finalized)
+            .await
+            .map_err(|e| eyre!("failed to read the finalized commits: {}", e))?
+            .into_iter()
+            .map(|(commit, _)| commit.to_hash256())
+            .collect::<HashSet<_>>();
+        let mut min_timestamp = self.get_last_finalized_block_header().await?.timestamp;
 
-        Ok(())
+        self.raw.checkout_clean().await?;
+        self.raw.checkout_detach_with_tree(finalized).await?;
+        let result = async {
+            for (commit, commit_hash) in commits {
+                let semantic_hash = commit.to_hash256();
+                let transaction = if let Commit::Transaction(transaction) = commit {
+                    transaction
+                } else {
+                    report
+                        .dropped
+                        .push((commit_hash, "not a transaction".to_owned()));
+                    continue;
+                };
+                if finalized_commits.contains(&semantic_hash) {
+                    report
+                        .dropped
+                        .push((commit_hash, "already finalized".to_owned()));
+                    continue;
+                }
+                let timestamp = if transaction.timestamp < min_timestamp {
+                    report.restamped.push(commit_hash);
+                    min_timestamp
+                } else {
+                    transaction.timestamp
+                };
+                min_timestamp = timestamp;
+                match self.raw.cherry_pick(commit_hash, timestamp).await {
+                    Ok(x) => report.replayed.push((commit_hash, x)),
+                    Err(raw::Error::Conflict(e)) => {
+                        return Err(eyre!(
+                            "transaction {} conflicts with `finalized`: {}",
+                            commit_hash,
+                            e
+                        ))
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let tip = self.raw.get_head().await?;
+            if tip != finalized {
+                let (commits, _) = read_and_verify_commits(self, tip)
+                    .await
+                    .map_err(|e| eyre!("the rebased commits are invalid: {}", e))?;
+                policy::check_content_policy(self, &commits)
+                    .await?
+                    .map_err(|e| eyre!(e))?;
+            }
+            Ok(tip)
+        }
+        .await;
+
+        match result {
+            Ok(tip) => {
+                self.raw.move_branch(WORK_BRANCH_NAME.into(), tip).await?;
+                self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
+                Ok(report)
+            }
+            Err(e) => {
+                self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
+                Err(e)
+            }
+        }
     }
 
     /// Broadcasts all the local messages.
-    pub async fn broadcast(&mut self) -> Result<(), Error> {
-        // TODO: perform git push
-        Ok(())
+    ///
+    /// It pushes the `a-#` branches, the `b-#` branches and the `fp` branch to every known peer,
+    /// signing each push with the private key (see [`PushVerifier`]).
+    ///
+    /// Returns the result for each peer, with the reasons if some of the branches are rejected.
+    pub async fn broadcast(&mut self) -> Result<Vec<(String, Result<(), String>)>, Error> {
+        let private_key = self
+            .private_key
+            .clone()
+            .ok_or_else(|| eyre!("a private key is required to broadcast"))?;
+        let peers = self.peers.read().await;
+        utils::add_remotes(self, &peers).await?;
+
+        let mut branches = Vec::new();
+        for branch in self.raw.list_branches().await? {
+            if branch.starts_with("a-") || branch.starts_with("b-") || branch == FP_BRANCH_NAME {
+                let commit_hash = self.raw.locate_branch(branch.clone()).await?;
+                branches.push((branch, commit_hash));
+            }
+        }
+
+        let mut result = Vec::new();
+        for peer in peers {
+            let mut errors = Vec::new();
+            for (branch, commit_hash) in &branches {
+                let push_option = PushVerifier {
+                    commit_hash: *commit_hash,
+                    branch_name: branch.clone(),
+                    timestamp: get_timestamp(),
+                }
+                .to_push_option(&private_key)?;
+                if let Err(e) = self
+                    .raw
+                    .push_option(peer.name.clone(), branch.clone(), Some(push_option))
+                    .await
+                {
+                    errors.push(format!("{branch}: {e}"));
+                }
+            }
+            if errors.is_empty() {
+                result.push((peer.name, Ok(())));
+            } else {
+                warn!(
+                    "failed to broadcast to {}: {}",
+                    peer.name,
+                    errors.join(", ")
+                );
+                result.push((peer.name, Err(errors.join(", "))));
+            }
+        }
+        Ok(result)
     }
 
     /// Fetches new commits from the network.
@@ -251,7 +733,8 @@ impl<T: RawRepository> DistributedRepository<T> {
     ///
     /// It may leave some remote repository (representing each peer) after the operation.
     ///
-    /// TODO: add fork detection logic considering the long range attack distance.
+    /// Branches forking off the `finalized` branch are rejected;
+    /// see [`Config::long_range_attack_distance`] and [`Self::list_evidence`].
     pub async fn fetch(&mut self) -> Result<(), Error> {
         utils::add_remotes(self, &self.peers.read().await).await?;
         // TODO: handle this
@@ -264,7 +747,7 @@ impl<T: RawRepository> DistributedRepository<T> {
                 branch_name,
                 serde_spb::to_string(&commit_hash).unwrap()
             );
-            let result = receive::receive(self, commit_hash).await?;
+            let result = receive::receive(self, commit_hash, Some(remote_name)).await?;
             if let Err(e) = result {
                 warn!("failed to apply remote branch {}: {}", branch_displayed, e);
             }
@@ -284,18 +767,58 @@ impl<T: RawRepository> DistributedRepository<T> {
         &mut self,
         commit_hash: CommitHash,
     ) -> Result<Result<(), String>, Error> {
-        receive::receive(self, commit_hash).await
+        receive::receive(self, commit_hash, None).await
     }
 
-    /// Serves the distributed repository protocol indefinitely.
+    /// Serves the distributed repository protocol until the returned handle is joined.
+    ///
+    /// It
+    /// - runs the Git server for the peers to fetch from and push to.
+    /// - periodically fetches from the peers.
+    /// - periodically receives the pushed branches and cleans the outdated ones.
+    ///
     /// It **verifies** all the incoming changes and applies them to the local repository
     /// only if they are valid.
-    pub async fn serve(
-        self,
-        _network_config: &NetworkConfig,
-        _peers: SharedKnownPeers,
-    ) -> Result<tokio::task::JoinHandle<Result<(), Error>>, Error> {
-        unimplemented!()
+    pub async fn serve(self, config: ServerConfig) -> Result<Serve<Self, Error>, Error> {
+        let known_branches = retrieve_local_branches(&self.raw).await?;
+        let this = Arc::new(RwLock::new(self));
+        let (termination_switch, termination_receiver) = tokio::sync::oneshot::channel::<()>();
+        let this_ = Arc::clone(&this);
+        let task = tokio::spawn(async move {
+            let git_server = if let Some(path) = &config.path {
+                Some(if let Some(executable) = &config.simperby_executable_path {
+                    server::run_server(path, config.port, executable).await
+                } else {
+                    server::run_server_legacy(&format!("{path}/repository"), config.port).await
+                })
+            } else {
+                None
+            };
+
+            let fetch_task = serve::serve_fetch(Arc::clone(&this_), config.fetch_interval)
+                .map(|r| r.map(|_| false));
+            let update_task =
+                serve::serve_update(Arc::clone(&this_), config.update_interval, known_branches)
+                    .map(|r| r.map(|_| false));
+            let mut tasks = vec![
+                fetch_task.boxed(),
+                update_task.boxed(),
+                termination_receiver.map(|_| Ok(true)).boxed(),
+            ];
+            loop {
+                let (result, _, remaining_futures) = future::select_all(tasks).await;
+                if result? {
+                    // `remaining_futures` drops here.
+                    break;
+                }
+                tasks = remaining_futures;
+            }
+            drop(git_server);
+            Ok(Arc::try_unwrap(this_)
+                .map_err(|_| eyre!("the repository is still being read"))?
+                .into_inner())
+        });
+        Ok(Serve::new(task, termination_switch, this))
     }
 
     /// Checks the validity of the repository, starting from the given height.
@@ -306,8 +829,92 @@ impl<T: RawRepository> DistributedRepository<T> {
     /// 3. the existence of merge commits
     /// 4. the canonical history of the `finalized` branch.
     /// 5. the reserved state in a valid format.
-    pub async fn check(&self, _starting_height: BlockHeight) -> Result<bool, Error> {
-        unimplemented!()
+    ///
+    /// The block at `starting_height` is trusted, so it must not be lower than the checkpoint
+    /// if the repository has been initialized from one.
+    pub async fn check(&self, starting_height: BlockHeight) -> Result<bool, Error> {
+        if let Some((_, checkpoint)) = self.get_checkpoint().await? {
+            if starting_height < checkpoint.height {
+                return Err(eyre!(
+                    "the history before the checkpoint (height {}) is not trusted",
+                    checkpoint.height
+                ));
+            }
+        }
+        let finalized = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let (starting_commit_hash, starting_header) = self
+            .find_block_commit(finalized, |header| header.height == starting_height)
+            .await?
+            .ok_or_else(|| eyre!("there is no finalized block at height {}", starting_height))?;
+        match self
+            .check_from(starting_commit_hash, starting_header)
+            .await?
+        {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                warn!("the repository is invalid: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    async fn check_from(
+        &self,
+        starting_commit_hash: CommitHash,
+        starting_header: BlockHeader,
+    ) -> Result<Result<(), String>, Error> {
+        let finalized = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let work = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+        let fp = self.raw.locate_branch(FP_BRANCH_NAME.into()).await?;
+        if self.raw.find_merge_base(finalized, work).await? != finalized {
+            return Ok(Err("`work` is not rebased on `finalized`".to_owned()));
+        }
+        if self.raw.list_ancestors(fp, Some(1)).await? != vec![finalized] {
+            return Ok(Err("`fp` is not on top of `finalized`".to_owned()));
+        }
+
+        let reserved_state = self
+            .raw
+            .read_reserved_state_at_commit(starting_commit_hash)
+            .await;
+        let reserved_state = match reserved_state {
+            Ok(x) => x,
+            Err(e) => return Ok(Err(format!("invalid reserved state: {e}"))),
+        };
+        let mut verifier = match CommitSequenceVerifier::new(starting_header, reserved_state) {
+            Ok(x) => x,
+            Err(e) => return Ok(Err(format!("starting block is not accepted by CSV: {e}"))),
+        };
+        let commits = match read_commits(self, starting_commit_hash, finalized).await {
+            Ok(x) => x,
+            Err(CommitError::Raw(e)) => return Err(e.into()),
+            Err(CommitError::Other(e)) => return Err(e),
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        for (commit, hash) in commits {
+            if let Err(e) = verifier.apply_commit(&commit) {
+                return Ok(Err(format!(
+                    "commit sequence verification failed ({hash}): {e}"
+                )));
+            }
+            if let Commit::Block(_) = commit {
+                let files = self.raw.list_files(hash).await?;
+                if let Err(e) = verifier.verify_repository_merkle_root(&files) {
+                    return Ok(Err(format!(
+                        "commit sequence verification failed ({hash}): {e}"
+                    )));
+                }
+            }
+        }
+
+        let fp = match fp_from_semantic_commit(self.raw.read_semantic_commit(fp).await?) {
+            Ok(x) => x,
+            Err(e) => return Ok(Err(format!("invalid `fp` commit: {e}"))),
+        };
+        if let Err(e) = verifier.verify_last_header_finalization(&fp.proof) {
+            return Ok(Err(format!("invalid finalization proof in `fp`: {e}")));
+        }
+        Ok(Ok(()))
     }
 
     /// Synchronizes the `finalized` branch to the given commit.
@@ -326,7 +933,15 @@ impl<T: RawRepository> DistributedRepository<T> {
         let block_branch_name =
             format!("b-{}", &block_hash.to_string()[0..BRANCH_NAME_HASH_DIGITS]);
         let block_commit_hash = self.raw.locate_branch(block_branch_name.clone()).await?;
+        self.sync_commit(block_commit_hash, last_block_proof).await
+    }
 
+    /// Synchronizes the `finalized` branch to the given block commit, as [`Self::sync`] does.
+    async fn sync_commit(
+        &mut self,
+        block_commit_hash: CommitHash,
+        last_block_proof: &FinalizationProof,
+    ) -> Result<(), Error> {
         if block_commit_hash
             == self
                 .raw
@@ -337,18 +952,8 @@ impl<T: RawRepository> DistributedRepository<T> {
             return Ok(());
         }
 
-        // Check if the last commit is a block commit.
-        let current_finalized_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
-        let new_commits =
-            utils::read_commits(self, current_finalized_commit, block_commit_hash).await?;
-        let last_block_header =
-            if let Commit::Block(last_block_header) = &new_commits.last().unwrap().0 {
-                last_block_header
-            } else {
-                return Err(eyre!("the last commit is not a block commit"));
-            };
-
         // Check if the given block commit is a descendant of the current finalized branch
+        let current_finalized_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
         if self
             .raw
             .find_merge_base(current_finalized_commit, block_commit_hash)
@@ -361,18 +966,15 @@ impl<T: RawRepository> DistributedRepository<T> {
         }
 
         // Verify every commit along the way.
-        let last_finalized_block_header = self.get_last_finalized_block_header().await?;
-        let reserved_state = self.get_reserved_state().await?;
-        let mut verifier = CommitSequenceVerifier::new(
-            last_finalized_block_header.clone(),
-            reserved_state.clone(),
-        )
-        .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
-        for (new_commit, new_commit_hash) in &new_commits {
-            verifier
-                .apply_commit(new_commit)
-                .map_err(|e| eyre!("verification error on commit {}: {}", new_commit_hash, e))?;
-        }
+        let (new_commits, verifier) = read_and_verify_commits(self, block_commit_hash).await?;
+
+        // Check if the last commit is a block commit.
+        let last_block_header =
+            if let Commit::Block(last_block_header) = &new_commits.last().unwrap().0 {
+                last_block_header
+            } else {
+                return Err(eyre!("the last commit is not a block commit"));
+            };
         verifier
             .verify_last_header_finalization(last_block_proof)
             .map_err(|e| eyre!("verification error on the last block header: {}", e))?;
@@ -388,12 +990,11 @@ impl<T: RawRepository> DistributedRepository<T> {
             .move_branch(FP_BRANCH_NAME.to_string(), block_commit_hash)
             .await?;
         self.raw.checkout(FP_BRANCH_NAME.into()).await?;
-        self.raw
-            .create_semantic_commit(format::fp_to_semantic_commit(&LastFinalizationProof {
-                height: last_block_header.height,
-                proof: last_block_proof.clone(),
-            }))
-            .await?;
+        self.create_fp_commit(&LastFinalizationProof {
+            height: last_block_header.height,
+            proof: last_block_proof.clone(),
+        })
+        .await?;
         Ok(())
     }
 
@@ -504,17 +1105,7 @@ impl<T: RawRepository> DistributedRepository<T> {
         }
 
         // Verify all the incoming commits
-        let finalized_header = self.get_last_finalized_block_header().await?;
-        let reserved_state = self.get_reserved_state().await?;
-        let finalized_commit_hash = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
-        let commits = utils::read_commits(self, finalized_commit_hash, agenda_commit_hash).await?;
-        let mut verifier = CommitSequenceVerifier::new(finalized_header.clone(), reserved_state)
-            .map_err(|e| eyre!("failed to create a verifier: {}", e))?;
-        for (commit, hash) in commits.iter() {
-            verifier
-                .apply_commit(commit)
-                .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
-        }
+        let (commits, _) = read_and_verify_commits(self, agenda_commit_hash).await?;
         // Verify agenda with agenda proof
         let agenda_commit = commits.iter().map(|(commit, _)| commit).last().unwrap();
         let agenda = match agenda_commit {
@@ -531,7 +1122,8 @@ impl<T: RawRepository> DistributedRepository<T> {
         };
 
         let agenda_proof_commit = Commit::AgendaProof(agenda_proof.clone());
-        let agenda_proof_semantic_commit = format::to_semantic_commit(&agenda_proof_commit);
+        let agenda_proof_semantic_commit =
+            format::to_semantic_commit(&agenda_proof_commit, self.get_reserved_state().await?)?;
         let agenda_proof_branch_name = format!(
             "a-{}",
             &agenda_proof_commit.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
@@ -561,6 +1153,8 @@ impl<T: RawRepository> DistributedRepository<T> {
     }
 
     /// Creates an agenda commit on top of the `work` branch.
+    ///
+    /// The commit is signed if the private key is of the `author`.
     pub async fn create_agenda(
         &mut self,
         author: PublicKey,
@@ -583,15 +1177,10 @@ impl<T: RawRepository> DistributedRepository<T> {
             ));
         }
         // Check the validity of the commit sequence
-        let reserved_state = self.get_reserved_state().await?;
-        let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state)
-            .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
-        let commits = read_commits(self, last_header_commit, work_commit).await?;
-        for (commit, hash) in commits.iter() {
-            verifier
-                .apply_commit(commit)
-                .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
-        }
+        let (commits, _) = read_and_verify_commits(self, work_commit).await?;
+        policy::check_content_policy(self, &commits)
+            .await?
+            .map_err(|e| eyre!(e))?;
 
         // Check whether the commit sequence is in the transaction phase.
         let mut transactions = Vec::new();
@@ -614,11 +1203,10 @@ impl<T: RawRepository> DistributedRepository<T> {
             height: last_header.height + 1,
         };
         let agenda_commit = Commit::Agenda(agenda.clone());
-        let semantic_commit = to_semantic_commit(&agenda_commit);
 
         self.raw.checkout_clean().await?;
         self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
-        let result = self.raw.create_semantic_commit(semantic_commit).await?;
+        let result = self.create_commit_signed_by_author(&agenda_commit).await?;
         let mut agenda_branch_name = agenda_commit.to_hash256().to_string();
         agenda_branch_name.truncate(BRANCH_NAME_HASH_DIGITS);
         let agenda_branch_name = format!("a-{agenda_branch_name}");
@@ -626,7 +1214,74 @@ impl<T: RawRepository> DistributedRepository<T> {
         Ok((agenda, result))
     }
 
+    /// Verifies the signature of the commit and the signatures of the `vote-#` and `veto-#` tags on it.
+    ///
+    /// It fails if any of them is invalid.
+    pub async fn verify_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<signing::CommitSignatures, Error> {
+        signing::verify_commit(self, commit_hash).await
+    }
+
+    /// Creates the commit on `HEAD`, signed if the private key is of its author.
+    async fn create_commit_signed_by_author(
+        &mut self,
+        commit: &Commit,
+    ) -> Result<CommitHash, Error> {
+        let semantic_commit = to_semantic_commit(commit, self.get_reserved_state().await?)?;
+        match &self.private_key {
+            Some(private_key) if private_key.public_key() == semantic_commit.author => {
+                let signature = signing::sign_commit(commit, private_key)?;
+                Ok(self
+                    .raw
+                    .create_signed_semantic_commit(semantic_commit, signature)
+                    .await?)
+            }
+            _ => Ok(self.raw.create_semantic_commit(semantic_commit).await?),
+        }
+    }
+
+    /// Creates the `fp` commit on `HEAD`, signed if there is the private key.
+    ///
+    /// As the `fp` commit has no author, it is signed by the node that records it.
+    pub(crate) async fn create_fp_commit(
+        &mut self,
+        finalization_proof: &LastFinalizationProof,
+    ) -> Result<CommitHash, Error> {
+        let semantic_commit = format::fp_to_semantic_commit(finalization_proof);
+        if let Some(private_key) = &self.private_key {
+            let signature = signing::sign_fp(finalization_proof, private_key)?;
+            Ok(self
+                .raw
+                .create_signed_semantic_commit(semantic_commit, signature)
+                .await?)
+        } else {
+            Ok(self.raw.create_semantic_commit(semantic_commit).await?)
+        }
+    }
+
+    /// Creates the tag, as a signed annotated tag if there is the private key.
+    async fn create_tag_signed(
+        &mut self,
+        tag: Tag,
+        commit: &Commit,
+        commit_hash: CommitHash,
+    ) -> Result<(), Error> {
+        if let Some(private_key) = &self.private_key {
+            let annotation = signing::sign_tag(&tag, commit, private_key, get_timestamp())?;
+            self.raw
+                .create_annotated_tag(tag, commit_hash, annotation)
+                .await?;
+        } else {
+            self.raw.create_tag(tag, commit_hash).await?;
+        }
+        Ok(())
+    }
+
     /// Puts a 'vote' tag on the commit.
+    ///
+    /// If there is the private key, it is an annotated tag carrying the signature (see [`signing`]).
     pub async fn vote(&mut self, commit_hash: CommitHash) -> Result<(), Error> {
         let semantic_commit = self.raw.read_semantic_commit(commit_hash).await?;
         let commit = format::from_semantic_commit(semantic_commit).map_err(|e| eyre!(e))?;
@@ -635,7 +1290,8 @@ impl<T: RawRepository> DistributedRepository<T> {
             let mut vote_tag_name = commit.to_hash256().to_string();
             vote_tag_name.truncate(TAG_NAME_HASH_DIGITS);
             let vote_tag_name = format!("vote-{vote_tag_name}");
-            self.raw.create_tag(vote_tag_name, commit_hash).await?;
+            self.create_tag_signed(vote_tag_name, &commit, commit_hash)
+                .await?;
             Ok(())
         } else {
             Err(eyre!("commit {} is not an agenda commit", commit_hash))
@@ -643,6 +1299,8 @@ impl<T: RawRepository> DistributedRepository<T> {
     }
 
     /// Puts a 'veto' tag on the commit.
+    ///
+    /// If there is the private key, it is an annotated tag carrying the signature (see [`signing`]).
     pub async fn veto(&mut self, commit_hash: CommitHash) -> Result<(), Error> {
         let semantic_commit = self.raw.read_semantic_commit(commit_hash).await?;
         let commit = format::from_semantic_commit(semantic_commit).map_err(|e| eyre!(e))?;
@@ -651,7 +1309,8 @@ impl<T: RawRepository> DistributedRepository<T> {
             let mut veto_tag_name = commit.to_hash256().to_string();
             veto_tag_name.truncate(TAG_NAME_HASH_DIGITS);
             let veto_tag_name = format!("veto-{veto_tag_name}");
-            self.raw.create_tag(veto_tag_name, commit_hash).await?;
+            self.create_tag_signed(veto_tag_name, &commit, commit_hash)
+                .await?;
             Ok(())
         } else {
             Err(eyre!("commit {} is not a block commit", commit_hash))
@@ -659,6 +1318,8 @@ impl<T: RawRepository> DistributedRepository<T> {
     }
 
     /// Creates a block commit on top of the `work` branch.
+    ///
+    /// The commit is signed if the private key is of the `author`.
     pub async fn create_block(
         &mut self,
         author: PublicKey,
@@ -681,17 +1342,10 @@ impl<T: RawRepository> DistributedRepository<T> {
         }
 
         // Check the validity of the commit sequence
-        let commits = read_commits(self, last_header_commit, work_commit).await?;
+        let (commits, _) = read_and_verify_commits(self, work_commit).await?;
         let last_header = self.get_last_finalized_block_header().await?;
         self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
         let reserved_state = self.get_reserved_state().await?;
-        let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state.clone())
-            .map_err(|e| eyre!("verification error on commit {}: {}", last_header_commit, e))?;
-        for (commit, hash) in commits.iter() {
-            verifier
-                .apply_commit(commit)
-                .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
-        }
 
         // Check whether the commit sequence is in the agenda proof phase or
         // extra-agenda transaction phase.
@@ -742,16 +1396,17 @@ impl<T: RawRepository> DistributedRepository<T> {
                     .map(|(commit, _)| commit.clone())
                     .collect::<Vec<_>>(),
             ),
-            repository_merkle_root: Hash256::zero(), // TODO
+            repository_merkle_root: BlockHeader::calculate_repository_merkle_root(
+                &self.raw.list_files(work_commit).await?,
+            ),
             validator_set: reserved_state.get_validator_set().unwrap(),
             version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
         };
         let block_commit = Commit::Block(block_header.clone());
-        let semantic_commit = to_semantic_commit(&block_commit);
 
         self.raw.checkout_clean().await?;
         self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
-        let result = self.raw.create_semantic_commit(semantic_commit).await?;
+        let result = self.create_commit_signed_by_author(&block_commit).await?;
         let mut block_branch_name = block_commit.to_hash256().to_string();
         block_branch_name.truncate(BRANCH_NAME_HASH_DIGITS);
         let block_branch_name = format!("b-{block_branch_name}");
@@ -759,11 +1414,56 @@ impl<T: RawRepository> DistributedRepository<T> {
         Ok((block_header, result))
     }
 
-    /// Creates an agenda commit on top of the `work` branch.
+    /// Creates an extra-agenda transaction commit on top of the `work` branch.
+    ///
+    /// The `work` branch must be on an agenda-proof or another extra-agenda transaction,
+    /// and the transaction is verified against the commit sequence before being committed.
     pub async fn create_extra_agenda_transaction(
         &mut self,
-        _transaction: &ExtraAgendaTransaction,
+        transaction: &ExtraAgendaTransaction,
     ) -> Result<CommitHash, Error> {
-        unimplemented!()
+        let work_commit = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+
+        // Check if the `work` branch is rebased on top of the `finalized` branch.
+        if self
+            .raw
+            .find_merge_base(last_header_commit, work_commit)
+            .await?
+            != last_header_commit
+        {
+            return Err(eyre!(
+                "branch {} should be rebased on {}",
+                WORK_BRANCH_NAME,
+                FINALIZED_BRANCH_NAME
+            ));
+        }
+
+        // Check the validity of the commit sequence
+        let (commits, mut verifier) = read_and_verify_commits(self, work_commit).await?;
+        let (last_commit, _) = commits
+            .last()
+            .ok_or_else(|| eyre!("branch {} is on the finalized block", WORK_BRANCH_NAME))?;
+        match last_commit {
+            Commit::AgendaProof(_) => (),
+            Commit::ExtraAgendaTransaction(_) => (),
+            x => {
+                return Err(eyre!(
+                    "an extra-agenda transaction can't be made on top of a commit {:?}",
+                    x
+                ))
+            }
+        }
+        let commit = Commit::ExtraAgendaTransaction(transaction.clone());
+        verifier
+            .apply_commit(&commit)
+            .map_err(|e| eyre!("invalid extra-agenda transaction: {}", e))?;
+
+        let reserved_state = self.raw.read_reserved_state_at_commit(work_commit).await?;
+        let semantic_commit = to_semantic_commit(&commit, reserved_state)?;
+        self.raw.checkout_clean().await?;
+        self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
+        let result = self.raw.create_semantic_commit(semantic_commit).await?;
+        Ok(result)
     }
 }
@@ -0,0 +1,278 @@
+use super::*;
+use raw::FileChange;
+
+/// The path of the content policy file in the repository.
+///
+/// The policy is a part of the reserved state, so it can be changed only by
+/// a reserved-state transaction in an agenda approved by the governance.
+pub const POLICY_FILE_PATH: &str = "reserved/policy.json";
+
+/// Checks the files changed by a transaction, returning the reason if it violates the policy.
+///
+/// The reserved directory can be changed only by a reserved-state transaction, which the CSV verifies.
+/// The approval of the protected paths is checked separately by [`check_approval`].
+pub fn check_changes(
+    policy: &ContentPolicy,
+    transaction: &Transaction,
+    changes: &[FileChange],
+) -> Result<(), String> {
+    for change in changes {
+        if change.path.starts_with("reserved/") {
+            if let Diff::Reserved(_) = transaction.diff {
+                continue;
+            }
+            return Err(format!(
+                "`{}` is in the reserved directory, which only a reserved-state transaction can change",
+                change.path
+            ));
+        }
+        // Deleting a file is always fine.
+        let size = if let Some(size) = change.size {
+            size
+        } else {
+            continue;
+        };
+        if let Some(max_file_size) = policy.max_file_size {
+            if size > max_file_size {
+                return Err(format!(
+                    "`{}` is {} bytes, exceeding the limit of {} bytes",
+                    change.path, size, max_file_size
+                ));
+            }
+        }
+        if policy.forbid_binary_files && change.binary {
+            return Err(format!(
+                "`{}` is a binary file, which is forbidden",
+                change.path
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the members who must approve the agenda of a transaction that makes the given changes.
+pub fn required_approvers(policy: &ContentPolicy, changes: &[FileChange]) -> Vec<MemberName> {
+    let mut approvers = policy
+        .protected_paths
+        .iter()
+        .filter(|protected_path| {
+            changes
+                .iter()
+                .any(|change| change.path.starts_with(&protected_path.prefix))
+        })
+        .flat_map(|protected_path| protected_path.approvers.iter().cloned())
+        .collect::<Vec<_>>();
+    approvers.sort();
+    approvers.dedup();
+    approvers
+}
+
+/// Checks whether the given agenda proof is signed by all the given approvers.
+///
+/// A member who delegated its governance voting power approves through the delegatee.
+/// Note that this does not verify the signatures; the CSV does.
+pub fn check_approval(
+    reserved_state: &ReservedState,
+    agenda_proof: &AgendaProof,
+    approvers: &[MemberName],
+) -> Result<(), String> {
+    for approver in approvers {
+        let member = reserved_state
+            .members
+            .iter()
+            .find(|member| &member.name == approver)
+            .ok_or_else(|| format!("approver {approver} is not a member"))?;
+        let voter = member.governance_delegatee.as_ref().unwrap_or(&member.name);
+        let voter_public_key = reserved_state
+            .query_public_key(voter)
+            .ok_or_else(|| format!("{voter} is not a member"))?;
+        if !agenda_proof
+            .proof
+            .iter()
+            .any(|signature| signature.signer() == &voter_public_key)
+        {
+            return Err(format!("the agenda is not approved by {approver}"));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the content policy from the reserved state of the `finalized` commit.
+pub(crate) async fn read_content_policy<T: RawRepository>(
+    this: &DistributedRepository<T>,
+) -> Result<ContentPolicy, Error> {
+    Ok(read_finalized_reserved_state(this).await?.content_policy)
+}
+
+async fn read_finalized_reserved_state<T: RawRepository>(
+    this: &DistributedRepository<T>,
+) -> Result<ReservedState, Error> {
+    let finalized = this.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    this.raw
+        .read_reserved_state_at_commit(finalized)
+        .await
+        .map_err(|e| {
+            eyre!(IntegrityError::new(format!(
+                "invalid reserved state in `finalized`: {e}"
+            )))
+        })
+}
+
+/// Checks the transaction commits among the given commits against the content policy.
+///
+/// This is a local check; the commit sequence verifier doesn't enforce the policy.
+///
+/// A transaction changing a protected path is accepted while its agenda is not approved yet,
+/// but the agenda proof that follows must be signed by the approvers of the path.
+///
+/// - Returns `Ok(Ok(()))` if all of them follow the policy.
+/// - Returns `Ok(Err(_))` with the reason if any of them violates the policy.
+/// - Returns `Err(_)` if an error occurs.
+pub(crate) async fn check_content_policy<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    commits: &[(Commit, CommitHash)],
+) -> Result<Result<(), String>, Error> {
+    let reserved_state = read_finalized_reserved_state(this).await?;
+    let policy = &reserved_state.content_policy;
+    // The transactions that wait for the approval, with their approvers.
+    let mut pending_transactions = Vec::new();
+    for (commit, commit_hash) in commits {
+        match commit {
+            Commit::Transaction(transaction) => {
+                let changes = this.raw.list_changed_files(*commit_hash).await?;
+                if let Err(e) = check_changes(policy, transaction, &changes) {
+                    return Ok(Err(format!(
+                        "transaction {commit_hash} violates the content policy: {e}"
+                    )));
+                }
+                let approvers = required_approvers(policy, &changes);
+                if !approvers.is_empty() {
+                    pending_transactions.push((*commit_hash, approvers));
+                }
+            }
+            Commit::AgendaProof(agenda_proof) => {
+                for (transaction_hash, approvers) in pending_transactions.drain(..) {
+                    if let Err(e) = check_approval(&reserved_state, agenda_proof, &approvers) {
+                        return Ok(Err(format!(
+                            "transaction {transaction_hash} violates the content policy: {e}"
+                        )));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_common::test_utils::generate_standard_genesis;
+
+    fn transaction(diff: Diff) -> Transaction {
+        Transaction {
+            author: PublicKey::zero(),
+            timestamp: 0,
+            head: "update".to_owned(),
+            body: String::new(),
+            diff,
+        }
+    }
+
+    fn change(path: &str, size: Option<u64>, binary: bool) -> FileChange {
+        FileChange {
+            path: path.to_owned(),
+            size,
+            binary,
+        }
+    }
+
+    #[test]
+    fn content_policy() {
+        let policy = ContentPolicy {
+            max_file_size: Some(100),
+            forbid_binary_files: true,
+            protected_paths: Vec::new(),
+        };
+        let tx = transaction(Diff::NonReserved(Hash256::hash("")));
+        check_changes(&policy, &tx, &[change("docs/a.md", Some(100), false)]).unwrap();
+        assert!(check_changes(&policy, &tx, &[change("docs/a.md", Some(101), false)]).is_err());
+        assert!(check_changes(&policy, &tx, &[change("docs/a.png", Some(10), true)]).is_err());
+        // Deletion is fine, even for a large binary file.
+        check_changes(&policy, &tx, &[change("docs/a.png", None, false)]).unwrap();
+        // The reserved directory, including the policy itself, is not for a non-reserved transaction.
+        assert!(check_changes(&policy, &tx, &[change(POLICY_FILE_PATH, Some(10), false)]).is_err());
+        assert!(check_changes(
+            &ContentPolicy::default(),
+            &tx,
+            &[change("reserved/members/a.json", None, false)]
+        )
+        .is_err());
+        // Unless it is a reserved-state transaction, which the CSV verifies.
+        let (reserved_state, _) = generate_standard_genesis(4);
+        check_changes(
+            &policy,
+            &transaction(Diff::Reserved(Box::new(reserved_state))),
+            &[change(POLICY_FILE_PATH, Some(1000), true)],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn default_content_policy() {
+        let policy: ContentPolicy = serde_spb::from_str("{}").unwrap();
+        assert_eq!(policy, ContentPolicy::default());
+        let tx = transaction(Diff::NonReserved(Hash256::hash("")));
+        check_changes(
+            &policy,
+            &tx,
+            &[change("treasury/a.bin", Some(u64::MAX), true)],
+        )
+        .unwrap();
+        assert!(required_approvers(&policy, &[change("treasury/a.bin", None, false)]).is_empty());
+    }
+
+    #[test]
+    fn protected_path_approval() {
+        let (mut reserved_state, keys) = generate_standard_genesis(4);
+        let policy = ContentPolicy {
+            max_file_size: None,
+            forbid_binary_files: false,
+            protected_paths: vec![
+                ProtectedPath {
+                    prefix: "treasury/".to_owned(),
+                    approvers: vec!["member-0000".to_owned(), "member-0002".to_owned()],
+                },
+                ProtectedPath {
+                    prefix: "treasury/budget".to_owned(),
+                    approvers: vec!["member-0000".to_owned()],
+                },
+            ],
+        };
+        assert!(required_approvers(&policy, &[change("docs/a.md", Some(1), false)]).is_empty());
+        let approvers = required_approvers(&policy, &[change("treasury/budget.json", None, false)]);
+        assert_eq!(approvers, vec!["member-0000", "member-0002"]);
+
+        let agenda = Agenda {
+            height: 1,
+            author: keys[0].0.clone(),
+            timestamp: 0,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+        };
+        let agenda_proof = AgendaProof {
+            height: 1,
+            agenda_hash: agenda.to_hash256(),
+            proof: keys[0..2]
+                .iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+        };
+        check_approval(&reserved_state, &agenda_proof, &approvers[0..1]).unwrap();
+        assert!(check_approval(&reserved_state, &agenda_proof, &approvers).is_err());
+        assert!(check_approval(&reserved_state, &agenda_proof, &["nobody".to_owned()]).is_err());
+        // A member approves through the delegatee.
+        reserved_state.members[2].governance_delegatee = Some("member-0001".to_owned());
+        check_approval(&reserved_state, &agenda_proof, &approvers).unwrap();
+    }
+}
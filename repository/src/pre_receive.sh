@@ -1,18 +1,20 @@
 #!/bin/sh
-eval "count=\$GIT_PUSH_OPTION_COUNT"
-eval "value=\$GIT_PUSH_OPTION_0"
-simperby_path=\$SIMPERBY_PATH
+count=$GIT_PUSH_OPTION_COUNT
+value=$GIT_PUSH_OPTION_0
+simperby_path=$SIMPERBY_PATH
 
 read oldRev newRev refname
-branch="$(echo $refname | awk '{split($0,a,"/"); print a[3]}')"
+branch="$(echo "$refname" | awk '{split($0,a,"/"); print a[3]}')"
 
-if [ $count != 1 ]
+if [ "$count" != 1 ]
 then
 	echo "The number of push option is not 1"
 	exit 1
 fi
 
-eval "$simperby_path $value $branch"
+# The push option is `<timestamp> <signature>` (see `PushVerifier`),
+# passed as a single argument so that it is never interpreted by the shell.
+"$simperby_path" check-push "$newRev" "$branch" "$value"
 status=$?
 if [ $status != 0 ]
 then exit 1
@@ -12,6 +12,99 @@ pub(crate) struct RawRepositoryImplInner {
     repo: Repository,
 }
 
+/// The email of the Git signature of a semantic commit, which is not used by Simperby
+/// but must be non-empty for Git.
+const SEMANTIC_COMMIT_EMAIL: &str = "simperby";
+
+/// The header of a Git commit where the signature of a signed semantic commit is stored.
+const COMMIT_SIGNATURE_FIELD: &str = "simperby-signature";
+
+/// The header of a Git commit or tag where the timestamp is stored in milliseconds,
+/// since the Git signature records the time only in seconds.
+const TIMESTAMP_FIELD: &str = "simperby-timestamp";
+
+/// Creates the Git signature (used for both the author and the committer) of a semantic commit.
+///
+/// The committer is set to be the same as the author so that the same semantic commit
+/// results in the same commit hash on every node.
+fn semantic_commit_signature(
+    author: &PublicKey,
+    timestamp: Timestamp,
+) -> Result<git2::Signature<'static>, Error> {
+    let time = git2::Time::new(timestamp.div_euclid(1000), 0);
+    git2::Signature::new(&author.to_string(), SEMANTIC_COMMIT_EMAIL, &time).map_err(Error::from)
+}
+
+/// Adds the `simperby-timestamp` header to the end of the headers of a Git object.
+fn add_timestamp_header(object: &[u8], timestamp: Timestamp) -> Result<Vec<u8>, Error> {
+    let end_of_headers = object
+        .windows(2)
+        .position(|x| x == b"\n\n")
+        .ok_or_else(|| Error::Unknown("git object without a message".to_string()))?;
+    let mut result = object[..=end_of_headers].to_vec();
+    result.extend_from_slice(format!("{TIMESTAMP_FIELD} {timestamp}\n").as_bytes());
+    result.extend_from_slice(&object[end_of_headers + 1..]);
+    Ok(result)
+}
+
+/// Runs a Git command in the working directory, without a shell.
+fn run_git(workdir: &str, args: &[&str]) -> Result<(), Error> {
+    let status = std::process::Command::new("git")
+        .current_dir(workdir)
+        .args(args)
+        .status()
+        .map_err(|_| Error::Unknown("failed to execute process".to_string()))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Unknown(format!(
+            "failed to run git {}",
+            args.join(" ")
+        )))
+    }
+}
+
+/// Reads the author and the timestamp of a semantic commit from the Git signature
+/// and the `simperby-timestamp` header, if any.
+///
+/// It fails if the signature name is not a public key (i.e., the commit was not created by Simperby),
+/// or if the header doesn't agree with the time of the signature.
+fn parse_author_and_timestamp(
+    signature: &git2::Signature,
+    timestamp_header: Option<&str>,
+) -> Result<(PublicKey, Timestamp), Error> {
+    let name = signature.name().unwrap_or_default();
+    let author = serde_spb::from_str::<PublicKey>(&format!("\"{name}\""))
+        .map_err(|_| Error::InvalidRepository(format!("invalid author: {name}")))?;
+    let seconds = signature.when().seconds();
+    let timestamp = if let Some(header) = timestamp_header {
+        let timestamp = header
+            .trim()
+            .parse::<Timestamp>()
+            .map_err(|_| Error::InvalidRepository(format!("invalid timestamp: {header}")))?;
+        if timestamp.div_euclid(1000) != seconds {
+            return Err(Error::InvalidRepository(format!(
+                "timestamp {timestamp} doesn't match the signature time {seconds}"
+            )));
+        }
+        timestamp
+    } else {
+        // A commit not created by Simperby has the time in seconds only.
+        seconds * 1000
+    };
+    Ok((author, timestamp))
+}
+
+/// Reads the value of the given header of a raw Git object.
+fn read_header(object: &[u8], field: &str) -> Option<String> {
+    let object = String::from_utf8_lossy(object);
+    object
+        .split("\n\n")
+        .next()?
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{field} ")).map(str::to_owned))
+}
+
 /// TODO: Error handling and its messages
 impl RawRepositoryImplInner {
     pub(crate) fn init(
@@ -36,6 +129,8 @@ impl RawRepositoryImplInner {
                     config.set_str("user.name", "name")?; // TODO: user.name value
                     config.set_str("user.email", "email")?; // TODO: user.email value
                     config.set_str("receive.advertisePushOptions", "true")?;
+                    // Accept a push to the checked-out branch (e.g., `fp`), updating the working tree.
+                    config.set_str("receive.denyCurrentBranch", "updateInstead")?;
                     config.set_str("sendpack.sideband", "false")?;
 
                     // Create an initial empty commit.
@@ -58,6 +153,12 @@ impl RawRepositoryImplInner {
         Self: Sized,
     {
         let repo = Repository::open(directory)?;
+        // libgit2 fails to walk the history beyond the boundary of a shallow repository.
+        if repo.is_shallow() {
+            return Err(Error::InvalidRepository(
+                "shallow repositories are not supported".to_string(),
+            ));
+        }
 
         Ok(Self { repo })
     }
@@ -69,6 +170,7 @@ impl RawRepositoryImplInner {
         let repo = Repository::clone(url, directory)?;
         let mut config = repo.config()?;
         config.set_str("receive.advertisePushOptions", "true")?;
+        config.set_str("receive.denyCurrentBranch", "updateInstead")?;
         config.set_str("sendpack.sideband", "false")?;
 
         Ok(Self { repo })
@@ -217,6 +319,64 @@ impl RawRepositoryImplInner {
         Ok(())
     }
 
+    pub(crate) fn create_annotated_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        annotation: TagAnnotation,
+    ) -> Result<(), Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let object = self.repo.find_object(oid, Some(ObjectType::Commit))?;
+        if !git2::Reference::is_valid_name(&format!("refs/tags/{tag}")) {
+            return Err(Error::Unknown(format!("invalid tag name: {tag}")));
+        }
+        // libgit2 builds (and validates) the tag object, but can't add a header to it,
+        // so the object with the header is written again.
+        let signature = semantic_commit_signature(&annotation.tagger, annotation.timestamp)?;
+        let tag_oid =
+            self.repo
+                .tag_annotation_create(&tag, &object, &signature, &annotation.message)?;
+        let odb = self.repo.odb()?;
+        let content = add_timestamp_header(odb.read(tag_oid)?.data(), annotation.timestamp)?;
+        let tag_oid = odb.write(ObjectType::Tag, &content)?;
+        self.repo
+            .reference(&format!("refs/tags/{tag}"), tag_oid, true, "tag")?;
+
+        Ok(())
+    }
+
+    pub(crate) fn read_tag_annotation(&self, tag: Tag) -> Result<Option<TagAnnotation>, Error> {
+        let reference = self
+            .repo
+            .find_reference(&("refs/tags/".to_owned() + &tag))?;
+        let object = self.repo.find_object(
+            reference
+                .target()
+                .ok_or_else(|| Error::Unknown("err".to_string()))?,
+            None,
+        )?;
+        let tag = if let Some(tag) = object.as_tag() {
+            tag
+        } else {
+            return Ok(None);
+        };
+        let (tagger, timestamp) = if let Some(signature) = tag.tagger() {
+            let odb = self.repo.odb()?;
+            let object = odb.read(tag.id())?;
+            parse_author_and_timestamp(
+                &signature,
+                read_header(object.data(), TIMESTAMP_FIELD).as_deref(),
+            )?
+        } else {
+            (PublicKey::zero(), 0)
+        };
+        Ok(Some(TagAnnotation {
+            tagger,
+            timestamp,
+            message: tag.message().unwrap_or_default().to_owned(),
+        }))
+    }
+
     pub(crate) fn locate_tag(&self, tag: Tag) -> Result<CommitHash, Error> {
         let reference = self
             .repo
@@ -237,9 +397,8 @@ impl RawRepositoryImplInner {
         let references = references
             .into_iter()
             .map(|reference| {
-                let oid = reference
-                    .target()
-                    .ok_or_else(|| Error::Unknown("err".to_string()))?;
+                // An annotated tag points to the tag object, not the commit.
+                let oid = reference.peel(ObjectType::Commit)?.id();
 
                 Ok((reference, oid))
             })
@@ -277,7 +436,8 @@ impl RawRepositoryImplInner {
         fs::write(p.as_str(), "test")
             .map_err(|_| Error::Unknown("full directory path does not exist".to_string()))?;
 
-        let sig = self.repo.signature()?;
+        // The author is `PublicKey::zero()` as there is no author given.
+        let sig = git2::Signature::now(&PublicKey::zero().to_string(), SEMANTIC_COMMIT_EMAIL)?;
         let mut index = self.repo.index()?;
         index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
         let id = index.write_tree()?;
@@ -308,31 +468,26 @@ impl RawRepositoryImplInner {
         &mut self,
         commit: SemanticCommit,
     ) -> Result<CommitHash, Error> {
-        match commit.diff {
-            Diff::None => {
-                let sig = self.repo.signature()?;
-                let mut index = self.repo.index()?;
-                let id = index.write_tree()?;
-                let tree = self.repo.find_tree(id)?;
-                let commit_message = format!("{}{}{}", commit.title, "\n\n", commit.body); // TODO: Check "\n" divides commit message's head and body.
-                let head = self.get_head()?;
-                let parent_oid = git2::Oid::from_bytes(&head.hash)?;
-                let parent_commit = self.repo.find_commit(parent_oid)?;
-
-                let oid = self.repo.commit(
-                    Some("HEAD"),
-                    &sig,
-                    &sig,
-                    commit_message.as_str(),
-                    &tree,
-                    &[&parent_commit],
-                )?;
+        self.create_semantic_commit_with_signature(commit, None)
+    }
 
-                let hash = <[u8; 20]>::try_from(oid.as_bytes())
-                    .map_err(|_| Error::Unknown("err".to_string()))?;
+    pub(crate) fn create_signed_semantic_commit(
+        &mut self,
+        commit: SemanticCommit,
+        signature: String,
+    ) -> Result<CommitHash, Error> {
+        self.create_semantic_commit_with_signature(commit, Some(signature))
+    }
 
-                Ok(CommitHash { hash })
-            }
+    fn create_semantic_commit_with_signature(
+        &mut self,
+        commit: SemanticCommit,
+        signature: Option<String>,
+    ) -> Result<CommitHash, Error> {
+        let sig = semantic_commit_signature(&commit.author, commit.timestamp)?;
+        let mut index = self.repo.index()?;
+        match commit.diff {
+            Diff::None => (),
             Diff::Reserved(reserved_state) => {
                 let path = self.repo.workdir().unwrap().to_str().unwrap();
                 tokio::runtime::Handle::current()
@@ -341,41 +496,135 @@ impl RawRepositoryImplInner {
                             .await
                     })
                     .map_err(|e| Error::Unknown(e.to_string()))?;
-
-                let mut index = self.repo.index()?;
                 index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+            }
+            Diff::General(_, _) => {
+                return Err(Error::InvalidRepository(
+                    "diff is Diff::General()".to_string(),
+                ))
+            }
+            Diff::NonReserved(_) => {
+                return Err(Error::InvalidRepository(
+                    "diff is Diff::NonReserved()".to_string(),
+                ))
+            }
+        }
+        let id = index.write_tree()?;
+        let tree = self.repo.find_tree(id)?;
+        let commit_message = format!("{}{}{}", commit.title, "\n\n", commit.body); // TODO: Check "\n" divides commit message's head and body.
+        let head = self.get_head()?;
+        let parent_oid = git2::Oid::from_bytes(&head.hash)?;
+        let parent_commit = self.repo.find_commit(parent_oid)?;
 
-                let sig = self.repo.signature()?;
-                let id = index.write_tree()?;
-                let tree = self.repo.find_tree(id)?;
-                let commit_message = format!("{}{}{}", commit.title, "\n\n", commit.body); // TODO: Check "\n" divides commit message's head and body.
-                let head = self.get_head()?;
-                let parent_oid = git2::Oid::from_bytes(&head.hash)?;
-                let parent_commit = self.repo.find_commit(parent_oid)?;
-
-                let oid = self.repo.commit(
-                    Some("HEAD"),
-                    &sig,
-                    &sig,
-                    commit_message.as_str(),
-                    &tree,
-                    &[&parent_commit],
-                )?;
+        let oid = self.write_commit(
+            &sig,
+            commit.timestamp,
+            commit_message.as_str(),
+            &tree,
+            &parent_commit,
+            signature.as_deref(),
+        )?;
 
-                let hash = <[u8; 20]>::try_from(oid.as_bytes())
-                    .map_err(|_| Error::Unknown("err".to_string()))?;
+        let hash =
+            <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
 
-                Ok(CommitHash { hash })
-            }
-            Diff::General(_, _) => Err(Error::InvalidRepository(
-                "diff is Diff::General()".to_string(),
-            )),
-            Diff::NonReserved(_) => Err(Error::InvalidRepository(
-                "diff is Diff::NonReserved()".to_string(),
+        Ok(CommitHash { hash })
+    }
+
+    /// Writes a commit with the `simperby-timestamp` header on `HEAD`, signed if `signature` is given.
+    fn write_commit(
+        &self,
+        sig: &git2::Signature,
+        timestamp: Timestamp,
+        message: &str,
+        tree: &git2::Tree,
+        parent: &git2::Commit,
+        signature: Option<&str>,
+    ) -> Result<Oid, Error> {
+        let content = self
+            .repo
+            .commit_create_buffer(sig, sig, message, tree, &[parent])?;
+        let content = add_timestamp_header(&content, timestamp)?;
+        let oid = if let Some(signature) = signature {
+            let content =
+                std::str::from_utf8(&content).map_err(|e| Error::Unknown(e.to_string()))?;
+            self.repo
+                .commit_signed(content, signature, Some(COMMIT_SIGNATURE_FIELD))?
+        } else {
+            self.repo.odb()?.write(ObjectType::Commit, &content)?
+        };
+        // Unlike `commit()`, writing the commit object doesn't update `HEAD`.
+        self.repo
+            .head()?
+            .set_target(oid, "commit (semantic commit)")?;
+        Ok(oid)
+    }
+
+    pub(crate) fn read_commit_signature(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<String>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        // Check that the commit exists, to distinguish it from an unsigned commit.
+        self.repo.find_commit(oid)?;
+        match self
+            .repo
+            .extract_signature(&oid, Some(COMMIT_SIGNATURE_FIELD))
+        {
+            Ok((signature, _)) => Ok(Some(
+                std::str::from_utf8(&signature)
+                    .map_err(|e| Error::Unknown(e.to_string()))?
+                    .to_owned(),
             )),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
         }
     }
 
+    pub(crate) fn cherry_pick(
+        &mut self,
+        commit_hash: CommitHash,
+        timestamp: Timestamp,
+    ) -> Result<CommitHash, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let commit = self.repo.find_commit(oid)?;
+        let head = self.repo.head()?.peel_to_commit()?;
+
+        let mut index = self.repo.cherrypick_commit(&commit, &head, 0, None)?;
+        if index.has_conflicts() {
+            let paths = index
+                .conflicts()?
+                .filter_map(|conflict| conflict.ok())
+                .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+                .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+                .collect::<Vec<_>>();
+            return Err(Error::Conflict(paths.join(", ")));
+        }
+        let tree_id = index.write_tree_to(&self.repo)?;
+        let tree = self.repo.find_tree(tree_id)?;
+        // Update the working tree and the index before moving `HEAD`.
+        self.repo.checkout_tree(tree.as_object(), None)?;
+
+        let author = commit.author();
+        let sig = git2::Signature::new(
+            author.name().unwrap_or_default(),
+            author.email().unwrap_or_default(),
+            &git2::Time::new(timestamp.div_euclid(1000), 0),
+        )?;
+        let oid = self.write_commit(
+            &sig,
+            timestamp,
+            commit.message().unwrap_or_default(),
+            &tree,
+            &head,
+            None,
+        )?;
+
+        let hash =
+            <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
+        Ok(CommitHash { hash })
+    }
+
     pub(crate) fn read_semantic_commit(
         &self,
         commit_hash: CommitHash,
@@ -390,16 +639,32 @@ impl RawRepositoryImplInner {
             .repo
             .diff_tree_to_tree(Some(&tree), Some(&parent_tree), None)?;
 
-        let diff = if diff.deltas().len() == 0 {
-            Diff::None
-        } else {
-            let patch = self.show_commit(commit_hash)?;
-            let hash = patch.to_hash256();
-            Diff::NonReserved(hash)
+        let (reserved_changed, non_reserved_changed) =
+            diff.deltas()
+                .fold((false, false), |(reserved, non_reserved), delta| {
+                    let is_reserved = [delta.old_file().path(), delta.new_file().path()]
+                        .into_iter()
+                        .flatten()
+                        .any(|path| path.starts_with("reserved"));
+                    (reserved || is_reserved, non_reserved || !is_reserved)
+                });
+        let diff = match (reserved_changed, non_reserved_changed) {
+            (false, false) => Diff::None,
+            (false, true) => {
+                let patch = self.show_commit(commit_hash)?;
+                Diff::NonReserved(patch.to_hash256())
+            }
+            (true, false) => {
+                Diff::Reserved(Box::new(self.read_reserved_state_at_commit(commit_hash)?))
+            }
+            (true, true) => {
+                let patch = self.show_commit(commit_hash)?;
+                Diff::General(
+                    Box::new(self.read_reserved_state_at_commit(commit_hash)?),
+                    patch.to_hash256(),
+                )
+            }
         };
-        /* TODO: If reserved state
-        let reserved_state = self.read_reserved_state()?;
-        Diff::Reserved(Box::new(reserved_state))*/
 
         let title = commit.summary();
         let title = if let Some(msg_title) = title {
@@ -416,13 +681,34 @@ impl RawRepositoryImplInner {
         }
         .to_string();
 
-        let semantic_commit = SemanticCommit { title, body, diff };
+        let timestamp_header = commit.header_field_bytes(TIMESTAMP_FIELD).ok();
+        let (author, timestamp) = parse_author_and_timestamp(
+            &commit.author(),
+            timestamp_header.as_ref().and_then(|x| x.as_str()),
+        )?;
+
+        let semantic_commit = SemanticCommit {
+            title,
+            body,
+            diff,
+            author,
+            timestamp,
+        };
 
         Ok(semantic_commit)
     }
 
     pub(crate) fn run_garbage_collection(&mut self) -> Result<(), Error> {
-        todo!()
+        let workdir = self.repo.workdir().unwrap().to_str().unwrap();
+        // The reflog keeps the orphan commits reachable, so it has to be expired first.
+        run_git(
+            workdir,
+            &["reflog", "expire", "--expire-unreachable=now", "--all"],
+        )?;
+        run_git(workdir, &["gc", "--prune=now", "--quiet"])?;
+        // Reopen the repository to drop the objects cached by libgit2.
+        self.repo = Repository::open(workdir)?;
+        Ok(())
     }
 
     pub(crate) fn checkout_clean(&mut self) -> Result<(), Error> {
@@ -446,6 +732,18 @@ impl RawRepositoryImplInner {
         Ok(())
     }
 
+    pub(crate) fn checkout_detach_with_tree(
+        &mut self,
+        commit_hash: CommitHash,
+    ) -> Result<(), Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let commit = self.repo.find_commit(oid)?;
+        self.repo.checkout_tree(commit.as_object(), None)?;
+        self.repo.set_head_detached(oid)?;
+
+        Ok(())
+    }
+
     pub(crate) fn get_head(&self) -> Result<CommitHash, Error> {
         let ref_head = self.repo.head()?;
         let oid = ref_head
@@ -638,6 +936,134 @@ impl RawRepositoryImplInner {
         Ok(reserved_state)
     }
 
+    pub(crate) fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let tree = self.repo.find_commit(oid)?.tree()?;
+        let read_blob = |id: Oid| -> Result<String, Error> {
+            let blob = self.repo.find_blob(id)?;
+            String::from_utf8(blob.content().to_vec()).map_err(|e| Error::Unknown(e.to_string()))
+        };
+        let read = |path: &str| -> Result<String, Error> {
+            let entry = tree.get_path(std::path::Path::new(path))?;
+            read_blob(entry.id())
+        };
+        fn parse<T: serde::de::DeserializeOwned>(content: &str) -> Result<T, Error> {
+            serde_spb::from_str(content).map_err(|e| Error::Unknown(e.to_string()))
+        }
+
+        let genesis_info: GenesisInfo = parse(&read("reserved/genesis_info.json")?)?;
+        let mut members: Vec<Member> = Vec::new();
+        let members_tree = tree
+            .get_path(std::path::Path::new("reserved/members"))?
+            .to_object(&self.repo)?
+            .peel_to_tree()?;
+        for entry in members_tree.iter() {
+            members.push(parse(&read_blob(entry.id())?)?);
+        }
+        members.sort_by(|m1, m2| m1.name.cmp(&m2.name));
+        let consensus_leader_order: Vec<MemberName> =
+            parse(&read("reserved/consensus_leader_order.json")?)?;
+        let version: String = parse(&read("reserved/version")?)?;
+        let content_policy: ContentPolicy =
+            match tree.get_path(std::path::Path::new("reserved/policy.json")) {
+                Ok(entry) => parse(&read_blob(entry.id())?)?,
+                Err(e) if e.code() == git2::ErrorCode::NotFound => ContentPolicy::default(),
+                Err(e) => return Err(e.into()),
+            };
+
+        Ok(ReservedState {
+            genesis_info,
+            members,
+            consensus_leader_order,
+            version,
+            content_policy,
+        })
+    }
+
+    pub(crate) fn list_files(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Vec<(String, Hash256)>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let tree = self.repo.find_commit(oid)?.tree()?;
+        let mut files = Vec::new();
+        let mut result = Ok(());
+        tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            if entry.kind() != Some(git2::ObjectType::Blob) {
+                return git2::TreeWalkResult::Ok;
+            }
+            match self.repo.find_blob(entry.id()) {
+                Ok(blob) => {
+                    let path = format!("{}{}", root, String::from_utf8_lossy(entry.name_bytes()));
+                    files.push((path, Hash256::hash(blob.content())));
+                    git2::TreeWalkResult::Ok
+                }
+                Err(e) => {
+                    result = Err(e);
+                    git2::TreeWalkResult::Abort
+                }
+            }
+        })?;
+        result?;
+        files.sort_by(|(x, _), (y, _)| x.cmp(y));
+        Ok(files)
+    }
+
+    pub(crate) fn list_changed_files(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Vec<FileChange>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let commit = self.repo.find_commit(oid)?;
+        let tree = commit.tree()?;
+        let parent_tree = commit.parent(0)?.tree()?;
+        let diff = self
+            .repo
+            .diff_tree_to_tree(Some(&parent_tree), Some(&tree), None)?;
+
+        let mut changes = Vec::new();
+        for delta in diff.deltas() {
+            let file = if delta.status() == git2::Delta::Deleted {
+                delta.old_file()
+            } else {
+                delta.new_file()
+            };
+            let path = file
+                .path()
+                .and_then(|path| path.to_str())
+                .ok_or_else(|| Error::Unknown("non UTF-8 path".to_string()))?
+                .to_string();
+            let (size, binary) = if delta.status() == git2::Delta::Deleted {
+                (None, false)
+            } else {
+                let blob = self.repo.find_blob(file.id())?;
+                (Some(blob.size() as u64), blob.is_binary())
+            };
+            changes.push(FileChange { path, size, binary });
+        }
+        changes.sort_by(|x, y| x.path.cmp(&y.path));
+        Ok(changes)
+    }
+
+    pub(crate) fn read_file(
+        &self,
+        commit_hash: CommitHash,
+        path: String,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let tree = self.repo.find_commit(oid)?.tree()?;
+        let entry = match tree.get_path(std::path::Path::new(&path)) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let blob = self.repo.find_blob(entry.id())?;
+        Ok(Some(blob.content().to_vec()))
+    }
+
     pub(crate) fn add_remote(
         &mut self,
         remote_name: String,
@@ -683,15 +1109,10 @@ impl RawRepositoryImplInner {
     ) -> Result<(), Error> {
         let workdir = self.repo.workdir().unwrap().to_str().unwrap();
 
-        if let Some(option_string) = option {
-            run_command(format!(
-                "cd {workdir} && git push {remote_name} {branch} --push-option='{option_string}'"
-            ))?
-        } else {
-            run_command(format!("cd {workdir} && git push {remote_name} {branch}"))?
-        };
-
-        Ok(())
+        let push_option = option.map(|option| format!("--push-option={option}"));
+        let mut args = vec!["push", remote_name.as_str(), branch.as_str()];
+        args.extend(push_option.as_deref());
+        run_git(workdir, &args)
     }
 
     pub(crate) fn list_remotes(&self) -> Result<Vec<(String, String)>, Error> {
@@ -729,14 +1150,20 @@ impl RawRepositoryImplInner {
     ) -> Result<Vec<(String, String, CommitHash)>, Error> {
         let branches = self.repo.branches(Some(git2::BranchType::Remote))?;
         let branches = branches
-            .map(|branch| {
-                let branch_name = branch?
-                    .0
-                    .name()?
-                    .map(|name| name.to_string())
-                    .ok_or_else(|| Error::Unknown("err".to_string()))?;
-
-                Ok(branch_name)
+            .filter_map(|branch| {
+                let branch = match branch {
+                    Ok((branch, _)) => branch,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                // Skip `<remote>/HEAD` of a cloned repository, which is a symbolic reference.
+                if branch.get().kind() == Some(git2::ReferenceType::Symbolic) {
+                    return None;
+                }
+                let branch_name = branch.name().map_err(Error::from).and_then(|name| {
+                    name.map(|name| name.to_string())
+                        .ok_or_else(|| Error::Unknown("err".to_string()))
+                });
+                Some(branch_name)
             })
             .collect::<Result<Vec<Branch>, Error>>()?;
 
@@ -0,0 +1,1384 @@
+use super::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Error = super::Error;
+
+/// A hook run for every branch pushed to an in-memory repository, like the `pre-receive` hook of Git.
+///
+/// It is given the branch, the commit to update the branch to, and the push option.
+/// The push is rejected if it fails.
+pub type PreReceiveHook =
+    Arc<dyn Fn(&str, CommitHash, Option<&str>) -> Result<(), String> + Send + Sync>;
+
+/// A set of in-memory repositories, where a remote URL refers to the repository
+/// whose `directory` is the same as the URL.
+///
+/// The repositories are owned by the set and by their handles, and freed when all of them are dropped.
+/// Different sets never share their repositories, even for the same `directory`.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRepositories {
+    repositories: Arc<Mutex<BTreeMap<String, Arc<Mutex<Store>>>>>,
+}
+
+impl InMemoryRepositories {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lookup(&self, url: &str) -> Result<Arc<Mutex<Store>>, Error> {
+        self.repositories
+            .lock()
+            .expect("in-memory repositories poisoned")
+            .get(url)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("repository {url}")))
+    }
+
+    fn register(&self, directory: &str, store: Store) -> Result<InMemoryRawRepository, Error> {
+        let mut repositories = self
+            .repositories
+            .lock()
+            .expect("in-memory repositories poisoned");
+        if repositories.contains_key(directory) {
+            return Err(Error::InvalidRepository(
+                "there is an already existing repository".to_string(),
+            ));
+        }
+        let store = Arc::new(Mutex::new(store));
+        repositories.insert(directory.to_owned(), Arc::clone(&store));
+        Ok(InMemoryRawRepository {
+            repositories: self.clone(),
+            store,
+        })
+    }
+
+    /// Initializes a repository in this set, like [`RawRepository::init`].
+    pub fn init(
+        &self,
+        directory: &str,
+        init_commit_message: &str,
+        init_commit_branch: &Branch,
+    ) -> Result<InMemoryRawRepository, Error> {
+        let initial_commit = CommitObject {
+            parent: None,
+            title: init_commit_message.to_owned(),
+            body: String::new(),
+            author: PublicKey::zero(),
+            timestamp: 0,
+            tree: Arc::new(Tree::default()),
+            signature: None,
+        };
+        let initial_commit_hash = initial_commit.hash();
+        let tree = Arc::clone(&initial_commit.tree);
+        let store = Store {
+            commits: vec![(initial_commit_hash, Arc::new(initial_commit))]
+                .into_iter()
+                .collect(),
+            branches: vec![(init_commit_branch.clone(), initial_commit_hash)]
+                .into_iter()
+                .collect(),
+            tags: BTreeMap::new(),
+            tag_annotations: BTreeMap::new(),
+            head: Head::Branch(init_commit_branch.clone()),
+            index: Arc::clone(&tree),
+            working_tree: tree,
+            remotes: BTreeMap::new(),
+            remote_tracking_branches: BTreeMap::new(),
+            pre_receive_hook: None,
+        };
+        self.register(directory, store)
+    }
+
+    /// Opens a repository in this set, like [`RawRepository::open`].
+    pub fn open(&self, directory: &str) -> Result<InMemoryRawRepository, Error> {
+        Ok(InMemoryRawRepository {
+            repositories: self.clone(),
+            store: self.lookup(directory)?,
+        })
+    }
+
+    /// Clones a repository in this set, like [`RawRepository::clone`].
+    pub fn clone_repository(
+        &self,
+        directory: &str,
+        url: &str,
+    ) -> Result<InMemoryRawRepository, Error> {
+        let origin = self.lookup(url)?;
+        let origin = origin.lock().expect("in-memory repository poisoned");
+        let commits = origin
+            .collect_reachable(
+                origin
+                    .branches
+                    .values()
+                    .chain(origin.tags.values())
+                    .copied()
+                    .chain(origin.head().ok()),
+                &mut HashSet::new(),
+            )?
+            .into_iter()
+            .collect();
+        let (branches, head) = match &origin.head {
+            Head::Branch(branch) => {
+                let commit_hash = origin.locate_branch(branch)?;
+                (
+                    vec![(branch.clone(), commit_hash)].into_iter().collect(),
+                    Head::Branch(branch.clone()),
+                )
+            }
+            Head::Detached(commit_hash) => (BTreeMap::new(), Head::Detached(*commit_hash)),
+        };
+        let tree = Arc::clone(&origin.commit(&origin.head()?)?.tree);
+        let store = Store {
+            commits,
+            branches,
+            tags: origin.tags.clone(),
+            tag_annotations: origin.tag_annotations.clone(),
+            head,
+            index: Arc::clone(&tree),
+            working_tree: tree,
+            remotes: vec![("origin".to_owned(), url.to_owned())]
+                .into_iter()
+                .collect(),
+            remote_tracking_branches: origin
+                .branches
+                .iter()
+                .map(|(branch, commit_hash)| (("origin".to_owned(), branch.clone()), *commit_hash))
+                .collect(),
+            pre_receive_hook: None,
+        };
+        drop(origin);
+        self.register(directory, store)
+    }
+
+    /// Removes the repository from this set, like deleting its directory.
+    ///
+    /// The existing handles to it remain valid, and it is freed when they are dropped.
+    pub fn remove(&self, directory: &str) -> Result<(), Error> {
+        self.repositories
+            .lock()
+            .expect("in-memory repositories poisoned")
+            .remove(directory)
+            .map(|_| ())
+            .ok_or_else(|| Error::NotFound(format!("repository {directory}")))
+    }
+}
+
+fn is_reserved(path: &str) -> bool {
+    path == "reserved" || path.starts_with("reserved/")
+}
+
+/// The files of a commit, the index or the working tree, by their paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Tree {
+    files: BTreeMap<String, Arc<Vec<u8>>>,
+}
+
+impl Tree {
+    fn hash(&self) -> Hash256 {
+        let files = self
+            .files
+            .iter()
+            .map(|(path, content)| (path, Hash256::hash(content.as_slice())))
+            .collect::<Vec<_>>();
+        Hash256::hash(serde_spb::to_vec(&files).unwrap())
+    }
+
+    fn reserved_state(&self) -> Result<ReservedState, Error> {
+        if !self.files.keys().any(|path| is_reserved(path)) {
+            return Err(Error::NotFound("reserved state".to_string()));
+        }
+        reserved_state::decode_reserved_state(
+            self.files
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_slice())),
+        )
+        .map_err(|e| Error::Unknown(e.to_string()))
+    }
+
+    /// Replaces the reserved directory with the given reserved state.
+    fn with_reserved_state(&self, reserved_state: &ReservedState) -> Result<Self, Error> {
+        let mut files = self.files.clone();
+        files.retain(|path, _| !is_reserved(path));
+        for (path, content) in reserved_state::encode_reserved_state(reserved_state)
+            .map_err(|e| Error::Unknown(e.to_string()))?
+        {
+            files.insert(path, Arc::new(content.into_bytes()));
+        }
+        Ok(Self { files })
+    }
+
+    /// Returns the paths of the files that differ between the two trees, sorted.
+    fn changed_paths(&self, other: &Self) -> Vec<String> {
+        self.files
+            .keys()
+            .chain(other.files.keys())
+            .filter(|path| self.files.get(*path) != other.files.get(*path))
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Whether the content is binary, by the same heuristic as Git; a NUL byte in the first 8000 bytes.
+fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|byte| *byte == 0)
+}
+
+#[derive(Debug, Clone)]
+struct CommitObject {
+    parent: Option<CommitHash>,
+    title: String,
+    body: String,
+    author: PublicKey,
+    timestamp: Timestamp,
+    tree: Arc<Tree>,
+    /// The signature stored in the `simperby-signature` header, if signed.
+    signature: Option<String>,
+}
+
+impl CommitObject {
+    /// Calculates the hash of the commit, which is determined only by its content like Git.
+    fn hash(&self) -> CommitHash {
+        let digest = Hash256::hash(
+            serde_spb::to_vec(&(
+                self.parent,
+                self.tree.hash(),
+                &self.title,
+                &self.body,
+                &self.author,
+                self.timestamp,
+                &self.signature,
+            ))
+            .unwrap(),
+        );
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&digest.as_ref()[0..20]);
+        CommitHash { hash }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Head {
+    Branch(Branch),
+    Detached(CommitHash),
+}
+
+struct Hook(PreReceiveHook);
+
+impl std::fmt::Debug for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PreReceiveHook")
+    }
+}
+
+#[derive(Debug)]
+struct Store {
+    commits: HashMap<CommitHash, Arc<CommitObject>>,
+    branches: BTreeMap<Branch, CommitHash>,
+    tags: BTreeMap<Tag, CommitHash>,
+    /// The annotations of the annotated tags.
+    tag_annotations: BTreeMap<Tag, TagAnnotation>,
+    head: Head,
+    /// The tree committed by the next semantic commit without a diff.
+    index: Arc<Tree>,
+    /// The files written with [`InMemoryRawRepository::write_file`] and by the checkouts.
+    working_tree: Arc<Tree>,
+    /// `remote_name` to `remote_url`.
+    remotes: BTreeMap<String, String>,
+    /// `(remote_name, branch_name)` to the commit.
+    remote_tracking_branches: BTreeMap<(String, String), CommitHash>,
+    pre_receive_hook: Option<Hook>,
+}
+
+impl Store {
+    fn commit(&self, commit_hash: &CommitHash) -> Result<&Arc<CommitObject>, Error> {
+        self.commits
+            .get(commit_hash)
+            .ok_or_else(|| Error::NotFound(format!("commit {commit_hash}")))
+    }
+
+    fn locate_branch(&self, branch: &str) -> Result<CommitHash, Error> {
+        self.branches
+            .get(branch)
+            .copied()
+            .ok_or_else(|| Error::NotFound(format!("branch {branch}")))
+    }
+
+    fn head(&self) -> Result<CommitHash, Error> {
+        match &self.head {
+            Head::Branch(branch) => self.locate_branch(branch),
+            Head::Detached(commit_hash) => Ok(*commit_hash),
+        }
+    }
+
+    /// Updates the working tree and the index to the tree of the given commit.
+    fn checkout_tree(&mut self, commit_hash: &CommitHash) -> Result<(), Error> {
+        let tree = Arc::clone(&self.commit(commit_hash)?.tree);
+        self.index = Arc::clone(&tree);
+        self.working_tree = tree;
+        Ok(())
+    }
+
+    /// Returns the given commit and all of its ancestors, from the given one to the initial one.
+    fn history(&self, commit_hash: CommitHash) -> Result<Vec<CommitHash>, Error> {
+        let mut result = vec![commit_hash];
+        let mut current = self.commit(&commit_hash)?;
+        while let Some(parent) = current.parent {
+            result.push(parent);
+            current = self.commit(&parent)?;
+        }
+        Ok(result)
+    }
+
+    /// Collects the commits reachable from the given commits, which are not in `known`.
+    fn collect_reachable(
+        &self,
+        tips: impl IntoIterator<Item = CommitHash>,
+        known: &mut HashSet<CommitHash>,
+    ) -> Result<Vec<(CommitHash, Arc<CommitObject>)>, Error> {
+        let mut result = Vec::new();
+        for tip in tips {
+            let mut next = Some(tip);
+            while let Some(commit_hash) = next {
+                if !known.insert(commit_hash) {
+                    break;
+                }
+                let commit = self.commit(&commit_hash)?;
+                result.push((commit_hash, Arc::clone(commit)));
+                next = commit.parent;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Creates a commit on top of `HEAD` and moves `HEAD` (and the branch it points to).
+    ///
+    /// The index is updated to the tree of the commit.
+    fn create_commit(&mut self, commit: CommitObject) -> Result<CommitHash, Error> {
+        let commit_hash = commit.hash();
+        self.index = Arc::clone(&commit.tree);
+        self.commits.insert(commit_hash, Arc::new(commit));
+        match &self.head {
+            Head::Branch(branch) => {
+                self.branches.insert(branch.clone(), commit_hash);
+            }
+            Head::Detached(_) => self.head = Head::Detached(commit_hash),
+        }
+        Ok(commit_hash)
+    }
+
+    fn find_merge_base(
+        &self,
+        commit_hash1: CommitHash,
+        commit_hash2: CommitHash,
+    ) -> Result<CommitHash, Error> {
+        let history1 = self
+            .history(commit_hash1)?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        self.history(commit_hash2)?
+            .into_iter()
+            .find(|commit_hash| history1.contains(commit_hash))
+            .ok_or_else(|| {
+                Error::NotFound(format!("merge base of {commit_hash1} and {commit_hash2}"))
+            })
+    }
+
+    /// Returns the tree of the parent of the given commit, which is empty for the initial commit.
+    fn parent_tree(&self, commit: &CommitObject) -> Result<Arc<Tree>, Error> {
+        match commit.parent {
+            Some(parent) => Ok(Arc::clone(&self.commit(&parent)?.tree)),
+            None => Ok(Arc::new(Tree::default())),
+        }
+    }
+
+    fn show_commit(&self, commit_hash: CommitHash) -> Result<String, Error> {
+        let commit = self.commit(&commit_hash)?;
+        let parent_tree = self.parent_tree(commit)?;
+        let changes = parent_tree
+            .changed_paths(&commit.tree)
+            .into_iter()
+            .map(|path| match commit.tree.files.get(&path) {
+                Some(content) => format!("{path} | {}", Hash256::hash(content.as_slice())),
+                None => format!("{path} | deleted"),
+            })
+            .collect::<Vec<_>>();
+        Ok(format!(
+            "From {}\nFrom: {}\nDate: {}\nSubject: {}\n\n{}\n---\n{}\n",
+            commit_hash,
+            commit.author,
+            commit.timestamp,
+            commit.title,
+            commit.body,
+            changes.join("\n")
+        ))
+    }
+}
+
+/// Merges the changes from `base` to `ours` and to `theirs`, or returns `None` if they conflict.
+fn merge3<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if theirs == base {
+        Some(ours.clone())
+    } else if ours == base || ours == theirs {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// A pure in-memory implementation of [`RawRepository`], for tests and simulations.
+///
+/// It follows the semantics of [`RawRepositoryImpl`] without touching the disk,
+/// including the working tree and the index, and the files of the reserved state.
+/// The repositories live in an [`InMemoryRepositories`], where the `directory` is the identifier
+/// of the repository and a remote URL refers to the repository whose `directory` is the same as the URL.
+/// Opening the same `directory` twice gives handles sharing the same repository, like Git does.
+///
+/// The constructors of [`RawRepository`] use a new, empty [`InMemoryRepositories`] of their own;
+/// use an [`InMemoryRepositories`] to open, to clone, or to reach other repositories.
+///
+/// The differences from [`RawRepositoryImpl`] are,
+/// - The commit hashes are deterministic but different from the ones of Git,
+///   and so are the patches of [`RawRepository::show_commit`].
+/// - A cherry-pick merges the changes by the files, not by the lines, so that the different changes
+///   to the same file always conflict.
+/// - A checkout replaces the working tree, discarding the files not committed.
+/// - A push option is given to the [`PreReceiveHook`] of the remote, if set,
+///   instead of a `pre-receive` script.
+#[derive(Debug)]
+pub struct InMemoryRawRepository {
+    repositories: InMemoryRepositories,
+    store: Arc<Mutex<Store>>,
+}
+
+impl InMemoryRawRepository {
+    fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().expect("in-memory repository poisoned")
+    }
+
+    /// Writes the file to the working tree, or removes it if `content` is `None`,
+    /// like writing it to the directory of a [`RawRepositoryImpl`].
+    pub fn write_file(&mut self, path: &str, content: Option<Vec<u8>>) {
+        let mut store = self.store();
+        let mut working_tree = (*store.working_tree).clone();
+        match content {
+            Some(content) => working_tree
+                .files
+                .insert(path.to_owned(), Arc::new(content)),
+            None => working_tree.files.remove(path),
+        };
+        store.working_tree = Arc::new(working_tree);
+    }
+
+    /// Sets the hook run for every branch pushed to this repository.
+    pub fn set_pre_receive_hook(&mut self, hook: Option<PreReceiveHook>) {
+        self.store().pre_receive_hook = hook.map(Hook);
+    }
+
+    fn create_semantic_commit_with_signature(
+        &mut self,
+        commit: SemanticCommit,
+        signature: Option<String>,
+    ) -> Result<CommitHash, Error> {
+        let mut store = self.store();
+        let head = store.head()?;
+        let tree = match commit.diff {
+            Diff::None => Arc::clone(&store.index),
+            Diff::Reserved(reserved_state) => {
+                // The whole working tree is added, like `git add -A`.
+                let tree = Arc::new(store.working_tree.with_reserved_state(&reserved_state)?);
+                store.working_tree = Arc::clone(&tree);
+                tree
+            }
+            Diff::General(_, _) => {
+                return Err(Error::InvalidRepository(
+                    "diff is Diff::General()".to_string(),
+                ))
+            }
+            Diff::NonReserved(_) => {
+                return Err(Error::InvalidRepository(
+                    "diff is Diff::NonReserved()".to_string(),
+                ))
+            }
+        };
+        let commit = CommitObject {
+            parent: Some(head),
+            title: commit.title,
+            body: commit.body,
+            author: commit.author,
+            timestamp: commit.timestamp,
+            tree,
+            signature,
+        };
+        store.create_commit(commit)
+    }
+}
+
+#[async_trait]
+impl RawRepository for InMemoryRawRepository {
+    async fn init(
+        directory: &str,
+        init_commit_message: &str,
+        init_commit_branch: &Branch,
+    ) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        InMemoryRepositories::new().init(directory, init_commit_message, init_commit_branch)
+    }
+
+    async fn open(directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        InMemoryRepositories::new().open(directory)
+    }
+
+    async fn clone(directory: &str, url: &str) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        InMemoryRepositories::new().clone_repository(directory, url)
+    }
+
+    async fn retrieve_commit_hash(&self, revision_selection: String) -> Result<CommitHash, Error> {
+        let store = self.store();
+        let invalid =
+            || Error::InvalidRepository(format!("invalid revision: {revision_selection}"));
+        let split = revision_selection
+            .find(['~', '^'])
+            .unwrap_or(revision_selection.len());
+        let (base, mut suffix) = revision_selection.split_at(split);
+        let mut commit_hash = if base == "HEAD" {
+            store.head()?
+        } else if let Some(commit_hash) = store.branches.get(base).or_else(|| store.tags.get(base))
+        {
+            *commit_hash
+        } else if let Some(commit_hash) = base.split_once('/').and_then(|(remote, branch)| {
+            store
+                .remote_tracking_branches
+                .get(&(remote.to_owned(), branch.to_owned()))
+        }) {
+            *commit_hash
+        } else {
+            let commit_hash: CommitHash =
+                serde_spb::from_str(&format!("\"{base}\"")).map_err(|_| invalid())?;
+            store.commit(&commit_hash)?;
+            commit_hash
+        };
+        while let Some(operator) = suffix.chars().next() {
+            suffix = &suffix[1..];
+            let digits = suffix
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(suffix.len());
+            let number = if digits == 0 {
+                1
+            } else {
+                suffix[0..digits].parse::<usize>().map_err(|_| invalid())?
+            };
+            suffix = &suffix[digits..];
+            // Every commit has at most one parent, so `^n` is valid only for `n <= 1`.
+            let generations = match operator {
+                '~' => number,
+                _ if number <= 1 => number,
+                _ => return Err(invalid()),
+            };
+            for _ in 0..generations {
+                commit_hash = store.commit(&commit_hash)?.parent.ok_or_else(invalid)?;
+            }
+        }
+        Ok(commit_hash)
+    }
+
+    async fn list_branches(&self) -> Result<Vec<Branch>, Error> {
+        Ok(self.store().branches.keys().cloned().collect())
+    }
+
+    async fn create_branch(
+        &self,
+        branch_name: Branch,
+        commit_hash: CommitHash,
+    ) -> Result<(), Error> {
+        let mut store = self.store();
+        store.commit(&commit_hash)?;
+        if store.branches.contains_key(&branch_name) {
+            return Err(Error::InvalidRepository(format!(
+                "branch {branch_name} already exists"
+            )));
+        }
+        store.branches.insert(branch_name, commit_hash);
+        Ok(())
+    }
+
+    async fn locate_branch(&self, branch: Branch) -> Result<CommitHash, Error> {
+        self.store().locate_branch(&branch)
+    }
+
+    async fn get_branches(&self, commit_hash: CommitHash) -> Result<Vec<Branch>, Error> {
+        Ok(self
+            .store()
+            .branches
+            .iter()
+            .filter(|(_, c)| **c == commit_hash)
+            .map(|(branch, _)| branch.clone())
+            .collect())
+    }
+
+    async fn move_branch(&mut self, branch: Branch, commit_hash: CommitHash) -> Result<(), Error> {
+        let mut store = self.store();
+        store.commit(&commit_hash)?;
+        store.locate_branch(&branch)?;
+        store.branches.insert(branch, commit_hash);
+        Ok(())
+    }
+
+    async fn delete_branch(&mut self, branch: Branch) -> Result<(), Error> {
+        let mut store = self.store();
+        store.locate_branch(&branch)?;
+        if matches!(&store.head, Head::Branch(current) if *current == branch) {
+            return Err(Error::InvalidRepository(
+                ("given branch is currently checkout branch").to_string(),
+            ));
+        }
+        store.branches.remove(&branch);
+        Ok(())
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, Error> {
+        Ok(self.store().tags.keys().cloned().collect())
+    }
+
+    async fn create_tag(&mut self, tag: Tag, commit_hash: CommitHash) -> Result<(), Error> {
+        let mut store = self.store();
+        store.commit(&commit_hash)?;
+        store.tag_annotations.remove(&tag);
+        store.tags.insert(tag, commit_hash);
+        Ok(())
+    }
+
+    async fn create_annotated_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        annotation: TagAnnotation,
+    ) -> Result<(), Error> {
+        let mut store = self.store();
+        store.commit(&commit_hash)?;
+        store.tags.insert(tag.clone(), commit_hash);
+        store.tag_annotations.insert(tag, annotation);
+        Ok(())
+    }
+
+    async fn read_tag_annotation(&self, tag: Tag) -> Result<Option<TagAnnotation>, Error> {
+        let store = self.store();
+        if !store.tags.contains_key(&tag) {
+            return Err(Error::NotFound(format!("tag {tag}")));
+        }
+        Ok(store.tag_annotations.get(&tag).cloned())
+    }
+
+    async fn locate_tag(&self, tag: Tag) -> Result<CommitHash, Error> {
+        self.store()
+            .tags
+            .get(&tag)
+            .copied()
+            .ok_or_else(|| Error::NotFound(format!("tag {tag}")))
+    }
+
+    async fn get_tag(&self, commit_hash: CommitHash) -> Result<Vec<Tag>, Error> {
+        Ok(self
+            .store()
+            .tags
+            .iter()
+            .filter(|(_, c)| **c == commit_hash)
+            .map(|(tag, _)| tag.clone())
+            .collect())
+    }
+
+    async fn remove_tag(&mut self, tag: Tag) -> Result<(), Error> {
+        let mut store = self.store();
+        store.tag_annotations.remove(&tag);
+        store
+            .tags
+            .remove(&tag)
+            .map(|_| ())
+            .ok_or_else(|| Error::NotFound(format!("tag {tag}")))
+    }
+
+    async fn create_commit(
+        &mut self,
+        commit_message: String,
+        _diff: Option<String>,
+    ) -> Result<CommitHash, Error> {
+        let mut store = self.store();
+        let head = store.head()?;
+        // Like `RawRepositoryImpl`, it writes the `test` file and adds the whole working tree.
+        let mut tree = (*store.working_tree).clone();
+        tree.files
+            .insert("test".to_owned(), Arc::new(b"test".to_vec()));
+        let tree = Arc::new(tree);
+        store.working_tree = Arc::clone(&tree);
+        let (title, body) = commit_message
+            .split_once("\n\n")
+            .unwrap_or((&commit_message, ""));
+        let commit = CommitObject {
+            parent: Some(head),
+            title: title.to_owned(),
+            body: body.to_owned(),
+            author: PublicKey::zero(),
+            timestamp: get_timestamp() / 1000 * 1000,
+            tree,
+            signature: None,
+        };
+        store.create_commit(commit)
+    }
+
+    async fn create_semantic_commit(
+        &mut self,
+        commit: SemanticCommit,
+    ) -> Result<CommitHash, Error> {
+        self.create_semantic_commit_with_signature(commit, None)
+    }
+
+    async fn create_signed_semantic_commit(
+        &mut self,
+        commit: SemanticCommit,
+        signature: String,
+    ) -> Result<CommitHash, Error> {
+        self.create_semantic_commit_with_signature(commit, Some(signature))
+    }
+
+    async fn read_commit_signature(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<String>, Error> {
+        Ok(self.store().commit(&commit_hash)?.signature.clone())
+    }
+
+    async fn cherry_pick(
+        &mut self,
+        commit_hash: CommitHash,
+        timestamp: Timestamp,
+    ) -> Result<CommitHash, Error> {
+        let mut store = self.store();
+        let commit = Arc::clone(store.commit(&commit_hash)?);
+        let base = Arc::clone(
+            &store
+                .commit(&commit.parent.ok_or_else(|| {
+                    Error::InvalidRepository("the initial commit has no parent".to_string())
+                })?)?
+                .tree,
+        );
+        let head = store.head()?;
+        let ours = Arc::clone(&store.commit(&head)?.tree);
+        let theirs = &commit.tree;
+        let mut tree = (*ours).clone();
+        let mut conflicts = Vec::new();
+        for path in base.changed_paths(theirs) {
+            match merge3(
+                &base.files.get(&path),
+                &ours.files.get(&path),
+                &theirs.files.get(&path),
+            ) {
+                Some(Some(content)) => {
+                    tree.files.insert(path, Arc::clone(content));
+                }
+                Some(None) => {
+                    tree.files.remove(&path);
+                }
+                None => conflicts.push(path),
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(Error::Conflict(conflicts.join(", ")));
+        }
+        let tree = Arc::new(tree);
+        store.working_tree = Arc::clone(&tree);
+        store.create_commit(CommitObject {
+            parent: Some(head),
+            title: commit.title.clone(),
+            body: commit.body.clone(),
+            author: commit.author.clone(),
+            timestamp,
+            tree,
+            signature: None,
+        })
+    }
+
+    async fn read_semantic_commit(&self, commit_hash: CommitHash) -> Result<SemanticCommit, Error> {
+        let store = self.store();
+        let commit = store.commit(&commit_hash)?;
+        let parent = commit.parent.ok_or_else(|| {
+            Error::InvalidRepository(format!("commit {commit_hash} has no parent"))
+        })?;
+        let changed_paths = store.commit(&parent)?.tree.changed_paths(&commit.tree);
+        let reserved_changed = changed_paths.iter().any(|path| is_reserved(path));
+        let non_reserved_changed = changed_paths.iter().any(|path| !is_reserved(path));
+        let diff = match (reserved_changed, non_reserved_changed) {
+            (false, false) => Diff::None,
+            (false, true) => Diff::NonReserved(store.show_commit(commit_hash)?.to_hash256()),
+            (true, false) => Diff::Reserved(Box::new(commit.tree.reserved_state()?)),
+            (true, true) => Diff::General(
+                Box::new(commit.tree.reserved_state()?),
+                store.show_commit(commit_hash)?.to_hash256(),
+            ),
+        };
+        Ok(SemanticCommit {
+            title: commit.title.clone(),
+            body: commit.body.clone(),
+            diff,
+            author: commit.author.clone(),
+            timestamp: commit.timestamp,
+        })
+    }
+
+    async fn run_garbage_collection(&mut self) -> Result<(), Error> {
+        let mut store = self.store();
+        let tips = store
+            .branches
+            .values()
+            .chain(store.tags.values())
+            .chain(store.remote_tracking_branches.values())
+            .copied()
+            .chain(store.head().ok())
+            .collect::<Vec<_>>();
+        let reachable = store
+            .collect_reachable(tips, &mut HashSet::new())?
+            .into_iter()
+            .map(|(commit_hash, _)| commit_hash)
+            .collect::<HashSet<_>>();
+        store
+            .commits
+            .retain(|commit_hash, _| reachable.contains(commit_hash));
+        Ok(())
+    }
+
+    async fn checkout_clean(&mut self) -> Result<(), Error> {
+        // Same as `RawRepositoryImpl`, which leaves the working tree as is.
+        Ok(())
+    }
+
+    async fn checkout(&mut self, branch: Branch) -> Result<(), Error> {
+        let mut store = self.store();
+        let commit_hash = store.locate_branch(&branch)?;
+        store.checkout_tree(&commit_hash)?;
+        store.head = Head::Branch(branch);
+        Ok(())
+    }
+
+    async fn checkout_detach(&mut self, commit_hash: CommitHash) -> Result<(), Error> {
+        let mut store = self.store();
+        store.commit(&commit_hash)?;
+        store.head = Head::Detached(commit_hash);
+        Ok(())
+    }
+
+    async fn checkout_detach_with_tree(&mut self, commit_hash: CommitHash) -> Result<(), Error> {
+        let mut store = self.store();
+        store.checkout_tree(&commit_hash)?;
+        store.head = Head::Detached(commit_hash);
+        Ok(())
+    }
+
+    async fn get_head(&self) -> Result<CommitHash, Error> {
+        self.store().head()
+    }
+
+    async fn get_initial_commit(&self) -> Result<CommitHash, Error> {
+        let store = self.store();
+        let head = store.head()?;
+        Ok(*store
+            .history(head)?
+            .last()
+            .expect("history always contains the given commit"))
+    }
+
+    async fn show_commit(&self, commit_hash: CommitHash) -> Result<String, Error> {
+        self.store().show_commit(commit_hash)
+    }
+
+    async fn list_ancestors(
+        &self,
+        commit_hash: CommitHash,
+        max: Option<usize>,
+    ) -> Result<Vec<CommitHash>, Error> {
+        let store = self.store();
+        let mut ancestors = Vec::new();
+        let mut current = store.commit(&commit_hash)?;
+        while let Some(parent) = current.parent {
+            if max == Some(ancestors.len()) {
+                break;
+            }
+            ancestors.push(parent);
+            current = store.commit(&parent)?;
+        }
+        Ok(ancestors)
+    }
+
+    async fn query_commit_path(
+        &self,
+        ancestor: CommitHash,
+        descendant: CommitHash,
+    ) -> Result<Vec<CommitHash>, Error> {
+        if ancestor == descendant {
+            return Ok(vec![]);
+        }
+        let store = self.store();
+        if store.find_merge_base(ancestor, descendant)? != ancestor {
+            return Err(Error::InvalidRepository(
+                "ancestor is not the merge base of two commits".to_string(),
+            ));
+        }
+        let mut path = store
+            .history(descendant)?
+            .into_iter()
+            .take_while(|commit_hash| *commit_hash != ancestor)
+            .collect::<Vec<_>>();
+        path.reverse();
+        Ok(path)
+    }
+
+    async fn list_children(&self, commit_hash: CommitHash) -> Result<Vec<CommitHash>, Error> {
+        let store = self.store();
+        store.commit(&commit_hash)?;
+        let mut children = store
+            .commits
+            .iter()
+            .filter(|(_, commit)| commit.parent == Some(commit_hash))
+            .map(|(child, _)| *child)
+            .collect::<Vec<_>>();
+        children.sort();
+        Ok(children)
+    }
+
+    async fn find_merge_base(
+        &self,
+        commit_hash1: CommitHash,
+        commit_hash2: CommitHash,
+    ) -> Result<CommitHash, Error> {
+        self.store().find_merge_base(commit_hash1, commit_hash2)
+    }
+
+    async fn read_reserved_state(&self) -> Result<ReservedState, Error> {
+        // It reads the working tree, like `RawRepositoryImpl`.
+        self.store().working_tree.reserved_state()
+    }
+
+    async fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error> {
+        self.store().commit(&commit_hash)?.tree.reserved_state()
+    }
+
+    async fn list_files(&self, commit_hash: CommitHash) -> Result<Vec<(String, Hash256)>, Error> {
+        Ok(self
+            .store()
+            .commit(&commit_hash)?
+            .tree
+            .files
+            .iter()
+            .map(|(path, content)| (path.clone(), Hash256::hash(content.as_slice())))
+            .collect())
+    }
+
+    async fn list_changed_files(&self, commit_hash: CommitHash) -> Result<Vec<FileChange>, Error> {
+        let store = self.store();
+        let commit = store.commit(&commit_hash)?;
+        let parent = store.commit(&commit.parent.ok_or_else(|| {
+            Error::InvalidRepository("the initial commit has no parent".to_string())
+        })?)?;
+        Ok(parent
+            .tree
+            .changed_paths(&commit.tree)
+            .into_iter()
+            .map(|path| {
+                let content = commit.tree.files.get(&path);
+                FileChange {
+                    size: content.map(|content| content.len() as u64),
+                    binary: matches!(content, Some(content) if is_binary(content)),
+                    path,
+                }
+            })
+            .collect())
+    }
+
+    async fn read_file(
+        &self,
+        commit_hash: CommitHash,
+        path: String,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .store()
+            .commit(&commit_hash)?
+            .tree
+            .files
+            .get(&path)
+            .map(|content| content.to_vec()))
+    }
+
+    async fn add_remote(&mut self, remote_name: String, remote_url: String) -> Result<(), Error> {
+        let mut store = self.store();
+        if store.remotes.contains_key(&remote_name) {
+            return Err(Error::InvalidRepository(format!(
+                "remote {remote_name} already exists"
+            )));
+        }
+        store.remotes.insert(remote_name, remote_url);
+        Ok(())
+    }
+
+    async fn remove_remote(&mut self, remote_name: String) -> Result<(), Error> {
+        let mut store = self.store();
+        store
+            .remotes
+            .remove(&remote_name)
+            .ok_or_else(|| Error::NotFound(format!("remote {remote_name}")))?;
+        store
+            .remote_tracking_branches
+            .retain(|(remote, _), _| *remote != remote_name);
+        Ok(())
+    }
+
+    async fn fetch_all(&mut self) -> Result<(), Error> {
+        let remotes = self.store().remotes.clone();
+        for (remote_name, remote_url) in remotes {
+            // Never lock two repositories at the same time, to avoid deadlocks.
+            let mut known = self.store().commits.keys().copied().collect::<HashSet<_>>();
+            let (branches, commits) = {
+                let remote = self.repositories.lookup(&remote_url)?;
+                let remote = remote.lock().expect("in-memory repository poisoned");
+                let commits =
+                    remote.collect_reachable(remote.branches.values().copied(), &mut known)?;
+                (remote.branches.clone(), commits)
+            };
+            let mut store = self.store();
+            store.commits.extend(commits);
+            for (branch, commit_hash) in branches {
+                store
+                    .remote_tracking_branches
+                    .insert((remote_name.clone(), branch), commit_hash);
+            }
+        }
+        Ok(())
+    }
+
+    async fn push_option(
+        &self,
+        remote_name: String,
+        branch: Branch,
+        option: Option<String>,
+    ) -> Result<(), Error> {
+        let (remote_url, commit_hash, commits) = {
+            let store = self.store();
+            let remote_url = store
+                .remotes
+                .get(&remote_name)
+                .cloned()
+                .ok_or_else(|| Error::NotFound(format!("remote {remote_name}")))?;
+            let commit_hash = store.locate_branch(&branch)?;
+            let commits = store.collect_reachable([commit_hash], &mut HashSet::new())?;
+            (remote_url, commit_hash, commits)
+        };
+        let remote = self.repositories.lookup(&remote_url)?;
+        let check_fast_forward = |remote: &Store| match remote.branches.get(&branch) {
+            Some(remote_commit_hash) if !commits.iter().any(|(c, _)| c == remote_commit_hash) => {
+                Err(Error::InvalidRepository(format!(
+                    "push rejected: {branch} is not a fast-forward"
+                )))
+            }
+            _ => Ok(()),
+        };
+        let hook = {
+            let mut remote = remote.lock().expect("in-memory repository poisoned");
+            check_fast_forward(&remote)?;
+            remote.commits.extend(commits.iter().cloned());
+            remote
+                .pre_receive_hook
+                .as_ref()
+                .map(|hook| Arc::clone(&hook.0))
+        };
+        // The hook may read the remote repository, so it is run without the lock.
+        if let Some(hook) = hook {
+            hook(&branch, commit_hash, option.as_deref())
+                .map_err(|e| Error::InvalidRepository(format!("push rejected: {e}")))?;
+        }
+        {
+            let mut remote = remote.lock().expect("in-memory repository poisoned");
+            check_fast_forward(&remote)?;
+            remote.branches.insert(branch.clone(), commit_hash);
+            // The checked-out branch is updated with its working tree, like `updateInstead` of Git.
+            if matches!(&remote.head, Head::Branch(current) if *current == branch) {
+                remote.checkout_tree(&commit_hash)?;
+            }
+        }
+        self.store()
+            .remote_tracking_branches
+            .insert((remote_name, branch), commit_hash);
+        Ok(())
+    }
+
+    async fn list_remotes(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .store()
+            .remotes
+            .iter()
+            .map(|(name, url)| (name.clone(), url.clone()))
+            .collect())
+    }
+
+    async fn list_remote_tracking_branches(
+        &self,
+    ) -> Result<Vec<(String, String, CommitHash)>, Error> {
+        Ok(self
+            .store()
+            .remote_tracking_branches
+            .iter()
+            .map(|((remote, branch), commit_hash)| (remote.clone(), branch.clone(), *commit_hash))
+            .collect())
+    }
+
+    async fn locate_remote_tracking_branch(
+        &self,
+        remote_name: String,
+        branch_name: String,
+    ) -> Result<CommitHash, Error> {
+        self.store()
+            .remote_tracking_branches
+            .get(&(remote_name.clone(), branch_name.clone()))
+            .copied()
+            .ok_or_else(|| Error::NotFound(format!("{remote_name}/{branch_name}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_common::{generate_keypair, test_utils::generate_standard_genesis};
+
+    const MAIN: &str = "main";
+
+    #[tokio::test]
+    async fn history() {
+        let repositories = InMemoryRepositories::new();
+        let mut repo = repositories
+            .init("history", "initial", &MAIN.into())
+            .unwrap();
+        repositories
+            .init("history", "initial", &MAIN.into())
+            .unwrap_err();
+
+        let c1 = repo.get_head().await.unwrap();
+        let c2 = repo.create_commit("second".to_owned(), None).await.unwrap();
+        let c3 = repo.create_commit("third".to_owned(), None).await.unwrap();
+        assert_eq!(repo.locate_branch(MAIN.into()).await.unwrap(), c3);
+        assert_eq!(repo.list_ancestors(c3, None).await.unwrap(), vec![c2, c1]);
+        assert_eq!(repo.list_ancestors(c3, Some(1)).await.unwrap(), vec![c2]);
+        assert_eq!(repo.query_commit_path(c1, c3).await.unwrap(), vec![c2, c3]);
+
+        // Fork at c1
+        repo.create_branch("branch_a".into(), c1).await.unwrap();
+        repo.create_branch("branch_a".into(), c2).await.unwrap_err();
+        repo.checkout("branch_a".into()).await.unwrap();
+        let c4 = repo.create_commit("fourth".to_owned(), None).await.unwrap();
+        assert_eq!(repo.find_merge_base(c3, c4).await.unwrap(), c1);
+        repo.query_commit_path(c3, c4).await.unwrap_err();
+        let mut children = vec![c2, c4];
+        children.sort();
+        assert_eq!(repo.list_children(c1).await.unwrap(), children);
+        assert_eq!(repo.get_initial_commit().await.unwrap(), c1);
+        repo.delete_branch("branch_a".into()).await.unwrap_err();
+
+        // Revision selection
+        repo.create_tag("tag_a".into(), c2).await.unwrap();
+        assert_eq!(repo.get_tag(c2).await.unwrap(), vec!["tag_a".to_owned()]);
+        for (revision, expected) in [
+            ("HEAD", c4),
+            ("HEAD~1", c1),
+            ("main^", c2),
+            ("main~2", c1),
+            ("tag_a", c2),
+        ] {
+            assert_eq!(
+                repo.retrieve_commit_hash(revision.into()).await.unwrap(),
+                expected
+            );
+        }
+        repo.retrieve_commit_hash("HEAD^2".into())
+            .await
+            .unwrap_err();
+        repo.retrieve_commit_hash(c3.to_string()).await.unwrap();
+
+        // Another handle to the same repository shares the state.
+        let repo2 = repositories.open("history").unwrap();
+        assert_eq!(
+            repo2.list_branches().await.unwrap(),
+            vec!["branch_a".to_owned(), MAIN.to_owned()]
+        );
+        // Nothing is shared with the other sets of repositories.
+        InMemoryRawRepository::open("history").await.unwrap_err();
+        InMemoryRepositories::new().open("history").unwrap_err();
+    }
+
+    /// The repositories are freed once the set and all the handles are dropped.
+    #[tokio::test]
+    async fn ownership() {
+        let repositories = InMemoryRepositories::new();
+        let repo = repositories.init("repo", "initial", &MAIN.into()).unwrap();
+        let store = Arc::downgrade(&repo.store);
+        repositories.remove("repo").unwrap();
+        repositories.open("repo").unwrap_err();
+        // The handle remains valid after the removal.
+        repo.get_head().await.unwrap();
+        drop(repo);
+        assert!(store.upgrade().is_none());
+
+        let repo = repositories.init("repo", "initial", &MAIN.into()).unwrap();
+        let store = Arc::downgrade(&repo.store);
+        drop(repo);
+        repositories.open("repo").unwrap();
+        drop(repositories);
+        assert!(store.upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn semantic_commit() {
+        let mut repo = InMemoryRepositories::new()
+            .init("semantic_commit", "initial", &MAIN.into())
+            .unwrap();
+        let (reserved_state, _) = generate_standard_genesis(4);
+        repo.create_semantic_commit(SemanticCommit {
+            title: "genesis".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::Reserved(Box::new(reserved_state.clone())),
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+        assert_eq!(repo.read_reserved_state().await.unwrap(), reserved_state);
+
+        let parent = repo.get_head().await.unwrap();
+        let commit = SemanticCommit {
+            title: "title".to_owned(),
+            body: "body".to_owned(),
+            diff: Diff::None,
+            author: generate_keypair("author").0,
+            timestamp: 1234,
+        };
+        let commit_hash = repo.create_semantic_commit(commit.clone()).await.unwrap();
+        let read = repo.read_semantic_commit(commit_hash).await.unwrap();
+        assert_eq!(read.title, commit.title);
+        assert_eq!(read.body, commit.body);
+        assert_eq!(read.diff, Diff::None);
+        assert_eq!(read.author, commit.author);
+        assert_eq!(read.timestamp, 1234);
+
+        // The same commit on the same parent results in the same hash.
+        repo.checkout_detach(parent).await.unwrap();
+        let commit_hash2 = repo.create_semantic_commit(commit).await.unwrap();
+        assert_eq!(commit_hash, commit_hash2);
+    }
+
+    #[tokio::test]
+    async fn remote() {
+        let repositories = InMemoryRepositories::new();
+        let server_directory = "remote_server".to_owned();
+        let mut server = repositories
+            .init(&server_directory, "initial", &MAIN.into())
+            .unwrap();
+        let mut client = repositories
+            .clone_repository("remote_client", &server_directory)
+            .unwrap();
+        assert_eq!(
+            client.list_remotes().await.unwrap(),
+            vec![("origin".to_owned(), server_directory.clone())]
+        );
+        let c1 = client.get_head().await.unwrap();
+
+        // Fetch
+        let c2 = server
+            .create_commit("second".to_owned(), None)
+            .await
+            .unwrap();
+        client.fetch_all().await.unwrap();
+        assert_eq!(
+            client
+                .locate_remote_tracking_branch("origin".into(), MAIN.into())
+                .await
+                .unwrap(),
+            c2
+        );
+        client.show_commit(c2).await.unwrap();
+
+        // Push
+        client.create_branch("branch_a".into(), c2).await.unwrap();
+        client.checkout("branch_a".into()).await.unwrap();
+        let c3 = client
+            .create_commit("third".to_owned(), None)
+            .await
+            .unwrap();
+        client
+            .push_option("origin".into(), "branch_a".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(server.locate_branch("branch_a".into()).await.unwrap(), c3);
+        // Non-fast-forward is rejected.
+        client.move_branch("branch_a".into(), c1).await.unwrap();
+        client
+            .push_option("origin".into(), "branch_a".into(), None)
+            .await
+            .unwrap_err();
+
+        // The push option is given to the hook, which can reject the push.
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_ = Arc::clone(&received);
+        server.set_pre_receive_hook(Some(Arc::new(move |branch, commit_hash, option| {
+            received_.lock().unwrap().push((
+                branch.to_owned(),
+                commit_hash,
+                option.map(str::to_owned),
+            ));
+            match option {
+                Some("reject") => Err("rejected".to_owned()),
+                _ => Ok(()),
+            }
+        })));
+        client.move_branch(MAIN.into(), c3).await.unwrap();
+        client
+            .push_option("origin".into(), MAIN.into(), Some("reject".into()))
+            .await
+            .unwrap_err();
+        assert_eq!(server.locate_branch(MAIN.into()).await.unwrap(), c2);
+        // The checked-out branch is updated with the working tree.
+        client
+            .push_option("origin".into(), MAIN.into(), Some("accept".into()))
+            .await
+            .unwrap();
+        assert_eq!(server.locate_branch(MAIN.into()).await.unwrap(), c3);
+        {
+            let store = server.store();
+            assert_eq!(store.working_tree, store.commit(&c3).unwrap().tree);
+        }
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                (MAIN.to_owned(), c3, Some("reject".to_owned())),
+                (MAIN.to_owned(), c3, Some("accept".to_owned()))
+            ]
+        );
+
+        // Garbage collection
+        server.checkout_detach(c1).await.unwrap();
+        let orphan = server
+            .create_commit("orphan".to_owned(), None)
+            .await
+            .unwrap();
+        server.checkout(MAIN.into()).await.unwrap();
+        server.run_garbage_collection().await.unwrap();
+        server.show_commit(orphan).await.unwrap_err();
+        server.show_commit(c3).await.unwrap();
+    }
+}
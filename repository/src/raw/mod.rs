@@ -1,4 +1,5 @@
 mod implementation;
+mod memory;
 pub mod reserved_state;
 #[cfg(test)]
 mod tests;
@@ -8,6 +9,7 @@ use async_trait::async_trait;
 use eyre::Result;
 use git2::{BranchType, ObjectType, Oid, Repository, RepositoryInitOptions};
 use implementation::RawRepositoryImplInner;
+pub use memory::{InMemoryRawRepository, InMemoryRepositories, PreReceiveHook};
 use simperby_common::reserved::ReservedState;
 use std::convert::TryFrom;
 use std::fs;
@@ -25,6 +27,9 @@ pub enum Error {
     /// (e.g., there is no merge commit, there must be a merge base, ..) is violated.
     #[error("the repository is invalid: {0}")]
     InvalidRepository(String),
+    /// The changes to apply conflict with the current ones, in the given paths.
+    #[error("conflict: {0}")]
+    Conflict(String),
     #[error("unknown error: {0}")]
     Unknown(String),
 }
@@ -36,11 +41,38 @@ impl From<git2::Error> for Error {
 }
 
 /// A commit with abstracted diff.
+///
+/// `author` and `timestamp` are stored as the Git author (and committer) signature;
+/// the name is the hex-encoded public key and the time is `timestamp` in seconds.
+/// Since Git records the time only in seconds, `timestamp` is also stored in milliseconds
+/// in the `simperby-timestamp` header of the commit, which is read back.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SemanticCommit {
     pub title: String,
     pub body: String,
     pub diff: Diff,
+    pub author: PublicKey,
+    pub timestamp: Timestamp,
+}
+
+/// The annotation of an annotated tag.
+///
+/// `tagger` and `timestamp` are stored as the Git tagger signature, like [`SemanticCommit`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagAnnotation {
+    pub tagger: PublicKey,
+    pub timestamp: Timestamp,
+    pub message: String,
+}
+
+/// A file changed by a commit, compared to its parent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: String,
+    /// The size of the file in bytes after the change, or `None` if it is deleted.
+    pub size: Option<u64>,
+    /// Whether the file is binary after the change.
+    pub binary: bool,
 }
 
 #[async_trait]
@@ -57,6 +89,8 @@ pub trait RawRepository: Send + Sync + 'static {
         Self: Sized;
 
     /// Loads an exisitng repository.
+    ///
+    /// Fails if the repository is shallow.
     async fn open(directory: &str) -> Result<Self, Error>
     where
         Self: Sized;
@@ -109,6 +143,19 @@ pub trait RawRepository: Send + Sync + 'static {
     /// Creates a tag on the given commit.
     async fn create_tag(&mut self, tag: Tag, commit_hash: CommitHash) -> Result<(), Error>;
 
+    /// Creates an annotated tag on the given commit.
+    async fn create_annotated_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        annotation: TagAnnotation,
+    ) -> Result<(), Error>;
+
+    /// Reads the annotation of the tag.
+    ///
+    /// Returns `None` if it is a lightweight tag.
+    async fn read_tag_annotation(&self, tag: Tag) -> Result<Option<TagAnnotation>, Error>;
+
     /// Gets the commit that the tag points to.
     async fn locate_tag(&self, tag: Tag) -> Result<CommitHash, Error>;
 
@@ -123,6 +170,8 @@ pub trait RawRepository: Send + Sync + 'static {
     // ----------------------
 
     /// Creates a commit from the currently checked out branch.
+    ///
+    /// The author is `PublicKey::zero()`, and the timestamp is the current time in seconds.
     async fn create_commit(
         &mut self,
         commit_message: String,
@@ -135,6 +184,32 @@ pub trait RawRepository: Send + Sync + 'static {
     async fn create_semantic_commit(&mut self, commit: SemanticCommit)
         -> Result<CommitHash, Error>;
 
+    /// Creates a semantic commit like [`RawRepository::create_semantic_commit`],
+    /// with the given signature stored in the `simperby-signature` header of the commit.
+    ///
+    /// Note that the signature is a part of the commit, so it changes the commit hash.
+    async fn create_signed_semantic_commit(
+        &mut self,
+        commit: SemanticCommit,
+        signature: String,
+    ) -> Result<CommitHash, Error>;
+
+    /// Reads the signature stored in the `simperby-signature` header of the commit.
+    ///
+    /// Returns `None` if the commit is not signed.
+    async fn read_commit_signature(&self, commit_hash: CommitHash)
+        -> Result<Option<String>, Error>;
+
+    /// Applies the changes of the given commit on top of `HEAD`, like `git cherry-pick`.
+    ///
+    /// The new commit has the same message and author but the given timestamp.
+    /// It fails with [`Error::Conflict`] if the changes conflict with `HEAD`, leaving `HEAD` as is.
+    async fn cherry_pick(
+        &mut self,
+        commit_hash: CommitHash,
+        timestamp: Timestamp,
+    ) -> Result<CommitHash, Error>;
+
     /// Reads the reserved state from the current working tree.
     async fn read_semantic_commit(&self, commit_hash: CommitHash) -> Result<SemanticCommit, Error>;
 
@@ -155,6 +230,13 @@ pub trait RawRepository: Send + Sync + 'static {
     /// Checkouts to the commit and make `HEAD` in a detached mode.
     async fn checkout_detach(&mut self, commit_hash: CommitHash) -> Result<(), Error>;
 
+    /// Checkouts to the commit and make `HEAD` in a detached mode,
+    /// updating the working tree and the index to the commit as well.
+    ///
+    /// Unlike [`RawRepository::checkout_detach`], the commits created afterwards
+    /// are based on the tree of the given commit (e.g., to replay commits on it).
+    async fn checkout_detach_with_tree(&mut self, commit_hash: CommitHash) -> Result<(), Error>;
+
     // ---------------
     // Various queries
     // ---------------
@@ -204,6 +286,31 @@ pub trait RawRepository: Send + Sync + 'static {
     /// Reads the reserved state from the currently checked out branch.
     async fn read_reserved_state(&self) -> Result<ReservedState, Error>;
 
+    /// Reads the reserved state from the given commit, without checking it out.
+    async fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error>;
+
+    /// Lists all the files in the tree of the given commit.
+    ///
+    /// Returns `(path, hash of the content)`, sorted by the path.
+    async fn list_files(&self, commit_hash: CommitHash) -> Result<Vec<(String, Hash256)>, Error>;
+
+    /// Lists the files changed by the given commit, compared to its parent.
+    ///
+    /// The result is sorted by the path.
+    async fn list_changed_files(&self, commit_hash: CommitHash) -> Result<Vec<FileChange>, Error>;
+
+    /// Reads the content of the file at the given path in the given commit.
+    ///
+    /// Returns `None` if there is no such file.
+    async fn read_file(
+        &self,
+        commit_hash: CommitHash,
+        path: String,
+    ) -> Result<Option<Vec<u8>>, Error>;
+
     // ----------------------
     // Remote-related methods
     // ----------------------
@@ -364,6 +471,27 @@ async fn helper_3<
     result
 }
 
+async fn helper_3_mut<
+    T1: Send + Sync + 'static + Clone,
+    T2: Send + Sync + 'static + Clone,
+    T3: Send + Sync + 'static + Clone,
+    R: Send + Sync + 'static,
+>(
+    s: &mut RawRepositoryImpl,
+    f: impl Fn(&mut RawRepositoryImplInner, T1, T2, T3) -> R + Send + 'static,
+    a1: T1,
+    a2: T2,
+    a3: T3,
+) -> R {
+    let mut lock = s.inner.lock().await;
+    let mut inner = lock.take().expect("RawRepoImpl invariant violated");
+    let (result, inner) = tokio::task::spawn_blocking(move || (f(&mut inner, a1, a2, a3), inner))
+        .await
+        .unwrap();
+    lock.replace(inner);
+    result
+}
+
 #[async_trait]
 impl RawRepository for RawRepositoryImpl {
     async fn init(
@@ -458,6 +586,26 @@ impl RawRepository for RawRepositoryImpl {
         helper_2_mut(self, RawRepositoryImplInner::create_tag, tag, commit_hash).await
     }
 
+    async fn create_annotated_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        annotation: TagAnnotation,
+    ) -> Result<(), Error> {
+        helper_3_mut(
+            self,
+            RawRepositoryImplInner::create_annotated_tag,
+            tag,
+            commit_hash,
+            annotation,
+        )
+        .await
+    }
+
+    async fn read_tag_annotation(&self, tag: Tag) -> Result<Option<TagAnnotation>, Error> {
+        helper_1(self, RawRepositoryImplInner::read_tag_annotation, tag).await
+    }
+
     async fn locate_tag(&self, tag: Tag) -> Result<CommitHash, Error> {
         helper_1(self, RawRepositoryImplInner::locate_tag, tag).await
     }
@@ -491,6 +639,46 @@ impl RawRepository for RawRepositoryImpl {
         helper_1_mut(self, RawRepositoryImplInner::create_semantic_commit, commit).await
     }
 
+    async fn create_signed_semantic_commit(
+        &mut self,
+        commit: SemanticCommit,
+        signature: String,
+    ) -> Result<CommitHash, Error> {
+        helper_2_mut(
+            self,
+            RawRepositoryImplInner::create_signed_semantic_commit,
+            commit,
+            signature,
+        )
+        .await
+    }
+
+    async fn read_commit_signature(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<String>, Error> {
+        helper_1(
+            self,
+            RawRepositoryImplInner::read_commit_signature,
+            commit_hash,
+        )
+        .await
+    }
+
+    async fn cherry_pick(
+        &mut self,
+        commit_hash: CommitHash,
+        timestamp: Timestamp,
+    ) -> Result<CommitHash, Error> {
+        helper_2_mut(
+            self,
+            RawRepositoryImplInner::cherry_pick,
+            commit_hash,
+            timestamp,
+        )
+        .await
+    }
+
     async fn read_semantic_commit(&self, commit_hash: CommitHash) -> Result<SemanticCommit, Error> {
         helper_1(
             self,
@@ -516,6 +704,15 @@ impl RawRepository for RawRepositoryImpl {
         helper_1_mut(self, RawRepositoryImplInner::checkout_detach, commit_hash).await
     }
 
+    async fn checkout_detach_with_tree(&mut self, commit_hash: CommitHash) -> Result<(), Error> {
+        helper_1_mut(
+            self,
+            RawRepositoryImplInner::checkout_detach_with_tree,
+            commit_hash,
+        )
+        .await
+    }
+
     async fn get_head(&self) -> Result<CommitHash, Error> {
         helper_0(self, RawRepositoryImplInner::get_head).await
     }
@@ -578,6 +775,39 @@ impl RawRepository for RawRepositoryImpl {
         helper_0(self, RawRepositoryImplInner::read_reserved_state).await
     }
 
+    async fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error> {
+        helper_1(
+            self,
+            RawRepositoryImplInner::read_reserved_state_at_commit,
+            commit_hash,
+        )
+        .await
+    }
+
+    async fn list_files(&self, commit_hash: CommitHash) -> Result<Vec<(String, Hash256)>, Error> {
+        helper_1(self, RawRepositoryImplInner::list_files, commit_hash).await
+    }
+
+    async fn list_changed_files(&self, commit_hash: CommitHash) -> Result<Vec<FileChange>, Error> {
+        helper_1(
+            self,
+            RawRepositoryImplInner::list_changed_files,
+            commit_hash,
+        )
+        .await
+    }
+
+    async fn read_file(
+        &self,
+        commit_hash: CommitHash,
+        path: String,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        helper_2(self, RawRepositoryImplInner::read_file, commit_hash, path).await
+    }
+
     async fn add_remote(&mut self, remote_name: String, remote_url: String) -> Result<(), Error> {
         helper_2_mut(
             self,
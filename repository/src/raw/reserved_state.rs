@@ -31,53 +31,109 @@ pub async fn read_reserved_state(path: &str) -> Result<ReservedState, Error> {
     let version = fs::read_to_string(format!("{}/{}", path, "reserved/version")).await?;
     let version: String = serde_spb::from_str(version.as_str())?;
 
+    let policy_path = format!("{}/{}", path, "reserved/policy.json");
+    let content_policy: ContentPolicy = if Path::new(&policy_path).exists() {
+        serde_spb::from_str(fs::read_to_string(policy_path).await?.as_str())?
+    } else {
+        ContentPolicy::default()
+    };
+
     let reserved_state = ReservedState {
         genesis_info,
         members,
         consensus_leader_order,
         version,
+        content_policy,
     };
 
     Ok(reserved_state)
 }
 
-/// Writes the given reserved state to the given path, overwriting the existing file.
-pub async fn write_reserved_state(path: &str, state: &ReservedState) -> Result<(), Error> {
-    let genesis_info = serde_spb::to_string(&state.genesis_info)?;
-    let consensus_leader_order = serde_spb::to_string(&state.consensus_leader_order)?;
-    let version = serde_spb::to_string(&state.version)?;
+/// Encodes the given reserved state into the files of the reserved directory.
+///
+/// Returns the paths of the files relative to the repository, with their contents.
+pub fn encode_reserved_state(state: &ReservedState) -> Result<Vec<(String, String)>, Error> {
+    let mut files = vec![
+        (
+            "reserved/genesis_info.json".to_owned(),
+            serde_spb::to_string(&state.genesis_info)?,
+        ),
+        (
+            "reserved/consensus_leader_order.json".to_owned(),
+            serde_spb::to_string(&state.consensus_leader_order)?,
+        ),
+        (
+            "reserved/version".to_owned(),
+            serde_spb::to_string(&state.version)?,
+        ),
+    ];
+    // The content policy file is optional.
+    if state.content_policy != ContentPolicy::default() {
+        files.push((
+            "reserved/policy.json".to_owned(),
+            serde_spb::to_string(&state.content_policy)?,
+        ));
+    }
+    for member in &state.members {
+        files.push((
+            format!("reserved/members/{}.json", member.name),
+            serde_spb::to_string(member)?,
+        ));
+    }
+    Ok(files)
+}
 
-    // Create files of reserved state.
-    let path = format!("{}/{}", path, "reserved");
-    let reserved_path = Path::new(path.as_str());
-    if !reserved_path.exists() {
-        fs::create_dir(path.as_str()).await?;
-    } else {
-        fs::remove_dir_all(path.as_str()).await?;
-        fs::create_dir(path.as_str()).await?;
+/// Decodes the reserved state from the files encoded by [`encode_reserved_state`].
+///
+/// `files` are `(path, content)`, where the files outside the reserved directory are ignored.
+pub fn decode_reserved_state<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+) -> Result<ReservedState, Error> {
+    let mut genesis_info = None;
+    let mut members: Vec<Member> = vec![];
+    let mut consensus_leader_order = None;
+    let mut version = None;
+    let mut content_policy = ContentPolicy::default();
+    for (path, content) in files {
+        let content = || std::str::from_utf8(content);
+        match path {
+            "reserved/genesis_info.json" => genesis_info = Some(serde_spb::from_str(content()?)?),
+            "reserved/consensus_leader_order.json" => {
+                consensus_leader_order = Some(serde_spb::from_str(content()?)?)
+            }
+            "reserved/version" => version = Some(serde_spb::from_str(content()?)?),
+            "reserved/policy.json" => content_policy = serde_spb::from_str(content()?)?,
+            _ if path.starts_with("reserved/members/") => {
+                members.push(serde_spb::from_str(content()?)?)
+            }
+            _ => (),
+        }
     }
+    members.sort_by(|m1, m2| m1.name.cmp(&m2.name));
+    let missing = |path: &str| eyre::eyre!("no such file: {}", path);
 
-    fs::write(
-        format!("{}/{}", path.as_str(), "genesis_info.json"),
-        genesis_info,
-    )
-    .await?;
-    fs::write(
-        format!("{}/{}", path.as_str(), "consensus_leader_order.json"),
-        consensus_leader_order,
-    )
-    .await?;
-    fs::write(format!("{}/{}", path.as_str(), "version"), version).await?;
+    Ok(ReservedState {
+        genesis_info: genesis_info.ok_or_else(|| missing("reserved/genesis_info.json"))?,
+        members,
+        consensus_leader_order: consensus_leader_order
+            .ok_or_else(|| missing("reserved/consensus_leader_order.json"))?,
+        version: version.ok_or_else(|| missing("reserved/version"))?,
+        content_policy,
+    })
+}
 
-    let path = format!("{}/{}", path.as_str(), "members");
-    let members_path = Path::new(path.as_str());
-    if !members_path.exists() {
-        fs::create_dir(path.as_str()).await?;
+/// Writes the given reserved state to the given path, overwriting the existing file.
+pub async fn write_reserved_state(path: &str, state: &ReservedState) -> Result<(), Error> {
+    let files = encode_reserved_state(state)?;
+
+    // Create files of reserved state.
+    let reserved_path = format!("{}/{}", path, "reserved");
+    if Path::new(reserved_path.as_str()).exists() {
+        fs::remove_dir_all(reserved_path.as_str()).await?;
     }
-    for member in &state.members {
-        let file_name = format!("{}{}", member.name, ".json");
-        let member = serde_spb::to_string(member)?;
-        fs::write(format!("{}/{}", path.as_str(), file_name), member).await?;
+    fs::create_dir_all(format!("{reserved_path}/members")).await?;
+    for (file_path, content) in files {
+        fs::write(format!("{path}/{file_path}"), content).await?;
     }
 
     Ok(())
@@ -91,7 +147,15 @@ mod tests {
 
     #[tokio::test]
     async fn format_reserved_state() {
-        let (reserved_state, _) = generate_standard_genesis(10);
+        let (mut reserved_state, _) = generate_standard_genesis(10);
+        reserved_state.content_policy = ContentPolicy {
+            max_file_size: Some(1024),
+            forbid_binary_files: true,
+            protected_paths: vec![ProtectedPath {
+                prefix: "treasury/".to_owned(),
+                approvers: vec!["member-0000".to_owned()],
+            }],
+        };
 
         let td = TempDir::new().unwrap();
         let path = td.path();
@@ -101,5 +165,14 @@ mod tests {
         let read_reserved_state = read_reserved_state(path).await.unwrap();
 
         assert_eq!(reserved_state, read_reserved_state);
+
+        let files = encode_reserved_state(&reserved_state).unwrap();
+        let decoded = decode_reserved_state(
+            files
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_bytes())),
+        )
+        .unwrap();
+        assert_eq!(reserved_state, decoded);
     }
 }
@@ -1,8 +1,13 @@
 use super::SemanticCommit;
 use crate::raw::Error;
-use crate::raw::{CommitHash, RawRepository, RawRepositoryImpl};
-
-use simperby_common::{test_utils::generate_standard_genesis, Diff, ToHash256};
+use crate::raw::{
+    CommitHash, FileChange, InMemoryRawRepository, InMemoryRepositories, RawRepository,
+    RawRepositoryImpl, TagAnnotation,
+};
+
+use simperby_common::{
+    generate_keypair, test_utils::generate_standard_genesis, Diff, Hash256, PublicKey, ToHash256,
+};
 use std::path::Path;
 use tempfile::TempDir;
 
@@ -246,6 +251,33 @@ async fn checkout_detach() {
     // assert_eq!(cur_head_name, "HEAD");
 }
 
+/// Checkout to commit in the detached mode, with and without updating the working tree.
+#[tokio::test]
+async fn checkout_detach_with_tree() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    // The second commit adds the file `test`.
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+
+    // `checkout_detach` moves `HEAD` only.
+    repo.checkout_detach(first_commit_hash).await.unwrap();
+    assert_eq!(repo.get_head().await.unwrap(), first_commit_hash);
+    assert!(path.join("test").exists());
+
+    repo.checkout_detach(second_commit_hash).await.unwrap();
+    repo.checkout_detach_with_tree(first_commit_hash)
+        .await
+        .unwrap();
+    assert_eq!(repo.get_head().await.unwrap(), first_commit_hash);
+    assert!(!path.join("test").exists());
+}
+
 /*
     c3 (HEAD -> main)
     |
@@ -465,6 +497,8 @@ async fn reserved_state() {
             title: "test".to_owned(),
             body: "test-body".to_owned(),
             diff: Diff::Reserved(Box::new(rs.clone())),
+            author: PublicKey::zero(),
+            timestamp: 0,
         })
         .await
         .unwrap();
@@ -472,6 +506,311 @@ async fn reserved_state() {
     let _semantic_commit = repo.read_semantic_commit(commit_hash).await.unwrap();
 
     assert_eq!(rs_after, rs);
+
+    let mut rs2 = rs.clone();
+    rs2.members.pop();
+    rs2.consensus_leader_order.pop();
+    repo.create_semantic_commit(SemanticCommit {
+        title: "test2".to_owned(),
+        body: "test-body".to_owned(),
+        diff: Diff::Reserved(Box::new(rs2.clone())),
+        author: PublicKey::zero(),
+        timestamp: 0,
+    })
+    .await
+    .unwrap();
+    assert_eq!(repo.read_reserved_state().await.unwrap(), rs2);
+    // The reserved state of the previous commit is read without checking it out.
+    assert_eq!(
+        repo.read_reserved_state_at_commit(commit_hash)
+            .await
+            .unwrap(),
+        rs
+    );
+}
+
+#[tokio::test]
+async fn list_files() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let (rs, _) = generate_standard_genesis(4);
+
+    repo.checkout(MAIN.into()).await.unwrap();
+    std::fs::write(path.join("README.md"), "hello").unwrap();
+    let commit_hash = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "test".to_owned(),
+            body: "test-body".to_owned(),
+            diff: Diff::Reserved(Box::new(rs)),
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    let files = repo.list_files(commit_hash).await.unwrap();
+
+    assert_eq!(files[0], ("README.md".to_owned(), Hash256::hash("hello")));
+    assert!(files[1..]
+        .iter()
+        .all(|(path, _)| path.starts_with("reserved/")));
+    assert!(files.iter().any(|(path, _)| path == "reserved/version"));
+    assert!(files.windows(2).all(|x| x[0].0 < x[1].0));
+}
+
+#[tokio::test]
+async fn list_changed_files_and_read_file() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let (rs, _) = generate_standard_genesis(4);
+
+    repo.checkout(MAIN.into()).await.unwrap();
+    std::fs::write(path.join("README.md"), "hello").unwrap();
+    let commit_hash1 = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "test1".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::Reserved(Box::new(rs.clone())),
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    std::fs::remove_file(path.join("README.md")).unwrap();
+    std::fs::write(path.join("image.bin"), [0, 1, 2, 0]).unwrap();
+    let commit_hash2 = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "test2".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::Reserved(Box::new(rs.clone())),
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+
+    let changes = repo.list_changed_files(commit_hash1).await.unwrap();
+    assert_eq!(
+        changes[0],
+        FileChange {
+            path: "README.md".to_owned(),
+            size: Some(5),
+            binary: false,
+        }
+    );
+    assert!(changes[1..]
+        .iter()
+        .all(|change| change.path.starts_with("reserved/")));
+    assert_eq!(
+        repo.list_changed_files(commit_hash2).await.unwrap(),
+        vec![
+            FileChange {
+                path: "README.md".to_owned(),
+                size: None,
+                binary: false,
+            },
+            FileChange {
+                path: "image.bin".to_owned(),
+                size: Some(4),
+                binary: true,
+            }
+        ]
+    );
+
+    assert_eq!(
+        repo.read_file(commit_hash1, "README.md".to_owned())
+            .await
+            .unwrap(),
+        Some(b"hello".to_vec())
+    );
+    assert_eq!(
+        repo.read_file(commit_hash2, "README.md".to_owned())
+            .await
+            .unwrap(),
+        None
+    );
+    assert!(repo
+        .read_file(commit_hash2, "reserved/version".to_owned())
+        .await
+        .unwrap()
+        .is_some());
+
+    // The reserved state is read back from the reserved directory, including the content policy.
+    let mut rs3 = rs.clone();
+    rs3.content_policy.max_file_size = Some(1024);
+    let commit_hash3 = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "test3".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::Reserved(Box::new(rs3.clone())),
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.list_changed_files(commit_hash3).await.unwrap(),
+        vec![FileChange {
+            path: "reserved/policy.json".to_owned(),
+            size: Some(
+                simperby_common::serde_spb::to_string(&rs3.content_policy)
+                    .unwrap()
+                    .len() as u64
+            ),
+            binary: false,
+        }]
+    );
+    assert_eq!(
+        repo.read_semantic_commit(commit_hash3).await.unwrap().diff,
+        Diff::Reserved(Box::new(rs3))
+    );
+    assert_eq!(
+        repo.read_semantic_commit(commit_hash1).await.unwrap().diff,
+        Diff::General(
+            Box::new(rs),
+            repo.show_commit(commit_hash1).await.unwrap().to_hash256()
+        )
+    );
+    assert!(matches!(
+        repo.read_semantic_commit(commit_hash2).await.unwrap().diff,
+        Diff::NonReserved(_)
+    ));
+}
+
+#[tokio::test]
+async fn cherry_pick() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    let initial_commit = repo.get_head().await.unwrap();
+    let (rs, _) = generate_standard_genesis(4);
+
+    // Branch A changes `a.txt`.
+    repo.create_branch(BRANCH_A.into(), initial_commit)
+        .await
+        .unwrap();
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    std::fs::write(path.join("a.txt"), "a").unwrap();
+    let commit_a = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "a".to_owned(),
+            body: "body-a".to_owned(),
+            diff: Diff::Reserved(Box::new(rs.clone())),
+            author: PublicKey::zero(),
+            timestamp: 1000,
+        })
+        .await
+        .unwrap();
+    // Branch B changes `b.txt`, and `a.txt` differently.
+    repo.create_branch(BRANCH_B.into(), initial_commit)
+        .await
+        .unwrap();
+    repo.checkout(BRANCH_B.into()).await.unwrap();
+    std::fs::write(path.join("b.txt"), "b").unwrap();
+    repo.create_semantic_commit(SemanticCommit {
+        title: "b".to_owned(),
+        body: "".to_owned(),
+        diff: Diff::Reserved(Box::new(rs.clone())),
+        author: PublicKey::zero(),
+        timestamp: 0,
+    })
+    .await
+    .unwrap();
+    let commit_b = repo.get_head().await.unwrap();
+
+    let picked = repo.cherry_pick(commit_a, 5432).await.unwrap();
+    assert_eq!(repo.get_head().await.unwrap(), picked);
+    assert_eq!(
+        repo.list_ancestors(picked, Some(1)).await.unwrap(),
+        vec![commit_b]
+    );
+    let semantic_commit = repo.read_semantic_commit(picked).await.unwrap();
+    assert_eq!(semantic_commit.title, "a");
+    assert_eq!(semantic_commit.body, "body-a");
+    assert_eq!(semantic_commit.timestamp, 5432);
+    assert_eq!(std::fs::read_to_string(path.join("a.txt")).unwrap(), "a");
+    assert_eq!(std::fs::read_to_string(path.join("b.txt")).unwrap(), "b");
+
+    // Picking it again on top of a conflicting change of `a.txt` fails.
+    std::fs::write(path.join("a.txt"), "conflict").unwrap();
+    let commit_c = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "c".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::Reserved(Box::new(rs)),
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        repo.cherry_pick(picked, 5000).await,
+        Err(Error::Conflict(_))
+    ));
+    assert_eq!(repo.get_head().await.unwrap(), commit_c);
+}
+
+#[tokio::test]
+async fn shallow_repository() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().join("origin");
+    let mut repo = init_repository_with_initial_commit(&path).await.unwrap();
+    repo.create_semantic_commit(SemanticCommit {
+        title: "second".to_owned(),
+        body: "".to_owned(),
+        diff: Diff::None,
+        author: PublicKey::zero(),
+        timestamp: 0,
+    })
+    .await
+    .unwrap();
+
+    let shallow_path = td.path().join("shallow");
+    let status = std::process::Command::new("git")
+        .arg("clone")
+        .arg("--depth=1")
+        .arg(format!("file://{}", path.to_str().unwrap()))
+        .arg(&shallow_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(RawRepositoryImpl::open(shallow_path.to_str().unwrap())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn garbage_collection() {
+    let td = TempDir::new().unwrap();
+    // The path is not interpreted by a shell, so it may contain a space.
+    let path = td.path().join("a repository");
+    let mut repo = init_repository_with_initial_commit(&path).await.unwrap();
+
+    let initial_commit = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_A.into(), initial_commit)
+        .await
+        .unwrap();
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    let orphan_commit = repo
+        .create_semantic_commit(SemanticCommit {
+            title: "orphan".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::None,
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    repo.checkout(MAIN.into()).await.unwrap();
+    repo.delete_branch(BRANCH_A.into()).await.unwrap();
+    repo.read_semantic_commit(orphan_commit).await.unwrap();
+
+    repo.run_garbage_collection().await.unwrap();
+    assert!(repo.read_semantic_commit(orphan_commit).await.is_err());
+    assert_eq!(repo.get_head().await.unwrap(), initial_commit);
 }
 
 #[tokio::test]
@@ -509,6 +848,116 @@ async fn semantic_commit() {
     assert_eq!(semantic_commit_nonreserved.diff, Diff::NonReserved(hash));
 }
 
+/// Create a semantic commit and check that the author and the timestamp are preserved.
+#[tokio::test]
+async fn semantic_commit_author_and_timestamp() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let (author, _) = generate_keypair("semantic_commit_author_and_timestamp");
+    let semantic_commit = SemanticCommit {
+        title: "title".to_owned(),
+        body: "body".to_owned(),
+        diff: Diff::None,
+        author: author.clone(),
+        timestamp: 1_676_000_000_123,
+    };
+    let commit_hash = repo
+        .create_semantic_commit(semantic_commit.clone())
+        .await
+        .unwrap();
+    let read_commit = repo.read_semantic_commit(commit_hash).await.unwrap();
+    assert_eq!(read_commit.author, author);
+    assert_eq!(read_commit.timestamp, semantic_commit.timestamp);
+
+    // The same semantic commit on the same parent results in the same commit hash.
+    let parent = repo.list_ancestors(commit_hash, Some(1)).await.unwrap()[0];
+    repo.checkout_detach(parent).await.unwrap();
+    let commit_hash_again = repo.create_semantic_commit(semantic_commit).await.unwrap();
+    assert_eq!(commit_hash, commit_hash_again);
+
+    // The initial commit is not created by Simperby, so its author is not a public key.
+    assert!(repo.read_semantic_commit(parent).await.is_err());
+}
+
+/// Create a signed semantic commit and an annotated tag, and read the signature and the annotation.
+#[tokio::test]
+async fn signed_commit_and_annotated_tag() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let (author, _) = generate_keypair("signed_commit_and_annotated_tag");
+    let semantic_commit = SemanticCommit {
+        title: "title".to_owned(),
+        body: "body".to_owned(),
+        diff: Diff::None,
+        author: author.clone(),
+        timestamp: 1_676_000_000_000,
+    };
+    let unsigned = repo
+        .create_semantic_commit(semantic_commit.clone())
+        .await
+        .unwrap();
+    assert_eq!(repo.read_commit_signature(unsigned).await.unwrap(), None);
+
+    repo.checkout(MAIN.into()).await.unwrap();
+    let signed = repo
+        .create_signed_semantic_commit(semantic_commit.clone(), "signature".to_owned())
+        .await
+        .unwrap();
+    assert_eq!(repo.get_head().await.unwrap(), signed);
+    assert_eq!(repo.locate_branch(MAIN.into()).await.unwrap(), signed);
+    assert_eq!(
+        repo.list_ancestors(signed, Some(1)).await.unwrap(),
+        vec![unsigned]
+    );
+    assert_eq!(
+        repo.read_commit_signature(signed).await.unwrap(),
+        Some("signature".to_owned())
+    );
+    let read_commit = repo.read_semantic_commit(signed).await.unwrap();
+    assert_eq!(read_commit.title, semantic_commit.title);
+    assert_eq!(read_commit.author, author);
+
+    let annotation = TagAnnotation {
+        tagger: author,
+        timestamp: 1_676_000_000_123,
+        message: "vote\n\nsignature".to_owned(),
+    };
+    repo.create_annotated_tag(TAG_A.into(), signed, annotation.clone())
+        .await
+        .unwrap();
+    repo.create_tag(TAG_B.into(), signed).await.unwrap();
+    assert_eq!(repo.locate_tag(TAG_A.into()).await.unwrap(), signed);
+    assert_eq!(
+        repo.get_tag(signed).await.unwrap(),
+        vec![TAG_A.to_owned(), TAG_B.to_owned()]
+    );
+    assert_eq!(
+        repo.read_tag_annotation(TAG_A.into()).await.unwrap(),
+        Some(annotation.clone())
+    );
+    assert_eq!(repo.read_tag_annotation(TAG_B.into()).await.unwrap(), None);
+
+    // A name or a message can't inject the headers of the tag object.
+    repo.create_annotated_tag("c\ntagger x".into(), signed, annotation.clone())
+        .await
+        .unwrap_err();
+    let annotation = TagAnnotation {
+        message: "\ntagger x <y> 0 +0000\nsimperby-timestamp 0\n\nveto".to_owned(),
+        ..annotation
+    };
+    repo.create_annotated_tag("c".into(), signed, annotation.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.read_tag_annotation("c".into()).await.unwrap(),
+        Some(annotation)
+    );
+}
+
 /*
     c3 (HEAD -> branch_b)
      |  c2 (branch_a, tag_a)
@@ -576,3 +1025,581 @@ async fn retrieve_commit_hash() {
     let commit_hash_tag_a_retrieve = repo.retrieve_commit_hash(TAG_A.into()).await.unwrap();
     assert_eq!(commit_hash_tag_a_retrieve, commit_hash_a);
 }
+
+/// Pushes with a push option to the checked-out branch of a non-bare repository,
+/// through the pre-receive hook of a serving node.
+#[tokio::test]
+async fn push_option_and_hook() {
+    let td = TempDir::new().unwrap();
+    let remote_path = td.path().join("remote");
+    let remote_path = remote_path.to_str().unwrap();
+    let remote = RawRepositoryImpl::init(remote_path, "initial", &"fp".into())
+        .await
+        .unwrap();
+    let initial_commit = remote.get_head().await.unwrap();
+
+    // The hook runs a fake `simperby` that records its arguments
+    // and rejects the push if the push option is `reject`.
+    let hooks = format!("{remote_path}/.git/hooks");
+    let args_path = td.path().join("args");
+    let args_path = args_path.to_str().unwrap();
+    let simperby_path = format!("{hooks}/simperby");
+    std::fs::write(
+        &simperby_path,
+        format!("#!/bin/sh\nprintf '%s\\n' \"$@\" > {args_path}\n[ \"$4\" != reject ]\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        format!("{hooks}/simperby-pre-receive"),
+        include_str!("../pre_receive.sh"),
+    )
+    .unwrap();
+    std::fs::write(
+        format!("{hooks}/pre-receive"),
+        format!("#!/bin/sh\nSIMPERBY_PATH={simperby_path} exec {hooks}/simperby-pre-receive\n"),
+    )
+    .unwrap();
+    for file in ["simperby", "simperby-pre-receive", "pre-receive"] {
+        crate::raw::run_command(format!("chmod +x {hooks}/{file}")).unwrap();
+    }
+
+    let local_path = td.path().join("local");
+    let mut local = RawRepositoryImpl::clone(local_path.to_str().unwrap(), remote_path)
+        .await
+        .unwrap();
+    let create_commit = |title: &str| SemanticCommit {
+        title: title.to_owned(),
+        body: "".to_owned(),
+        diff: Diff::None,
+        author: PublicKey::zero(),
+        timestamp: 0,
+    };
+
+    let commit = local
+        .create_semantic_commit(create_commit("commit"))
+        .await
+        .unwrap();
+    local
+        .push_option("origin".into(), "fp".into(), Some("reject".into()))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        remote.locate_branch("fp".into()).await.unwrap(),
+        initial_commit
+    );
+    let args = std::fs::read_to_string(args_path).unwrap();
+    assert_eq!(
+        args.lines().collect::<Vec<_>>(),
+        vec!["check-push", &commit.to_string(), "fp", "reject"]
+    );
+
+    // The push option is passed as a single argument, never interpreted by the shell.
+    let pwned_path = td.path().join("pwned");
+    let push_option = format!("1234 $(touch {})", pwned_path.to_str().unwrap());
+    local
+        .push_option("origin".into(), "fp".into(), Some(push_option.clone()))
+        .await
+        .unwrap();
+    assert!(!pwned_path.exists());
+    let args = std::fs::read_to_string(args_path).unwrap();
+    assert_eq!(
+        args.lines().collect::<Vec<_>>(),
+        vec!["check-push", &commit.to_string(), "fp", &push_option]
+    );
+    // The checked-out branch of the remote has been updated.
+    assert_eq!(remote.locate_branch("fp".into()).await.unwrap(), commit);
+    assert_eq!(remote.get_head().await.unwrap(), commit);
+}
+
+/// A backend of [`RawRepository`], to run the same scenario against every implementation.
+#[async_trait::async_trait]
+trait Backend: Send + Sync {
+    type Repository: RawRepository;
+
+    /// Returns the directory of the repository, which is also its URL.
+    fn directory(&self, name: &str) -> String;
+
+    async fn init(&self, name: &str) -> Self::Repository;
+
+    async fn clone(&self, name: &str, origin: &str) -> Self::Repository;
+
+    /// Writes the file to the working tree, or removes it if `content` is `None`.
+    fn write_file(
+        &self,
+        repo: &mut Self::Repository,
+        name: &str,
+        path: &str,
+        content: Option<&[u8]>,
+    );
+}
+
+struct GitBackend(TempDir);
+
+#[async_trait::async_trait]
+impl Backend for GitBackend {
+    type Repository = RawRepositoryImpl;
+
+    fn directory(&self, name: &str) -> String {
+        self.0.path().join(name).to_str().unwrap().to_owned()
+    }
+
+    async fn init(&self, name: &str) -> RawRepositoryImpl {
+        RawRepositoryImpl::init(&self.directory(name), "initial", &MAIN.into())
+            .await
+            .unwrap()
+    }
+
+    async fn clone(&self, name: &str, origin: &str) -> RawRepositoryImpl {
+        RawRepositoryImpl::clone(&self.directory(name), &self.directory(origin))
+            .await
+            .unwrap()
+    }
+
+    fn write_file(
+        &self,
+        _: &mut RawRepositoryImpl,
+        name: &str,
+        path: &str,
+        content: Option<&[u8]>,
+    ) {
+        let path = Path::new(&self.directory(name)).join(path);
+        match content {
+            Some(content) => std::fs::write(path, content).unwrap(),
+            None => std::fs::remove_file(path).unwrap(),
+        }
+    }
+}
+
+struct MemoryBackend(InMemoryRepositories);
+
+#[async_trait::async_trait]
+impl Backend for MemoryBackend {
+    type Repository = InMemoryRawRepository;
+
+    fn directory(&self, name: &str) -> String {
+        format!("memory://{name}")
+    }
+
+    async fn init(&self, name: &str) -> InMemoryRawRepository {
+        self.0
+            .init(&self.directory(name), "initial", &MAIN.into())
+            .unwrap()
+    }
+
+    async fn clone(&self, name: &str, origin: &str) -> InMemoryRawRepository {
+        self.0
+            .clone_repository(&self.directory(name), &self.directory(origin))
+            .unwrap()
+    }
+
+    fn write_file(
+        &self,
+        repo: &mut InMemoryRawRepository,
+        _: &str,
+        path: &str,
+        content: Option<&[u8]>,
+    ) {
+        repo.write_file(path, content.map(<[u8]>::to_vec));
+    }
+}
+
+/// The observations of a scenario, where the commits are named by the order they are observed in,
+/// since the backends give them different hashes.
+#[derive(Default)]
+struct Transcript {
+    commits: Vec<CommitHash>,
+    lines: Vec<String>,
+}
+
+impl Transcript {
+    fn name(&mut self, commit_hash: CommitHash) -> String {
+        let index = match self.commits.iter().position(|c| *c == commit_hash) {
+            Some(index) => index,
+            None => {
+                self.commits.push(commit_hash);
+                self.commits.len() - 1
+            }
+        };
+        format!("c{index}")
+    }
+
+    fn names(&mut self, commit_hashes: Vec<CommitHash>) -> Vec<String> {
+        commit_hashes.into_iter().map(|c| self.name(c)).collect()
+    }
+
+    fn record(&mut self, label: &str, observation: impl std::fmt::Debug) {
+        self.lines.push(format!("{label}: {observation:?}"));
+    }
+
+    fn record_commits(&mut self, label: &str, commit_hashes: Vec<CommitHash>) {
+        let names = self.names(commit_hashes);
+        self.record(label, names);
+    }
+
+    fn record_commit(&mut self, label: &str, result: Result<CommitHash, Error>) {
+        let observation = result.map(|c| self.name(c)).map_err(error_kind);
+        self.record(label, observation);
+    }
+}
+
+/// Only [`Error::Conflict`] is specified by [`RawRepository`]; the other errors differ by the backends.
+fn error_kind(error: Error) -> &'static str {
+    match error {
+        Error::Conflict(_) => "conflict",
+        _ => "error",
+    }
+}
+
+fn diff_kind(diff: &Diff) -> String {
+    match diff {
+        Diff::None => "none".to_owned(),
+        Diff::Reserved(reserved_state) => format!("reserved {}", reserved_state.version),
+        Diff::NonReserved(_) => "non-reserved".to_owned(),
+        Diff::General(reserved_state, _) => format!("general {}", reserved_state.version),
+    }
+}
+
+/// Records everything observable about the commit, except for the patch of `show_commit`.
+async fn record_commit_content<R: RawRepository>(
+    transcript: &mut Transcript,
+    repo: &R,
+    commit_hash: CommitHash,
+) {
+    let name = transcript.name(commit_hash);
+    transcript.record(
+        &format!("files of {name}"),
+        repo.list_files(commit_hash).await.unwrap(),
+    );
+    transcript.record(
+        &format!("changes of {name}"),
+        repo.list_changed_files(commit_hash).await.unwrap(),
+    );
+    let commit = repo.read_semantic_commit(commit_hash).await.unwrap();
+    transcript.record(
+        &format!("commit {name}"),
+        (commit.title, commit.body, diff_kind(&commit.diff)),
+    );
+    transcript.record(
+        &format!("reserved state version of {name}"),
+        repo.read_reserved_state_at_commit(commit_hash)
+            .await
+            .map(|reserved_state| reserved_state.version)
+            .map_err(error_kind),
+    );
+}
+
+/// Runs a scenario through every method of [`RawRepository`], recording what is observed.
+///
+/// The garbage collection isn't observable without the hashes, and `list_children` isn't
+/// implemented for Git yet.
+async fn run_scenario<B: Backend>(backend: &B) -> Vec<String> {
+    let mut t = Transcript::default();
+    let (rs, _) = generate_standard_genesis(4);
+    let mut rs2 = rs.clone();
+    rs2.version = "0.3.0".to_owned();
+    rs2.content_policy.max_file_size = Some(1024);
+    let semantic_commit = |title: &str, diff: Diff| SemanticCommit {
+        title: title.to_owned(),
+        body: format!("body of {title}"),
+        diff,
+        author: PublicKey::zero(),
+        timestamp: 0,
+    };
+
+    let mut repo = backend.init("server").await;
+    let c0 = repo.get_head().await.unwrap();
+    t.name(c0);
+    t.record(
+        "reserved state at c0",
+        repo.read_reserved_state_at_commit(c0)
+            .await
+            .map_err(error_kind),
+    );
+
+    // The reserved state is committed with the working tree.
+    backend.write_file(&mut repo, "server", "README.md", Some(b"hello"));
+    let c1 = repo
+        .create_semantic_commit(semantic_commit(
+            "genesis",
+            Diff::Reserved(Box::new(rs.clone())),
+        ))
+        .await
+        .unwrap();
+    record_commit_content(&mut t, &repo, c1).await;
+    t.record(
+        "reserved state",
+        repo.read_reserved_state().await.unwrap() == rs,
+    );
+
+    // A semantic commit without a diff doesn't add the working tree.
+    backend.write_file(&mut repo, "server", "image.bin", Some(&[0, 1, 2, 0]));
+    backend.write_file(&mut repo, "server", "README.md", None);
+    let c2 = repo
+        .create_semantic_commit(semantic_commit("empty", Diff::None))
+        .await
+        .unwrap();
+    record_commit_content(&mut t, &repo, c2).await;
+
+    // A plain commit adds the working tree.
+    let c3 = repo.create_commit("third".to_owned(), None).await.unwrap();
+    record_commit_content(&mut t, &repo, c3).await;
+    for path in ["image.bin", "README.md", "test", "reserved/version"] {
+        t.record(
+            &format!("{path} at c3"),
+            repo.read_file(c3, path.to_owned()).await.unwrap(),
+        );
+    }
+    let c4 = repo
+        .create_semantic_commit(semantic_commit(
+            "rs2",
+            Diff::Reserved(Box::new(rs2.clone())),
+        ))
+        .await
+        .unwrap();
+    record_commit_content(&mut t, &repo, c4).await;
+    repo.create_semantic_commit(semantic_commit(
+        "general",
+        Diff::General(Box::new(rs), Hash256::zero()),
+    ))
+    .await
+    .unwrap_err();
+
+    // History
+    repo.create_branch(BRANCH_A.into(), c1).await.unwrap();
+    t.record(
+        "duplicate branch",
+        repo.create_branch(BRANCH_A.into(), c2)
+            .await
+            .map_err(error_kind),
+    );
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    t.record(
+        "reserved state version on branch a",
+        repo.read_reserved_state().await.unwrap().version,
+    );
+    backend.write_file(&mut repo, "server", "a.txt", Some(b"a"));
+    let c5 = repo.create_commit("fifth".to_owned(), None).await.unwrap();
+    record_commit_content(&mut t, &repo, c5).await;
+    t.record_commit("merge base", repo.find_merge_base(c4, c5).await);
+    let path = repo.query_commit_path(c1, c4).await.unwrap();
+    t.record_commits("path", path);
+    t.record(
+        "invalid path",
+        repo.query_commit_path(c4, c5).await.map_err(error_kind),
+    );
+    let ancestors = repo.list_ancestors(c4, None).await.unwrap();
+    t.record_commits("ancestors", ancestors);
+    let ancestors = repo.list_ancestors(c4, Some(2)).await.unwrap();
+    t.record_commits("two ancestors", ancestors);
+    t.record_commit("initial commit", repo.get_initial_commit().await);
+    let mut branches = repo.list_branches().await.unwrap();
+    branches.sort();
+    t.record("branches", branches);
+    t.record("branches on c1", repo.get_branches(c1).await.unwrap());
+    t.record(
+        "delete the checked-out branch",
+        repo.delete_branch(BRANCH_A.into())
+            .await
+            .map_err(error_kind),
+    );
+
+    // Cherry-pick
+    let c6 = repo.cherry_pick(c4, 1000).await.unwrap();
+    record_commit_content(&mut t, &repo, c6).await;
+    let c7 = repo.cherry_pick(c3, 2000).await.unwrap();
+    record_commit_content(&mut t, &repo, c7).await;
+    repo.checkout(MAIN.into()).await.unwrap();
+    backend.write_file(&mut repo, "server", "a.txt", Some(b"b"));
+    let c8 = repo.create_commit("eighth".to_owned(), None).await.unwrap();
+    record_commit_content(&mut t, &repo, c8).await;
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    t.record(
+        "conflicting cherry-pick",
+        repo.cherry_pick(c8, 3000).await.map_err(error_kind),
+    );
+    t.record_commit("head after the conflict", repo.get_head().await);
+
+    // A detached checkout keeps the index, unless it is with the tree.
+    repo.checkout_detach(c1).await.unwrap();
+    let c9 = repo
+        .create_semantic_commit(semantic_commit("detached", Diff::None))
+        .await
+        .unwrap();
+    record_commit_content(&mut t, &repo, c9).await;
+    t.record_commit("detached head", repo.get_head().await);
+    repo.checkout_detach_with_tree(c1).await.unwrap();
+    let c10 = repo
+        .create_semantic_commit(semantic_commit("detached with tree", Diff::None))
+        .await
+        .unwrap();
+    record_commit_content(&mut t, &repo, c10).await;
+    repo.checkout_clean().await.unwrap();
+    repo.checkout(MAIN.into()).await.unwrap();
+    repo.create_branch(BRANCH_B.into(), c10).await.unwrap();
+    repo.move_branch(BRANCH_B.into(), c9).await.unwrap();
+    t.record_commit("moved branch", repo.locate_branch(BRANCH_B.into()).await);
+    repo.delete_branch(BRANCH_B.into()).await.unwrap();
+    t.record_commit("deleted branch", repo.locate_branch(BRANCH_B.into()).await);
+
+    // Tags and signatures
+    repo.create_tag(TAG_A.into(), c1).await.unwrap();
+    let annotation = TagAnnotation {
+        tagger: PublicKey::zero(),
+        timestamp: 1234,
+        message: "message".to_owned(),
+    };
+    repo.create_annotated_tag(TAG_B.into(), c4, annotation)
+        .await
+        .unwrap();
+    t.record("tags", repo.list_tags().await.unwrap());
+    t.record("tags on c1", repo.get_tag(c1).await.unwrap());
+    t.record_commit("tag b", repo.locate_tag(TAG_B.into()).await);
+    t.record(
+        "annotations",
+        (
+            repo.read_tag_annotation(TAG_A.into()).await.unwrap(),
+            repo.read_tag_annotation(TAG_B.into()).await.unwrap(),
+        ),
+    );
+    repo.remove_tag(TAG_A.into()).await.unwrap();
+    t.record_commit("removed tag", repo.locate_tag(TAG_A.into()).await);
+    let signed = repo
+        .create_signed_semantic_commit(semantic_commit("signed", Diff::None), "sig".to_owned())
+        .await
+        .unwrap();
+    t.record(
+        "signatures",
+        (
+            repo.read_commit_signature(signed).await.unwrap(),
+            repo.read_commit_signature(c8).await.unwrap(),
+        ),
+    );
+    for (label, revision) in [
+        ("HEAD", "HEAD".to_owned()),
+        ("HEAD~2", "HEAD~2".to_owned()),
+        ("main^", "main^".to_owned()),
+        ("branch_a~1", format!("{BRANCH_A}~1")),
+        ("tag_b", TAG_B.to_owned()),
+        ("the hash of c5", c5.to_string()),
+    ] {
+        t.record_commit(label, repo.retrieve_commit_hash(revision).await);
+    }
+
+    // Remotes
+    let mut client = backend.clone("client", "server").await;
+    t.record(
+        "remotes",
+        client
+            .list_remotes()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>(),
+    );
+    t.record("client branches", client.list_branches().await.unwrap());
+    t.record_commit("client head", client.get_head().await);
+    t.record(
+        "client reserved state version",
+        client.read_reserved_state().await.unwrap().version,
+    );
+    backend.write_file(&mut repo, "server", "b.txt", Some(b"b"));
+    let server_commit = repo
+        .create_commit("eleventh".to_owned(), None)
+        .await
+        .unwrap();
+    t.name(server_commit);
+    client.fetch_all().await.unwrap();
+    let mut remote_tracking_branches = client
+        .list_remote_tracking_branches()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(remote, branch, c)| (remote, branch, t.name(c)))
+        .collect::<Vec<_>>();
+    remote_tracking_branches.sort();
+    t.record("remote tracking branches", remote_tracking_branches);
+    t.record_commit(
+        "origin/main",
+        client
+            .locate_remote_tracking_branch("origin".into(), MAIN.into())
+            .await,
+    );
+
+    // Pushes, to a new branch, to the checked-out branch, and not a fast-forward.
+    client
+        .create_branch(BRANCH_B.into(), server_commit)
+        .await
+        .unwrap();
+    client.checkout(BRANCH_B.into()).await.unwrap();
+    backend.write_file(&mut client, "client", "c.txt", Some(b"c"));
+    let client_commit = client
+        .create_commit("twelfth".to_owned(), None)
+        .await
+        .unwrap();
+    t.name(client_commit);
+    t.record(
+        "push",
+        client
+            .push_option("origin".into(), BRANCH_B.into(), Some("option".into()))
+            .await
+            .map_err(error_kind),
+    );
+    t.record_commit("pushed branch", repo.locate_branch(BRANCH_B.into()).await);
+    client
+        .move_branch(MAIN.into(), client_commit)
+        .await
+        .unwrap();
+    t.record(
+        "push to the checked-out branch",
+        client
+            .push_option("origin".into(), MAIN.into(), None)
+            .await
+            .map_err(error_kind),
+    );
+    t.record_commit("server head", repo.get_head().await);
+    t.record(
+        "server reserved state version",
+        repo.read_reserved_state().await.unwrap().version,
+    );
+    client
+        .move_branch(BRANCH_B.into(), server_commit)
+        .await
+        .unwrap();
+    t.record(
+        "non-fast-forward push",
+        client
+            .push_option("origin".into(), BRANCH_B.into(), None)
+            .await
+            .map_err(error_kind),
+    );
+    t.record_commit(
+        "not pushed branch",
+        repo.locate_branch(BRANCH_B.into()).await,
+    );
+    t.record(
+        "duplicate remote",
+        client
+            .add_remote("origin".into(), backend.directory("server"))
+            .await
+            .map_err(error_kind),
+    );
+    client.remove_remote("origin".into()).await.unwrap();
+    t.record(
+        "remote tracking branches after removing the remote",
+        client.list_remote_tracking_branches().await.unwrap(),
+    );
+
+    t.lines
+}
+
+/// The in-memory implementation behaves the same as the Git one.
+#[tokio::test]
+async fn backends_agree() {
+    let git = run_scenario(&GitBackend(TempDir::new().unwrap())).await;
+    let memory = run_scenario(&MemoryBackend(InMemoryRepositories::new())).await;
+    for (git, memory) in git.iter().zip(memory.iter()) {
+        assert_eq!(git, memory);
+    }
+    assert_eq!(git.len(), memory.len());
+}
@@ -17,40 +17,189 @@ async fn advance_finalized_branch<T: RawRepository>(
         .move_branch(FP_BRANCH_NAME.into(), to_be_finalized_block_commit_hash)
         .await?;
     this.raw.checkout(FP_BRANCH_NAME.into()).await?;
+    this.create_fp_commit(&finalization_proof).await?;
+    Ok(())
+}
+
+/// Persists the evidence as a commit on top of the conflicting block, tagged with `evidence-#`.
+///
+/// It does nothing if there is already an evidence for the same fork point;
+/// one evidence is enough for a fork.
+async fn store_evidence<T: RawRepository>(
+    this: &mut DistributedRepository<T>,
+    evidence: &ForkEvidence,
+) -> Result<(), Error> {
+    let tag_name = format!(
+        "{}{}",
+        EVIDENCE_TAG_PREFIX,
+        &evidence.fork_point.to_string()[0..TAG_NAME_HASH_DIGITS]
+    );
+    if this.raw.locate_tag(tag_name.clone()).await.is_ok() {
+        return Ok(());
+    }
+    this.raw.checkout_clean().await?;
     this.raw
-        .create_semantic_commit(format::fp_to_semantic_commit(&finalization_proof))
+        .checkout_detach(evidence.conflicting_block_commit)
+        .await?;
+    let evidence_commit_hash = this
+        .raw
+        .create_semantic_commit(format::evidence_to_semantic_commit(evidence))
         .await?;
+    this.raw.create_tag(tag_name, evidence_commit_hash).await?;
+    this.raw.checkout(FP_BRANCH_NAME.into()).await?;
     Ok(())
 }
 
+/// Returns the last block header at or before the given commit.
+async fn read_last_block_header<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    mut commit_hash: CommitHash,
+) -> Result<BlockHeader, Error> {
+    loop {
+        if let Commit::Block(header) = this.read_commit(commit_hash).await? {
+            return Ok(header);
+        }
+        commit_hash = this.raw.list_ancestors(commit_hash, Some(1)).await?[0];
+    }
+}
+
+/// Receives a branch that forks off the `finalized` branch at `merge_base`.
+///
+/// If the fork point is farther than `long_range_attack_distance` blocks behind `finalized`,
+/// the branch is considered a long range attack and rejected.
+/// Otherwise, the branch is rejected as well but if it contains a finalized block,
+/// which conflicts with the `finalized` branch, it is persisted as a [`ForkEvidence`].
+async fn receive_fork<T: RawRepository>(
+    this: &mut DistributedRepository<T>,
+    merge_base: CommitHash,
+    tip_commit_hash: CommitHash,
+    source: Option<String>,
+) -> Result<Result<(), String>, Error> {
+    // Collect the blocks finalized after the fork point, in the reverse order.
+    let distance = this.config.long_range_attack_distance;
+    let mut canonical_blocks = Vec::new();
+    let mut commit_hash = this.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    while commit_hash != merge_base && canonical_blocks.len() <= distance {
+        if let Commit::Block(header) = this.read_commit(commit_hash).await? {
+            canonical_blocks.push(header);
+        }
+        commit_hash = this.raw.list_ancestors(commit_hash, Some(1)).await?[0];
+    }
+    if canonical_blocks.len() > distance {
+        return Ok(Err(format!(
+            "the received branch forks off more than {distance} blocks behind the last finalized block (long range attack)"
+        )));
+    }
+    let canonical_block = canonical_blocks
+        .pop()
+        .expect("the fork point is behind the last finalized block");
+
+    // If the branch ends with a finalization proof commit, consider the direct parent as the tip.
+    let last_finalization_proof =
+        format::fp_from_semantic_commit(this.raw.read_semantic_commit(tip_commit_hash).await?).ok();
+    let tip_commit_hash = if last_finalization_proof.is_some() {
+        this.raw.list_ancestors(tip_commit_hash, Some(1)).await?[0]
+    } else {
+        tip_commit_hash
+    };
+    let commits = if tip_commit_hash == merge_base {
+        Vec::new()
+    } else {
+        match read_commits(this, merge_base, tip_commit_hash).await {
+            Ok(x) => x,
+            Err(CommitError::Commit(error, commit)) => {
+                return Ok(Err(format!("failed to parse commit {commit}: {error}")));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    // Find the last finalized block in the forked branch.
+    // A block is finalized if the next block carries its finalization proof,
+    // or if it is the last block followed by a valid finalization proof commit.
+    let mut last_header = read_last_block_header(this, merge_base).await?;
+    let mut last_header_commit_hash = None;
+    let mut finalized_block = None;
+    for (commit, commit_hash) in commits {
+        if let Commit::Block(header) = commit {
+            if let Err(e) = verify::verify_header_to_header(&last_header, &header) {
+                return Ok(Err(format!(
+                    "invalid block header in the forked branch at {commit_hash}: {e}"
+                )));
+            }
+            if let Some(last_header_commit_hash) = last_header_commit_hash {
+                finalized_block = Some((
+                    last_header,
+                    header.prev_block_finalization_proof.clone(),
+                    last_header_commit_hash,
+                ));
+            }
+            last_header = header;
+            last_header_commit_hash = Some(commit_hash);
+        }
+    }
+    if let (Some(proof), Some(commit_hash)) = (last_finalization_proof, last_header_commit_hash) {
+        if proof.height == last_header.height
+            && verify::verify_finalization_proof(&last_header, &proof.proof).is_ok()
+        {
+            finalized_block = Some((last_header, proof.proof, commit_hash));
+        }
+    }
+
+    if let Some((conflicting_block, conflicting_block_proof, conflicting_block_commit)) =
+        finalized_block
+    {
+        let evidence = ForkEvidence {
+            fork_point: merge_base,
+            canonical_block,
+            conflicting_block,
+            conflicting_block_proof,
+            conflicting_block_commit,
+            source,
+        };
+        log::error!(
+            "conflicting finalization detected: block {} at height {} (commit {}, from {}) forks off the finalized branch",
+            evidence.conflicting_block.to_hash256(),
+            evidence.conflicting_block.height,
+            evidence.conflicting_block_commit,
+            evidence.source.as_deref().unwrap_or("unknown peer")
+        );
+        store_evidence(this, &evidence).await?;
+        Ok(Err(
+            "the received branch contains a finalized block conflicting with the finalized branch."
+                .to_owned(),
+        ))
+    } else {
+        Ok(Err(
+            "the received branch tip commit is not a descendant of the last finalized block."
+                .to_owned(),
+        ))
+    }
+}
+
 /// Receives a new branch from a remote peer (either fetch or push).
 ///
+/// `source` is the name of the peer, if known.
+///
 /// - Returns `Ok(Ok(()))` if the branch is successfully received.
 /// - Returns `Ok(Err(_))` if the branch is invalid and thus rejected, with the reason.
 /// - Returns `Err(_)` if an error occurs.
 pub async fn receive<T: RawRepository>(
     this: &mut DistributedRepository<T>,
     tip_commit_hash: CommitHash,
+    source: Option<String>,
 ) -> Result<Result<(), String>, Error> {
-    let last_finalized_block_header = this.get_last_finalized_block_header().await?;
     let last_finalized_commit_hash = this.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
-    let reserved_state = this.get_reserved_state().await?;
-    let mut csv =
-        CommitSequenceVerifier::new(last_finalized_block_header.clone(), reserved_state.clone())
-            .map_err(|e| {
-                IntegrityError::new(format!("finalized branch is not accepted by CSV: {e}"))
-            })?;
 
-    if this
+    let merge_base = this
         .raw
         .find_merge_base(last_finalized_commit_hash, tip_commit_hash)
-        .await?
-        != last_finalized_commit_hash
-    {
-        return Ok(Err(
-            "the received branch tip commit is not a descendant of the last finalized block."
-                .to_owned(),
-        ));
+        .await?;
+    if merge_base == tip_commit_hash {
+        return Ok(Err("the received commit is already finalized.".to_owned()));
+    }
+    if merge_base != last_finalized_commit_hash {
+        return receive_fork(this, merge_base, tip_commit_hash, source).await;
     }
 
     // If the branch ends with a finalization proof commit
@@ -65,19 +214,20 @@ pub async fn receive<T: RawRepository>(
         }
 
         // Read the commits in the branch and verify them
-        let commits = match read_commits(this, last_finalized_commit_hash, commit_hash).await {
+        let (commits, csv) = match read_and_verify_commits(this, commit_hash).await {
             Ok(x) => x,
             Err(CommitError::Commit(error, commit)) => {
                 return Ok(Err(format!("failed to parse commit {commit}: {error}")));
             }
-            Err(e) => return Err(e.into()),
-        };
-        for (commit, commit_hash) in &commits {
-            if let Err(e) = csv.apply_commit(commit) {
+            Err(CommitError::Verification(e, commit_hash)) => {
                 return Ok(Err(format!(
                     "commit sequence verification failed: {e} at {commit_hash}",
                 )));
             }
+            Err(e) => return Err(e.into()),
+        };
+        if let Err(e) = policy::check_content_policy(this, &commits).await? {
+            return Ok(Err(e));
         }
 
         let (last_commit, last_commit_hash) = commits.last().expect(
@@ -104,19 +254,20 @@ pub async fn receive<T: RawRepository>(
         }
 
         // Read the commits in the branch and verify them
-        let commits = match read_commits(this, last_finalized_commit_hash, tip_commit_hash).await {
+        let (commits, csv) = match read_and_verify_commits(this, tip_commit_hash).await {
             Ok(x) => x,
             Err(CommitError::Commit(error, commit)) => {
                 return Ok(Err(format!("failed to parse commit {commit}: {error}",)));
             }
-            Err(e) => return Err(e.into()),
-        };
-        for (commit, commit_hash) in &commits {
-            if let Err(e) = csv.apply_commit(commit) {
+            Err(CommitError::Verification(e, commit_hash)) => {
                 return Ok(Err(format!(
                     "commit sequence verification failed: {e} at {commit_hash}",
                 )));
             }
+            Err(e) => return Err(e.into()),
+        };
+        if let Err(e) = policy::check_content_policy(this, &commits).await? {
+            return Ok(Err(e));
         }
 
         // If the commit sequence contains block commit(s) that can be finalized
@@ -138,30 +289,14 @@ pub async fn receive<T: RawRepository>(
         }
 
         // Create the associate branch
-        let branch_name = match &commits
+        let last_commit = &commits
             .last()
             .expect("already checked that the received commit is not a finalization proof commit")
-            .0
-        {
-            Commit::Agenda(agenda) => {
-                format!(
-                    "a-{}",
-                    &agenda.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
-                )
-            }
-            Commit::AgendaProof(agenda_proof) => {
-                format!(
-                    "a-{}",
-                    &agenda_proof.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
-                )
-            }
-            Commit::Block(block) => {
-                format!(
-                    "b-{}",
-                    &block.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
-                )
-            }
-            x => return Ok(Err(format!("commit sequence ends with: {x:?}"))),
+            .0;
+        let branch_name = if let Some(x) = branch_name_for_commit(last_commit) {
+            x
+        } else {
+            return Ok(Err(format!("commit sequence ends with: {last_commit:?}")));
         };
         if this.raw.locate_branch(branch_name.clone()).await.is_ok() {
             return Ok(Err(format!("branch already exists: {branch_name}",)));
@@ -0,0 +1,389 @@
+use super::*;
+use raw::FileChange;
+
+/// A change of the reserved state made by a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReservedStateChange {
+    MemberAdded {
+        name: MemberName,
+        governance_voting_power: VotingPower,
+        consensus_voting_power: VotingPower,
+    },
+    MemberRemoved {
+        name: MemberName,
+    },
+    PublicKeyChanged {
+        name: MemberName,
+    },
+    GovernanceVotingPowerChanged {
+        name: MemberName,
+        before: VotingPower,
+        after: VotingPower,
+    },
+    ConsensusVotingPowerChanged {
+        name: MemberName,
+        before: VotingPower,
+        after: VotingPower,
+    },
+    GovernanceDelegateeChanged {
+        name: MemberName,
+        before: Option<MemberName>,
+        after: Option<MemberName>,
+    },
+    ConsensusDelegateeChanged {
+        name: MemberName,
+        before: Option<MemberName>,
+        after: Option<MemberName>,
+    },
+    LeaderOrderChanged {
+        before: Vec<MemberName>,
+        after: Vec<MemberName>,
+    },
+    VersionChanged {
+        before: String,
+        after: String,
+    },
+    /// The genesis info, which must never be changed, is changed.
+    GenesisInfoChanged,
+}
+
+fn delegatee_to_string(delegatee: &Option<MemberName>) -> String {
+    match delegatee {
+        Some(name) => format!("`{name}`"),
+        None => "nobody".to_owned(),
+    }
+}
+
+impl fmt::Display for ReservedStateChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemberAdded {
+                name,
+                governance_voting_power,
+                consensus_voting_power,
+            } => write!(
+                f,
+                "member `{name}` added (governance voting power {governance_voting_power}, consensus voting power {consensus_voting_power})"
+            ),
+            Self::MemberRemoved { name } => write!(f, "member `{name}` removed"),
+            Self::PublicKeyChanged { name } => {
+                write!(f, "public key of `{name}` changed")
+            }
+            Self::GovernanceVotingPowerChanged {
+                name,
+                before,
+                after,
+            } => write!(
+                f,
+                "governance voting power of `{name}` changed: {before} -> {after}"
+            ),
+            Self::ConsensusVotingPowerChanged {
+                name,
+                before,
+                after,
+            } => write!(
+                f,
+                "consensus voting power of `{name}` changed: {before} -> {after}"
+            ),
+            Self::GovernanceDelegateeChanged {
+                name,
+                before,
+                after,
+            } => write!(
+                f,
+                "governance delegatee of `{name}` changed: {} -> {}",
+                delegatee_to_string(before),
+                delegatee_to_string(after)
+            ),
+            Self::ConsensusDelegateeChanged {
+                name,
+                before,
+                after,
+            } => write!(
+                f,
+                "consensus delegatee of `{name}` changed: {} -> {}",
+                delegatee_to_string(before),
+                delegatee_to_string(after)
+            ),
+            Self::LeaderOrderChanged { before, after } => write!(
+                f,
+                "consensus leader order changed: [{}] -> [{}]",
+                before.join(", "),
+                after.join(", ")
+            ),
+            Self::VersionChanged { before, after } => {
+                write!(f, "version changed: {before} -> {after}")
+            }
+            Self::GenesisInfoChanged => write!(f, "genesis info changed"),
+        }
+    }
+}
+
+/// Lists the changes of the reserved state from `before` to `after`.
+pub fn diff_reserved_state(
+    before: &ReservedState,
+    after: &ReservedState,
+) -> Vec<ReservedStateChange> {
+    let mut changes = Vec::new();
+    if before.genesis_info != after.genesis_info {
+        changes.push(ReservedStateChange::GenesisInfoChanged);
+    }
+    for member in &before.members {
+        if !after.members.iter().any(|m| m.name == member.name) {
+            changes.push(ReservedStateChange::MemberRemoved {
+                name: member.name.clone(),
+            });
+        }
+    }
+    for member in &after.members {
+        let previous = if let Some(previous) = before.members.iter().find(|m| m.name == member.name)
+        {
+            previous
+        } else {
+            changes.push(ReservedStateChange::MemberAdded {
+                name: member.name.clone(),
+                governance_voting_power: member.governance_voting_power,
+                consensus_voting_power: member.consensus_voting_power,
+            });
+            continue;
+        };
+        let name = member.name.clone();
+        if previous.public_key != member.public_key {
+            changes.push(ReservedStateChange::PublicKeyChanged { name: name.clone() });
+        }
+        if previous.governance_voting_power != member.governance_voting_power {
+            changes.push(ReservedStateChange::GovernanceVotingPowerChanged {
+                name: name.clone(),
+                before: previous.governance_voting_power,
+                after: member.governance_voting_power,
+            });
+        }
+        if previous.consensus_voting_power != member.consensus_voting_power {
+            changes.push(ReservedStateChange::ConsensusVotingPowerChanged {
+                name: name.clone(),
+                before: previous.consensus_voting_power,
+                after: member.consensus_voting_power,
+            });
+        }
+        if previous.governance_delegatee != member.governance_delegatee {
+            changes.push(ReservedStateChange::GovernanceDelegateeChanged {
+                name: name.clone(),
+                before: previous.governance_delegatee.clone(),
+                after: member.governance_delegatee.clone(),
+            });
+        }
+        if previous.consensus_delegatee != member.consensus_delegatee {
+            changes.push(ReservedStateChange::ConsensusDelegateeChanged {
+                name,
+                before: previous.consensus_delegatee.clone(),
+                after: member.consensus_delegatee.clone(),
+            });
+        }
+    }
+    if before.consensus_leader_order != after.consensus_leader_order {
+        changes.push(ReservedStateChange::LeaderOrderChanged {
+            before: before.consensus_leader_order.clone(),
+            after: after.consensus_leader_order.clone(),
+        });
+    }
+    if before.version != after.version {
+        changes.push(ReservedStateChange::VersionChanged {
+            before: before.version.clone(),
+            after: after.version.clone(),
+        });
+    }
+    changes
+}
+
+/// A transaction included in an agenda, prepared for the review.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionReview {
+    pub commit_hash: CommitHash,
+    pub author: PublicKey,
+    /// The name of the author, if the author is a member of the `finalized` reserved state.
+    pub author_name: Option<MemberName>,
+    pub timestamp: Timestamp,
+    pub head: String,
+    pub body: String,
+    /// The files changed by the transaction, compared to its parent.
+    pub files: Vec<FileChange>,
+    /// The changes of the reserved state made by the transaction.
+    pub reserved_state_changes: Vec<ReservedStateChange>,
+}
+
+/// The review of an agenda: every transaction that the agenda would finalize,
+/// on top of the `finalized` branch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgendaReview {
+    pub agenda_commit: CommitHash,
+    pub agenda: Agenda,
+    /// The name of the agenda author, if the author is a member of the `finalized` reserved state.
+    pub author_name: Option<MemberName>,
+    pub transactions: Vec<TransactionReview>,
+}
+
+fn author_to_string(author: &PublicKey, author_name: &Option<MemberName>) -> String {
+    match author_name {
+        Some(name) => name.clone(),
+        None => author.to_string(),
+    }
+}
+
+impl fmt::Display for AgendaReview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Agenda {} for height {} by {} ({} transactions)",
+            self.agenda_commit,
+            self.agenda.height,
+            author_to_string(&self.agenda.author, &self.author_name),
+            self.transactions.len()
+        )?;
+        for transaction in &self.transactions {
+            writeln!(f)?;
+            writeln!(f, "* {} {}", transaction.commit_hash, transaction.head)?;
+            writeln!(
+                f,
+                "  by {} at {}",
+                author_to_string(&transaction.author, &transaction.author_name),
+                transaction.timestamp
+            )?;
+            for file in &transaction.files {
+                match file.size {
+                    Some(size) => writeln!(f, "  M {} ({} bytes)", file.path, size)?,
+                    None => writeln!(f, "  D {}", file.path)?,
+                }
+            }
+            for change in &transaction.reserved_state_changes {
+                writeln!(f, "  - {change}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reviews the agenda at the tip of the given branch.
+pub(crate) async fn review_agenda<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    branch: &str,
+) -> Result<AgendaReview, Error> {
+    if !branch.starts_with("a-") {
+        return Err(eyre!("`{}` is not an agenda branch", branch));
+    }
+    let tip = this.raw.locate_branch(branch.into()).await?;
+    let (commits, _) = read_and_verify_commits(this, tip)
+        .await
+        .map_err(|e| eyre!("branch {} is invalid: {}", branch, e))?;
+    let (agenda_index, agenda) = commits
+        .iter()
+        .enumerate()
+        .find_map(|(i, (commit, _))| match commit {
+            Commit::Agenda(agenda) => Some((i, agenda.clone())),
+            _ => None,
+        })
+        .ok_or_else(|| eyre!("branch {} has no agenda", branch))?;
+    let agenda_commit = commits[agenda_index].1;
+
+    let finalized = this.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    let finalized_reserved_state = this.raw.read_reserved_state_at_commit(finalized).await?;
+    let mut previous_commit = finalized;
+    let mut transactions = Vec::new();
+    for (commit, commit_hash) in &commits[..agenda_index] {
+        let transaction = if let Commit::Transaction(transaction) = commit {
+            transaction
+        } else {
+            previous_commit = *commit_hash;
+            continue;
+        };
+        let files = this.raw.list_changed_files(*commit_hash).await?;
+        let reserved_state_changes = if files.iter().any(|f| f.path.starts_with("reserved/")) {
+            diff_reserved_state(
+                &this
+                    .raw
+                    .read_reserved_state_at_commit(previous_commit)
+                    .await?,
+                &this.raw.read_reserved_state_at_commit(*commit_hash).await?,
+            )
+        } else {
+            Vec::new()
+        };
+        transactions.push(TransactionReview {
+            commit_hash: *commit_hash,
+            author: transaction.author.clone(),
+            author_name: finalized_reserved_state.query_name(&transaction.author),
+            timestamp: transaction.timestamp,
+            head: transaction.head.clone(),
+            body: transaction.body.clone(),
+            files,
+            reserved_state_changes,
+        });
+        previous_commit = *commit_hash;
+    }
+    Ok(AgendaReview {
+        agenda_commit,
+        author_name: finalized_reserved_state.query_name(&agenda.author),
+        agenda,
+        transactions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_state_changes() {
+        let (before, _) = test_utils::generate_standard_genesis(4);
+        let mut after = before.clone();
+        after.members.remove(3);
+        after.members[0].governance_voting_power += 1;
+        after.members[1].consensus_delegatee = Some(after.members[2].name.clone());
+        let mut new_member = after.members[2].clone();
+        new_member.name = "new".to_owned();
+        new_member.public_key = PublicKey::zero();
+        after.members.push(new_member);
+        after.consensus_leader_order = after.members.iter().map(|m| m.name.clone()).collect();
+        after.version = "99.0.0".to_owned();
+
+        assert_eq!(diff_reserved_state(&before, &before), Vec::new());
+        let changes = diff_reserved_state(&before, &after);
+        assert_eq!(
+            changes,
+            vec![
+                ReservedStateChange::MemberRemoved {
+                    name: before.members[3].name.clone()
+                },
+                ReservedStateChange::GovernanceVotingPowerChanged {
+                    name: before.members[0].name.clone(),
+                    before: before.members[0].governance_voting_power,
+                    after: before.members[0].governance_voting_power + 1,
+                },
+                ReservedStateChange::ConsensusDelegateeChanged {
+                    name: before.members[1].name.clone(),
+                    before: None,
+                    after: Some(before.members[2].name.clone()),
+                },
+                ReservedStateChange::MemberAdded {
+                    name: "new".to_owned(),
+                    governance_voting_power: before.members[2].governance_voting_power,
+                    consensus_voting_power: before.members[2].consensus_voting_power,
+                },
+                ReservedStateChange::LeaderOrderChanged {
+                    before: before.consensus_leader_order.clone(),
+                    after: after.consensus_leader_order.clone(),
+                },
+                ReservedStateChange::VersionChanged {
+                    before: before.version.clone(),
+                    after: "99.0.0".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            changes[2].to_string(),
+            format!(
+                "consensus delegatee of `{}` changed: nobody -> `{}`",
+                before.members[1].name, before.members[2].name
+            )
+        );
+    }
+}
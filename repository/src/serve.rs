@@ -0,0 +1,86 @@
+use super::*;
+use utils::*;
+
+pub async fn serve_fetch<T: RawRepository>(
+    this: Arc<RwLock<DistributedRepository<T>>>,
+    interval: Option<std::time::Duration>,
+) -> Result<(), Error> {
+    let interval = if let Some(x) = interval {
+        x
+    } else {
+        return Ok(());
+    };
+    loop {
+        if let Err(e) = this.write().await.fetch().await {
+            warn!("failed to fetch from the peers: {}", e);
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Periodically receives the branches pushed by the peers and cleans the outdated ones.
+///
+/// `known_branches` is the snapshot of the local branches taken before the server started,
+/// so that every branch pushed afterwards is verified.
+pub async fn serve_update<T: RawRepository>(
+    this: Arc<RwLock<DistributedRepository<T>>>,
+    interval: Option<std::time::Duration>,
+    mut known_branches: HashSet<(Branch, CommitHash)>,
+) -> Result<(), Error> {
+    let interval = if let Some(x) = interval {
+        x
+    } else {
+        return Ok(());
+    };
+    loop {
+        let mut this = this.write().await;
+        if let Err(e) = receive_pushed_branches(&mut this, &known_branches).await {
+            warn!("failed to receive the pushed branches: {}", e);
+        }
+        if let Err(e) = this.clean(false).await {
+            warn!("failed to clean the repository: {}", e);
+        }
+        known_branches = retrieve_local_branches(&this.raw).await?;
+        drop(this);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Receives the `fp`, `a-#` and `b-#` branches that are not in `known_branches`.
+///
+/// As a push bypasses the verification, a pushed `a-#` or `b-#` branch is deleted first
+/// and a pushed `fp` branch is moved back to the known commit.
+/// Then the pushed commit is received as if it were fetched; a valid finalization proof
+/// advances the `finalized` branch, and anything else is rejected.
+async fn receive_pushed_branches<T: RawRepository>(
+    this: &mut DistributedRepository<T>,
+    known_branches: &HashSet<(Branch, CommitHash)>,
+) -> Result<(), Error> {
+    let known_fp = known_branches
+        .iter()
+        .find(|(branch, _)| branch == FP_BRANCH_NAME)
+        .map(|(_, commit_hash)| *commit_hash);
+    for (branch, commit_hash) in retrieve_local_branches(&this.raw).await? {
+        if known_branches.contains(&(branch.clone(), commit_hash)) {
+            continue;
+        }
+        if branch == FP_BRANCH_NAME {
+            let known_fp = if let Some(x) = known_fp {
+                x
+            } else {
+                continue;
+            };
+            this.raw.checkout_clean().await?;
+            this.raw.move_branch(branch.clone(), known_fp).await?;
+            this.raw.checkout(branch.clone()).await?;
+        } else if branch.starts_with("a-") || branch.starts_with("b-") {
+            this.raw.delete_branch(branch.clone()).await?;
+        } else {
+            continue;
+        }
+        if let Err(e) = receive::receive(this, commit_hash, None).await? {
+            warn!("rejected the pushed branch {}: {}", branch, e);
+        }
+    }
+    Ok(())
+}
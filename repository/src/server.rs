@@ -61,6 +61,8 @@ pub async fn run_server_legacy(path: &str, port: u16) -> GitServer {
 /// - `path` is the path to the root directory of a Simperby blockchain (not the repository path)
 /// - `port` is the port to run the server on
 /// - `simperby_executable_path` is the path to the Simperby executable, which will be executed by the hook.
+///
+/// The hook runs `<simperby_executable_path> check-push <commit> <branch> <timestamp> <signature>`.
 pub async fn run_server(path: &str, port: u16, simperby_executable_path: &str) -> GitServer {
     // Make a pre-receive hook file and give it an execution permission.
     let path_hook = format!("{path}/repository/repo/.git/hooks/pre-receive");
@@ -71,6 +73,18 @@ pub async fn run_server(path: &str, port: u16, simperby_executable_path: &str) -
     }
     fs::write(&path_hook, hook_content).await.unwrap();
     raw::run_command(format!("chmod +x {path_hook}")).unwrap();
+    // Accept the push options, and the pushes to the checked-out branch (e.g., `fp`).
+    for (key, value) in [
+        ("receive.advertisePushOptions", "true"),
+        ("receive.denyCurrentBranch", "updateInstead"),
+    ] {
+        let status = std::process::Command::new("git")
+            .current_dir(format!("{path}/repository/repo"))
+            .args(["config", key, value])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
 
     let td = tempfile::TempDir::new().unwrap();
     let pid_path = format!("{}/pid", td.path().to_slash().unwrap().into_owned());
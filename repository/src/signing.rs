@@ -0,0 +1,148 @@
+use super::*;
+use raw::TagAnnotation;
+
+/// The data that a member signs to approve a commit with a `vote-#` or `veto-#` tag.
+///
+/// The signature is stored in the message of the annotated tag as `<tag>\n\n<signature>`,
+/// where the signature is a serialized `TypedSignature<TagVerifier>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagVerifier {
+    pub tag: Tag,
+    /// The hash of the tagged commit (i.e., `Commit::to_hash256()`).
+    pub commit: Hash256,
+}
+
+impl ToHash256 for TagVerifier {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// The verified signatures of a commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitSignatures {
+    /// The signer of the commit itself, if signed.
+    ///
+    /// It is the author for a block or agenda commit,
+    /// and the node that recorded the proof for an `fp` commit.
+    pub author: Option<PublicKey>,
+    /// The signers of the annotated `vote-#` and `veto-#` tags on the commit.
+    pub tags: Vec<(Tag, PublicKey)>,
+}
+
+/// Returns the member who is expected to sign the commit, if any.
+///
+/// Only the block and agenda commits are signed, since the other commits are not read back
+/// exactly as they were created (e.g., the timestamp of a transaction is truncated).
+fn commit_author(commit: &Commit) -> Option<&PublicKey> {
+    match commit {
+        Commit::Block(header) => Some(&header.author),
+        Commit::Agenda(agenda) => Some(&agenda.author),
+        _ => None,
+    }
+}
+
+/// Signs the commit, returning the signature to store in the commit header.
+pub(crate) fn sign_commit(commit: &Commit, private_key: &PrivateKey) -> Result<String, Error> {
+    let signature = TypedSignature::sign(commit, private_key)?;
+    Ok(serde_spb::to_string(&signature)?)
+}
+
+/// Signs the finalization proof of an `fp` commit, returning the signature to store in the commit header.
+pub(crate) fn sign_fp(
+    finalization_proof: &LastFinalizationProof,
+    private_key: &PrivateKey,
+) -> Result<String, Error> {
+    let signature = TypedSignature::sign(finalization_proof, private_key)?;
+    Ok(serde_spb::to_string(&signature)?)
+}
+
+/// Creates the annotation of a `vote-#` or `veto-#` tag, signing the tagged commit.
+pub(crate) fn sign_tag(
+    tag: &Tag,
+    commit: &Commit,
+    private_key: &PrivateKey,
+    timestamp: Timestamp,
+) -> Result<TagAnnotation, Error> {
+    let signature = TypedSignature::sign(
+        &TagVerifier {
+            tag: tag.clone(),
+            commit: commit.to_hash256(),
+        },
+        private_key,
+    )?;
+    Ok(TagAnnotation {
+        tagger: private_key.public_key(),
+        timestamp,
+        message: format!("{}\n\n{}", tag, serde_spb::to_string(&signature)?),
+    })
+}
+
+/// Verifies the signature of the commit and the signatures of the tags on it.
+///
+/// The unsigned commit and the lightweight tags are not errors; they are just not listed.
+pub(crate) async fn verify_commit<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    commit_hash: CommitHash,
+) -> Result<CommitSignatures, Error> {
+    let mut result = CommitSignatures::default();
+    let semantic_commit = this.raw.read_semantic_commit(commit_hash).await?;
+    if let Ok(finalization_proof) = format::fp_from_semantic_commit(semantic_commit) {
+        // An `fp` commit has no author and no tags.
+        if let Some(signature) = this.raw.read_commit_signature(commit_hash).await? {
+            let signature: TypedSignature<LastFinalizationProof> = serde_spb::from_str(&signature)
+                .map_err(|e| eyre!("invalid signature format of commit {}: {}", commit_hash, e))?;
+            signature
+                .verify(&finalization_proof)
+                .map_err(|e| eyre!("invalid signature of commit {}: {}", commit_hash, e))?;
+            result.author = Some(signature.signer().clone());
+        }
+        return Ok(result);
+    }
+
+    let commit = this.read_commit(commit_hash).await?;
+    if let Some(signature) = this.raw.read_commit_signature(commit_hash).await? {
+        let signature: TypedSignature<Commit> = serde_spb::from_str(&signature)
+            .map_err(|e| eyre!("invalid signature format of commit {}: {}", commit_hash, e))?;
+        signature
+            .verify(&commit)
+            .map_err(|e| eyre!("invalid signature of commit {}: {}", commit_hash, e))?;
+        if let Some(author) = commit_author(&commit) {
+            if author != signature.signer() {
+                return Err(eyre!(
+                    "commit {} is signed by {}, not by its author {}",
+                    commit_hash,
+                    signature.signer(),
+                    author
+                ));
+            }
+        }
+        result.author = Some(signature.signer().clone());
+    }
+
+    for tag in this.raw.get_tag(commit_hash).await? {
+        if !(tag.starts_with("vote-") || tag.starts_with("veto-")) {
+            continue;
+        }
+        let annotation = if let Some(annotation) = this.raw.read_tag_annotation(tag.clone()).await?
+        {
+            annotation
+        } else {
+            continue;
+        };
+        let signature: TypedSignature<TagVerifier> = annotation
+            .message
+            .trim_end()
+            .rsplit_once("\n\n")
+            .and_then(|(_, signature)| serde_spb::from_str(signature).ok())
+            .ok_or_else(|| eyre!("invalid signature format of tag {}", tag))?;
+        signature
+            .verify(&TagVerifier {
+                tag: tag.clone(),
+                commit: commit.to_hash256(),
+            })
+            .map_err(|e| eyre!("invalid signature of tag {}: {}", tag, e))?;
+        result.tags.push((tag, signature.signer().clone()));
+    }
+    Ok(result)
+}
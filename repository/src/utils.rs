@@ -45,11 +45,14 @@ pub enum CommitError {
     Raw(#[from] raw::Error),
     #[error("failed to parse commit ({1}): {0}")]
     Commit(eyre::Error, CommitHash),
+    #[error("commit sequence verification failed ({1}): {0}")]
+    Verification(simperby_common::verify::Error, CommitHash),
+    #[error("{0}")]
+    Other(eyre::Error),
 }
 
 /// Reads the git commits to `Commit`s, from the very next commit of ancestor to descendant.
 /// `ancestor` not included, `descendant` included.
-/// It fails if the two commits are the same.
 /// It fails if the ancestor is not the merge base of the two commits.
 pub async fn read_commits<T: RawRepository>(
     this: &DistributedRepository<T>,
@@ -57,6 +60,14 @@ pub async fn read_commits<T: RawRepository>(
     descendant: CommitHash,
 ) -> Result<Vec<(Commit, CommitHash)>, CommitError> {
     let commits = this.raw.query_commit_path(ancestor, descendant).await?;
+    parse_commits(this, commits).await
+}
+
+/// Reads the given git commits to `Commit`s.
+async fn parse_commits<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    commits: Vec<CommitHash>,
+) -> Result<Vec<(Commit, CommitHash)>, CommitError> {
     let commits = stream::iter(commits.iter().cloned().map(|c| {
         let raw = &this.raw;
         async move { raw.read_semantic_commit(c).await.map(|x| (x, c)) }
@@ -76,3 +87,116 @@ pub async fn read_commits<T: RawRepository>(
         .map_err(|(e, c)| CommitError::Commit(e, c))?;
     Ok(commits)
 }
+
+/// Reads and verifies the commits from the very next commit of `finalized` to `descendant`,
+/// returning them with the verifier that has applied all of them.
+///
+/// The commits that have been already verified on top of the current `finalized`
+/// are taken from the verification cache, neither read nor verified again.
+/// It fails if `finalized` is not the merge base of the two commits.
+pub async fn read_and_verify_commits<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    descendant: CommitHash,
+) -> Result<(Vec<(Commit, CommitHash)>, CommitSequenceVerifier), CommitError> {
+    let finalized = this.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    let path = this.raw.query_commit_path(finalized, descendant).await?;
+
+    let mut cache = this.verification_cache.lock().await;
+    cache.set_base(finalized);
+    let (mut commits, mut verifier) = if let Some(x) = cache.get(&path) {
+        x
+    } else {
+        let last_finalized_block_header = this
+            .get_last_finalized_block_header()
+            .await
+            .map_err(CommitError::Other)?;
+        let reserved_state = this
+            .get_reserved_state()
+            .await
+            .map_err(CommitError::Other)?;
+        let verifier = CommitSequenceVerifier::new(last_finalized_block_header, reserved_state)
+            .map_err(|e| {
+                CommitError::Other(eyre!(IntegrityError::new(format!(
+                    "finalized branch is not accepted by CSV: {e}"
+                ))))
+            })?;
+        (Vec::new(), verifier)
+    };
+    if commits.len() == path.len() {
+        return Ok((commits, verifier));
+    }
+
+    let new_commits = parse_commits(this, path[commits.len()..].to_vec()).await?;
+    for (commit, hash) in new_commits {
+        verifier
+            .apply_commit(&commit)
+            .map_err(|e| CommitError::Verification(e, hash))?;
+        // A block commit has the same tree as its parent, which the block commits to.
+        if let Commit::Block(_) = commit {
+            let files = this.raw.list_files(hash).await?;
+            verifier
+                .verify_repository_merkle_root(&files)
+                .map_err(|e| CommitError::Verification(e, hash))?;
+        }
+        commits.push((commit, hash));
+    }
+    cache.insert(path, verifier.clone());
+    if let Err(e) = cache.save().await {
+        warn!("failed to save the verification cache: {}", e);
+    }
+    Ok((commits, verifier))
+}
+
+/// Returns the name of the branch that the commit sequence ending with the given commit
+/// must be stored in, or `None` if such a sequence can't be a branch.
+pub fn branch_name_for_commit(commit: &Commit) -> Option<Branch> {
+    match commit {
+        Commit::Agenda(agenda) => Some(format!(
+            "a-{}",
+            &agenda.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
+        )),
+        Commit::AgendaProof(agenda_proof) => Some(format!(
+            "a-{}",
+            &agenda_proof.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
+        )),
+        Commit::Block(block) => Some(format!(
+            "b-{}",
+            &block.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
+        )),
+        _ => None,
+    }
+}
+
+/// Verifies the `a-#` or `b-#` branch again, which must be branched from the `finalized` branch.
+///
+/// - Returns `Ok(Err(_))` if the branch is invalid, with the reason.
+/// - Returns `Err(_)` if an error occurs.
+pub async fn verify_branch<T: RawRepository>(
+    this: &DistributedRepository<T>,
+    branch: &str,
+    commit_hash: CommitHash,
+) -> Result<Result<(), String>, Error> {
+    if commit_hash == this.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await? {
+        return Ok(Err("already finalized".to_owned()));
+    }
+    let (commits, _) = match read_and_verify_commits(this, commit_hash).await {
+        Ok(x) => x,
+        Err(CommitError::Commit(error, commit)) => {
+            return Ok(Err(format!("failed to parse commit {commit}: {error}")));
+        }
+        Err(CommitError::Verification(e, commit_hash)) => {
+            return Ok(Err(format!(
+                "commit sequence verification failed: {e} at {commit_hash}",
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let (last_commit, _) = commits
+        .last()
+        .expect("already checked that the commit is not the last finalized block");
+    match branch_name_for_commit(last_commit) {
+        Some(x) if x == branch => Ok(Ok(())),
+        Some(x) => Ok(Err(format!("branch name must be {x}"))),
+        None => Ok(Err(format!("commit sequence ends with: {last_commit:?}"))),
+    }
+}
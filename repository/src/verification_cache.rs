@@ -0,0 +1,327 @@
+use super::*;
+use std::collections::HashMap;
+
+/// A verified commit sequence from the next commit of the base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Tip {
+    /// The commits from the next commit of the base to the tip.
+    path: Vec<CommitHash>,
+    /// The state of the verifier right after applying the tip,
+    /// whose total commits are the base block followed by the commits of `path`.
+    verifier: CommitSequenceVerifier,
+}
+
+/// The cache file, signed by the node so that a tampered file is not trusted.
+#[derive(Debug, Serialize, Deserialize)]
+struct SignedCache {
+    content: String,
+    signature: Signature,
+}
+
+/// A persistent cache of the commit sequence verification results.
+///
+/// Every tip is verified on top of `base`, which is the `finalized` commit at the time of
+/// the verification. Only the verifier state at the tip of each verified sequence is kept,
+/// since it holds all the commits of the sequence. Once `finalized` moves, the tips that
+/// extend the new `finalized` are kept with the commits after it, and the others are discarded.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct VerificationCache {
+    base: Option<CommitHash>,
+    tips: HashMap<CommitHash, Tip>,
+    /// The file that this cache is persisted to, and the key to sign it with.
+    /// If `None`, it lives only in memory.
+    #[serde(skip)]
+    file: Option<(String, PrivateKey)>,
+    /// Whether there are changes that are not persisted yet.
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl VerificationCache {
+    /// Loads the cache from the given file, which must have been signed by the given key.
+    ///
+    /// If the file doesn't exist, is corrupted or is not signed by the key, it starts with an empty cache.
+    /// If there is no key, the cache lives only in memory.
+    pub(crate) async fn load(path: Option<String>, private_key: Option<PrivateKey>) -> Self {
+        let file = if let (Some(path), Some(private_key)) = (path, private_key) {
+            Some((path, private_key))
+        } else {
+            return Self::default();
+        };
+        let (path, private_key) = file.as_ref().expect("checked above");
+        let mut cache = match tokio::fs::read_to_string(path).await {
+            Ok(content) => Self::decode(&content, &private_key.public_key()).unwrap_or_else(|e| {
+                warn!("discarding invalid verification cache ({}): {}", path, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        cache.file = file;
+        cache
+    }
+
+    fn decode(file: &str, public_key: &PublicKey) -> Result<Self, Error> {
+        let signed: SignedCache = serde_spb::from_str(file)?;
+        signed
+            .signature
+            .verify(Hash256::hash(&signed.content), public_key)?;
+        Ok(serde_spb::from_str(&signed.content)?)
+    }
+
+    /// Persists the cache to the file, if configured and changed.
+    ///
+    /// The file is replaced atomically, so that it is never left half-written.
+    pub(crate) async fn save(&mut self) -> Result<(), Error> {
+        if let (Some((path, private_key)), true) = (&self.file, self.dirty) {
+            let content = serde_spb::to_string(self)?;
+            let signature = Signature::sign(Hash256::hash(&content), private_key)?;
+            let temp_path = format!("{path}.tmp");
+            tokio::fs::write(
+                &temp_path,
+                serde_spb::to_string(&SignedCache { content, signature })?,
+            )
+            .await?;
+            tokio::fs::rename(&temp_path, path).await?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Moves the base of the cache to the given `finalized` commit.
+    ///
+    /// The tips that extend the block commit `finalized` are kept with the commits after it.
+    pub(crate) fn set_base(&mut self, finalized: CommitHash) {
+        if self.base == Some(finalized) {
+            return;
+        }
+        let tips = std::mem::take(&mut self.tips);
+        for (tip_hash, mut tip) in tips {
+            if let Some(index) = tip.path.iter().position(|c| *c == finalized) {
+                // The base block is the first of the total commits.
+                if index + 1 < tip.path.len() && tip.verifier.forget_before(index + 1).is_ok() {
+                    tip.path.drain(..=index);
+                    self.tips.insert(tip_hash, tip);
+                }
+            }
+        }
+        self.base = Some(finalized);
+        self.dirty = true;
+    }
+
+    /// Returns the commits and the verifier state of the longest verified prefix
+    /// of the given commit sequence from the next commit of the base.
+    pub(crate) fn get(
+        &self,
+        path: &[CommitHash],
+    ) -> Option<(Vec<(Commit, CommitHash)>, CommitSequenceVerifier)> {
+        let tip = path.iter().enumerate().rev().find_map(|(i, c)| {
+            self.tips
+                .get(c)
+                .filter(|tip| tip.path.as_slice() == &path[..=i])
+        })?;
+        let commits = tip.verifier.get_total_commits()[1..]
+            .iter()
+            .cloned()
+            .zip(tip.path.iter().cloned())
+            .collect();
+        Some((commits, tip.verifier.clone()))
+    }
+
+    /// Records that the given commit sequence from the next commit of the base has been verified,
+    /// resulting in the given verifier state.
+    pub(crate) fn insert(&mut self, path: Vec<CommitHash>, verifier: CommitSequenceVerifier) {
+        let tip_hash = if let Some(x) = path.last() {
+            *x
+        } else {
+            return;
+        };
+        // The tips that this sequence extends are not needed anymore.
+        for (i, c) in path.iter().enumerate() {
+            if self
+                .tips
+                .get(c)
+                .map(|tip| tip.path.as_slice() == &path[..=i])
+                .unwrap_or(false)
+            {
+                self.tips.remove(c);
+            }
+        }
+        self.tips.insert(tip_hash, Tip { path, verifier });
+        self.dirty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_common::test_utils::generate_standard_genesis;
+    use tempfile::TempDir;
+
+    fn commit_hash(x: u8) -> CommitHash {
+        CommitHash { hash: [x; 20] }
+    }
+
+    /// Returns the commits of the first block on top of the genesis followed by the next agenda,
+    /// with the verifier of the genesis.
+    fn first_block() -> (Vec<Commit>, CommitSequenceVerifier, PrivateKey) {
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let genesis_header = reserved_state.genesis_info.header.clone();
+        let verifier = CommitSequenceVerifier::new(genesis_header.clone(), reserved_state).unwrap();
+        let agenda = Agenda {
+            height: 1,
+            author: keys[0].0.clone(),
+            timestamp: 1,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+        };
+        let agenda_proof = AgendaProof {
+            height: 1,
+            agenda_hash: agenda.to_hash256(),
+            proof: keys
+                .iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+        };
+        let commits = vec![Commit::Agenda(agenda), Commit::AgendaProof(agenda_proof)];
+        let block = BlockHeader {
+            author: keys[0].0.clone(),
+            prev_block_finalization_proof: keys
+                .iter()
+                .map(|(_, private_key)| TypedSignature::sign(&genesis_header, private_key).unwrap())
+                .collect(),
+            previous_hash: genesis_header.to_hash256(),
+            height: 1,
+            timestamp: 2,
+            commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&commits),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: genesis_header.validator_set.clone(),
+            version: genesis_header.version,
+        };
+        let next_agenda = Agenda {
+            height: 2,
+            author: keys[0].0.clone(),
+            timestamp: 3,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+        };
+        let commits = [
+            commits,
+            vec![Commit::Block(block), Commit::Agenda(next_agenda)],
+        ]
+        .concat();
+        (commits, verifier, keys[0].1.clone())
+    }
+
+    #[test]
+    fn hit_on_verified_prefix() {
+        let (commits, mut verifier, _) = first_block();
+        let mut cache = VerificationCache::default();
+        cache.set_base(commit_hash(0));
+        let path = vec![commit_hash(1), commit_hash(2)];
+        for commit in &commits[0..2] {
+            verifier.apply_commit(commit).unwrap();
+        }
+        cache.insert(path.clone(), verifier.clone());
+
+        // A sequence extending the verified one skips the verified commits.
+        let (cached_commits, cached_verifier) = cache
+            .get(&[commit_hash(1), commit_hash(2), commit_hash(3)])
+            .unwrap();
+        assert_eq!(
+            cached_commits,
+            vec![
+                (commits[0].clone(), commit_hash(1)),
+                (commits[1].clone(), commit_hash(2))
+            ]
+        );
+        assert_eq!(
+            cached_verifier.get_total_commits(),
+            verifier.get_total_commits()
+        );
+        // The verifier state of a commit in the middle is not kept.
+        assert!(cache.get(&[commit_hash(1), commit_hash(3)]).is_none());
+        assert!(cache.get(&[commit_hash(2)]).is_none());
+
+        // Extending the tip replaces it.
+        verifier.apply_commit(&commits[2]).unwrap();
+        let path = vec![commit_hash(1), commit_hash(2), commit_hash(3)];
+        cache.insert(path.clone(), verifier);
+        assert_eq!(cache.tips.len(), 1);
+        assert_eq!(cache.get(&path).unwrap().0.len(), 3);
+    }
+
+    #[test]
+    fn move_base() {
+        let (commits, mut verifier, _) = first_block();
+        for commit in &commits {
+            verifier.apply_commit(commit).unwrap();
+        }
+        let mut cache = VerificationCache::default();
+        cache.set_base(commit_hash(0));
+        let path = vec![
+            commit_hash(1),
+            commit_hash(2),
+            commit_hash(3),
+            commit_hash(4),
+        ];
+        cache.insert(path, verifier);
+
+        // The block becomes finalized, and the tip beyond it is kept.
+        cache.set_base(commit_hash(3));
+        let (cached_commits, cached_verifier) = cache.get(&[commit_hash(4)]).unwrap();
+        assert_eq!(cached_commits, vec![(commits[3].clone(), commit_hash(4))]);
+        assert_eq!(cached_verifier.get_total_commits(), &commits[2..]);
+        // A commit that is not a block can't be the base.
+        cache.set_base(commit_hash(4));
+        assert!(cache.tips.is_empty());
+    }
+
+    #[tokio::test]
+    async fn persistence() {
+        let td = TempDir::new().unwrap();
+        let path = td.path().join("cache.json").to_str().unwrap().to_owned();
+        let (commits, mut verifier, private_key) = first_block();
+        verifier.apply_commit(&commits[0]).unwrap();
+
+        let mut cache =
+            VerificationCache::load(Some(path.clone()), Some(private_key.clone())).await;
+        cache.set_base(commit_hash(0));
+        cache.insert(vec![commit_hash(1)], verifier);
+        cache.save().await.unwrap();
+        assert!(!td.path().join("cache.json.tmp").exists());
+
+        let cache = VerificationCache::load(Some(path.clone()), Some(private_key.clone())).await;
+        assert_eq!(cache.base, Some(commit_hash(0)));
+        assert!(cache.get(&[commit_hash(1)]).is_some());
+
+        // It is not trusted with another key.
+        let (_, other_key) = generate_keypair("other");
+        let cache = VerificationCache::load(Some(path), Some(other_key)).await;
+        assert!(cache.tips.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tampered_file() {
+        let td = TempDir::new().unwrap();
+        let path = td.path().join("cache.json").to_str().unwrap().to_owned();
+        let (commits, mut verifier, private_key) = first_block();
+        verifier.apply_commit(&commits[0]).unwrap();
+
+        let mut cache =
+            VerificationCache::load(Some(path.clone()), Some(private_key.clone())).await;
+        cache.set_base(commit_hash(0));
+        cache.insert(vec![commit_hash(1)], verifier);
+        cache.save().await.unwrap();
+
+        // Claim that another commit has been verified.
+        let mut signed: SignedCache =
+            serde_spb::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let mut content: VerificationCache = serde_spb::from_str(&signed.content).unwrap();
+        let tip = content.tips.remove(&commit_hash(1)).unwrap();
+        content.insert(vec![commit_hash(9)], tip.verifier);
+        signed.content = serde_spb::to_string(&content).unwrap();
+        std::fs::write(&path, serde_spb::to_string(&signed).unwrap()).unwrap();
+
+        let cache = VerificationCache::load(Some(path), Some(private_key)).await;
+        assert!(cache.get(&[commit_hash(9)]).is_none());
+        assert!(cache.tips.is_empty());
+    }
+}
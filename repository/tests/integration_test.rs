@@ -12,6 +12,7 @@ async fn basic_1() {
     let config = Config {
         mirrors: Vec::new(),
         long_range_attack_distance: 1,
+        verification_cache_path: None,
     };
     let peers = vec![Peer {
         public_key: keys[0].0.clone(),
@@ -32,6 +33,7 @@ async fn basic_1() {
             .unwrap(),
         config.clone(),
         peers.clone(),
+        None,
     )
     .await
     .unwrap();
@@ -55,6 +57,7 @@ async fn basic_1() {
             .unwrap(),
         config,
         peers.clone(),
+        None,
     )
     .await
     .unwrap();
@@ -114,3 +117,1061 @@ async fn basic_1() {
 
     git_server.await.unwrap();
 }
+
+fn in_memory_peer(name: &str, public_key: PublicKey, port: u16) -> Peer {
+    Peer {
+        public_key,
+        name: name.to_owned(),
+        address: "127.0.0.1:1".parse().unwrap(),
+        ports: vec![("repository".to_owned(), port)].into_iter().collect(),
+        message: "".to_owned(),
+        recently_seen_timestamp: 0,
+    }
+}
+
+/// Returns the directory of an in-memory repository that is reachable as the given peer.
+fn in_memory_peer_directory(peer: &Peer) -> String {
+    format!("git://127.0.0.1:{}/repo", peer.ports["repository"])
+}
+
+/// Creates an in-memory pre-genesis repository.
+async fn create_in_memory_pre_genesis(
+    repositories: &InMemoryRepositories,
+    directory: &str,
+    reserved_state: ReservedState,
+) {
+    let mut raw = repositories
+        .init(directory, "initial", &"main".into())
+        .unwrap();
+    raw.create_semantic_commit(SemanticCommit {
+        title: "genesis".to_owned(),
+        body: "".to_owned(),
+        diff: Diff::Reserved(Box::new(reserved_state)),
+        author: PublicKey::zero(),
+        timestamp: 0,
+    })
+    .await
+    .unwrap();
+}
+
+/// Clones an in-memory repository, including its reserved branches.
+async fn clone_in_memory(
+    repositories: &InMemoryRepositories,
+    directory: &str,
+    url: &str,
+) -> InMemoryRawRepository {
+    let raw = repositories.clone_repository(directory, url).unwrap();
+    for branch in [FINALIZED_BRANCH_NAME, WORK_BRANCH_NAME, FP_BRANCH_NAME] {
+        let commit_hash = raw
+            .locate_remote_tracking_branch("origin".into(), branch.into())
+            .await
+            .unwrap();
+        // The branch that `HEAD` points to already exists.
+        let _ = raw.create_branch(branch.into(), commit_hash).await;
+    }
+    raw
+}
+
+/// Creates an agenda and a block authored by `keys[author]`, and finalizes them.
+async fn progress_height(
+    repo: &mut DistributedRepository<InMemoryRawRepository>,
+    keys: &[(PublicKey, PrivateKey)],
+    author: usize,
+) -> BlockHeader {
+    let (agenda, _) = repo.create_agenda(keys[author].0.clone()).await.unwrap();
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+        )
+        .await
+        .unwrap();
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), agenda_proof)
+        .await
+        .unwrap();
+    let (block, block_commit) = repo.create_block(keys[author].0.clone()).await.unwrap();
+    let block_proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block, private_key).unwrap())
+        .collect();
+    repo.sync(&block.to_hash256(), &block_proof).await.unwrap();
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), block_commit)
+        .await
+        .unwrap();
+    block
+}
+
+/// Same as `basic_1` but over the in-memory raw repositories, for multiple heights.
+#[tokio::test]
+async fn basic_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let server_peer = in_memory_peer("server-node", keys[0].0.clone(), 1);
+    let server_directory = in_memory_peer_directory(&server_peer);
+
+    create_in_memory_pre_genesis(&repositories, &server_directory, rs).await;
+    let mut server_node_repo = DistributedRepository::new(
+        repositories.open(&server_directory).unwrap(),
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    server_node_repo.genesis().await.unwrap();
+    let mut client_node_repo = DistributedRepository::new(
+        clone_in_memory(
+            &repositories,
+            "memory://basic_in_memory/client",
+            &server_directory,
+        )
+        .await,
+        config,
+        SharedKnownPeers::new_static(vec![server_peer]),
+        None,
+    )
+    .await
+    .unwrap();
+
+    for _ in 0..10 {
+        let block = progress_height(&mut server_node_repo, &keys, 0).await;
+        client_node_repo.fetch().await.unwrap();
+        assert_eq!(
+            client_node_repo
+                .get_last_finalized_block_header()
+                .await
+                .unwrap(),
+            block
+        );
+    }
+}
+
+/// Two servers finalize different blocks at the same height.
+/// A client following one of them must reject the other one,
+/// recording the evidence only if the fork is within the long range attack distance.
+#[tokio::test]
+async fn fork_evidence() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let server_peer = in_memory_peer("server-node", keys[0].0.clone(), 2);
+    let server_directory = in_memory_peer_directory(&server_peer);
+    let fork_peer = in_memory_peer("fork-node", keys[1].0.clone(), 3);
+    let fork_directory = in_memory_peer_directory(&fork_peer);
+
+    create_in_memory_pre_genesis(&repositories, &server_directory, rs).await;
+    let mut server_node_repo = DistributedRepository::new(
+        repositories.open(&server_directory).unwrap(),
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    server_node_repo.genesis().await.unwrap();
+    let mut fork_node_repo = DistributedRepository::new(
+        clone_in_memory(&repositories, &fork_directory, &server_directory).await,
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    let mut client_node_repo = DistributedRepository::new(
+        clone_in_memory(
+            &repositories,
+            "memory://fork_evidence/client",
+            &server_directory,
+        )
+        .await,
+        config.clone(),
+        SharedKnownPeers::new_static(vec![server_peer.clone(), fork_peer.clone()]),
+        None,
+    )
+    .await
+    .unwrap();
+    let mut far_client_node_repo = DistributedRepository::new(
+        clone_in_memory(
+            &repositories,
+            "memory://fork_evidence/far_client",
+            &server_directory,
+        )
+        .await,
+        Config {
+            long_range_attack_distance: 0,
+            ..config
+        },
+        SharedKnownPeers::new_static(vec![server_peer, fork_peer]),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let block = progress_height(&mut server_node_repo, &keys, 0).await;
+    client_node_repo.fetch().await.unwrap();
+    far_client_node_repo.fetch().await.unwrap();
+    let conflicting_blocks = [
+        progress_height(&mut fork_node_repo, &keys, 1).await,
+        progress_height(&mut fork_node_repo, &keys, 1).await,
+    ];
+
+    client_node_repo.fetch().await.unwrap();
+    assert_eq!(
+        client_node_repo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap(),
+        block
+    );
+    let evidence = client_node_repo.list_evidence().await.unwrap();
+    assert_eq!(evidence.len(), 1);
+    assert_eq!(evidence[0].canonical_block, block);
+    assert!(conflicting_blocks.contains(&evidence[0].conflicting_block));
+    assert_eq!(evidence[0].source.as_deref(), Some("fork-node"));
+
+    // The evidence survives the cleanup.
+    client_node_repo.clean(false).await.unwrap();
+    assert_eq!(client_node_repo.list_evidence().await.unwrap(), evidence);
+
+    far_client_node_repo.fetch().await.unwrap();
+    assert_eq!(
+        far_client_node_repo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap(),
+        block
+    );
+    assert!(far_client_node_repo
+        .list_evidence()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// A client broadcasts its agenda to the known peers, reporting the result for each of them.
+#[tokio::test]
+async fn broadcast_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let server_peer = in_memory_peer("server-node", keys[0].0.clone(), 4);
+    let server_directory = in_memory_peer_directory(&server_peer);
+    // Nothing is served as this peer.
+    let offline_peer = in_memory_peer("offline-node", keys[2].0.clone(), 5);
+
+    create_in_memory_pre_genesis(&repositories, &server_directory, rs).await;
+    let mut server_node_repo = DistributedRepository::new(
+        repositories.open(&server_directory).unwrap(),
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        Some(keys[0].1.clone()),
+    )
+    .await
+    .unwrap();
+    server_node_repo.genesis().await.unwrap();
+    let mut client_node_repo = DistributedRepository::new(
+        clone_in_memory(
+            &repositories,
+            "memory://broadcast_in_memory/client",
+            &server_directory,
+        )
+        .await,
+        config,
+        SharedKnownPeers::new_static(vec![server_peer, offline_peer]),
+        Some(keys[1].1.clone()),
+    )
+    .await
+    .unwrap();
+
+    let (agenda, agenda_commit) = client_node_repo
+        .create_agenda(keys[1].0.clone())
+        .await
+        .unwrap();
+    let result = client_node_repo.broadcast().await.unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0], ("server-node".to_owned(), Ok(())));
+    assert_eq!(result[1].0, "offline-node");
+    assert!(result[1].1.is_err());
+
+    let agenda_branch_name = format!(
+        "a-{}",
+        &agenda.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
+    );
+    assert_eq!(
+        server_node_repo
+            .get_raw()
+            .locate_branch(agenda_branch_name)
+            .await
+            .unwrap(),
+        agenda_commit
+    );
+}
+
+/// A serving node receives the pushed branches, rejecting the invalid ones.
+#[tokio::test]
+async fn serve_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let server_peer = in_memory_peer("server-node", keys[0].0.clone(), 6);
+    let server_directory = in_memory_peer_directory(&server_peer);
+
+    create_in_memory_pre_genesis(&repositories, &server_directory, rs).await;
+    let mut server_node_repo = DistributedRepository::new(
+        repositories.open(&server_directory).unwrap(),
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        Some(keys[0].1.clone()),
+    )
+    .await
+    .unwrap();
+    server_node_repo.genesis().await.unwrap();
+    let mut client_node_repo = DistributedRepository::new(
+        clone_in_memory(
+            &repositories,
+            "memory://serve_in_memory/client",
+            &server_directory,
+        )
+        .await,
+        config,
+        SharedKnownPeers::new_static(vec![server_peer]),
+        Some(keys[1].1.clone()),
+    )
+    .await
+    .unwrap();
+
+    let server = server_node_repo
+        .serve(ServerConfig {
+            path: None,
+            port: 6,
+            simperby_executable_path: None,
+            fetch_interval: None,
+            update_interval: Some(std::time::Duration::from_millis(100)),
+        })
+        .await
+        .unwrap();
+    let (agenda, agenda_commit) = client_node_repo
+        .create_agenda(keys[1].0.clone())
+        .await
+        .unwrap();
+    client_node_repo.broadcast().await.unwrap();
+    // An invalid branch that bypasses the verification.
+    let raw = client_node_repo.get_raw_mut();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let invalid_commit = raw
+        .create_semantic_commit(SemanticCommit {
+            title: "invalid".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::None,
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    raw.create_branch("a-00000000".into(), invalid_commit)
+        .await
+        .unwrap();
+    raw.push_option("server-node".into(), "a-00000000".into(), None)
+        .await
+        .unwrap();
+    // A forged `fp` branch, moved in the server's repository directly.
+    let mut server_raw = repositories.open(&server_directory).unwrap();
+    let fp_commit = server_raw
+        .locate_branch(FP_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    server_raw
+        .move_branch(FP_BRANCH_NAME.into(), invalid_commit)
+        .await
+        .unwrap();
+
+    let agenda_branch_name = format!(
+        "a-{}",
+        &agenda.to_hash256().to_string()[0..BRANCH_NAME_HASH_DIGITS]
+    );
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        loop {
+            {
+                let server_node_repo = server.read().await;
+                let raw = server_node_repo.get_raw();
+                if raw.locate_branch(agenda_branch_name.clone()).await.ok() == Some(agenda_commit)
+                    && raw.locate_branch("a-00000000".into()).await.is_err()
+                    && raw.locate_branch(FP_BRANCH_NAME.into()).await.unwrap() == fp_commit
+                {
+                    break;
+                }
+            }
+            sleep_ms(50).await;
+        }
+    })
+    .await
+    .expect("the server didn't verify the pushed branches in time");
+    server.join().await.unwrap().unwrap();
+}
+
+/// Creates extra-agenda transactions on top of an agenda-proof and finalizes a block with them.
+#[tokio::test]
+async fn extra_agenda_transaction_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let directory = "memory://extra_agenda_transaction_in_memory/server";
+    create_in_memory_pre_genesis(&repositories, directory, rs).await;
+    let mut repo = DistributedRepository::new(
+        repositories.open(directory).unwrap(),
+        config,
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    let delegate = |block_height: BlockHeight| {
+        let data = DelegationTransactionData {
+            delegator: keys[1].0.clone(),
+            delegatee: keys[2].0.clone(),
+            governance: true,
+            block_height,
+        };
+        ExtraAgendaTransaction::Delegate(TxDelegate {
+            delegator: data.delegator.clone(),
+            delegatee: data.delegatee.clone(),
+            governance: true,
+            proof: TypedSignature::sign(&data, &keys[1].1).unwrap(),
+            timestamp: 1,
+        })
+    };
+
+    // An extra-agenda transaction can't be made before the agenda is approved.
+    let (agenda, _) = repo.create_agenda(keys[0].0.clone()).await.unwrap();
+    assert!(repo
+        .create_extra_agenda_transaction(&delegate(1))
+        .await
+        .is_err());
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+        )
+        .await
+        .unwrap();
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), agenda_proof)
+        .await
+        .unwrap();
+
+    // The proof must be signed for the next block.
+    assert!(repo
+        .create_extra_agenda_transaction(&delegate(2))
+        .await
+        .is_err());
+    let tx_commit = repo
+        .create_extra_agenda_transaction(&delegate(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.get_raw()
+            .locate_branch(WORK_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        tx_commit
+    );
+    // The delegator can't delegate twice.
+    assert!(repo
+        .create_extra_agenda_transaction(&delegate(1))
+        .await
+        .is_err());
+
+    let (block, _) = repo.create_block(keys[0].0.clone()).await.unwrap();
+    let block_proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block, private_key).unwrap())
+        .collect();
+    repo.sync(&block.to_hash256(), &block_proof).await.unwrap();
+    assert_eq!(repo.get_last_finalized_block_header().await.unwrap(), block);
+
+    // The block finalizes the delegation in both the reserved state and its validator set.
+    let reserved_state = repo.get_reserved_state().await.unwrap();
+    assert_eq!(
+        reserved_state.members[1].consensus_delegatee,
+        Some("member-0002".to_owned())
+    );
+    assert_eq!(
+        reserved_state.members[1].governance_delegatee,
+        Some("member-0002".to_owned())
+    );
+    assert_eq!(
+        reserved_state.consensus_leader_order,
+        vec!["member-0000", "member-0002", "member-0003"]
+    );
+    assert_eq!(
+        block.validator_set,
+        vec![
+            (keys[0].0.clone(), 1),
+            (keys[2].0.clone(), 2),
+            (keys[3].0.clone(), 1)
+        ]
+    );
+}
+
+/// Cleans the repository in both the soft and the hard mode.
+#[tokio::test]
+async fn clean_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let directory = "memory://clean_in_memory/server";
+    create_in_memory_pre_genesis(&repositories, directory, rs).await;
+    let mut repo = DistributedRepository::new(
+        repositories.open(directory).unwrap(),
+        config,
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    // The soft mode deletes the invalid branches only.
+    let (agenda, agenda_commit) = repo.create_agenda(keys[0].0.clone()).await.unwrap();
+    repo.vote(agenda_commit).await.unwrap();
+    for branch in ["a-00000000", "misc"] {
+        repo.get_raw_mut()
+            .create_branch(branch.into(), agenda_commit)
+            .await
+            .unwrap();
+    }
+    // Note that the pre-genesis `main` branch is outdated.
+    let report = repo.clean(false).await.unwrap();
+    let mut deleted = report
+        .branches
+        .iter()
+        .map(|(branch, _)| branch.as_str())
+        .collect::<Vec<_>>();
+    deleted.sort();
+    assert_eq!(deleted, vec!["a-00000000", "main"]);
+    assert!(report.tags.is_empty());
+    assert_eq!(
+        repo.get_agendas().await.unwrap(),
+        vec![(agenda_commit, agenda.to_hash256())]
+    );
+
+    // Finalize the agenda so that the `misc` branch and the vote tag become outdated.
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+        )
+        .await
+        .unwrap();
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), agenda_proof)
+        .await
+        .unwrap();
+    let (block, block_commit) = repo.create_block(keys[0].0.clone()).await.unwrap();
+    let block_proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block, private_key).unwrap())
+        .collect();
+    repo.sync(&block.to_hash256(), &block_proof).await.unwrap();
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), block_commit)
+        .await
+        .unwrap();
+    let report = repo.clean(false).await.unwrap();
+    assert!(report.branches.iter().any(|(branch, _)| branch == "misc"));
+    assert!(report.tags.is_empty());
+
+    // The hard mode deletes the other branches, the outdated tags and the orphan commits.
+    let raw = repo.get_raw_mut();
+    raw.create_branch("orphan".into(), block_commit)
+        .await
+        .unwrap();
+    raw.checkout("orphan".into()).await.unwrap();
+    let orphan_commit = raw
+        .create_semantic_commit(SemanticCommit {
+            title: "orphan".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::None,
+            author: PublicKey::zero(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let report = repo.clean(true).await.unwrap();
+    assert!(report.branches.iter().any(|(branch, _)| branch == "orphan"));
+    assert_eq!(report.tags.len(), 1);
+    assert!(report.tags[0].starts_with("vote-"));
+    let mut branches = repo.get_raw().list_branches().await.unwrap();
+    branches.sort();
+    assert_eq!(
+        branches,
+        vec![FINALIZED_BRANCH_NAME, FP_BRANCH_NAME, WORK_BRANCH_NAME]
+    );
+    assert!(repo
+        .get_raw()
+        .read_semantic_commit(orphan_commit)
+        .await
+        .is_err());
+    assert_eq!(repo.get_last_finalized_block_header().await.unwrap(), block);
+}
+
+/// A new member clones the repository from a checkpoint,
+/// verifying only the history after it.
+#[tokio::test]
+async fn checkpoint_clone_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let server_directory = "memory://checkpoint_clone_in_memory/server";
+    create_in_memory_pre_genesis(&repositories, server_directory, rs).await;
+    let mut server_node_repo = DistributedRepository::new(
+        repositories.open(server_directory).unwrap(),
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    server_node_repo.genesis().await.unwrap();
+    let mut blocks = Vec::new();
+    for _ in 0..3 {
+        blocks.push(progress_height(&mut server_node_repo, &keys, 0).await);
+    }
+    let checkpoint = |proof_signers: usize| Checkpoint {
+        block_hash: blocks[0].to_hash256(),
+        proof: keys[0..proof_signers]
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(&blocks[0], private_key).unwrap())
+            .collect(),
+    };
+
+    // A checkpoint with an insufficient finalization proof must be rejected.
+    let mut client_node_repo = DistributedRepository::new(
+        repositories
+            .clone_repository(
+                "memory://checkpoint_clone_in_memory/client1",
+                server_directory,
+            )
+            .unwrap(),
+        config.clone(),
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(client_node_repo
+        .initialize_from_checkpoint(&checkpoint(1))
+        .await
+        .is_err());
+
+    let mut client_node_repo = DistributedRepository::new(
+        repositories
+            .clone_repository(
+                "memory://checkpoint_clone_in_memory/client2",
+                server_directory,
+            )
+            .unwrap(),
+        config,
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    client_node_repo
+        .initialize_from_checkpoint(&checkpoint(4))
+        .await
+        .unwrap();
+    assert_eq!(
+        client_node_repo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap(),
+        blocks[2]
+    );
+    assert_eq!(
+        client_node_repo.get_checkpoint().await.unwrap().unwrap().1,
+        blocks[0]
+    );
+
+    // The history is trusted only from the checkpoint.
+    assert!(client_node_repo.check(1).await.unwrap());
+    assert!(client_node_repo.check(3).await.unwrap());
+    assert!(client_node_repo.check(0).await.is_err());
+    assert!(server_node_repo.check(0).await.unwrap());
+}
+
+/// Creates a transaction commit on top of the `work` branch.
+async fn create_transaction(
+    repo: &mut DistributedRepository<InMemoryRawRepository>,
+    head: &str,
+    timestamp: Timestamp,
+    diff: Diff,
+) -> CommitHash {
+    let raw = repo.get_raw_mut();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let reserved_state = raw.read_reserved_state().await.unwrap();
+    raw.create_semantic_commit(
+        format::to_semantic_commit(
+            &Commit::Transaction(Transaction {
+                author: PublicKey::zero(),
+                timestamp,
+                head: head.to_owned(),
+                body: "".to_owned(),
+                diff,
+            }),
+            reserved_state,
+        )
+        .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+/// The `work` branch left behind `finalized` is rebased without any merge commit.
+#[tokio::test]
+async fn rebase_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let directory = "memory://rebase_in_memory/server";
+    create_in_memory_pre_genesis(&repositories, directory, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        repositories.open(directory).unwrap(),
+        config,
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let genesis_commit = repo
+        .get_raw()
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    // Nothing to do if already rebased.
+    assert_eq!(repo.rebase().await.unwrap(), RebaseReport::default());
+
+    // Prepare a `work` branch with a transaction and an agenda, on the genesis.
+    let transaction = create_transaction(&mut repo, "tx", 0, Diff::None).await;
+    let (_, agenda_commit) = repo.create_agenda(keys[0].0.clone()).await.unwrap();
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    // Meanwhile another block changing the reserved state is finalized.
+    let mut rs1 = rs.clone();
+    rs1.version = "0.3.0".to_owned();
+    create_transaction(
+        &mut repo,
+        "tx-reserved",
+        0,
+        Diff::Reserved(Box::new(rs1.clone())),
+    )
+    .await;
+    let block = progress_height(&mut repo, &keys, 0).await;
+
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), agenda_commit)
+        .await
+        .unwrap();
+    let report = repo.rebase().await.unwrap();
+    assert_eq!(report.replayed.len(), 1);
+    assert_eq!(report.replayed[0].0, transaction);
+    assert_eq!(report.restamped, vec![transaction]);
+    assert_eq!(
+        report.dropped,
+        vec![(agenda_commit, "not a transaction".to_owned())]
+    );
+    let work = repo
+        .get_raw()
+        .locate_branch(WORK_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(work, report.replayed[0].1);
+    assert_eq!(
+        repo.get_raw().list_ancestors(work, Some(1)).await.unwrap(),
+        vec![repo
+            .get_raw()
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap()]
+    );
+    if let Commit::Transaction(tx) = repo.read_commit(work).await.unwrap() {
+        assert_eq!(tx.head, "tx");
+        assert!(tx.timestamp >= block.timestamp);
+    } else {
+        panic!("the rebased commit must be a transaction");
+    }
+    repo.create_agenda(keys[0].0.clone()).await.unwrap();
+
+    // A transaction that `finalized` already contains is dropped.
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    let transaction = create_transaction(&mut repo, "tx", 0, Diff::None).await;
+    let duplicate =
+        create_transaction(&mut repo, "tx-reserved", 0, Diff::Reserved(Box::new(rs1))).await;
+    let report = repo.rebase().await.unwrap();
+    assert_eq!(report.replayed.len(), 1);
+    assert_eq!(report.replayed[0].0, transaction);
+    assert_eq!(
+        report.dropped,
+        vec![(duplicate, "already finalized".to_owned())]
+    );
+
+    // A reserved state change conflicting with `finalized` can't be rebased.
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    let mut rs2 = rs;
+    rs2.version = "0.4.0".to_owned();
+    let conflicting =
+        create_transaction(&mut repo, "tx-conflict", 0, Diff::Reserved(Box::new(rs2))).await;
+    assert!(repo.rebase().await.is_err());
+    assert_eq!(
+        repo.get_raw()
+            .locate_branch(WORK_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        conflicting
+    );
+}
+
+/// An agenda is reviewed with the transactions and the changes of the reserved state.
+#[tokio::test]
+async fn review_agenda_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let directory = "memory://review_agenda_in_memory/server";
+    create_in_memory_pre_genesis(&repositories, directory, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        repositories.open(directory).unwrap(),
+        config,
+        SharedKnownPeers::new_static(Vec::new()),
+        None,
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    let tx1 = create_transaction(&mut repo, "tx1", 0, Diff::None).await;
+    let mut rs1 = rs.clone();
+    rs1.members[1].consensus_voting_power += 1;
+    rs1.version = "0.3.0".to_owned();
+    let tx2 = create_transaction(&mut repo, "tx2", 0, Diff::Reserved(Box::new(rs1))).await;
+    let (agenda, agenda_commit) = repo.create_agenda(keys[0].0.clone()).await.unwrap();
+    let branch = repo
+        .get_raw()
+        .get_branches(agenda_commit)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|branch| branch.starts_with("a-"))
+        .unwrap();
+
+    let review = repo.review_agenda(&branch).await.unwrap();
+    assert_eq!(review.agenda_commit, agenda_commit);
+    assert_eq!(review.agenda, agenda);
+    assert_eq!(review.author_name, Some(rs.members[0].name.clone()));
+    assert_eq!(review.transactions.len(), 2);
+    assert_eq!(review.transactions[0].commit_hash, tx1);
+    assert_eq!(review.transactions[0].head, "tx1");
+    assert!(review.transactions[0].files.is_empty());
+    assert!(review.transactions[0].reserved_state_changes.is_empty());
+    assert_eq!(review.transactions[1].commit_hash, tx2);
+    assert_eq!(
+        review.transactions[1]
+            .files
+            .iter()
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            format!("reserved/members/{}.json", rs.members[1].name),
+            "reserved/version".to_owned()
+        ]
+    );
+    assert_eq!(
+        review.transactions[1].reserved_state_changes,
+        vec![
+            review::ReservedStateChange::ConsensusVotingPowerChanged {
+                name: rs.members[1].name.clone(),
+                before: rs.members[1].consensus_voting_power,
+                after: rs.members[1].consensus_voting_power + 1,
+            },
+            review::ReservedStateChange::VersionChanged {
+                before: rs.version.clone(),
+                after: "0.3.0".to_owned(),
+            },
+        ]
+    );
+    assert!(review.to_string().contains("version changed"));
+    let json = serde_spb::to_string(&review).unwrap();
+    assert_eq!(
+        serde_spb::from_str::<review::AgendaReview>(&json).unwrap(),
+        review
+    );
+
+    assert!(repo.review_agenda(WORK_BRANCH_NAME).await.is_err());
+}
+
+/// Agenda commits, `fp` commits and vote tags are signed, and the signatures are verified.
+#[tokio::test]
+async fn signed_commit_and_tag_in_memory() {
+    setup_test();
+    let repositories = InMemoryRepositories::new();
+
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        mirrors: Vec::new(),
+        long_range_attack_distance: 1,
+        verification_cache_path: None,
+    };
+    let directory = "memory://signed_commit_and_tag_in_memory/server";
+    create_in_memory_pre_genesis(&repositories, directory, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        repositories.open(directory).unwrap(),
+        config,
+        SharedKnownPeers::new_static(Vec::new()),
+        Some(keys[0].1.clone()),
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let genesis_commit = repo
+        .get_raw()
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    // The `fp` commit is signed by the node that recorded it.
+    let fp_commit = repo
+        .get_raw()
+        .locate_branch(FP_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.verify_commit(fp_commit).await.unwrap(),
+        signing::CommitSignatures {
+            author: Some(keys[0].0.clone()),
+            tags: Vec::new(),
+        }
+    );
+
+    // An agenda by someone else is not signed.
+    let (_, unsigned_agenda) = repo.create_agenda(keys[1].0.clone()).await.unwrap();
+    assert_eq!(
+        repo.verify_commit(unsigned_agenda).await.unwrap(),
+        signing::CommitSignatures::default()
+    );
+
+    repo.get_raw_mut()
+        .move_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    let (_, agenda_commit) = repo.create_agenda(keys[0].0.clone()).await.unwrap();
+    assert!(repo
+        .get_raw()
+        .read_commit_signature(agenda_commit)
+        .await
+        .unwrap()
+        .is_some());
+    repo.vote(agenda_commit).await.unwrap();
+    let signatures = repo.verify_commit(agenda_commit).await.unwrap();
+    assert_eq!(signatures.author, Some(keys[0].0.clone()));
+    assert_eq!(signatures.tags.len(), 1);
+    assert!(signatures.tags[0].0.starts_with("vote-"));
+    assert_eq!(signatures.tags[0].1, keys[0].0);
+
+    // A signature for another tag doesn't verify.
+    let vote_tag = signatures.tags[0].0.clone();
+    let annotation = repo
+        .get_raw()
+        .read_tag_annotation(vote_tag.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    repo.get_raw_mut()
+        .remove_tag(vote_tag.clone())
+        .await
+        .unwrap();
+    repo.get_raw_mut()
+        .create_annotated_tag(
+            vote_tag.replace("vote-", "veto-"),
+            agenda_commit,
+            annotation,
+        )
+        .await
+        .unwrap();
+    assert!(repo.verify_commit(agenda_commit).await.is_err());
+}
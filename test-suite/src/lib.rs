@@ -2,7 +2,9 @@ use path_slash::PathExt as _;
 use simperby_node::simperby_common::*;
 use simperby_node::simperby_network::primitives::Storage;
 use simperby_node::simperby_network::{
-    dms, storage::StorageImpl, Dms, NetworkConfig, Peer, SharedKnownPeers,
+    dms::{self, DmsMessage},
+    storage::StorageImpl,
+    Dms, NetworkConfig, Peer, SharedKnownPeers,
 };
 use tempfile::TempDir;
 
@@ -98,21 +100,24 @@ pub fn dispense_port() -> u16 {
         .expect("wtf did we have tests more than 1000?")
 }
 
-pub async fn create_test_dms(
+pub async fn create_test_dms<M: DmsMessage>(
     network_config: NetworkConfig,
     dms_key: String,
     peers: SharedKnownPeers,
-) -> Dms {
+) -> Dms<M> {
     let path = create_temp_dir();
     StorageImpl::create(&path).await.unwrap();
     let storage = StorageImpl::open(&path).await.unwrap();
     Dms::new(
         storage,
-        dms_key,
+        dms_key.clone(),
         dms::Config {
+            name: dms_key,
             fetch_interval: Some(std::time::Duration::from_millis(500)),
             broadcast_interval: Some(std::time::Duration::from_millis(500)),
             network_config,
+            limits: Default::default(),
+            relays: Vec::new(),
         },
         peers,
     )
@@ -125,7 +130,7 @@ pub async fn setup_server_client_nodes(
     client_n: usize,
 ) -> (NetworkConfig, Vec<NetworkConfig>, SharedKnownPeers) {
     let (public_key, private_key) = generate_keypair_random();
-    let server = NetworkConfig {
+    let mut server = NetworkConfig {
         network_id: network_id.clone(),
         ports: vec![(format!("dms-{network_id}"), dispense_port())]
             .into_iter()
@@ -148,6 +153,14 @@ pub async fn setup_server_client_nodes(
         };
         clients.push(network_config);
     }
+    // The RPC is served only to the members.
+    let members = std::iter::once(&server)
+        .chain(clients.iter())
+        .map(|config| config.public_key.clone())
+        .collect::<Vec<_>>();
+    for config in std::iter::once(&mut server).chain(clients.iter_mut()) {
+        config.members = members.clone();
+    }
     let peer = SharedKnownPeers::new_static(vec![Peer {
         public_key: server.public_key.clone(),
         name: "server".to_owned(),